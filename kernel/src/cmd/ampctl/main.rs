@@ -1,10 +1,15 @@
 use amp::internal::{
-    exec::scheduler::{ExecutionContext, Scheduler},
+    exec::bundle,
+    exec::checkpoint::CheckpointStore,
+    exec::replay::ReplayBundle,
+    exec::scheduler::{ExecutionContext, ExecutionError, Scheduler},
     plan::ir::Plan,
-    registry::{default_registry, fetch_remote_registry, load_tool_registry},
+    registry::{default_registry, fetch_remote_registry, load_tool_auth, load_tool_registry},
+    trace::trace::{decode_public_key, Keyring, Trace, TraceSigner},
 };
 use clap::{Parser, Subcommand};
 use std::fs;
+use std::io::Write;
 
 #[derive(Parser)]
 #[command(name = "ampctl")]
@@ -29,6 +34,50 @@ enum Commands {
         /// Output file for results
         #[arg(short, long)]
         out: Option<String>,
+
+        /// Maximum number of independent nodes to run concurrently
+        #[arg(long)]
+        max_parallelism: Option<usize>,
+
+        /// Sub-plan that spawn nodes can reference by name (NAME=PATH, repeatable)
+        #[arg(long = "sub-plan", value_name = "NAME=PATH")]
+        sub_plans: Vec<String>,
+
+        /// SQLite database to checkpoint execution state to after every node
+        #[arg(long)]
+        checkpoint_db: Option<String>,
+
+        /// Plan ID to checkpoint under (defaults to a new UUID)
+        #[arg(long, requires = "checkpoint_db")]
+        plan_id: Option<String>,
+
+        /// Write a signed replay bundle (tar.gz) of the run to this file
+        #[arg(long)]
+        bundle: Option<String>,
+    },
+    /// Resume a checkpointed plan from its last completed node
+    Resume {
+        /// Plan ID to resume
+        #[arg(long)]
+        plan_id: String,
+
+        /// SQLite database the plan was checkpointed to
+        #[arg(long)]
+        checkpoint_db: String,
+
+        /// Output file for results
+        #[arg(short, long)]
+        out: Option<String>,
+
+        /// Maximum number of independent nodes to run concurrently
+        #[arg(long)]
+        max_parallelism: Option<usize>,
+    },
+    /// Validate a plan file without running it
+    Validate {
+        /// Path to the plan file
+        #[arg(short, long)]
+        plan_file: String,
     },
     /// Stream trace for a plan
     Trace {
@@ -36,7 +85,7 @@ enum Commands {
         #[arg(long)]
         plan_id: String,
     },
-    /// Create a replay bundle
+    /// Download the replay bundle of a plan executed by a running kernel
     Bundle {
         /// Plan ID to bundle
         #[arg(long)]
@@ -45,6 +94,57 @@ enum Commands {
         /// Output file for bundle
         #[arg(short, long)]
         out: String,
+
+        /// Kernel base URL (defaults to AMP_KERNEL_URL or http://localhost:7777)
+        #[arg(long)]
+        kernel_url: Option<String>,
+    },
+    /// Replay a bundle offline and report where it diverges from the recorded run
+    Replay {
+        /// Path to the replay bundle
+        #[arg(short, long)]
+        bundle: String,
+
+        /// Output file for the replay report
+        #[arg(short, long)]
+        out: Option<String>,
+
+        /// Base64 Ed25519 public key the bundle may be signed with; repeat
+        /// for keys retired by rotation
+        #[arg(long = "trusted-key", required = true)]
+        trusted_keys: Vec<String>,
+    },
+    /// Check a bundle's manifest signature and file hashes
+    VerifyBundle {
+        /// Path to the replay bundle
+        #[arg(short, long)]
+        bundle: String,
+
+        /// Base64 Ed25519 public key the bundle may be signed with; repeat
+        /// for keys retired by rotation
+        #[arg(long = "trusted-key", required = true)]
+        trusted_keys: Vec<String>,
+    },
+    /// Check the signatures and hash chain of a saved trace stream
+    VerifyTrace {
+        /// JSON file with a trace array or a GET /v1/trace response
+        #[arg(short, long)]
+        file: String,
+
+        /// Base64 Ed25519 public key the traces may be signed with; repeat
+        /// for keys retired by rotation
+        #[arg(long = "public-key", required = true)]
+        public_keys: Vec<String>,
+    },
+    /// Generate an Ed25519 signing key for the kernel
+    Keygen {
+        /// Output file for the secret key
+        #[arg(short, long)]
+        out: String,
+
+        /// Key file format: pem (PKCS#8) or raw (32 bytes)
+        #[arg(long, default_value = "pem")]
+        format: String,
     },
 }
 
@@ -59,14 +159,68 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             plan_file,
             vars_file,
             out,
+            max_parallelism,
+            sub_plans,
+            checkpoint_db,
+            plan_id,
+            bundle,
+        } => {
+            let checkpoint = checkpoint_db.as_ref().map(|path| {
+                let plan_id = plan_id
+                    .clone()
+                    .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+                (path.as_str(), plan_id)
+            });
+            run_plan(
+                plan_file,
+                vars_file,
+                out,
+                *max_parallelism,
+                sub_plans,
+                checkpoint,
+                bundle,
+            )
+            .await?;
+        }
+        Commands::Resume {
+            plan_id,
+            checkpoint_db,
+            out,
+            max_parallelism,
         } => {
-            run_plan(plan_file, vars_file, out).await?;
+            resume_plan(plan_id, checkpoint_db, out, *max_parallelism).await?;
+        }
+        Commands::Validate { plan_file } => {
+            validate_plan(plan_file)?;
         }
         Commands::Trace { plan_id } => {
             trace_plan(plan_id).await?;
         }
-        Commands::Bundle { plan_id, out } => {
-            create_bundle(plan_id, out).await?;
+        Commands::Bundle {
+            plan_id,
+            out,
+            kernel_url,
+        } => {
+            create_bundle(plan_id, out, kernel_url).await?;
+        }
+        Commands::Replay {
+            bundle,
+            out,
+            trusted_keys,
+        } => {
+            replay_bundle(bundle, out, trusted_keys).await?;
+        }
+        Commands::VerifyBundle {
+            bundle,
+            trusted_keys,
+        } => {
+            verify_bundle(bundle, trusted_keys)?;
+        }
+        Commands::VerifyTrace { file, public_keys } => {
+            verify_trace(file, public_keys)?;
+        }
+        Commands::Keygen { out, format } => {
+            keygen(out, format)?;
         }
     }
 
@@ -77,6 +231,10 @@ async fn run_plan(
     plan_file: &str,
     vars_file: &Option<String>,
     out: &Option<String>,
+    max_parallelism: Option<usize>,
+    sub_plans: &[String],
+    checkpoint: Option<(&str, String)>,
+    bundle: &Option<String>,
 ) -> Result<(), Box<dyn std::error::Error>> {
     // Read the plan file
     let plan_content = fs::read_to_string(plan_file)?;
@@ -101,30 +259,132 @@ async fn run_plan(
             ctx.tool_urls.insert(name, url);
         }
     }
+    for (name, auth) in load_tool_auth()? {
+        ctx.set_tool_auth(&name, auth)?;
+    }
 
     // Set signals from plan
     ctx.signals = plan.signals.clone();
 
+    if let Some(max_parallelism) = max_parallelism {
+        ctx.max_parallelism = max_parallelism;
+    }
+
+    for entry in sub_plans {
+        let (name, path) = entry
+            .split_once('=')
+            .ok_or_else(|| format!("Invalid --sub-plan '{}', expected NAME=PATH", entry))?;
+        let sub_plan: Plan = serde_json::from_str(&fs::read_to_string(path)?)?;
+        ctx.register_sub_plan(name, sub_plan);
+    }
+
     merge_remote_registry(&mut ctx).await;
+    ctx.discover_tools().await;
 
     plan.validate_with_tools(ctx.tool_urls.keys().map(|k| k.as_str()))
         .map_err(|e| format!("Plan validation failed: {}", e))?;
 
     hydrate_tool_specs(&mut ctx).await;
 
+    plan.validate_with_tool_specs(ctx.tool_urls.keys().map(|k| k.as_str()), &ctx.tool_specs)
+        .map_err(|e| format!("Plan validation failed: {}", e))?;
+
+    let plan_id = checkpoint.map(|(path, plan_id)| {
+        eprintln!("Checkpointing plan {} to {}", plan_id, path);
+        ctx.plan_id = plan_id.clone();
+        ctx.enable_checkpoints(CheckpointStore::open(path), plan_id.clone());
+        plan_id
+    });
+
     // Execute the plan
+    let inputs = ctx.variables.clone();
     let scheduler = Scheduler;
-    let result = scheduler.execute_plan(ctx, &plan).await;
+    let (final_ctx, result) = scheduler.run(ctx, &plan).await;
 
+    if let Some(bundle_path) = bundle {
+        let recorded = ReplayBundle::record(
+            &final_ctx.plan_id,
+            &plan,
+            inputs,
+            &final_ctx,
+            result.as_ref().err().map(|e| e.to_string()),
+        );
+        // Without a configured key the bundle is signed with a fresh one
+        let signer = match TraceSigner::from_env()? {
+            Some(signer) => signer,
+            None => TraceSigner::new()?,
+        };
+        fs::write(bundle_path, bundle::pack(&recorded, &signer)?)?;
+        eprintln!(
+            "Replay bundle written to {} (signed by {})",
+            bundle_path,
+            signer.public_key_base64()
+        );
+    }
+
+    print_outcome(result.map(|_| final_ctx), plan_id.as_deref(), out)
+}
+
+async fn resume_plan(
+    plan_id: &str,
+    checkpoint_db: &str,
+    out: &Option<String>,
+    max_parallelism: Option<usize>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut ctx = ExecutionContext::new();
+    for (name, url) in load_tool_registry() {
+        ctx.tool_urls.insert(name, url);
+    }
+
+    if ctx.tool_urls.is_empty() {
+        for (name, url) in default_registry() {
+            ctx.tool_urls.insert(name, url);
+        }
+    }
+    for (name, auth) in load_tool_auth()? {
+        ctx.set_tool_auth(&name, auth)?;
+    }
+
+    if let Some(max_parallelism) = max_parallelism {
+        ctx.max_parallelism = max_parallelism;
+    }
+
+    merge_remote_registry(&mut ctx).await;
+
+    let store = CheckpointStore::open(checkpoint_db);
+    let result = Scheduler.resume(ctx, store, plan_id).await;
+
+    print_outcome(result, Some(plan_id), out)
+}
+
+fn print_outcome(
+    result: Result<ExecutionContext, ExecutionError>,
+    plan_id: Option<&str>,
+    out: &Option<String>,
+) -> Result<(), Box<dyn std::error::Error>> {
     match result {
         Ok(final_ctx) => {
             // Output result
-            let output = serde_json::json!({
-                "status": "completed",
+            let status = if final_ctx.stop_reason.is_some() {
+                "stopped"
+            } else {
+                "completed"
+            };
+            if let Some(reason) = &final_ctx.stop_reason {
+                eprintln!("Plan stopped: {}", reason);
+            }
+            let mut output = serde_json::json!({
+                "status": status,
+                "run_id": final_ctx.run_id,
+                "stop_reason": final_ctx.stop_reason,
                 "variables": final_ctx.variables,
                 "trace_count": final_ctx.trace_events.len(),
                 "completed_nodes": final_ctx.completed_nodes,
+                "skipped_nodes": final_ctx.skipped_nodes,
             });
+            if let Some(plan_id) = plan_id {
+                output["plan_id"] = serde_json::json!(plan_id);
+            }
 
             if let Some(out_path) = out {
                 fs::write(out_path, serde_json::to_string_pretty(&output)?)?;
@@ -137,16 +397,40 @@ async fn run_plan(
         }
         Err(e) => {
             eprintln!("Plan execution failed: {}", e);
+            if let Some(plan_id) = plan_id {
+                eprintln!(
+                    "Resume with: ampctl resume --plan-id {} --checkpoint-db <db>",
+                    plan_id
+                );
+            }
             Err(Box::new(e))
         }
     }
 }
 
+fn validate_plan(plan_file: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let plan_content = fs::read_to_string(plan_file)?;
+    let plan: Plan = serde_json::from_str(&plan_content)?;
+
+    plan.validate()
+        .map_err(|e| format!("Plan validation failed: {}", e))?;
+
+    let output = serde_json::json!({
+        "status": "valid",
+        "nodes": plan.nodes.len(),
+    });
+    println!("{}", serde_json::to_string_pretty(&output)?);
+
+    Ok(())
+}
+
 async fn hydrate_tool_specs(ctx: &mut ExecutionContext) {
     let client = ctx.tool_client.clone();
+    // Tools whose specs discovery already registered are not fetched again
     let entries: Vec<(String, String)> = ctx
         .tool_urls
         .iter()
+        .filter(|(k, _)| !ctx.tool_specs.contains_key(*k))
         .map(|(k, v)| (k.clone(), v.clone()))
         .collect();
 
@@ -187,11 +471,171 @@ async fn trace_plan(plan_id: &str) -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
-async fn create_bundle(plan_id: &str, out: &str) -> Result<(), Box<dyn std::error::Error>> {
-    // In a real implementation, this would connect to a running kernel instance
-    // and create a bundle of plan + traces + toolspecs
-    println!("Creating bundle for plan: {}", plan_id);
-    println!("Bundle would be written to: {}", out);
+async fn create_bundle(
+    plan_id: &str,
+    out: &str,
+    kernel_url: &Option<String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let base_url = kernel_url
+        .clone()
+        .or_else(|| std::env::var("AMP_KERNEL_URL").ok())
+        .unwrap_or_else(|| "http://localhost:7777".to_string());
+
+    let response = reqwest::Client::new()
+        .post(format!(
+            "{}/v1/replay/bundle",
+            base_url.trim_end_matches('/')
+        ))
+        .json(&serde_json::json!({ "plan_id": plan_id }))
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        return Err(format!("Kernel returned {} for plan {}: {}", status, plan_id, body).into());
+    }
+
+    let archive = response.bytes().await?;
+    let (recorded, manifest) = bundle::unpack(&archive, &kernel_keyring(&base_url).await?)?;
+    fs::write(out, &archive)?;
+    println!(
+        "Bundle for plan {} written to {} ({} tool exchanges, {} traces, signed by {})",
+        plan_id,
+        out,
+        recorded.tool_io.len(),
+        recorded.traces.len(),
+        manifest.public_key
+    );
+
+    Ok(())
+}
+
+/// The current and retired keys the kernel at `base_url` publishes.
+async fn kernel_keyring(base_url: &str) -> Result<Keyring, Box<dyn std::error::Error>> {
+    let response: serde_json::Value = reqwest::Client::new()
+        .get(format!("{}/v1/keys", base_url.trim_end_matches('/')))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    let mut keyring = Keyring::new();
+    for key in response["keys"].as_array().into_iter().flatten() {
+        let public_key = key["public_key"]
+            .as_str()
+            .ok_or("Kernel published a key without public_key")?;
+        keyring.add(decode_public_key(public_key)?, key["status"] == "retired");
+    }
+    Ok(keyring)
+}
+
+/// Reads and verifies a bundle archive signed by one of `trusted_keys`.
+fn read_bundle(
+    bundle_file: &str,
+    trusted_keys: &[String],
+) -> Result<(ReplayBundle, bundle::BundleManifest), Box<dyn std::error::Error>> {
+    let mut keyring = Keyring::new();
+    for trusted_key in trusted_keys {
+        keyring.add(decode_public_key(trusted_key)?, false);
+    }
+    Ok(bundle::unpack(&fs::read(bundle_file)?, &keyring)?)
+}
+
+fn verify_bundle(
+    bundle_file: &str,
+    trusted_keys: &[String],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (_, manifest) = read_bundle(bundle_file, trusted_keys)?;
+    println!("{}", serde_json::to_string_pretty(&manifest)?);
+    eprintln!(
+        "Bundle for plan {} is intact ({} files, signed by {})",
+        manifest.plan_id,
+        manifest.files.len(),
+        manifest.public_key
+    );
+    Ok(())
+}
+
+fn verify_trace(
+    trace_file: &str,
+    public_keys: &[String],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut content: serde_json::Value = serde_json::from_str(&fs::read_to_string(trace_file)?)?;
+    if let Some(traces) = content.get_mut("traces") {
+        content = traces.take();
+    }
+    let traces: Vec<Trace> = serde_json::from_value(content)?;
+
+    let mut keyring = Keyring::new();
+    for public_key in public_keys {
+        keyring.add(decode_public_key(public_key)?, false);
+    }
+    keyring.verify_chain(&traces)?;
+    println!("{} trace events verified", traces.len());
+    Ok(())
+}
+
+fn keygen(out: &str, format: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let signer = TraceSigner::new()?;
+    let material = match format {
+        "pem" => signer.to_pem().into_bytes(),
+        "raw" => signer.secret_bytes().to_vec(),
+        other => return Err(format!("Unknown key format {} (expected pem or raw)", other).into()),
+    };
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(out)?.write_all(&material)?;
+
+    println!("key_id: {}", signer.key_id());
+    println!("public_key: {}", signer.public_key_base64());
+    eprintln!(
+        "Secret key written to {}; start the kernel with AMP_SIGNING_KEY_FILE={}",
+        out, out
+    );
+    Ok(())
+}
+
+async fn replay_bundle(
+    bundle_file: &str,
+    out: &Option<String>,
+    trusted_keys: &[String],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (recorded, _) = read_bundle(bundle_file, trusted_keys)?;
+    let report = Scheduler.replay(&recorded).await;
+
+    let output = serde_json::to_string_pretty(&report)?;
+    if let Some(out_path) = out {
+        fs::write(out_path, output)?;
+        println!("Replay report written to {}", out_path);
+    } else {
+        println!("{}", output);
+    }
+
+    if let Some(divergence) = &report.divergence {
+        eprintln!("Replay diverged at {}", divergence);
+    }
+    if report.error != report.recorded_error {
+        eprintln!(
+            "Replay ended with {:?}, the recorded run with {:?}",
+            report.error, report.recorded_error
+        );
+    }
+    if report.unused_exchanges > 0 {
+        eprintln!(
+            "{} recorded tool exchanges were not replayed",
+            report.unused_exchanges
+        );
+    }
+    if !report.is_faithful() {
+        return Err(format!("Replay of plan {} diverged", report.plan_id).into());
+    }
 
+    eprintln!("Replay of plan {} matches the recorded run", report.plan_id);
     Ok(())
 }
@@ -1,64 +1,207 @@
 use axum::{
-    extract::{Json, Path, State},
-    http::StatusCode,
-    response::IntoResponse,
+    body::Bytes,
+    extract::{Json, Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse,
+    },
     routing::{get, post},
     Router,
 };
+use futures::{Stream, StreamExt};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use tokio::sync::{oneshot, OwnedSemaphorePermit, RwLock, Semaphore};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
 
 use crate::internal::{
-    exec::scheduler::{ExecutionContext, Scheduler},
+    exec::bundle::{self, BundleError},
+    exec::checkpoint::CheckpointStore,
+    exec::replay::{ReplayBundle, ReplayReport},
+    exec::run::{RunHandle, RunOutcome, RunResult, RunState, RunStatus},
+    exec::scheduler::{ExecutionContext, ExecutionError, Scheduler},
     plan::ir::Plan,
-    registry::{default_registry, fetch_remote_registry, load_tool_registry},
-    trace::trace::Trace,
+    registry::{
+        default_registry, fetch_remote_registry, load_tool_auth, load_tool_registry, RegistryError,
+    },
+    tools::{auth::ToolAuth, spec::ToolError},
+    trace::live::{LiveEvent, LiveTrace},
+    trace::store::{
+        MemoryTraceStore, SqliteTraceStore, TracePage, TraceQuery, TraceStore, TraceStoreError,
+    },
+    trace::trace::{decode_public_key, encode_public_key, Keyring, Trace, TraceError, TraceSigner},
 };
 use std::collections::HashMap;
 use std::env;
+use std::time::Duration;
+
+/// How long a finished run stays in memory when `AMP_RUN_RETENTION_SECS`
+/// is unset.
+pub const DEFAULT_RUN_RETENTION: Duration = Duration::from_secs(300);
 
 // State to hold execution context and traces
 #[derive(Clone)]
 pub struct AppState {
     pub exec_context: Arc<RwLock<ExecutionContext>>,
     pub plans: Arc<RwLock<std::collections::HashMap<String, Plan>>>,
-    pub plan_traces: Arc<RwLock<std::collections::HashMap<String, Vec<Trace>>>>,
+    pub traces: Arc<dyn TraceStore>, // SQLite when AMP_TRACE_DB is set
+    pub plan_bundles: Arc<RwLock<std::collections::HashMap<String, ReplayBundle>>>,
+    pub live_traces: Arc<RwLock<std::collections::HashMap<String, LiveTrace>>>,
+    pub runs: Arc<RwLock<std::collections::HashMap<String, RunHandle>>>, // by run id
+    pub run_slots: Arc<Semaphore>, // limited by AMP_MAX_CONCURRENT_RUNS
     pub tool_registry: Arc<HashMap<String, String>>,
+    pub tool_auth: Arc<HashMap<String, ToolAuth>>, // registry credentials by tool name
+    pub checkpoints: Option<CheckpointStore>,      // enabled by AMP_CHECKPOINT_DB
+    pub signer: Arc<TraceSigner>,                  // the kernel's current Ed25519 key
+    pub keyring: Arc<Keyring>,                     // current and retired public keys
+    pub retention: Duration, // finished runs stay in memory this long (AMP_RUN_RETENTION_SECS)
+}
+
+/// Why the kernel API could not start.
+#[derive(Debug, thiserror::Error)]
+pub enum StartupError {
+    #[error("Failed to load tool credentials: {0}")]
+    ToolAuth(#[from] RegistryError),
+    #[error("Failed to set up the kernel signing key: {0}")]
+    SigningKey(#[from] TraceError),
 }
 
 impl AppState {
-    pub fn new(registry: HashMap<String, String>) -> Self {
-        Self {
+    pub fn new(registry: HashMap<String, String>) -> Result<Self, StartupError> {
+        let signer = load_signer()?;
+        let keyring = load_keyring(&signer);
+        Ok(Self {
             exec_context: Arc::new(RwLock::new(ExecutionContext::new())),
             plans: Arc::new(RwLock::new(std::collections::HashMap::new())),
-            plan_traces: Arc::new(RwLock::new(std::collections::HashMap::new())),
+            traces: load_trace_store(),
+            plan_bundles: Arc::new(RwLock::new(std::collections::HashMap::new())),
+            live_traces: Arc::new(RwLock::new(std::collections::HashMap::new())),
+            runs: Arc::new(RwLock::new(std::collections::HashMap::new())),
+            run_slots: Arc::new(Semaphore::new(
+                env::var("AMP_MAX_CONCURRENT_RUNS")
+                    .ok()
+                    .and_then(|value| value.parse::<usize>().ok())
+                    .filter(|slots| *slots > 0)
+                    .unwrap_or(Semaphore::MAX_PERMITS),
+            )),
             tool_registry: Arc::new(registry),
+            tool_auth: Arc::new(load_tool_auth()?),
+            checkpoints: env::var("AMP_CHECKPOINT_DB")
+                .ok()
+                .filter(|path| !path.is_empty())
+                .map(CheckpointStore::open),
+            signer: Arc::new(signer),
+            keyring: Arc::new(keyring),
+            retention: env::var("AMP_RUN_RETENTION_SECS")
+                .ok()
+                .and_then(|value| value.parse::<u64>().ok())
+                .map_or(DEFAULT_RUN_RETENTION, Duration::from_secs),
+        })
+    }
+}
+
+/// Opens the SQLite trace database named by `AMP_TRACE_DB`, or keeps traces
+/// in memory when it is unset.
+fn load_trace_store() -> Arc<dyn TraceStore> {
+    match env::var("AMP_TRACE_DB")
+        .ok()
+        .filter(|path| !path.is_empty())
+    {
+        Some(path) => Arc::new(SqliteTraceStore::open(path)),
+        None => Arc::new(MemoryTraceStore::new()),
+    }
+}
+
+/// Loads the kernel's signing key from `AMP_SIGNING_KEY_FILE` or
+/// `AMP_SIGNING_KEY`, falling back to an ephemeral key.
+fn load_signer() -> Result<TraceSigner, TraceError> {
+    if let Some(signer) = TraceSigner::from_env()? {
+        return Ok(signer);
+    }
+    tracing::warn!(
+        "No signing key configured (set AMP_SIGNING_KEY_FILE); traces signed with an \
+         ephemeral key cannot be verified after a restart"
+    );
+    TraceSigner::new()
+}
+
+/// The current key plus the retired public keys listed, base64 and comma
+/// separated, in `AMP_RETIRED_PUBLIC_KEYS`.
+fn load_keyring(signer: &TraceSigner) -> Keyring {
+    let mut keyring = Keyring::new();
+    for encoded in env::var("AMP_RETIRED_PUBLIC_KEYS")
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|encoded| !encoded.is_empty())
+    {
+        match decode_public_key(encoded) {
+            Ok(public_key) => keyring.add(public_key, true),
+            Err(e) => tracing::warn!("Ignoring retired public key {}: {}", encoded, e),
         }
     }
+    keyring.add(*signer.get_public_key(), false);
+    keyring
+}
+
+pub fn create_router() -> Result<Router, StartupError> {
+    Ok(router(AppState::new(load_tool_registry())?))
 }
 
-pub fn create_router() -> Router {
-    let registry = load_tool_registry();
+/// The kernel API, serving from `state`.
+pub fn router(state: AppState) -> Router {
     Router::new()
         .route("/v1/plan/execute", post(execute_plan))
+        .route("/v1/plan/:plan_id/resume", post(resume_plan))
         .route("/v1/trace/:plan_id", get(get_trace))
+        .route("/v1/trace/:plan_id/stream", get(stream_trace))
+        .route("/v1/traces", get(query_traces))
+        .route("/v1/runs/:run_id", get(get_run))
+        .route("/v1/runs/:run_id/cancel", post(cancel_run))
         .route("/v1/replay/bundle", post(create_bundle))
-        .with_state(AppState::new(registry))
+        .route("/v1/replay", post(replay_bundle))
+        .route("/v1/keys", get(get_keys))
+        .with_state(state)
 }
 
 #[derive(Deserialize)]
 pub struct ExecuteRequest {
     pub plan: Plan,
     pub inputs: Option<serde_json::Value>,
+    /// Answer once the run has finished, with its result, instead of as
+    /// soon as it is queued.
+    #[serde(default)]
+    pub wait: bool,
 }
 
 #[derive(Serialize)]
 pub struct ExecuteResponse {
     pub plan_id: String,
+    pub run_id: String,
     pub stream_url: String,
-    pub status: String,
+    pub status_url: String,
+    pub status: RunState,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<RunResult>,
+}
+
+impl ExecuteResponse {
+    fn for_run(run: &RunStatus) -> Self {
+        Self {
+            plan_id: run.plan_id.clone(),
+            run_id: run.run_id.clone(),
+            stream_url: format!("/v1/trace/{}/stream", run.plan_id),
+            status_url: format!("/v1/runs/{}", run.run_id),
+            status: run.state,
+            error: run.error.clone(),
+            result: run.result.clone(),
+        }
+    }
 }
 
 #[derive(Serialize)]
@@ -73,12 +216,6 @@ async fn execute_plan(
 ) -> Result<Json<ExecuteResponse>, (StatusCode, Json<serde_json::Value>)> {
     let plan_id = Uuid::new_v4().to_string();
 
-    // Store the plan
-    {
-        let mut plans = state.plans.write().await;
-        plans.insert(plan_id.clone(), request.plan.clone());
-    }
-
     // Validate the plan first
     if let Err(e) = request.plan.validate() {
         return Err((
@@ -88,29 +225,38 @@ async fn execute_plan(
     }
 
     // Prepare execution context with inputs if provided
-    let mut ctx = ExecutionContext::new();
+    let mut ctx = base_context(&state, &request.plan).await.map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({"error": e.to_string()})),
+        )
+    })?;
     if let Some(inputs) = request.inputs {
         if let serde_json::Value::Object(map) = inputs {
             ctx.variables = map.into_iter().collect();
         }
     }
+    ctx.plan_id = plan_id.clone();
     ctx.signals = request.plan.signals.clone();
-
-    for (name, url) in state.tool_registry.iter() {
-        ctx.tool_urls.insert(name.clone(), url.clone());
+    if let Some(store) = &state.checkpoints {
+        ctx.enable_checkpoints(store.clone(), plan_id.clone());
     }
 
-    if ctx.tool_urls.is_empty() {
-        for (name, url) in default_registry() {
-            ctx.tool_urls.insert(name, url);
-        }
+    if let Err(e) = request
+        .plan
+        .validate_with_tools(ctx.tool_urls.keys().map(|k| k.as_str()))
+    {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({"error": format!("Plan validation failed: {}", e)})),
+        ));
     }
 
-    merge_remote_registry(&mut ctx).await;
+    hydrate_tool_specs(&mut ctx).await;
 
     if let Err(e) = request
         .plan
-        .validate_with_tools(ctx.tool_urls.keys().map(|k| k.as_str()))
+        .validate_with_tool_specs(ctx.tool_urls.keys().map(|k| k.as_str()), &ctx.tool_specs)
     {
         return Err((
             StatusCode::BAD_REQUEST,
@@ -118,41 +264,348 @@ async fn execute_plan(
         ));
     }
 
-    hydrate_tool_specs(&mut ctx).await;
+    // Only valid plans are kept, to be spawned as sub-plans by their id
+    {
+        let mut plans = state.plans.write().await;
+        plans.insert(plan_id.clone(), request.plan.clone());
+    }
 
-    // Execute the plan
-    let scheduler = Scheduler;
-    let result = scheduler.execute_plan(ctx, &request.plan).await;
+    // Execute the plan in the background, streaming its traces at stream_url
+    let run = track_run(&state, &mut ctx, LiveTrace::new(), request.plan.nodes.len()).await;
+    let handle = run.handle.clone();
+    let wait = request.wait;
+    let execution = tokio::spawn(async move {
+        let _slot = wait_for_slot(&state, &run, &ctx.cancel).await;
+        // Keep a replay bundle whether or not the plan succeeds
+        let inputs = ctx.variables.clone();
+        let (final_ctx, result) = Scheduler.run(ctx, &request.plan).await;
+        let bundle = ReplayBundle::record(
+            &plan_id,
+            &request.plan,
+            inputs,
+            &final_ctx,
+            result.as_ref().err().map(|e| e.to_string()),
+        );
+        {
+            let mut plan_bundles = state.plan_bundles.write().await;
+            plan_bundles.insert(plan_id.clone(), bundle);
+        }
+        finish_execution(&state, run, &request.plan, Some(&final_ctx), result).await;
+    });
 
-    match result {
-        Ok(final_ctx) => {
-            // Store traces for this plan
-            {
-                let mut plan_traces = state.plan_traces.write().await;
-                plan_traces.insert(plan_id.clone(), final_ctx.trace_events.clone());
-            }
+    if wait && execution.await.is_err() {
+        return Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({"error": "Plan execution panicked"})),
+        ));
+    }
+    Ok(Json(ExecuteResponse::for_run(&handle.status())))
+}
 
-            Ok(Json(ExecuteResponse {
-                plan_id: plan_id.clone(),
-                stream_url: format!("/v1/trace/{}", plan_id),
-                status: "completed".to_string(),
-            }))
+/// Continues a checkpointed plan from its last completed node.
+async fn resume_plan(
+    Path(plan_id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<ExecuteResponse>, (StatusCode, Json<serde_json::Value>)> {
+    let store = state.checkpoints.clone().ok_or_else(|| {
+        (
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({"error": "Checkpointing is not enabled (set AMP_CHECKPOINT_DB)"})),
+        )
+    })?;
+    let checkpoint = match store.load(&plan_id).await {
+        Ok(Some(checkpoint)) => checkpoint,
+        Ok(None) => {
+            return Err((
+                StatusCode::NOT_FOUND,
+                Json(
+                    serde_json::json!({"error": format!("No checkpoint found for plan {}", plan_id)}),
+                ),
+            ))
         }
         Err(e) => {
-            tracing::error!("Plan execution failed for plan {}: {}", plan_id, e);
-            Err((
+            return Err((
                 StatusCode::INTERNAL_SERVER_ERROR,
-                Json(serde_json::json!({"error": format!("Plan execution failed: {}", e)})),
+                Json(serde_json::json!({"error": e.to_string()})),
             ))
         }
+    };
+
+    let plan = checkpoint.plan;
+    {
+        let mut plans = state.plans.write().await;
+        plans.insert(plan_id.clone(), plan.clone());
+    }
+
+    // The stream replays the traces recorded before the checkpoint first
+    let mut ctx = base_context(&state, &plan).await.map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({"error": e.to_string()})),
+        )
+    })?;
+    ctx.plan_id = plan_id.clone();
+    // The resumed run's traces are stored after those of the checkpoint
+    if let Err(e) = state.traces.save(&plan_id, &checkpoint.trace_events).await {
+        tracing::error!("Failed to store traces for plan {}: {}", plan_id, e);
     }
+    let history = LiveTrace::with_history(checkpoint.trace_events);
+    let run = track_run(&state, &mut ctx, history, plan.nodes.len()).await;
+    run.handle.report_progress(
+        checkpoint.completed_nodes.len(),
+        checkpoint.skipped_nodes.len(),
+        vec![],
+    );
+    let response = ExecuteResponse::for_run(&run.handle.status());
+    tokio::spawn(async move {
+        let _slot = wait_for_slot(&state, &run, &ctx.cancel).await;
+        match Scheduler.resume(ctx, store, &plan_id).await {
+            Ok(final_ctx) => finish_execution(&state, run, &plan, Some(&final_ctx), Ok(())).await,
+            Err(e) => finish_execution(&state, run, &plan, None, Err(e)).await,
+        }
+    });
+
+    Ok(Json(response))
+}
+
+/// A run started through the API: its live trace stream, its status and
+/// the recorder storing its traces.
+struct TrackedRun {
+    live: LiveTrace,
+    handle: RunHandle,
+    recorder: TraceRecorder,
+}
+
+/// Appends a run's traces to the trace store as the scheduler publishes
+/// them, so the stored stream keeps up with the run however it ends.
+struct TraceRecorder {
+    total: oneshot::Sender<usize>,
+    task: JoinHandle<()>,
+}
+
+impl TraceRecorder {
+    /// Records the traces published to `live` after those it already holds.
+    fn start(store: Arc<dyn TraceStore>, plan_id: String, live: &LiveTrace) -> Self {
+        let (total, mut until) = oneshot::channel();
+        let mut stored = live.published();
+        let mut events = Box::pin(live.subscribe(stored.checked_sub(1)));
+        let task = tokio::spawn(async move {
+            let mut limit: Option<usize> = None;
+            while limit.is_none_or(|limit| stored < limit) {
+                tokio::select! {
+                    event = events.next() => {
+                        let Some(LiveEvent::Trace { trace, .. }) = event else {
+                            break;
+                        };
+                        if let Err(e) = store.append(&plan_id, &[*trace]).await {
+                            tracing::error!("Failed to store a trace of plan {}: {}", plan_id, e);
+                        }
+                        stored += 1;
+                    }
+                    total = &mut until, if limit.is_none() => limit = Some(total.unwrap_or(0)),
+                }
+            }
+        });
+        Self { total, task }
+    }
+
+    /// Waits until the first `total` traces of the run's stream are stored.
+    async fn finish(self, total: usize) {
+        let _ = self.total.send(total);
+        let _ = self.task.await;
+    }
+}
+
+/// Registers the run `ctx` is about to execute. Its traces are published to
+/// `live` at the plan's stream URL and its status at `/v1/runs/{run_id}`.
+async fn track_run(
+    state: &AppState,
+    ctx: &mut ExecutionContext,
+    live: LiveTrace,
+    total_nodes: usize,
+) -> TrackedRun {
+    let handle = RunHandle::new(
+        ctx.plan_id.clone(),
+        ctx.run_id.clone(),
+        total_nodes,
+        ctx.cancel.clone(),
+    );
+    let recorder = TraceRecorder::start(state.traces.clone(), ctx.plan_id.clone(), &live);
+    ctx.live = Some(live.clone());
+    ctx.run = Some(handle.clone());
+    {
+        let mut live_traces = state.live_traces.write().await;
+        live_traces.insert(ctx.plan_id.clone(), live.clone());
+    }
+    {
+        let mut runs = state.runs.write().await;
+        runs.insert(ctx.run_id.clone(), handle.clone());
+    }
+    TrackedRun {
+        live,
+        handle,
+        recorder,
+    }
+}
+
+/// Keeps a run queued until one of the `AMP_MAX_CONCURRENT_RUNS` slots is
+/// free, then marks it running. A run cancelled while queued gets no slot,
+/// even when one frees up at the same time; the scheduler then ends it
+/// before discovering tools or running any node.
+async fn wait_for_slot(
+    state: &AppState,
+    run: &TrackedRun,
+    cancel: &CancellationToken,
+) -> Option<OwnedSemaphorePermit> {
+    let slot = tokio::select! {
+        biased;
+        _ = cancel.cancelled() => None,
+        slot = state.run_slots.clone().acquire_owned() => slot.ok(),
+    }
+    .filter(|_| !cancel.is_cancelled());
+    if slot.is_some() {
+        run.handle.start();
+    }
+    slot
+}
+
+/// Builds an execution context with the kernel's tool registry, sub-plans,
+/// parallelism settings and signing key.
+async fn base_context(state: &AppState, plan: &Plan) -> Result<ExecutionContext, ToolError> {
+    let mut ctx = ExecutionContext::new();
+    ctx.enable_signing(state.signer.clone());
+
+    // Previously submitted plans can be spawned as sub-plans by their plan
+    // id. Only those `plan` reaches, directly or through other sub-plans,
+    // are registered.
+    {
+        let plans = state.plans.read().await;
+        let mut pending: Vec<String> = plan.sub_plan_names().into_iter().collect();
+        while let Some(id) = pending.pop() {
+            if ctx.sub_plans.contains_key(&id) {
+                continue;
+            }
+            if let Some(sub_plan) = plans.get(&id) {
+                pending.extend(sub_plan.sub_plan_names());
+                ctx.register_sub_plan(id, sub_plan.clone());
+            }
+        }
+    }
+    if let Some(max_parallelism) = env::var("AMP_MAX_PARALLELISM")
+        .ok()
+        .and_then(|value| value.parse::<usize>().ok())
+    {
+        ctx.max_parallelism = max_parallelism;
+    }
+
+    for (name, url) in state.tool_registry.iter() {
+        ctx.tool_urls.insert(name.clone(), url.clone());
+    }
+
+    if ctx.tool_urls.is_empty() {
+        for (name, url) in default_registry() {
+            ctx.tool_urls.insert(name, url);
+        }
+    }
+
+    for (name, auth) in state.tool_auth.iter() {
+        ctx.set_tool_auth(name, auth.clone())?;
+    }
+
+    merge_remote_registry(&mut ctx).await;
+    ctx.discover_tools().await;
+    Ok(ctx)
+}
+
+/// Waits for the traces of a finished run to be stored, records how it ended
+/// and what it produced, and ends its live stream. `final_ctx` is the run's
+/// context when it is still available.
+async fn finish_execution(
+    state: &AppState,
+    run: TrackedRun,
+    plan: &Plan,
+    final_ctx: Option<&ExecutionContext>,
+    result: Result<(), ExecutionError>,
+) {
+    let RunStatus {
+        plan_id, run_id, ..
+    } = run.handle.status();
+
+    // Every trace is stored before the run is reported finished
+    run.recorder.finish(run.live.published()).await;
+
+    let stop_reason = final_ctx.and_then(|ctx| ctx.stop_reason.clone());
+    let (status, error) = match result {
+        // A plan ended early by its stop_conditions reports "stopped", not an error
+        Ok(()) if stop_reason.is_some() => (RunState::Stopped, None),
+        Ok(()) => (RunState::Succeeded, None),
+        Err(e @ ExecutionError::Cancelled(_)) => (RunState::Cancelled, Some(e.to_string())),
+        Err(e) => {
+            tracing::error!("Plan execution failed for plan {}: {}", plan_id, e);
+            (
+                RunState::Failed,
+                Some(format!("Plan execution failed: {}", e)),
+            )
+        }
+    };
+
+    // Only the outputs the plan declares are returned, never its scratch variables
+    let result = final_ctx
+        .filter(|_| matches!(status, RunState::Succeeded | RunState::Stopped))
+        .map(|ctx| RunResult::from_context(ctx, plan));
+    let outcome = RunOutcome {
+        plan_id,
+        run_id,
+        status,
+        stop_reason,
+        error,
+        result,
+    };
+    run.handle.finish(&outcome);
+    // Stored before the stream ends, so it can be served once the run is forgotten
+    if let Err(e) = state.traces.save_run(&run.handle.status()).await {
+        tracing::error!(
+            "Failed to store the outcome of run {}: {}",
+            outcome.run_id,
+            e
+        );
+    }
+    run.live.finish(outcome);
+    forget_after_retention(state, run.handle.status());
+}
+
+/// Drops a finished run's handle, live stream and replay bundle from memory
+/// once the retention period has passed. Its traces and status stay in the
+/// trace store, which serves them from then on.
+fn forget_after_retention(state: &AppState, run: RunStatus) {
+    let state = state.clone();
+    let RunStatus {
+        plan_id, run_id, ..
+    } = run;
+    tokio::spawn(async move {
+        tokio::time::sleep(state.retention).await;
+        state.runs.write().await.remove(&run_id);
+        {
+            // A resumed run of the plan may be streaming under its id by now
+            let mut live_traces = state.live_traces.write().await;
+            if live_traces
+                .get(&plan_id)
+                .is_some_and(LiveTrace::is_finished)
+            {
+                live_traces.remove(&plan_id);
+            }
+        }
+        state.plan_bundles.write().await.remove(&plan_id);
+    });
 }
 
 async fn hydrate_tool_specs(ctx: &mut ExecutionContext) {
     let client = ctx.tool_client.clone();
+    // Tools whose specs discovery already registered are not fetched again
     let entries: Vec<(String, String)> = ctx
         .tool_urls
         .iter()
+        .filter(|(k, _)| !ctx.tool_specs.contains_key(*k))
         .map(|(k, v)| (k.clone(), v.clone()))
         .collect();
 
@@ -187,16 +640,17 @@ async fn get_trace(
     Path(plan_id): Path<String>,
     State(state): State<AppState>,
 ) -> Result<Json<TraceResponse>, (StatusCode, Json<serde_json::Value>)> {
-    let plan_traces = state.plan_traces.read().await;
-    let traces = plan_traces
-        .get(&plan_id)
+    let traces = state
+        .traces
+        .load(&plan_id)
+        .await
+        .map_err(trace_store_error)?
         .ok_or_else(|| {
             (
                 StatusCode::NOT_FOUND,
                 Json(serde_json::json!({"error": format!("Plan {} not found", plan_id)})),
             )
-        })?
-        .clone();
+        })?;
 
     Ok(Json(TraceResponse {
         plan_id: plan_id.clone(),
@@ -204,44 +658,241 @@ async fn get_trace(
     }))
 }
 
+/// Reports a run's state, progress and the nodes it is running.
+async fn get_run(
+    Path(run_id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<RunStatus>, (StatusCode, Json<serde_json::Value>)> {
+    let run = state.runs.read().await.get(&run_id).map(RunHandle::status);
+    match run {
+        Some(status) => Ok(Json(status)),
+        None => stored_run(&state, &run_id).await.map(Json),
+    }
+}
+
+/// The stored status of a finished run no longer held in memory.
+async fn stored_run(
+    state: &AppState,
+    run_id: &str,
+) -> Result<RunStatus, (StatusCode, Json<serde_json::Value>)> {
+    state
+        .traces
+        .load_run(run_id)
+        .await
+        .map_err(trace_store_error)?
+        .ok_or_else(|| run_not_found(run_id))
+}
+
+/// Cancels a queued or running run. In-flight tool calls are abandoned and
+/// the run ends as `cancelled` with a `plan_cancelled` trace.
+async fn cancel_run(
+    Path(run_id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<(StatusCode, Json<RunStatus>), (StatusCode, Json<serde_json::Value>)> {
+    let run = state.runs.read().await.get(&run_id).cloned();
+    let Some(run) = run else {
+        // Only finished runs are forgotten
+        let status = stored_run(&state, &run_id).await?;
+        return Err(already_finished(&run_id, &status));
+    };
+    if !run.cancel() {
+        return Err(already_finished(&run_id, &run.status()));
+    }
+    Ok((StatusCode::ACCEPTED, Json(run.status())))
+}
+
+fn already_finished(run_id: &str, status: &RunStatus) -> (StatusCode, Json<serde_json::Value>) {
+    (
+        StatusCode::CONFLICT,
+        Json(serde_json::json!({
+            "error": format!("Run {} has already finished", run_id),
+            "state": status.state,
+        })),
+    )
+}
+
+fn run_not_found(run_id: &str) -> (StatusCode, Json<serde_json::Value>) {
+    (
+        StatusCode::NOT_FOUND,
+        Json(serde_json::json!({"error": format!("Run {} not found", run_id)})),
+    )
+}
+
+/// Streams a plan's traces as Server-Sent Events while it runs, numbered by
+/// their position in the trace stream and followed by an `end` event with
+/// the outcome. Clients reconnecting with `Last-Event-ID` resume after it.
+/// Plans no longer held in memory are streamed from the trace store.
+async fn stream_trace(
+    Path(plan_id): Path<String>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<
+    Sse<impl Stream<Item = Result<Event, axum::Error>>>,
+    (StatusCode, Json<serde_json::Value>),
+> {
+    let live = state.live_traces.read().await.get(&plan_id).cloned();
+    let live = match live {
+        Some(live) => live,
+        None => stored_stream(&state, &plan_id).await?,
+    };
+    let last_event_id = headers
+        .get("last-event-id")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<usize>().ok());
+
+    let events = live.subscribe(last_event_id).map(|event| match event {
+        LiveEvent::Trace { id, trace } => Event::default()
+            .id(id.to_string())
+            .event("trace")
+            .json_data(trace),
+        LiveEvent::End { id, outcome } => Event::default()
+            .id(id.to_string())
+            .event("end")
+            .json_data(outcome),
+    });
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+/// The finished stream of `plan_id` rebuilt from the trace store: its
+/// stored traces, ended by the outcome of its last run when one was stored.
+async fn stored_stream(
+    state: &AppState,
+    plan_id: &str,
+) -> Result<LiveTrace, (StatusCode, Json<serde_json::Value>)> {
+    let traces = state
+        .traces
+        .load(plan_id)
+        .await
+        .map_err(trace_store_error)?;
+    let outcome = state
+        .traces
+        .last_run(plan_id)
+        .await
+        .map_err(trace_store_error)?
+        .and_then(|run| run.outcome());
+    if traces.is_none() && outcome.is_none() {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({"error": format!("Plan {} not found", plan_id)})),
+        ));
+    }
+
+    let live = LiveTrace::with_history(traces.unwrap_or_default());
+    if let Some(outcome) = outcome {
+        live.finish(outcome);
+    }
+    Ok(live)
+}
+
+/// Searches stored traces across plans. Filters combine; pass `next_cursor`
+/// from a page as `cursor` to fetch the next one.
+async fn query_traces(
+    Query(query): Query<TraceQuery>,
+    State(state): State<AppState>,
+) -> Result<Json<TracePage>, (StatusCode, Json<serde_json::Value>)> {
+    state
+        .traces
+        .query(&query)
+        .await
+        .map(Json)
+        .map_err(trace_store_error)
+}
+
+fn trace_store_error(e: TraceStoreError) -> (StatusCode, Json<serde_json::Value>) {
+    let status = match e {
+        TraceStoreError::InvalidCursor(_) => StatusCode::BAD_REQUEST,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    (status, Json(serde_json::json!({"error": e.to_string()})))
+}
+
+#[derive(Serialize)]
+pub struct PublicKeyInfo {
+    pub key_id: String,
+    pub algorithm: String,
+    pub public_key: String,
+    pub status: String,
+}
+
+#[derive(Serialize)]
+pub struct KeysResponse {
+    pub keys: Vec<PublicKeyInfo>,
+}
+
+/// Publishes the current and retired public keys that trace signatures can
+/// be checked against, by the `key_id` each trace carries.
+async fn get_keys(State(state): State<AppState>) -> Json<KeysResponse> {
+    Json(KeysResponse {
+        keys: state
+            .keyring
+            .entries()
+            .iter()
+            .map(|entry| PublicKeyInfo {
+                key_id: entry.key_id.clone(),
+                algorithm: "ed25519".to_string(),
+                public_key: encode_public_key(&entry.public_key),
+                status: if entry.retired { "retired" } else { "current" }.to_string(),
+            })
+            .collect(),
+    })
+}
+
 #[derive(Deserialize)]
 pub struct BundleRequest {
     pub plan_id: String,
 }
 
+/// Packs the replay bundle recorded for an execution into a signed tar.gz
+/// archive: its plan, inputs, hydrated tool specs, every tool request and
+/// response and the trace stream, with a manifest of their hashes.
 async fn create_bundle(
     State(state): State<AppState>,
     Json(request): Json<BundleRequest>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
-    // In a real implementation, this would create a tar.gz bundle
-    // For now, return a dummy response
-    let plans = state.plans.read().await;
-    let plan = plans.get(&request.plan_id).ok_or_else(|| {
+    let plan_bundles = state.plan_bundles.read().await;
+    let recorded = plan_bundles.get(&request.plan_id).ok_or_else(|| {
         (
             StatusCode::NOT_FOUND,
-            Json(serde_json::json!({"error": format!("Plan {} not found", request.plan_id)})),
+            Json(serde_json::json!({"error": format!("No replay bundle for plan {}", request.plan_id)})),
         )
     })?;
 
-    let plan_traces = state.plan_traces.read().await;
-    let traces = plan_traces.get(&request.plan_id)
-        .ok_or_else(|| {
-            (StatusCode::NOT_FOUND, Json(serde_json::json!({"error": format!("Traces for plan {} not found", request.plan_id)})))
-        })?
-        .clone();
-
-    let bundle_data = serde_json::json!({
-        "plan": plan,
-        "traces": traces,
-        "plan_id": request.plan_id
-    });
-
-    let serialized = serde_json::to_vec(&bundle_data).map_err(|e| {
+    let archive = bundle::pack(recorded, &state.signer).map_err(|e| {
         (
             StatusCode::INTERNAL_SERVER_ERROR,
-            Json(serde_json::json!({"error": format!("Serialization error: {}", e)})),
+            Json(serde_json::json!({"error": e.to_string()})),
         )
     })?;
 
-    Ok((StatusCode::OK, serialized))
+    Ok((
+        StatusCode::OK,
+        [
+            (header::CONTENT_TYPE, "application/gzip".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}.tar.gz\"", request.plan_id),
+            ),
+        ],
+        archive,
+    ))
+}
+
+/// Verifies an uploaded bundle archive and replays it without contacting
+/// any tool. Tampered bundles, and bundles not signed by a key of the
+/// kernel's keyring, are rejected.
+async fn replay_bundle(
+    State(state): State<AppState>,
+    body: Bytes,
+) -> Result<Json<ReplayReport>, (StatusCode, Json<serde_json::Value>)> {
+    let (recorded, _) = bundle::unpack(&body, &state.keyring).map_err(|e| {
+        let status = match e {
+            BundleError::Integrity(_) | BundleError::Signature(_) => {
+                StatusCode::UNPROCESSABLE_ENTITY
+            }
+            BundleError::Io(_) | BundleError::Format(_) => StatusCode::BAD_REQUEST,
+        };
+        (status, Json(serde_json::json!({"error": e.to_string()})))
+    })?;
+
+    Ok(Json(Scheduler.replay(&recorded).await))
 }
@@ -0,0 +1,345 @@
+use crate::internal::{
+    exec::replay::{ReplayBundle, ToolExchange, BUNDLE_VERSION},
+    plan::ir::{Plan, Signals},
+    tools::spec::ToolSpec,
+    trace::trace::{decode_public_key, verify_bytes, Keyring, Trace, TraceSigner},
+};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::io::Read;
+
+/// Value of `format` in every bundle manifest.
+pub const BUNDLE_FORMAT: &str = "amp-replay-bundle";
+
+const MANIFEST: &str = "manifest.json";
+const RUN: &str = "run.json";
+const PLAN: &str = "plan.json";
+const INPUTS: &str = "inputs.json";
+const TRACES: &str = "traces.jsonl";
+const TOOL_IO: &str = "tool_io.jsonl";
+const TOOL_SPECS_DIR: &str = "toolspecs/";
+const SUB_PLANS_DIR: &str = "subplans/";
+
+/// Lists every file of a bundle archive with its SHA-256, and is signed by
+/// the key that produced the archive.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BundleManifest {
+    pub format: String,
+    pub version: u32,
+    pub plan_id: String,
+    pub created_at: String,
+    pub files: BTreeMap<String, String>, // archive path to hex SHA-256
+    pub public_key: String,              // base64 Ed25519 key of the signer
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>, // over the manifest serialised without this field
+}
+
+impl BundleManifest {
+    fn signed_bytes(&self) -> Result<Vec<u8>, BundleError> {
+        let unsigned = Self {
+            signature: None,
+            ..self.clone()
+        };
+        serde_json::to_vec(&unsigned).map_err(|e| BundleError::Format(e.to_string()))
+    }
+}
+
+/// Run metadata that has no file of its own.
+#[derive(Serialize, Deserialize)]
+struct RunInfo {
+    plan_id: String,
+    created_at: String,
+    #[serde(default)]
+    signals: Option<Signals>,
+    tools: Vec<String>,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+/// Packs a replay bundle into a gzipped tar archive:
+///
+/// ```text
+/// manifest.json          format, version, SHA-256 of every other file, signature
+/// run.json               plan id, signals, available tools, error
+/// plan.json
+/// inputs.json
+/// toolspecs/<tool>.json
+/// subplans/<name>.json
+/// traces.jsonl
+/// tool_io.jsonl
+/// ```
+///
+/// The manifest is signed with `signer`.
+pub fn pack(bundle: &ReplayBundle, signer: &TraceSigner) -> Result<Vec<u8>, BundleError> {
+    let mut files: BTreeMap<String, Vec<u8>> = BTreeMap::new();
+    files.insert(
+        RUN.to_string(),
+        to_json(&RunInfo {
+            plan_id: bundle.plan_id.clone(),
+            created_at: bundle.created_at.clone(),
+            signals: bundle.signals.clone(),
+            tools: bundle.tools.clone(),
+            error: bundle.error.clone(),
+        })?,
+    );
+    files.insert(PLAN.to_string(), to_json(&bundle.plan)?);
+    files.insert(INPUTS.to_string(), to_json(&bundle.inputs)?);
+    for (name, spec) in &bundle.tool_specs {
+        files.insert(entry_path(TOOL_SPECS_DIR, name)?, to_json(spec)?);
+    }
+    for (name, plan) in &bundle.sub_plans {
+        files.insert(entry_path(SUB_PLANS_DIR, name)?, to_json(plan)?);
+    }
+    files.insert(TRACES.to_string(), to_jsonl(&bundle.traces)?);
+    files.insert(TOOL_IO.to_string(), to_jsonl(&bundle.tool_io)?);
+
+    let mut manifest = BundleManifest {
+        format: BUNDLE_FORMAT.to_string(),
+        version: BUNDLE_VERSION,
+        plan_id: bundle.plan_id.clone(),
+        created_at: bundle.created_at.clone(),
+        files: files
+            .iter()
+            .map(|(path, content)| (path.clone(), sha256_hex(content)))
+            .collect(),
+        public_key: signer.public_key_base64(),
+        signature: None,
+    };
+    manifest.signature = Some(signer.sign_bytes(&manifest.signed_bytes()?));
+
+    let mut archive = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+    append(&mut archive, MANIFEST, &to_json(&manifest)?)?;
+    for (path, content) in &files {
+        append(&mut archive, path, content)?;
+    }
+    archive
+        .into_inner()
+        .and_then(|encoder| encoder.finish())
+        .map_err(|e| BundleError::Io(e.to_string()))
+}
+
+/// Reads a bundle archive, rejecting it unless the manifest was signed by
+/// one of the `trusted` keys and every file matches its manifest hash.
+pub fn unpack(
+    archive: &[u8],
+    trusted: &Keyring,
+) -> Result<(ReplayBundle, BundleManifest), BundleError> {
+    let mut files = read_entries(archive)?;
+    let manifest: BundleManifest = from_json(
+        MANIFEST,
+        &files
+            .remove(MANIFEST)
+            .ok_or_else(|| BundleError::Format(format!("{} is missing", MANIFEST)))?,
+    )?;
+    verify_manifest(&manifest, trusted)?;
+
+    for (path, expected) in &manifest.files {
+        let content = files
+            .get(path)
+            .ok_or_else(|| BundleError::Integrity(format!("{} is missing", path)))?;
+        let actual = sha256_hex(content);
+        if &actual != expected {
+            return Err(BundleError::Integrity(format!(
+                "{} has SHA-256 {} but the manifest lists {}",
+                path, actual, expected
+            )));
+        }
+    }
+    if let Some(path) = files
+        .keys()
+        .find(|path| !manifest.files.contains_key(*path))
+    {
+        return Err(BundleError::Integrity(format!(
+            "{} is not listed in the manifest",
+            path
+        )));
+    }
+
+    let file = |path: &str| {
+        files
+            .get(path)
+            .ok_or_else(|| BundleError::Format(format!("{} is missing", path)))
+    };
+    let run: RunInfo = from_json(RUN, file(RUN)?)?;
+    if run.plan_id != manifest.plan_id {
+        return Err(BundleError::Integrity(format!(
+            "{} is for plan {} but the manifest is for plan {}",
+            RUN, run.plan_id, manifest.plan_id
+        )));
+    }
+    let plan: Plan = from_json(PLAN, file(PLAN)?)?;
+    let inputs: HashMap<String, Value> = from_json(INPUTS, file(INPUTS)?)?;
+    let traces: Vec<Trace> = from_jsonl(TRACES, file(TRACES)?)?;
+    let tool_io: Vec<ToolExchange> = from_jsonl(TOOL_IO, file(TOOL_IO)?)?;
+
+    let mut tool_specs = HashMap::new();
+    let mut sub_plans = HashMap::new();
+    for (path, content) in &files {
+        if let Some(name) = entry_name(path, TOOL_SPECS_DIR) {
+            tool_specs.insert(name.to_string(), from_json::<ToolSpec>(path, content)?);
+        } else if let Some(name) = entry_name(path, SUB_PLANS_DIR) {
+            sub_plans.insert(name.to_string(), from_json::<Plan>(path, content)?);
+        }
+    }
+
+    let bundle = ReplayBundle {
+        version: manifest.version,
+        plan_id: run.plan_id,
+        created_at: run.created_at,
+        plan,
+        inputs,
+        signals: run.signals,
+        tools: run.tools,
+        tool_specs,
+        sub_plans,
+        tool_io,
+        traces,
+        error: run.error,
+    };
+    Ok((bundle, manifest))
+}
+
+fn verify_manifest(manifest: &BundleManifest, trusted: &Keyring) -> Result<(), BundleError> {
+    if manifest.format != BUNDLE_FORMAT {
+        return Err(BundleError::Format(format!(
+            "unknown bundle format '{}'",
+            manifest.format
+        )));
+    }
+    if manifest.version == 0 || manifest.version > BUNDLE_VERSION {
+        return Err(BundleError::Format(format!(
+            "unsupported bundle version {} (this kernel reads up to {})",
+            manifest.version, BUNDLE_VERSION
+        )));
+    }
+
+    // The manifest names its signer, but only a key from `trusted` may
+    // vouch for it, or anyone could re-sign an edited bundle
+    let claimed = decode_public_key(&manifest.public_key)
+        .map_err(|e| BundleError::Signature(e.to_string()))?;
+    let public_key = trusted
+        .entries()
+        .iter()
+        .map(|entry| &entry.public_key)
+        .find(|public_key| public_key.as_bytes() == claimed.as_bytes())
+        .ok_or_else(|| {
+            BundleError::Signature(format!(
+                "manifest is signed by {}, which is not a trusted key",
+                manifest.public_key
+            ))
+        })?;
+    let signature = manifest
+        .signature
+        .as_deref()
+        .ok_or_else(|| BundleError::Signature("manifest is not signed".to_string()))?;
+    verify_bytes(public_key, &manifest.signed_bytes()?, signature)
+        .map_err(|e| BundleError::Signature(e.to_string()))
+}
+
+fn read_entries(archive: &[u8]) -> Result<BTreeMap<String, Vec<u8>>, BundleError> {
+    let mut files = BTreeMap::new();
+    let mut archive = tar::Archive::new(GzDecoder::new(archive));
+    let entries = archive
+        .entries()
+        .map_err(|e| BundleError::Io(e.to_string()))?;
+    for entry in entries {
+        let mut entry = entry.map_err(|e| BundleError::Io(e.to_string()))?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let path = entry
+            .path()
+            .map_err(|e| BundleError::Io(e.to_string()))?
+            .to_string_lossy()
+            .into_owned();
+        let mut content = Vec::new();
+        entry
+            .read_to_end(&mut content)
+            .map_err(|e| BundleError::Io(e.to_string()))?;
+        if files.insert(path.clone(), content).is_some() {
+            return Err(BundleError::Integrity(format!(
+                "{} appears more than once",
+                path
+            )));
+        }
+    }
+    Ok(files)
+}
+
+fn append<W: std::io::Write>(
+    archive: &mut tar::Builder<W>,
+    path: &str,
+    content: &[u8],
+) -> Result<(), BundleError> {
+    let mut header = tar::Header::new_ustar();
+    header.set_size(content.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(0);
+    header.set_entry_type(tar::EntryType::Regular);
+    archive
+        .append_data(&mut header, path, content)
+        .map_err(|e| BundleError::Io(e.to_string()))
+}
+
+/// Path of a named entry in `dir`, refusing names that would escape it.
+fn entry_path(dir: &str, name: &str) -> Result<String, BundleError> {
+    if name.is_empty() || name.contains('/') || name.contains('\\') || name.starts_with('.') {
+        return Err(BundleError::Format(format!(
+            "'{}' cannot be used as a file name in {}",
+            name, dir
+        )));
+    }
+    Ok(format!("{}{}.json", dir, name))
+}
+
+fn entry_name<'a>(path: &'a str, dir: &str) -> Option<&'a str> {
+    path.strip_prefix(dir)?.strip_suffix(".json")
+}
+
+fn sha256_hex(content: &[u8]) -> String {
+    hex::encode(Sha256::digest(content))
+}
+
+fn to_json<T: Serialize>(value: &T) -> Result<Vec<u8>, BundleError> {
+    serde_json::to_vec_pretty(value).map_err(|e| BundleError::Format(e.to_string()))
+}
+
+fn to_jsonl<T: Serialize>(values: &[T]) -> Result<Vec<u8>, BundleError> {
+    let mut out = Vec::new();
+    for value in values {
+        serde_json::to_writer(&mut out, value).map_err(|e| BundleError::Format(e.to_string()))?;
+        out.push(b'\n');
+    }
+    Ok(out)
+}
+
+fn from_json<T: DeserializeOwned>(path: &str, content: &[u8]) -> Result<T, BundleError> {
+    serde_json::from_slice(content).map_err(|e| BundleError::Format(format!("{}: {}", path, e)))
+}
+
+fn from_jsonl<T: DeserializeOwned>(path: &str, content: &[u8]) -> Result<Vec<T>, BundleError> {
+    content
+        .split(|byte| *byte == b'\n')
+        .enumerate()
+        .filter(|(_, line)| !line.is_empty())
+        .map(|(index, line)| {
+            serde_json::from_slice(line)
+                .map_err(|e| BundleError::Format(format!("{} line {}: {}", path, index + 1, e)))
+        })
+        .collect()
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum BundleError {
+    #[error("Bundle I/O error: {0}")]
+    Io(String),
+    #[error("Invalid bundle: {0}")]
+    Format(String),
+    #[error("Bundle integrity check failed: {0}")]
+    Integrity(String),
+    #[error("Bundle signature check failed: {0}")]
+    Signature(String),
+}
@@ -0,0 +1,427 @@
+use crate::internal::{
+    exec::scheduler::{ExecutionContext, StopReason},
+    plan::ir::Plan,
+    trace::trace::{Trace, TraceChain},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions};
+use sqlx::{Executor, Row};
+use std::collections::HashMap;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::sync::OnceCell;
+
+const SCHEMA: &str = r#"
+CREATE TABLE IF NOT EXISTS checkpoints (
+    plan_id TEXT PRIMARY KEY,
+    status TEXT NOT NULL,
+    state TEXT NOT NULL,
+    updated_at TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS tool_journal (
+    plan_id TEXT NOT NULL,
+    call_key TEXT NOT NULL,
+    tool TEXT NOT NULL,
+    result TEXT,
+    PRIMARY KEY (plan_id, call_key)
+);
+CREATE TABLE IF NOT EXISTS checkpoint_traces (
+    plan_id TEXT NOT NULL,
+    seq INTEGER NOT NULL,
+    trace TEXT NOT NULL,
+    PRIMARY KEY (plan_id, seq)
+);
+"#;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckpointStatus {
+    Running,
+    Completed,
+    Stopped,
+    Failed,
+}
+
+impl CheckpointStatus {
+    fn as_str(&self) -> &'static str {
+        match self {
+            CheckpointStatus::Running => "running",
+            CheckpointStatus::Completed => "completed",
+            CheckpointStatus::Stopped => "stopped",
+            CheckpointStatus::Failed => "failed",
+        }
+    }
+
+    /// Whether a plan in this state still has nodes left to run.
+    pub fn is_resumable(&self) -> bool {
+        matches!(self, CheckpointStatus::Running | CheckpointStatus::Failed)
+    }
+}
+
+/// Durable snapshot of a plan execution, taken after every completed node.
+/// Traces are stored apart from the snapshot, each one written once.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub plan_id: String,
+    pub plan: Plan,
+    pub status: CheckpointStatus,
+    pub variables: HashMap<String, Value>,
+    pub completed_nodes: Vec<String>,
+    pub skipped_nodes: Vec<String>,
+    pub branch_not_taken: HashMap<String, Vec<String>>,
+    pub executed_nodes: usize,
+    pub total_latency_ms: f64,
+    pub total_cost_usd: f64,
+    pub total_tokens: u64,
+    #[serde(default)]
+    pub total_fuel: u64,
+    /// Filled in by [`CheckpointStore::load`]; never part of the snapshot.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub trace_events: Vec<Trace>,
+    pub stop_reason: Option<StopReason>,
+    pub error: Option<String>,
+    pub updated_at: String,
+}
+
+impl Checkpoint {
+    pub fn capture(
+        plan_id: &str,
+        plan: &Plan,
+        ctx: &ExecutionContext,
+        status: CheckpointStatus,
+        executed_nodes: usize,
+    ) -> Self {
+        let mut completed_nodes: Vec<String> = ctx.completed_nodes.iter().cloned().collect();
+        completed_nodes.sort();
+        let mut skipped_nodes: Vec<String> = ctx.skipped_nodes.iter().cloned().collect();
+        skipped_nodes.sort();
+        let branch_not_taken = ctx
+            .branch_not_taken
+            .iter()
+            .map(|(branch, targets)| {
+                let mut targets: Vec<String> = targets.iter().cloned().collect();
+                targets.sort();
+                (branch.clone(), targets)
+            })
+            .collect();
+
+        Self {
+            plan_id: plan_id.to_string(),
+            plan: plan.clone(),
+            status,
+            variables: ctx.variables.clone(),
+            completed_nodes,
+            skipped_nodes,
+            branch_not_taken,
+            executed_nodes,
+            total_latency_ms: ctx.total_latency_ms,
+            total_cost_usd: ctx.total_cost_usd,
+            total_tokens: ctx.total_tokens,
+            total_fuel: ctx.total_fuel,
+            trace_events: Vec::new(),
+            stop_reason: ctx.stop_reason.clone(),
+            error: None,
+            updated_at: chrono::Utc::now().to_rfc3339(),
+        }
+    }
+
+    /// Restores the execution state into `ctx`, leaving its tool
+    /// configuration untouched.
+    pub fn restore(&self, ctx: &mut ExecutionContext) {
+        ctx.variables = self.variables.clone();
+        ctx.completed_nodes = self.completed_nodes.iter().cloned().collect();
+        ctx.skipped_nodes = self.skipped_nodes.iter().cloned().collect();
+        ctx.branch_not_taken = self
+            .branch_not_taken
+            .iter()
+            .map(|(branch, targets)| (branch.clone(), targets.iter().cloned().collect()))
+            .collect();
+        ctx.running_nodes.clear();
+        ctx.total_latency_ms = self.total_latency_ms;
+        ctx.total_cost_usd = self.total_cost_usd;
+        ctx.total_tokens = self.total_tokens;
+        ctx.total_fuel = self.total_fuel;
+        ctx.trace_events = self.trace_events.clone();
+        ctx.trace_chain = TraceChain::following(ctx.trace_events.last());
+        ctx.stop_reason = self.stop_reason.clone();
+        if ctx.signals.is_none() {
+            ctx.signals = self.plan.signals.clone();
+        }
+    }
+}
+
+/// A journaled invocation of a tool with `side_effects: true`. An entry
+/// without a result was started but never finished.
+#[derive(Debug, Clone)]
+pub struct JournalEntry {
+    pub tool: String,
+    pub result: Option<Value>,
+}
+
+/// SQLite-backed storage for checkpoints and the side-effect journal.
+#[derive(Debug, Clone)]
+pub struct CheckpointStore {
+    pool: SqlitePool,
+    schema: Arc<OnceCell<()>>,
+}
+
+impl CheckpointStore {
+    /// Opens (creating if needed) a checkpoint database at `path`. The
+    /// connection is established on first use.
+    pub fn open(path: impl AsRef<Path>) -> Self {
+        let options = SqliteConnectOptions::new()
+            .filename(path)
+            .create_if_missing(true);
+        Self::with_options(options)
+    }
+
+    /// A private in-memory database, mainly useful for tests.
+    pub fn in_memory() -> Self {
+        let options = SqliteConnectOptions::from_str("sqlite::memory:")
+            .expect("valid in-memory database url");
+        Self::with_options(options)
+    }
+
+    fn with_options(options: SqliteConnectOptions) -> Self {
+        // A single long-lived connection: SQLite serialises writers anyway,
+        // and an in-memory database only lives as long as its connection.
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .connect_lazy_with(options);
+        Self {
+            pool,
+            schema: Arc::new(OnceCell::new()),
+        }
+    }
+
+    async fn pool(&self) -> Result<&SqlitePool, CheckpointError> {
+        self.schema
+            .get_or_try_init(|| async { (&self.pool).execute(SCHEMA).await.map(|_| ()) })
+            .await
+            .map_err(|e| CheckpointError::Database(e.to_string()))?;
+        Ok(&self.pool)
+    }
+
+    /// Saves `checkpoint` along with the traces of its execution so far.
+    /// Only the traces not stored by an earlier save are written.
+    pub async fn save(
+        &self,
+        checkpoint: &Checkpoint,
+        traces: &[Trace],
+    ) -> Result<(), CheckpointError> {
+        let state = serde_json::to_string(checkpoint)
+            .map_err(|e| CheckpointError::Serialization(e.to_string()))?;
+        let mut tx = self
+            .pool()
+            .await?
+            .begin()
+            .await
+            .map_err(|e| CheckpointError::Database(e.to_string()))?;
+
+        let stored: i64 = sqlx::query("SELECT COUNT(*) FROM checkpoint_traces WHERE plan_id = ?")
+            .bind(&checkpoint.plan_id)
+            .fetch_one(&mut *tx)
+            .await
+            .map_err(|e| CheckpointError::Database(e.to_string()))?
+            .get(0);
+        for (seq, trace) in traces.iter().enumerate().skip(stored as usize) {
+            let trace = serde_json::to_string(trace)
+                .map_err(|e| CheckpointError::Serialization(e.to_string()))?;
+            sqlx::query("INSERT INTO checkpoint_traces (plan_id, seq, trace) VALUES (?, ?, ?)")
+                .bind(&checkpoint.plan_id)
+                .bind(seq as i64)
+                .bind(trace)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| CheckpointError::Database(e.to_string()))?;
+        }
+
+        sqlx::query(
+            "INSERT INTO checkpoints (plan_id, status, state, updated_at) VALUES (?, ?, ?, ?)
+             ON CONFLICT(plan_id) DO UPDATE SET
+                status = excluded.status, state = excluded.state, updated_at = excluded.updated_at",
+        )
+        .bind(&checkpoint.plan_id)
+        .bind(checkpoint.status.as_str())
+        .bind(state)
+        .bind(&checkpoint.updated_at)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| CheckpointError::Database(e.to_string()))?;
+        tx.commit()
+            .await
+            .map_err(|e| CheckpointError::Database(e.to_string()))
+    }
+
+    pub async fn load(&self, plan_id: &str) -> Result<Option<Checkpoint>, CheckpointError> {
+        let row = sqlx::query("SELECT state FROM checkpoints WHERE plan_id = ?")
+            .bind(plan_id)
+            .fetch_optional(self.pool().await?)
+            .await
+            .map_err(|e| CheckpointError::Database(e.to_string()))?;
+
+        let Some(row) = row else {
+            return Ok(None);
+        };
+        let state: String = row.get("state");
+        let mut checkpoint: Checkpoint = serde_json::from_str(&state)
+            .map_err(|e| CheckpointError::Serialization(e.to_string()))?;
+
+        let rows =
+            sqlx::query("SELECT trace FROM checkpoint_traces WHERE plan_id = ? ORDER BY seq")
+                .bind(plan_id)
+                .fetch_all(self.pool().await?)
+                .await
+                .map_err(|e| CheckpointError::Database(e.to_string()))?;
+        // Snapshots from before traces were stored apart still carry their own
+        if !rows.is_empty() {
+            checkpoint.trace_events = rows
+                .iter()
+                .map(|row| serde_json::from_str(row.get::<&str, _>("trace")))
+                .collect::<Result<_, _>>()
+                .map_err(|e| CheckpointError::Serialization(e.to_string()))?;
+        }
+        Ok(Some(checkpoint))
+    }
+
+    /// Removes the journal and traces of a previous execution with the same
+    /// plan id.
+    pub async fn reset_journal(&self, plan_id: &str) -> Result<(), CheckpointError> {
+        for table in ["tool_journal", "checkpoint_traces"] {
+            sqlx::query(&format!("DELETE FROM {} WHERE plan_id = ?", table))
+                .bind(plan_id)
+                .execute(self.pool().await?)
+                .await
+                .map_err(|e| CheckpointError::Database(e.to_string()))?;
+        }
+        Ok(())
+    }
+
+    pub async fn journal_entry(
+        &self,
+        plan_id: &str,
+        call_key: &str,
+    ) -> Result<Option<JournalEntry>, CheckpointError> {
+        let row =
+            sqlx::query("SELECT tool, result FROM tool_journal WHERE plan_id = ? AND call_key = ?")
+                .bind(plan_id)
+                .bind(call_key)
+                .fetch_optional(self.pool().await?)
+                .await
+                .map_err(|e| CheckpointError::Database(e.to_string()))?;
+
+        row.map(|row| {
+            let result = row
+                .get::<Option<String>, _>("result")
+                .map(|result| serde_json::from_str(&result))
+                .transpose()
+                .map_err(|e| CheckpointError::Serialization(e.to_string()))?;
+            Ok(JournalEntry {
+                tool: row.get("tool"),
+                result,
+            })
+        })
+        .transpose()
+    }
+
+    /// Records that a side-effecting call is about to be made.
+    pub async fn journal_start(
+        &self,
+        plan_id: &str,
+        call_key: &str,
+        tool: &str,
+    ) -> Result<(), CheckpointError> {
+        sqlx::query("INSERT INTO tool_journal (plan_id, call_key, tool) VALUES (?, ?, ?)")
+            .bind(plan_id)
+            .bind(call_key)
+            .bind(tool)
+            .execute(self.pool().await?)
+            .await
+            .map_err(|e| CheckpointError::Database(e.to_string()))?;
+        Ok(())
+    }
+
+    pub async fn journal_finish(
+        &self,
+        plan_id: &str,
+        call_key: &str,
+        result: &Value,
+    ) -> Result<(), CheckpointError> {
+        let result = serde_json::to_string(result)
+            .map_err(|e| CheckpointError::Serialization(e.to_string()))?;
+        sqlx::query("UPDATE tool_journal SET result = ? WHERE plan_id = ? AND call_key = ?")
+            .bind(result)
+            .bind(plan_id)
+            .bind(call_key)
+            .execute(self.pool().await?)
+            .await
+            .map_err(|e| CheckpointError::Database(e.to_string()))?;
+        Ok(())
+    }
+
+    /// Drops the journal entry of a call that failed, so it may be retried.
+    pub async fn journal_abandon(
+        &self,
+        plan_id: &str,
+        call_key: &str,
+    ) -> Result<(), CheckpointError> {
+        sqlx::query("DELETE FROM tool_journal WHERE plan_id = ? AND call_key = ?")
+            .bind(plan_id)
+            .bind(call_key)
+            .execute(self.pool().await?)
+            .await
+            .map_err(|e| CheckpointError::Database(e.to_string()))?;
+        Ok(())
+    }
+}
+
+/// Attaches a [`CheckpointStore`] to an execution. Sub-plans share their
+/// parent's checkpointer under a scope named after the spawning node; only
+/// the top-level plan writes checkpoints.
+#[derive(Debug, Clone)]
+pub struct Checkpointer {
+    pub store: CheckpointStore,
+    pub plan_id: String,
+    scope: String,
+}
+
+impl Checkpointer {
+    pub fn new(store: CheckpointStore, plan_id: impl Into<String>) -> Self {
+        Self {
+            store,
+            plan_id: plan_id.into(),
+            scope: String::new(),
+        }
+    }
+
+    pub(crate) fn scoped(&self, node_id: &str) -> Self {
+        Self {
+            store: self.store.clone(),
+            plan_id: self.plan_id.clone(),
+            scope: format!("{}{}/", self.scope, node_id),
+        }
+    }
+
+    pub(crate) fn is_root(&self) -> bool {
+        self.scope.is_empty()
+    }
+
+    /// Identifies one call by its node and resolved arguments, so a resumed
+    /// node finds the calls it made before the interruption.
+    pub(crate) fn call_key(&self, node_id: &str, args: Option<&Value>) -> String {
+        let args = args.map(|args| args.to_string()).unwrap_or_default();
+        format!("{}{}:{}", self.scope, node_id, args)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum CheckpointError {
+    #[error("Checkpoint database error: {0}")]
+    Database(String),
+    #[error("Checkpoint serialization error: {0}")]
+    Serialization(String),
+}
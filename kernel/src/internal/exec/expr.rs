@@ -0,0 +1,761 @@
+//! Sandboxed expression evaluator used by `assert` and `branch` conditions.
+//!
+//! Expressions are side-effect free: they can only read variables through the
+//! resolver supplied by the caller, and both their length and nesting depth are
+//! bounded. Supported syntax:
+//!
+//! - literals: numbers, `"strings"` / `'strings'`, `true`, `false`, `null`, `[a, b]`
+//! - references: `$var.field[0]`, `$var.items[*].score`
+//! - arithmetic: `+ - * / %` and unary `-`
+//! - comparisons: `== != < <= > >=`
+//! - boolean logic: `&& || !` (or `and`, `or`, `not`)
+//! - functions: `len`, `count`, `sum`, `mean`, `min`, `max`
+
+use serde_json::Value;
+
+const MAX_EXPRESSION_LEN: usize = 4096;
+const MAX_NESTING_DEPTH: usize = 64;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ExprError {
+    #[error("Parse error at position {position}: {message}")]
+    Parse { position: usize, message: String },
+    #[error("Reference to unknown variable: ${0}")]
+    UnknownReference(String),
+    #[error("Unknown function: {0}")]
+    UnknownFunction(String),
+    #[error("Type error in `{expr}`: {message}")]
+    Type { expr: String, message: String },
+}
+
+/// Outcome of evaluating an expression, along with the value of every
+/// reference, call and operator sub-expression in evaluation order.
+#[derive(Debug, Clone)]
+pub struct Evaluation {
+    pub value: Value,
+    pub steps: Vec<(String, Value)>,
+}
+
+impl Evaluation {
+    pub fn is_truthy(&self) -> bool {
+        is_truthy(&self.value)
+    }
+
+    /// Renders the recorded sub-expressions as `expr = value` pairs.
+    pub fn explain(&self) -> String {
+        self.steps
+            .iter()
+            .map(|(expr, value)| format!("{} = {}", expr, value))
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+
+    pub fn steps_json(&self) -> Value {
+        Value::Array(
+            self.steps
+                .iter()
+                .map(|(expr, value)| serde_json::json!({ "expr": expr, "value": value }))
+                .collect(),
+        )
+    }
+}
+
+/// Parses and evaluates `source`, looking up `$references` through `resolve`.
+/// The resolver receives the reference without its leading `$`.
+pub fn evaluate<F>(source: &str, resolve: F) -> Result<Evaluation, ExprError>
+where
+    F: Fn(&str) -> Option<Value>,
+{
+    if source.len() > MAX_EXPRESSION_LEN {
+        return Err(ExprError::Parse {
+            position: MAX_EXPRESSION_LEN,
+            message: format!("expression exceeds {} characters", MAX_EXPRESSION_LEN),
+        });
+    }
+
+    let tokens = tokenize(source)?;
+    let mut parser = Parser {
+        tokens,
+        pos: 0,
+        depth: 0,
+        source_len: source.len(),
+    };
+    let ast = parser.parse_expression()?;
+    if let Some(token) = parser.peek() {
+        return Err(ExprError::Parse {
+            position: token.start,
+            message: "unexpected trailing input".to_string(),
+        });
+    }
+
+    let mut evaluator = Evaluator {
+        source,
+        resolve,
+        steps: Vec::new(),
+    };
+    let value = evaluator.eval(&ast)?;
+    Ok(Evaluation {
+        value,
+        steps: evaluator.steps,
+    })
+}
+
+pub fn is_truthy(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Bool(b) => *b,
+        Value::Number(n) => n.as_f64().map(|v| v != 0.0).unwrap_or(false),
+        Value::String(s) => !s.is_empty() && s != "false",
+        Value::Array(items) => !items.is_empty(),
+        Value::Object(map) => !map.is_empty(),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind {
+    Number(f64),
+    Str(String),
+    Ident(String),
+    Ref(String),
+    Op(&'static str),
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+    Comma,
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: TokenKind,
+    start: usize,
+    end: usize,
+}
+
+const OPERATORS: &[&str] = &[
+    "==", "!=", "<=", ">=", "&&", "||", "<", ">", "!", "+", "-", "*", "/", "%",
+];
+
+fn tokenize(source: &str) -> Result<Vec<Token>, ExprError> {
+    let chars: Vec<(usize, char)> = source.char_indices().collect();
+    let byte_at = |i: usize| chars.get(i).map(|(b, _)| *b).unwrap_or(source.len());
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let (start, c) = chars[i];
+
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+
+        let single = match c {
+            '(' => Some(TokenKind::LParen),
+            ')' => Some(TokenKind::RParen),
+            '[' => Some(TokenKind::LBracket),
+            ']' => Some(TokenKind::RBracket),
+            ',' => Some(TokenKind::Comma),
+            _ => None,
+        };
+        if let Some(kind) = single {
+            tokens.push(Token {
+                kind,
+                start,
+                end: start + 1,
+            });
+            i += 1;
+            continue;
+        }
+
+        if c.is_ascii_digit()
+            || (c == '.' && chars.get(i + 1).is_some_and(|(_, n)| n.is_ascii_digit()))
+        {
+            let mut j = i;
+            while j < chars.len() && (chars[j].1.is_ascii_digit() || chars[j].1 == '.') {
+                j += 1;
+            }
+            if j < chars.len() && (chars[j].1 == 'e' || chars[j].1 == 'E') {
+                let mut k = j + 1;
+                if k < chars.len() && (chars[k].1 == '+' || chars[k].1 == '-') {
+                    k += 1;
+                }
+                if k < chars.len() && chars[k].1.is_ascii_digit() {
+                    j = k;
+                    while j < chars.len() && chars[j].1.is_ascii_digit() {
+                        j += 1;
+                    }
+                }
+            }
+            let end = byte_at(j);
+            let text = &source[start..end];
+            let number = text.parse::<f64>().map_err(|_| ExprError::Parse {
+                position: start,
+                message: format!("invalid number '{}'", text),
+            })?;
+            tokens.push(Token {
+                kind: TokenKind::Number(number),
+                start,
+                end,
+            });
+            i = j;
+            continue;
+        }
+
+        if c == '"' || c == '\'' {
+            let mut value = String::new();
+            let mut j = i + 1;
+            let mut closed = false;
+            while j < chars.len() {
+                let ch = chars[j].1;
+                if ch == '\\' && j + 1 < chars.len() {
+                    value.push(match chars[j + 1].1 {
+                        'n' => '\n',
+                        't' => '\t',
+                        other => other,
+                    });
+                    j += 2;
+                    continue;
+                }
+                if ch == c {
+                    closed = true;
+                    break;
+                }
+                value.push(ch);
+                j += 1;
+            }
+            if !closed {
+                return Err(ExprError::Parse {
+                    position: start,
+                    message: "unterminated string literal".to_string(),
+                });
+            }
+            tokens.push(Token {
+                kind: TokenKind::Str(value),
+                start,
+                end: byte_at(j + 1),
+            });
+            i = j + 1;
+            continue;
+        }
+
+        if c == '$' {
+            let mut j = i + 1;
+            while j < chars.len() {
+                let ch = chars[j].1;
+                if ch.is_alphanumeric() || matches!(ch, '_' | '.' | '[' | ']' | '*') {
+                    j += 1;
+                } else {
+                    break;
+                }
+            }
+            let end = byte_at(j);
+            let reference = &source[start + 1..end];
+            if reference.is_empty() {
+                return Err(ExprError::Parse {
+                    position: start,
+                    message: "empty variable reference".to_string(),
+                });
+            }
+            tokens.push(Token {
+                kind: TokenKind::Ref(reference.to_string()),
+                start,
+                end,
+            });
+            i = j;
+            continue;
+        }
+
+        if c.is_alphabetic() || c == '_' {
+            let mut j = i;
+            while j < chars.len() && (chars[j].1.is_alphanumeric() || chars[j].1 == '_') {
+                j += 1;
+            }
+            let end = byte_at(j);
+            let word = &source[start..end];
+            let kind = match word {
+                "and" => TokenKind::Op("&&"),
+                "or" => TokenKind::Op("||"),
+                "not" => TokenKind::Op("!"),
+                _ => TokenKind::Ident(word.to_string()),
+            };
+            tokens.push(Token { kind, start, end });
+            i = j;
+            continue;
+        }
+
+        let rest = &source[start..];
+        if let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(**op)) {
+            tokens.push(Token {
+                kind: TokenKind::Op(op),
+                start,
+                end: start + op.len(),
+            });
+            i += op.chars().count();
+            continue;
+        }
+
+        return Err(ExprError::Parse {
+            position: start,
+            message: format!("unexpected character '{}'", c),
+        });
+    }
+
+    Ok(tokens)
+}
+
+#[derive(Debug)]
+enum Expr {
+    Literal(Value),
+    Ref(String),
+    List(Vec<Node>),
+    Call(String, Vec<Node>),
+    Unary(&'static str, Box<Node>),
+    Binary(&'static str, Box<Node>, Box<Node>),
+}
+
+#[derive(Debug)]
+struct Node {
+    expr: Expr,
+    start: usize,
+    end: usize,
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    depth: usize,
+    source_len: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn peek_op(&self, ops: &[&'static str]) -> Option<&'static str> {
+        match self.peek().map(|t| &t.kind) {
+            Some(TokenKind::Op(op)) if ops.contains(op) => Some(op),
+            _ => None,
+        }
+    }
+
+    fn expect(&mut self, kind: TokenKind, what: &str) -> Result<Token, ExprError> {
+        match self.next() {
+            Some(token) if token.kind == kind => Ok(token),
+            Some(token) => Err(ExprError::Parse {
+                position: token.start,
+                message: format!("expected {}", what),
+            }),
+            None => Err(ExprError::Parse {
+                position: self.source_len,
+                message: format!("expected {}", what),
+            }),
+        }
+    }
+
+    fn parse_expression(&mut self) -> Result<Node, ExprError> {
+        self.depth += 1;
+        if self.depth > MAX_NESTING_DEPTH {
+            return Err(ExprError::Parse {
+                position: self.peek().map(|t| t.start).unwrap_or(self.source_len),
+                message: format!("expression nested deeper than {}", MAX_NESTING_DEPTH),
+            });
+        }
+        let node = self.parse_binary(0);
+        self.depth -= 1;
+        node
+    }
+
+    /// Precedence climbing over the binary operator levels, loosest first.
+    fn parse_binary(&mut self, level: usize) -> Result<Node, ExprError> {
+        const LEVELS: &[&[&str]] = &[
+            &["||"],
+            &["&&"],
+            &["==", "!=", "<", "<=", ">", ">="],
+            &["+", "-"],
+            &["*", "/", "%"],
+        ];
+
+        if level == LEVELS.len() {
+            return self.parse_unary();
+        }
+
+        let mut lhs = self.parse_binary(level + 1)?;
+        while let Some(op) = self.peek_op(LEVELS[level]) {
+            self.pos += 1;
+            let rhs = self.parse_binary(level + 1)?;
+            lhs = Node {
+                start: lhs.start,
+                end: rhs.end,
+                expr: Expr::Binary(op, Box::new(lhs), Box::new(rhs)),
+            };
+            // Comparisons do not chain: `a < b < c` is rejected.
+            if level == 2 && self.peek_op(LEVELS[level]).is_some() {
+                return Err(ExprError::Parse {
+                    position: self.peek().map(|t| t.start).unwrap_or(self.source_len),
+                    message: "comparisons cannot be chained".to_string(),
+                });
+            }
+        }
+        Ok(lhs)
+    }
+
+    fn parse_unary(&mut self) -> Result<Node, ExprError> {
+        if let Some(op) = self.peek_op(&["!", "-"]) {
+            let start = self.next().map(|t| t.start).unwrap_or(0);
+            self.depth += 1;
+            if self.depth > MAX_NESTING_DEPTH {
+                return Err(ExprError::Parse {
+                    position: start,
+                    message: format!("expression nested deeper than {}", MAX_NESTING_DEPTH),
+                });
+            }
+            let operand = self.parse_unary();
+            self.depth -= 1;
+            let operand = operand?;
+            return Ok(Node {
+                start,
+                end: operand.end,
+                expr: Expr::Unary(op, Box::new(operand)),
+            });
+        }
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<Node, ExprError> {
+        let token = self.next().ok_or(ExprError::Parse {
+            position: self.source_len,
+            message: "unexpected end of expression".to_string(),
+        })?;
+
+        let literal = |value: Value| Node {
+            expr: Expr::Literal(value),
+            start: token.start,
+            end: token.end,
+        };
+
+        match token.kind.clone() {
+            TokenKind::Number(n) => Ok(literal(number_value(n))),
+            TokenKind::Str(s) => Ok(literal(Value::String(s))),
+            TokenKind::Ref(reference) => Ok(Node {
+                expr: Expr::Ref(reference),
+                start: token.start,
+                end: token.end,
+            }),
+            TokenKind::Ident(word) => match word.as_str() {
+                "true" => Ok(literal(Value::Bool(true))),
+                "false" => Ok(literal(Value::Bool(false))),
+                "null" => Ok(literal(Value::Null)),
+                _ => {
+                    self.expect(TokenKind::LParen, "'(' after function name")?;
+                    let (args, end) = self.parse_list(TokenKind::RParen, "')'")?;
+                    Ok(Node {
+                        expr: Expr::Call(word, args),
+                        start: token.start,
+                        end,
+                    })
+                }
+            },
+            TokenKind::LParen => {
+                let inner = self.parse_expression()?;
+                let close = self.expect(TokenKind::RParen, "')'")?;
+                Ok(Node {
+                    expr: inner.expr,
+                    start: token.start,
+                    end: close.end,
+                })
+            }
+            TokenKind::LBracket => {
+                let (items, end) = self.parse_list(TokenKind::RBracket, "']'")?;
+                Ok(Node {
+                    expr: Expr::List(items),
+                    start: token.start,
+                    end,
+                })
+            }
+            _ => Err(ExprError::Parse {
+                position: token.start,
+                message: "expected a value".to_string(),
+            }),
+        }
+    }
+
+    fn parse_list(
+        &mut self,
+        close: TokenKind,
+        what: &str,
+    ) -> Result<(Vec<Node>, usize), ExprError> {
+        let mut items = Vec::new();
+        if let Some(token) = self.peek() {
+            if token.kind == close {
+                let end = token.end;
+                self.pos += 1;
+                return Ok((items, end));
+            }
+        }
+        loop {
+            items.push(self.parse_expression()?);
+            match self.next() {
+                Some(token) if token.kind == TokenKind::Comma => continue,
+                Some(token) if token.kind == close => return Ok((items, token.end)),
+                Some(token) => {
+                    return Err(ExprError::Parse {
+                        position: token.start,
+                        message: format!("expected ',' or {}", what),
+                    })
+                }
+                None => {
+                    return Err(ExprError::Parse {
+                        position: self.source_len,
+                        message: format!("expected {}", what),
+                    })
+                }
+            }
+        }
+    }
+}
+
+struct Evaluator<'a, F> {
+    source: &'a str,
+    resolve: F,
+    steps: Vec<(String, Value)>,
+}
+
+impl<F> Evaluator<'_, F>
+where
+    F: Fn(&str) -> Option<Value>,
+{
+    fn text(&self, node: &Node) -> String {
+        self.source[node.start..node.end].trim().to_string()
+    }
+
+    fn type_error(&self, node: &Node, message: String) -> ExprError {
+        ExprError::Type {
+            expr: self.text(node),
+            message,
+        }
+    }
+
+    fn record(&mut self, node: &Node, value: Value) -> Value {
+        self.steps.push((self.text(node), value.clone()));
+        value
+    }
+
+    fn eval(&mut self, node: &Node) -> Result<Value, ExprError> {
+        match &node.expr {
+            Expr::Literal(value) => Ok(value.clone()),
+            Expr::Ref(reference) => {
+                let value = (self.resolve)(reference)
+                    .ok_or_else(|| ExprError::UnknownReference(reference.clone()))?;
+                Ok(self.record(node, value))
+            }
+            Expr::List(items) => {
+                let values = items
+                    .iter()
+                    .map(|item| self.eval(item))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(Value::Array(values))
+            }
+            Expr::Call(name, args) => {
+                let values = args
+                    .iter()
+                    .map(|arg| self.eval(arg))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let value = self.call(node, name, &values)?;
+                Ok(self.record(node, value))
+            }
+            Expr::Unary(op, operand) => {
+                let value = self.eval(operand)?;
+                let result = match *op {
+                    "!" => Value::Bool(!is_truthy(&value)),
+                    _ => match value.as_f64() {
+                        Some(n) => number_value(-n),
+                        None => {
+                            return Err(self.type_error(node, format!("cannot negate {}", value)))
+                        }
+                    },
+                };
+                Ok(self.record(node, result))
+            }
+            Expr::Binary(op, lhs, rhs) => {
+                let left = self.eval(lhs)?;
+                let result = match *op {
+                    "&&" if !is_truthy(&left) => Value::Bool(false),
+                    "||" if is_truthy(&left) => Value::Bool(true),
+                    "&&" | "||" => Value::Bool(is_truthy(&self.eval(rhs)?)),
+                    _ => {
+                        let right = self.eval(rhs)?;
+                        self.binary(node, op, &left, &right)?
+                    }
+                };
+                Ok(self.record(node, result))
+            }
+        }
+    }
+
+    fn binary(
+        &self,
+        node: &Node,
+        op: &str,
+        left: &Value,
+        right: &Value,
+    ) -> Result<Value, ExprError> {
+        match op {
+            "==" => return Ok(Value::Bool(values_equal(left, right))),
+            "!=" => return Ok(Value::Bool(!values_equal(left, right))),
+            _ => {}
+        }
+
+        if matches!(op, "<" | "<=" | ">" | ">=") {
+            let ordering = match (left, right) {
+                (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+                _ => match (left.as_f64(), right.as_f64()) {
+                    (Some(a), Some(b)) => a.partial_cmp(&b),
+                    _ => None,
+                },
+            };
+            let ordering = ordering.ok_or_else(|| {
+                self.type_error(node, format!("cannot compare {} {} {}", left, op, right))
+            })?;
+            let result = match op {
+                "<" => ordering.is_lt(),
+                "<=" => ordering.is_le(),
+                ">" => ordering.is_gt(),
+                _ => ordering.is_ge(),
+            };
+            return Ok(Value::Bool(result));
+        }
+
+        if op == "+" {
+            if let (Value::String(a), Value::String(b)) = (left, right) {
+                return Ok(Value::String(format!("{}{}", a, b)));
+            }
+        }
+
+        let (a, b) = match (left.as_f64(), right.as_f64()) {
+            (Some(a), Some(b)) => (a, b),
+            _ => {
+                return Err(self.type_error(
+                    node,
+                    format!("cannot apply '{}' to {} and {}", op, left, right),
+                ))
+            }
+        };
+        if (op == "/" || op == "%") && b == 0.0 {
+            return Err(self.type_error(node, "division by zero".to_string()));
+        }
+        let result = match op {
+            "+" => a + b,
+            "-" => a - b,
+            "*" => a * b,
+            "/" => a / b,
+            _ => a % b,
+        };
+        Ok(number_value(result))
+    }
+
+    fn call(&self, node: &Node, name: &str, args: &[Value]) -> Result<Value, ExprError> {
+        match name {
+            "len" => {
+                let [value] = args else {
+                    return Err(
+                        self.type_error(node, "len() takes exactly one argument".to_string())
+                    );
+                };
+                let len = match value {
+                    Value::Array(items) => items.len(),
+                    Value::Object(map) => map.len(),
+                    Value::String(s) => s.chars().count(),
+                    other => {
+                        return Err(self.type_error(node, format!("len() of {}", other)));
+                    }
+                };
+                Ok(Value::from(len))
+            }
+            "count" => match args {
+                [Value::Array(items)] => Ok(Value::from(
+                    items.iter().filter(|item| !item.is_null()).count(),
+                )),
+                [Value::Array(items), needle] => Ok(Value::from(
+                    items
+                        .iter()
+                        .filter(|item| values_equal(item, needle))
+                        .count(),
+                )),
+                _ => Err(self.type_error(
+                    node,
+                    "count() takes an array and an optional value to match".to_string(),
+                )),
+            },
+            "sum" | "mean" | "min" | "max" => {
+                let mut numbers = Vec::new();
+                for arg in args {
+                    self.collect_numbers(node, name, arg, &mut numbers)?;
+                }
+                if numbers.is_empty() {
+                    return if name == "sum" {
+                        Ok(number_value(0.0))
+                    } else {
+                        Err(self.type_error(node, format!("{}() of an empty collection", name)))
+                    };
+                }
+                let result = match name {
+                    "sum" => numbers.iter().sum(),
+                    "mean" => numbers.iter().sum::<f64>() / numbers.len() as f64,
+                    "min" => numbers.iter().copied().fold(f64::INFINITY, f64::min),
+                    _ => numbers.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+                };
+                Ok(number_value(result))
+            }
+            _ => Err(ExprError::UnknownFunction(name.to_string())),
+        }
+    }
+
+    fn collect_numbers(
+        &self,
+        node: &Node,
+        name: &str,
+        value: &Value,
+        out: &mut Vec<f64>,
+    ) -> Result<(), ExprError> {
+        match value {
+            Value::Array(items) => {
+                for item in items {
+                    self.collect_numbers(node, name, item, out)?;
+                }
+                Ok(())
+            }
+            Value::Number(n) => {
+                out.push(n.as_f64().unwrap_or(0.0));
+                Ok(())
+            }
+            other => {
+                Err(self.type_error(node, format!("{}() expects numbers, got {}", name, other)))
+            }
+        }
+    }
+}
+
+fn values_equal(left: &Value, right: &Value) -> bool {
+    match (left.as_f64(), right.as_f64()) {
+        (Some(a), Some(b)) => a == b,
+        _ => left == right,
+    }
+}
+
+fn number_value(n: f64) -> Value {
+    if n.fract() == 0.0 && n.abs() < i64::MAX as f64 {
+        Value::from(n as i64)
+    } else {
+        serde_json::Number::from_f64(n)
+            .map(Value::Number)
+            .unwrap_or(Value::Null)
+    }
+}
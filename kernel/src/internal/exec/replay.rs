@@ -0,0 +1,331 @@
+use crate::internal::{
+    exec::scheduler::ExecutionContext,
+    plan::ir::{ErrorKind, Plan, Signals},
+    tools::spec::ToolSpec,
+    trace::trace::Trace,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+/// Format version written into every [`ReplayBundle`].
+pub const BUNDLE_VERSION: u32 = 1;
+
+/// Trace data fields that are expected to differ between a run and its
+/// replay, such as jittered retry backoffs.
+const VOLATILE_TRACE_FIELDS: &[&str] = &["backoff_ms"];
+
+/// A failed tool interaction as it was observed during the recorded run.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RecordedError {
+    pub kind: ErrorKind,
+    pub message: String,
+}
+
+/// One request/response pair between a node and a tool. Every attempt made
+/// under a retry policy is recorded separately.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ToolExchange {
+    pub step_id: String,
+    pub tool: String,
+    pub attempt: u32,
+    pub request: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<RecordedError>,
+    pub latency_ms: f64,
+    /// WebAssembly fuel the call burned, for metered tools.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fuel: Option<u64>,
+}
+
+impl ToolExchange {
+    pub(crate) fn new(
+        step_id: &str,
+        tool: &str,
+        attempt: u32,
+        request: Option<Value>,
+        outcome: &Result<Value, (ErrorKind, String)>,
+        latency_ms: f64,
+    ) -> Self {
+        let (response, error) = match outcome {
+            Ok(response) => (Some(response.clone()), None),
+            Err((kind, message)) => (
+                None,
+                Some(RecordedError {
+                    kind: *kind,
+                    message: message.clone(),
+                }),
+            ),
+        };
+        Self {
+            step_id: step_id.to_string(),
+            tool: tool.to_string(),
+            attempt,
+            request,
+            response,
+            error,
+            latency_ms,
+            fuel: None,
+        }
+    }
+
+    /// The recorded result, in the shape the scheduler uses for live calls.
+    pub(crate) fn outcome(&self) -> Result<Value, (ErrorKind, String)> {
+        match &self.error {
+            Some(error) => Err((error.kind, error.message.clone())),
+            None => Ok(self.response.clone().unwrap_or(Value::Null)),
+        }
+    }
+}
+
+/// Everything needed to re-run a plan execution offline: the plan and its
+/// inputs, the tools and hydrated specs it could use, every tool exchange
+/// and the trace stream it produced.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayBundle {
+    pub version: u32,
+    pub plan_id: String,
+    pub created_at: String,
+    pub plan: Plan,
+    pub inputs: HashMap<String, Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signals: Option<Signals>,
+    pub tools: Vec<String>,
+    pub tool_specs: HashMap<String, ToolSpec>,
+    #[serde(default)]
+    pub sub_plans: HashMap<String, Plan>,
+    pub tool_io: Vec<ToolExchange>,
+    pub traces: Vec<Trace>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl ReplayBundle {
+    /// Captures a finished (or failed) execution of `plan`. `inputs` are the
+    /// variables the execution started with.
+    pub fn record(
+        plan_id: &str,
+        plan: &Plan,
+        inputs: HashMap<String, Value>,
+        ctx: &ExecutionContext,
+        error: Option<String>,
+    ) -> Self {
+        let mut tools: Vec<String> = ctx.tool_urls.keys().cloned().collect();
+        tools.sort();
+
+        Self {
+            version: BUNDLE_VERSION,
+            plan_id: plan_id.to_string(),
+            created_at: chrono::Utc::now().to_rfc3339(),
+            plan: plan.clone(),
+            inputs,
+            signals: ctx.signals.clone(),
+            tools,
+            tool_specs: ctx.tool_specs.clone(),
+            sub_plans: (*ctx.sub_plans).clone(),
+            tool_io: ctx.tool_io.clone(),
+            traces: ctx.trace_events.clone(),
+            error,
+        }
+    }
+}
+
+/// Serves tool calls from the exchanges of a recorded run. Calls are matched
+/// by step and tool in the order they were made, and must repeat the
+/// recorded request exactly. Sub-plans share their parent's recording under
+/// a scope named after the spawning node.
+#[derive(Debug, Clone)]
+pub struct ReplaySource {
+    recorded: Arc<Mutex<HashMap<String, VecDeque<ToolExchange>>>>,
+    scope: String,
+}
+
+impl ReplaySource {
+    pub fn new(tool_io: &[ToolExchange]) -> Self {
+        let mut recorded: HashMap<String, VecDeque<ToolExchange>> = HashMap::new();
+        for exchange in tool_io {
+            recorded
+                .entry(Self::key(&exchange.step_id, &exchange.tool))
+                .or_default()
+                .push_back(exchange.clone());
+        }
+        Self {
+            recorded: Arc::new(Mutex::new(recorded)),
+            scope: String::new(),
+        }
+    }
+
+    fn key(step_id: &str, tool: &str) -> String {
+        format!("{}|{}", step_id, tool)
+    }
+
+    pub(crate) fn scoped(&self, node_id: &str) -> Self {
+        Self {
+            recorded: self.recorded.clone(),
+            scope: format!("{}{}/", self.scope, node_id),
+        }
+    }
+
+    /// Takes the next recorded exchange of `tool` by `step_id`, or describes
+    /// how the replayed call departs from the recording.
+    pub(crate) fn next(
+        &self,
+        step_id: &str,
+        tool: &str,
+        request: Option<&Value>,
+    ) -> Result<ToolExchange, String> {
+        let step_id = format!("{}{}", self.scope, step_id);
+        let exchange = self
+            .recorded
+            .lock()
+            .expect("replay recording lock poisoned")
+            .get_mut(&Self::key(&step_id, tool))
+            .and_then(|queue| queue.pop_front())
+            .ok_or_else(|| {
+                format!(
+                    "step {} called {} more often than in the recorded run",
+                    step_id, tool
+                )
+            })?;
+
+        let same_request = match (exchange.request.as_ref(), request) {
+            (Some(recorded), Some(request)) => same_value(recorded, request),
+            (recorded, request) => recorded == request,
+        };
+        if !same_request {
+            return Err(format!(
+                "step {} called {} (attempt {}) with {} but the recorded request was {}",
+                step_id,
+                tool,
+                exchange.attempt,
+                request.cloned().unwrap_or(Value::Null),
+                exchange.request.clone().unwrap_or(Value::Null)
+            ));
+        }
+        Ok(exchange)
+    }
+
+    /// Number of recorded exchanges the replay has not consumed.
+    pub fn remaining(&self) -> usize {
+        self.recorded
+            .lock()
+            .expect("replay recording lock poisoned")
+            .values()
+            .map(VecDeque::len)
+            .sum()
+    }
+}
+
+/// The first trace at which a replay departs from the recorded run. A
+/// missing side means that stream ended before the other.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Divergence {
+    pub index: usize,
+    pub expected: Option<Value>,
+    pub actual: Option<Value>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let render = |trace: &Option<Value>| {
+            trace
+                .as_ref()
+                .map(|trace| trace.to_string())
+                .unwrap_or_else(|| "end of trace".to_string())
+        };
+        write!(
+            f,
+            "trace {} differs: expected {}, got {}",
+            self.index,
+            render(&self.expected),
+            render(&self.actual)
+        )
+    }
+}
+
+/// Reduces a trace to the fields a faithful replay must reproduce. Ids,
+/// timestamps, signatures and volatile data fields are dropped.
+pub fn normalize_trace(trace: &Trace) -> Value {
+    let mut data = trace.data.clone();
+    if let Some(Value::Object(fields)) = data.as_mut() {
+        for field in VOLATILE_TRACE_FIELDS {
+            fields.remove(*field);
+        }
+    }
+    serde_json::json!({
+        "event_type": trace.event_type,
+        "step_id": trace.step_id,
+        "cost_usd": trace.cost_usd,
+        "tokens_in": trace.tokens_in,
+        "tokens_out": trace.tokens_out,
+        "citations": trace.citations,
+        "data": data,
+    })
+}
+
+/// Compares two trace streams and returns the first point where they differ.
+pub fn first_divergence(recorded: &[Trace], replayed: &[Trace]) -> Option<Divergence> {
+    let len = recorded.len().max(replayed.len());
+    (0..len).find_map(|index| {
+        let expected = recorded.get(index).map(normalize_trace);
+        let actual = replayed.get(index).map(normalize_trace);
+        let same = match (&expected, &actual) {
+            (Some(expected), Some(actual)) => same_value(expected, actual),
+            _ => false,
+        };
+        (!same).then_some(Divergence {
+            index,
+            expected,
+            actual,
+        })
+    })
+}
+
+/// Structural equality that tolerates the last-digit drift of floats parsed
+/// back from a serialized bundle.
+fn same_value(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(x), Value::Number(y)) if x.is_f64() || y.is_f64() => {
+            match (x.as_f64(), y.as_f64()) {
+                (Some(x), Some(y)) => (x - y).abs() <= 1e-9 * x.abs().max(y.abs()).max(1.0),
+                _ => false,
+            }
+        }
+        (Value::Array(xs), Value::Array(ys)) => {
+            xs.len() == ys.len() && xs.iter().zip(ys).all(|(x, y)| same_value(x, y))
+        }
+        (Value::Object(xs), Value::Object(ys)) => {
+            xs.len() == ys.len()
+                && xs
+                    .iter()
+                    .all(|(key, x)| ys.get(key).is_some_and(|y| same_value(x, y)))
+        }
+        _ => a == b,
+    }
+}
+
+/// Outcome of replaying a [`ReplayBundle`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayReport {
+    pub plan_id: String,
+    /// Error of the recorded run, if it failed.
+    pub recorded_error: Option<String>,
+    /// Error of the replay, if it failed.
+    pub error: Option<String>,
+    pub divergence: Option<Divergence>,
+    /// Recorded tool exchanges the replay never asked for.
+    pub unused_exchanges: usize,
+    pub traces: Vec<Trace>,
+}
+
+impl ReplayReport {
+    /// Whether the replay reproduced the recorded run: the same traces, the
+    /// same outcome and every recorded tool exchange consumed.
+    pub fn is_faithful(&self) -> bool {
+        self.divergence.is_none() && self.error == self.recorded_error && self.unused_exchanges == 0
+    }
+}
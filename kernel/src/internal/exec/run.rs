@@ -0,0 +1,255 @@
+use crate::internal::{
+    evidence::verify::VerificationResult,
+    exec::scheduler::{ExecutionContext, StopReason},
+    plan::ir::Plan,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use tokio_util::sync::CancellationToken;
+
+/// Lifecycle of a run started through the API.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RunState {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+    Cancelled,
+    Stopped,
+}
+
+impl RunState {
+    pub fn is_finished(&self) -> bool {
+        !matches!(self, RunState::Queued | RunState::Running)
+    }
+}
+
+/// How a run ended.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RunOutcome {
+    pub plan_id: String,
+    pub run_id: String,
+    pub status: RunState,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop_reason: Option<StopReason>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// What the run produced. Only runs that succeeded or were stopped by
+    /// their stop conditions have one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<RunResult>,
+}
+
+/// The declared outputs of a finished plan, with what it spent and how well
+/// the evidence it checked supported its claims.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RunResult {
+    pub outputs: BTreeMap<String, Value>,
+    pub budget: BudgetTotals,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub evidence: Option<EvidenceTotals>,
+}
+
+/// Totals of the plan's `budget_summary`, next to the caps from its signals.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BudgetTotals {
+    pub total_latency_ms: f64,
+    pub latency_budget_ms: Option<u64>,
+    pub total_cost_usd: f64,
+    pub cost_cap_usd: Option<f64>,
+    pub total_tokens: u64,
+    pub total_fuel: u64,
+    pub fuel_cap: Option<u64>,
+}
+
+/// The `evidence_summary` traces of a run added up. `mean_confidence` is
+/// weighted by the number of claims of each summary.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct EvidenceTotals {
+    pub steps: Vec<String>,
+    pub total_claims: usize,
+    pub supported_claims: usize,
+    pub contradicted_claims: usize,
+    pub mean_confidence: f64,
+}
+
+impl RunResult {
+    pub fn from_context(ctx: &ExecutionContext, plan: &Plan) -> Self {
+        let signals = plan.signals.as_ref();
+        Self {
+            outputs: ctx.plan_outputs(plan),
+            budget: BudgetTotals {
+                total_latency_ms: ctx.total_latency_ms,
+                latency_budget_ms: signals.and_then(|s| s.latency_budget_ms),
+                total_cost_usd: ctx.total_cost_usd,
+                cost_cap_usd: signals.and_then(|s| s.cost_cap_usd),
+                total_tokens: ctx.total_tokens,
+                total_fuel: ctx.total_fuel,
+                fuel_cap: signals.and_then(|s| s.fuel_cap),
+            },
+            evidence: EvidenceTotals::from_context(ctx),
+        }
+    }
+}
+
+impl EvidenceTotals {
+    /// `None` when the run recorded no evidence summary.
+    fn from_context(ctx: &ExecutionContext) -> Option<Self> {
+        let summaries: Vec<(&str, VerificationResult)> = ctx
+            .trace_events
+            .iter()
+            .filter(|trace| trace.event_type == "evidence_summary")
+            .filter_map(|trace| {
+                let summary = serde_json::from_value(trace.data.clone()?).ok()?;
+                Some((trace.step_id.as_str(), summary))
+            })
+            .collect();
+        if summaries.is_empty() {
+            return None;
+        }
+
+        let mut totals = EvidenceTotals {
+            steps: vec![],
+            total_claims: 0,
+            supported_claims: 0,
+            contradicted_claims: 0,
+            mean_confidence: 0.0,
+        };
+        let mut confidence_sum = 0.0;
+        for (step_id, summary) in summaries {
+            totals.steps.push(step_id.to_string());
+            totals.total_claims += summary.total_claims;
+            totals.supported_claims += summary.supported_claims;
+            totals.contradicted_claims += summary.contradicted_claims;
+            confidence_sum += summary.mean_confidence * summary.total_claims as f64;
+        }
+        if totals.total_claims > 0 {
+            totals.mean_confidence = confidence_sum / totals.total_claims as f64;
+        }
+        Some(totals)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RunProgress {
+    pub completed_nodes: usize,
+    pub skipped_nodes: usize,
+    pub total_nodes: usize,
+}
+
+/// Snapshot of a run, as reported by `GET /v1/runs/{run_id}`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunStatus {
+    pub run_id: String,
+    pub plan_id: String,
+    pub state: RunState,
+    pub progress: RunProgress,
+    pub current_nodes: Vec<String>, // nodes of the plan running right now
+    pub created_at: DateTime<Utc>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop_reason: Option<StopReason>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<RunResult>,
+}
+
+impl RunStatus {
+    /// How the run ended, once it has finished.
+    pub fn outcome(&self) -> Option<RunOutcome> {
+        self.state.is_finished().then(|| RunOutcome {
+            plan_id: self.plan_id.clone(),
+            run_id: self.run_id.clone(),
+            status: self.state,
+            stop_reason: self.stop_reason.clone(),
+            error: self.error.clone(),
+            result: self.result.clone(),
+        })
+    }
+}
+
+/// Tracks a run in the background: the scheduler reports its progress here
+/// and callers read its status or cancel it. Clones share the same run.
+#[derive(Debug, Clone)]
+pub struct RunHandle {
+    status: Arc<Mutex<RunStatus>>,
+    cancel: CancellationToken,
+}
+
+impl RunHandle {
+    /// A queued run of `plan_id`, cancelled through `cancel`.
+    pub fn new(
+        plan_id: impl Into<String>,
+        run_id: impl Into<String>,
+        total_nodes: usize,
+        cancel: CancellationToken,
+    ) -> Self {
+        let status = RunStatus {
+            run_id: run_id.into(),
+            plan_id: plan_id.into(),
+            state: RunState::Queued,
+            progress: RunProgress {
+                total_nodes,
+                ..RunProgress::default()
+            },
+            current_nodes: vec![],
+            created_at: Utc::now(),
+            started_at: None,
+            finished_at: None,
+            stop_reason: None,
+            error: None,
+            result: None,
+        };
+        Self {
+            status: Arc::new(Mutex::new(status)),
+            cancel,
+        }
+    }
+
+    pub fn status(&self) -> RunStatus {
+        self.status.lock().unwrap().clone()
+    }
+
+    /// Moves a queued run to running. Does nothing once it has finished.
+    pub fn start(&self) {
+        let mut status = self.status.lock().unwrap();
+        if status.state == RunState::Queued {
+            status.state = RunState::Running;
+            status.started_at = Some(Utc::now());
+        }
+    }
+
+    pub fn report_progress(&self, completed: usize, skipped: usize, current_nodes: Vec<String>) {
+        let mut status = self.status.lock().unwrap();
+        status.progress.completed_nodes = completed;
+        status.progress.skipped_nodes = skipped;
+        status.current_nodes = current_nodes;
+    }
+
+    pub fn finish(&self, outcome: &RunOutcome) {
+        let mut status = self.status.lock().unwrap();
+        status.state = outcome.status;
+        status.finished_at = Some(Utc::now());
+        status.current_nodes.clear();
+        status.stop_reason = outcome.stop_reason.clone();
+        status.error = outcome.error.clone();
+        status.result = outcome.result.clone();
+    }
+
+    /// Asks the run to stop. In-flight nodes are abandoned at their next
+    /// await point and the scheduler records a `plan_cancelled` trace.
+    /// Returns false when the run has already finished.
+    pub fn cancel(&self) -> bool {
+        if self.status.lock().unwrap().state.is_finished() {
+            return false;
+        }
+        self.cancel.cancel();
+        true
+    }
+}
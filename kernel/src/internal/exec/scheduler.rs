@@ -1,11 +1,37 @@
 use crate::internal::{
-    plan::ir::{Node, Plan},
-    tools::spec::{ToolClient, ToolSpec},
+    exec::checkpoint::{Checkpoint, CheckpointStatus, CheckpointStore, Checkpointer},
+    exec::expr::{self, is_truthy, Evaluation, ExprError},
+    exec::replay::{first_divergence, ReplayBundle, ReplayReport, ReplaySource, ToolExchange},
+    exec::run::RunHandle,
+    mem::store::MemoryError,
+    plan::ir::{ErrorKind, Node, Operation, Plan, RetryPolicy, DEFAULT_TIMEOUT_MS},
+    tools::auth::ToolAuth,
+    tools::spec::{ToolClient, ToolError, ToolSpec},
+    tools::transport::InprocTransport,
+    trace::live::LiveTrace,
+    trace::trace::{new_span_id, Trace, TraceChain, TraceSigner},
 };
+use futures::{
+    future,
+    stream::{self, Stream, StreamExt},
+};
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::cmp::Ordering;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
 use tokio::time::{timeout, Duration};
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+/// Default number of ready nodes the scheduler will run at the same time.
+pub const DEFAULT_MAX_PARALLELISM: usize = 8;
+
+/// Maximum nesting depth for `spawn` sub-plans.
+pub const MAX_SPAWN_DEPTH: usize = 8;
 
 #[derive(Debug)]
 pub struct ExecutionContext {
@@ -13,32 +39,242 @@ pub struct ExecutionContext {
     pub tool_client: ToolClient,
     pub tool_specs: HashMap<String, ToolSpec>,
     pub tool_urls: HashMap<String, String>, // tool name to url mapping
+    pub discovered_urls: HashSet<String>,   // servers discover_tools has already listed
     pub capability_index: HashMap<String, Vec<String>>,
     pub signals: Option<crate::internal::plan::ir::Signals>,
+    pub plan_id: String, // stamped on every trace; defaults to the run id
+    pub run_id: String,  // this execution attempt, fresh for every context
+    pub span_id: String, // span of the plan, or of the node a fork executes
+    pub parent_span_id: Option<String>,
     pub trace_events: Vec<crate::internal::trace::trace::Trace>,
     pub completed_nodes: HashSet<String>,
     pub running_nodes: HashSet<String>,
+    pub skipped_nodes: HashSet<String>,
+    pub branch_not_taken: HashMap<String, HashSet<String>>, // branch id to deactivated targets
     pub total_latency_ms: f64,
     pub total_cost_usd: f64,
     pub total_tokens: u64,
+    pub total_fuel: u64, // WebAssembly fuel burned by metered tools
+    pub max_parallelism: usize,
+    pub sub_plans: Arc<HashMap<String, Plan>>, // named plans that spawn nodes can reference
+    pub spawn_depth: usize,
+    pub stop_reason: Option<StopReason>, // set when stop_conditions ended the plan early
+    pub checkpoints: Option<Checkpointer>,
+    pub tool_io: Vec<ToolExchange>, // every tool request/response, in trace order
+    pub replay: Option<ReplaySource>, // serves tool calls from a recorded run when set
+    pub signer: Option<Arc<TraceSigner>>, // signs and chains every pushed trace when set
+    pub(crate) trace_chain: TraceChain,
+    pub live: Option<LiveTrace>, // publishes every pushed trace to stream subscribers
+    pub run: Option<RunHandle>,  // receives the progress of a tracked run
+    pub cancel: CancellationToken, // stops the execution, abandoning in-flight nodes
 }
 
 impl ExecutionContext {
     pub fn new() -> Self {
+        let run_id = Uuid::new_v4().to_string();
         Self {
+            plan_id: run_id.clone(),
+            run_id,
+            span_id: new_span_id(),
+            parent_span_id: None,
             variables: HashMap::new(),
             tool_client: ToolClient::new(),
             tool_specs: HashMap::new(),
             tool_urls: HashMap::new(),
+            discovered_urls: HashSet::new(),
             capability_index: HashMap::new(),
             signals: None,
             trace_events: vec![],
             completed_nodes: HashSet::new(),
             running_nodes: HashSet::new(),
+            skipped_nodes: HashSet::new(),
+            branch_not_taken: HashMap::new(),
             total_latency_ms: 0.0,
             total_cost_usd: 0.0,
             total_tokens: 0,
+            total_fuel: 0,
+            max_parallelism: DEFAULT_MAX_PARALLELISM,
+            sub_plans: Arc::new(HashMap::new()),
+            spawn_depth: 0,
+            stop_reason: None,
+            checkpoints: None,
+            tool_io: vec![],
+            replay: None,
+            signer: None,
+            trace_chain: TraceChain::new(),
+            live: None,
+            run: None,
+            cancel: CancellationToken::new(),
+        }
+    }
+
+    /// Checkpoints this execution to `store` under `plan_id` after every
+    /// node, so it can be continued with [`Scheduler::resume`].
+    pub fn enable_checkpoints(&mut self, store: CheckpointStore, plan_id: impl Into<String>) {
+        self.checkpoints = Some(Checkpointer::new(store, plan_id));
+    }
+
+    /// Signs every trace pushed from now on with `signer`, chaining it to the
+    /// traces already recorded.
+    pub fn enable_signing(&mut self, signer: Arc<TraceSigner>) {
+        self.trace_chain = TraceChain::following(self.trace_events.last());
+        self.signer = Some(signer);
+    }
+
+    /// Appends `trace` to the execution's trace stream, stamped with the
+    /// execution's plan and run ids and its current span. With signing enabled
+    /// the trace is linked to its predecessor and signed first, so it must
+    /// not be modified afterwards.
+    pub fn push_trace(&mut self, mut trace: Trace) {
+        trace.plan_id = self.plan_id.clone();
+        trace.run_id.get_or_insert_with(|| self.run_id.clone());
+        // Traces merged from forks and sub-plans keep the span they were emitted in
+        if trace.span_id.is_none() {
+            trace.span_id = Some(self.span_id.clone());
+            trace.parent_span_id = self.parent_span_id.clone();
+        }
+        if let Some(signer) = &self.signer {
+            if let Err(e) = signer.sign_linked(&mut self.trace_chain, &mut trace) {
+                tracing::warn!("Failed to sign {} trace: {}", trace.event_type, e);
+            }
+        }
+        if let Some(live) = &self.live {
+            live.publish(&trace);
+        }
+        self.trace_events.push(trace);
+    }
+
+    /// Creates a copy of this context for running a single node alongside its
+    /// siblings. The fork sees the current variables and budget totals but
+    /// starts with empty trace and tool I/O buffers, so its effects can be
+    /// merged back deterministically, in ready order.
+    fn fork(&self) -> Self {
+        Self {
+            plan_id: self.plan_id.clone(),
+            run_id: self.run_id.clone(),
+            span_id: self.span_id.clone(),
+            parent_span_id: self.parent_span_id.clone(),
+            variables: self.variables.clone(),
+            tool_client: self.tool_client.clone(),
+            tool_specs: self.tool_specs.clone(),
+            tool_urls: self.tool_urls.clone(),
+            discovered_urls: self.discovered_urls.clone(),
+            capability_index: self.capability_index.clone(),
+            signals: self.signals.clone(),
+            trace_events: vec![],
+            completed_nodes: self.completed_nodes.clone(),
+            running_nodes: HashSet::new(),
+            skipped_nodes: self.skipped_nodes.clone(),
+            branch_not_taken: self.branch_not_taken.clone(),
+            total_latency_ms: self.total_latency_ms,
+            total_cost_usd: self.total_cost_usd,
+            total_tokens: self.total_tokens,
+            total_fuel: self.total_fuel,
+            max_parallelism: self.max_parallelism,
+            sub_plans: self.sub_plans.clone(),
+            spawn_depth: self.spawn_depth,
+            stop_reason: None,
+            checkpoints: self.checkpoints.clone(),
+            tool_io: vec![],
+            replay: self.replay.clone(),
+            // Fork traces are signed and published when they are merged back, in ready order
+            signer: None,
+            trace_chain: TraceChain::new(),
+            live: None,
+            run: None,
+            cancel: self.cancel.clone(),
+        }
+    }
+
+    /// Creates an isolated context for a sub-plan spawned by `node`. The child
+    /// shares tool configuration with this context but only sees the variables
+    /// listed in the node's `bind` map, and starts with its own budget totals.
+    fn child_context(&self, node: &Node) -> Result<Self, ExecutionError> {
+        let mut child = Self::new();
+        child.plan_id = self.plan_id.clone();
+        child.run_id = self.run_id.clone();
+        child.parent_span_id = Some(self.span_id.clone());
+        child.tool_client = self.tool_client.clone();
+        child.tool_specs = self.tool_specs.clone();
+        child.tool_urls = self.tool_urls.clone();
+        child.discovered_urls = self.discovered_urls.clone();
+        child.capability_index = self.capability_index.clone();
+        child.max_parallelism = self.max_parallelism;
+        child.sub_plans = self.sub_plans.clone();
+        child.spawn_depth = self.spawn_depth + 1;
+        child.checkpoints = self.checkpoints.as_ref().map(|c| c.scoped(&node.id));
+        child.replay = self.replay.as_ref().map(|r| r.scoped(&node.id));
+        child.cancel = self.cancel.clone();
+
+        for (name, reference) in node.bind.iter().flatten() {
+            let reference = reference.strip_prefix('$').unwrap_or(reference);
+            let value = self.resolve_reference(reference).ok_or_else(|| {
+                ExecutionError::ValidationError(format!(
+                    "Spawn {} binds '{}' to unknown variable: ${}",
+                    node.id, name, reference
+                ))
+            })?;
+            child.variables.insert(name.clone(), value);
+        }
+
+        Ok(child)
+    }
+
+    /// Computes the budget for a sub-plan: the tightest of the parent's
+    /// remaining budget, the slice requested by the spawn node and the
+    /// sub-plan's own signals.
+    fn budget_slice(
+        &self,
+        requested: Option<&Value>,
+        child_signals: Option<&crate::internal::plan::ir::Signals>,
+    ) -> crate::internal::plan::ir::Signals {
+        fn tightest(limits: [Option<f64>; 3]) -> Option<f64> {
+            limits.into_iter().flatten().reduce(f64::min)
         }
+
+        let parent = self.signals.as_ref();
+        let latency_ms = tightest([
+            parent
+                .and_then(|s| s.latency_budget_ms)
+                .map(|budget| (budget as f64 - self.total_latency_ms).max(0.0)),
+            requested
+                .and_then(|budget| budget.get("latency_ms"))
+                .and_then(Value::as_f64),
+            child_signals
+                .and_then(|s| s.latency_budget_ms)
+                .map(|budget| budget as f64),
+        ]);
+        let cost_usd = tightest([
+            parent
+                .and_then(|s| s.cost_cap_usd)
+                .map(|cap| (cap - self.total_cost_usd).max(0.0)),
+            requested
+                .and_then(|budget| budget.get("cost_usd"))
+                .and_then(Value::as_f64),
+            child_signals.and_then(|s| s.cost_cap_usd),
+        ]);
+        let fuel = tightest([
+            parent
+                .and_then(|s| s.fuel_cap)
+                .map(|cap| cap.saturating_sub(self.total_fuel) as f64),
+            requested
+                .and_then(|budget| budget.get("fuel"))
+                .and_then(Value::as_f64),
+            child_signals.and_then(|s| s.fuel_cap).map(|cap| cap as f64),
+        ]);
+
+        crate::internal::plan::ir::Signals {
+            latency_budget_ms: latency_ms.map(|ms| ms as u64),
+            cost_cap_usd: cost_usd,
+            fuel_cap: fuel.map(|fuel| fuel as u64),
+            risk: child_signals
+                .and_then(|s| s.risk)
+                .or_else(|| parent.and_then(|s| s.risk)),
+        }
+    }
+
+    pub fn register_sub_plan(&mut self, name: impl Into<String>, plan: Plan) {
+        Arc::make_mut(&mut self.sub_plans).insert(name.into(), plan);
     }
 
     pub fn has_budget_remaining(&self) -> bool {
@@ -79,86 +315,395 @@ impl ExecutionContext {
     }
 
     fn resolve_reference(&self, reference: &str) -> Option<Value> {
-        if reference.is_empty() {
+        let split = reference.find(['.', '[']).unwrap_or(reference.len());
+        let root = &reference[..split];
+        if root.is_empty() {
             return None;
         }
 
-        let mut chars = reference.chars().peekable();
-        let mut root = String::new();
+        lookup_path(self.variables.get(root)?, &reference[split..])
+    }
 
-        while let Some(&c) = chars.peek() {
-            if c == '.' || c == '[' {
-                break;
+    /// Invokes a tool, checking the arguments against the spec's `io.input`
+    /// schema before the call and the result against `io.output` after it.
+    /// Also returns the fuel the call burned, even when it failed, when its
+    /// transport meters compute; it may burn no more than the plan's
+    /// `fuel_cap` has left.
+    async fn invoke_tool_checked(
+        &self,
+        tool_url: &str,
+        tool_name: &str,
+        spec: Option<&ToolSpec>,
+        args: Option<Value>,
+    ) -> (Result<Value, ToolError>, Option<u64>) {
+        if let Some(spec) = spec {
+            if let Err(e) = spec.validate_input(args.as_ref()) {
+                return (Err(e), None);
             }
-            root.push(c);
-            chars.next();
         }
+        let fuel_limit = self
+            .signals
+            .as_ref()
+            .and_then(|s| s.fuel_cap)
+            .map(|cap| cap.saturating_sub(self.total_fuel));
+        let (result, fuel) = self
+            .tool_client
+            .invoke_tool_metered(tool_url, tool_name, args, fuel_limit)
+            .await;
+        let result = result.and_then(|result| match spec {
+            Some(spec) => spec.validate_output(&result).map(|_| result),
+            None => Ok(result),
+        });
+        (result, fuel)
+    }
 
-        if root.is_empty() {
-            return None;
+    /// Invokes a node's tool under its `timeout_ms` and retry policy. Every
+    /// attempt is charged against the budget and recorded as a `tool_attempt`
+    /// trace; the usage of the successful attempt is returned with the result.
+    /// Each attempt is also kept in `tool_io`. When replaying, attempts are
+    /// served from the recording instead of the tool.
+    async fn invoke_with_policy(
+        &mut self,
+        node: &Node,
+        resolution: &ToolResolution,
+        args: Option<Value>,
+    ) -> Result<(Value, UsageRecord), ExecutionError> {
+        let policy = match &node.retry {
+            Some(policy) => policy.clone(),
+            None if node.op == Operation::Retry => RetryPolicy::default(),
+            None => RetryPolicy::single_attempt(),
+        };
+        let timeout_ms = node.timeout_ms.unwrap_or(DEFAULT_TIMEOUT_MS);
+
+        // Calls with side effects are journaled when checkpointing, so a
+        // resumed plan never makes them twice. Replays make no real calls.
+        let side_effects = resolution
+            .spec
+            .as_ref()
+            .and_then(|spec| spec.constraints.as_ref())
+            .and_then(|constraints| constraints.side_effects)
+            .unwrap_or(false);
+        let journal = match self.checkpoints.clone() {
+            Some(checkpoints) if side_effects && self.replay.is_none() => {
+                let key = checkpoints.call_key(&node.id, args.as_ref());
+                Some((checkpoints, key))
+            }
+            _ => None,
+        };
+        if let Some((checkpoints, key)) = &journal {
+            let entry = checkpoints
+                .store
+                .journal_entry(&checkpoints.plan_id, key)
+                .await
+                .map_err(|e| ExecutionError::CheckpointError(e.to_string()))?;
+            match entry {
+                Some(entry) => {
+                    let Some(result) = entry.result else {
+                        return Err(ExecutionError::CheckpointError(format!(
+                            "Tool {} has side effects and its call from node {} was interrupted \
+                             or timed out before completing; refusing to invoke it again",
+                            entry.tool, node.id
+                        )));
+                    };
+                    let usage = self.record_tool_usage(
+                        &resolution.tool_name,
+                        resolution.spec.as_ref(),
+                        0.0,
+                        None,
+                        None,
+                    )?;
+                    let mut trace = crate::internal::trace::trace::Trace::new(
+                        "tool_replayed".to_string(),
+                        node.id.clone(),
+                        format!(
+                            "Replayed journaled result of {} (side effects)",
+                            resolution.tool_name
+                        ),
+                    );
+                    trace.data = Some(serde_json::json!({ "tool": resolution.tool_name }));
+                    self.push_trace(trace);
+                    return Ok((result, usage));
+                }
+                None => checkpoints
+                    .store
+                    .journal_start(&checkpoints.plan_id, key, &resolution.tool_name)
+                    .await
+                    .map_err(|e| ExecutionError::CheckpointError(e.to_string()))?,
+            }
         }
 
-        let mut current = self.variables.get(&root)?.clone();
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let (outcome, elapsed_ms, fuel) = match &self.replay {
+                Some(replay) => {
+                    let exchange = replay
+                        .next(&node.id, &resolution.tool_name, args.as_ref())
+                        .map_err(ExecutionError::ReplayDivergence)?;
+                    (exchange.outcome(), exchange.latency_ms, exchange.fuel)
+                }
+                None => {
+                    let start = std::time::Instant::now();
+                    let (outcome, fuel) = match timeout(
+                        Duration::from_millis(timeout_ms),
+                        self.invoke_tool_checked(
+                            &resolution.tool_url,
+                            &resolution.tool_name,
+                            resolution.spec.as_ref(),
+                            args.clone(),
+                        ),
+                    )
+                    .await
+                    {
+                        Ok((Ok(result), fuel)) => (Ok(result), fuel),
+                        Ok((Err(e), fuel)) => (Err((tool_error_kind(&e), e.to_string())), fuel),
+                        Err(_) => (
+                            Err((
+                                ErrorKind::Timeout,
+                                format!(
+                                    "Tool call {} timed out after {} ms",
+                                    resolution.tool_name, timeout_ms
+                                ),
+                            )),
+                            None,
+                        ),
+                    };
+                    (outcome, start.elapsed().as_secs_f64() * 1000.0, fuel)
+                }
+            };
+            let mut exchange = ToolExchange::new(
+                &node.id,
+                &resolution.tool_name,
+                attempt,
+                args.clone(),
+                &outcome,
+                elapsed_ms,
+            );
+            exchange.fuel = fuel;
+            self.tool_io.push(exchange);
+            let usage = self.record_tool_usage(
+                &resolution.tool_name,
+                resolution.spec.as_ref(),
+                elapsed_ms,
+                None,
+                fuel,
+            );
 
-        while let Some(&c) = chars.peek() {
-            match c {
-                '.' => {
-                    chars.next();
-                    let mut key = String::new();
-                    while let Some(&next) = chars.peek() {
-                        if next == '.' || next == '[' {
-                            break;
-                        }
-                        key.push(next);
-                        chars.next();
-                    }
+            // A metered call that failed having burned the rest of the fuel
+            // cap cannot do better on another attempt
+            let fuel_exhausted = outcome.is_err()
+                && fuel.is_some()
+                && self
+                    .signals
+                    .as_ref()
+                    .and_then(|s| s.fuel_cap)
+                    .is_some_and(|cap| self.total_fuel >= cap);
+            // A side-effecting call that timed out may still have happened,
+            // so it is never made again
+            let unsettled = journal.is_some() && matches!(outcome, Err((ErrorKind::Timeout, _)));
+            let backoff_ms = match &outcome {
+                Err((kind, _))
+                    if policy.is_retryable(*kind)
+                        && attempt < policy.max_attempts
+                        && !fuel_exhausted
+                        && !unsettled =>
+                {
+                    Some(policy.backoff_ms(attempt, rand::random::<f64>()))
+                }
+                _ => None,
+            };
 
-                    if key.is_empty() {
-                        return None;
+            let mut trace = crate::internal::trace::trace::Trace::new(
+                "tool_attempt".to_string(),
+                node.id.clone(),
+                format!(
+                    "Attempt {}/{} of {}",
+                    attempt, policy.max_attempts, resolution.tool_name
+                ),
+            );
+            trace.cost_usd = usage.as_ref().ok().map(|u| u.cost_usd);
+            trace.data = Some(serde_json::json!({
+                "tool": resolution.tool_name,
+                "attempt": attempt,
+                "max_attempts": policy.max_attempts,
+                "timeout_ms": timeout_ms,
+                "latency_ms": elapsed_ms,
+                "fuel": fuel,
+                "outcome": if outcome.is_ok() { "ok" } else { "error" },
+                "error_kind": outcome.as_ref().err().map(|(kind, _)| kind.to_string()),
+                "error": outcome.as_ref().err().map(|(_, message)| message),
+                "backoff_ms": backoff_ms,
+            }));
+            self.push_trace(trace);
+
+            let usage = match usage {
+                Ok(usage) => usage,
+                Err(e) => {
+                    if let Some((checkpoints, key)) = &journal {
+                        let outcome = outcome.as_ref().map_err(|(kind, _)| *kind);
+                        Self::finish_journal(checkpoints, key, outcome).await?;
                     }
-
-                    match current {
-                        Value::Object(ref map) => {
-                            if let Some(value) = map.get(&key) {
-                                current = value.clone();
-                            } else {
-                                return None;
-                            }
-                        }
-                        _ => return None,
+                    return Err(e);
+                }
+            };
+            match outcome {
+                Ok(result) => {
+                    if let Some((checkpoints, key)) = &journal {
+                        Self::finish_journal(checkpoints, key, Ok(&result)).await?;
                     }
+                    return Ok((result, usage));
                 }
-                '[' => {
-                    chars.next();
-                    let mut index_str = String::new();
-                    while let Some(&next) = chars.peek() {
-                        if next == ']' {
-                            break;
+                Err((kind, message)) => {
+                    if let Some(delay) = backoff_ms {
+                        tracing::warn!(
+                            "Attempt {} failed for tool {}, retrying in {} ms: {}",
+                            attempt,
+                            resolution.tool_name,
+                            delay,
+                            message
+                        );
+                        if self.replay.is_none() {
+                            tokio::time::sleep(Duration::from_millis(delay)).await;
                         }
-                        index_str.push(next);
-                        chars.next();
+                        continue;
                     }
 
-                    if chars.peek() == Some(&']') {
-                        chars.next();
-                    } else {
-                        return None;
+                    if let Some((checkpoints, key)) = &journal {
+                        Self::finish_journal(checkpoints, key, Err(kind)).await?;
                     }
-
-                    let index: usize = index_str.parse().ok()?;
-
-                    match current {
-                        Value::Array(ref arr) => {
-                            current = arr.get(index)?.clone();
-                        }
-                        _ => return None,
+                    if fuel_exhausted {
+                        return Err(ExecutionError::BudgetExceeded(format!(
+                            "Fuel budget exceeded: {} burned the remaining fuel of {} ({})",
+                            resolution.tool_name, self.total_fuel, message
+                        )));
                     }
+                    let message = if attempt > 1 {
+                        format!("{} (after {} attempts)", message, attempt)
+                    } else {
+                        message
+                    };
+                    return Err(match kind {
+                        ErrorKind::Timeout => ExecutionError::TimeoutError(message),
+                        _ => ExecutionError::ToolExecutionError(message),
+                    });
                 }
-                _ => return None,
             }
         }
+    }
+
+    /// Performs a memory store operation, or serves it from the recording
+    /// when replaying. Like tool calls, each operation is kept in `tool_io`.
+    async fn memory_exchange(
+        &mut self,
+        node: &Node,
+        tool_name: &str,
+        operation: &str,
+        request: Value,
+        live: impl Future<Output = Result<Value, MemoryError>>,
+    ) -> Result<(Value, f64), ExecutionError> {
+        let (outcome, elapsed_ms) = match &self.replay {
+            Some(replay) => {
+                let exchange = replay
+                    .next(&node.id, tool_name, Some(&request))
+                    .map_err(ExecutionError::ReplayDivergence)?;
+                (exchange.outcome(), exchange.latency_ms)
+            }
+            None => {
+                let start = std::time::Instant::now();
+                let outcome = live
+                    .await
+                    .map_err(|e| (ErrorKind::Communication, e.to_string()));
+                (outcome, start.elapsed().as_secs_f64() * 1000.0)
+            }
+        };
+        self.tool_io.push(ToolExchange::new(
+            &node.id,
+            tool_name,
+            1,
+            Some(request),
+            &outcome,
+            elapsed_ms,
+        ));
+
+        match outcome {
+            Ok(result) => Ok((result, elapsed_ms)),
+            Err((_, message)) => Err(ExecutionError::ToolExecutionError(format!(
+                "{} failed: {}",
+                operation, message
+            ))),
+        }
+    }
 
-        Some(current)
+    /// Records the result of a journaled call, or drops the entry when the
+    /// call failed so that it may be made again. A call that timed out may
+    /// have taken effect anyway, so its entry stays started and a resumed
+    /// plan refuses to make it again.
+    async fn finish_journal(
+        checkpoints: &Checkpointer,
+        key: &str,
+        outcome: Result<&Value, ErrorKind>,
+    ) -> Result<(), ExecutionError> {
+        let finished = match outcome {
+            Ok(result) => {
+                checkpoints
+                    .store
+                    .journal_finish(&checkpoints.plan_id, key, result)
+                    .await
+            }
+            Err(ErrorKind::Timeout) => return Ok(()),
+            Err(_) => {
+                checkpoints
+                    .store
+                    .journal_abandon(&checkpoints.plan_id, key)
+                    .await
+            }
+        };
+        finished.map_err(|e| ExecutionError::CheckpointError(e.to_string()))
+    }
+
+    /// Binds a node's result to the variables named in its `out` map. Each path
+    /// is rooted at `result`, e.g. `result`, `result.hits` or `result.items[0].id`.
+    /// Nothing is bound unless every path resolves.
+    fn bind_outputs(&mut self, node: &Node, result: &Value) -> Result<(), ExecutionError> {
+        let Some(out_map) = &node.out else {
+            return Ok(());
+        };
+
+        let mut bound = Vec::with_capacity(out_map.len());
+        for (var_name, path) in out_map {
+            let value = extract_result_path(result, path).ok_or_else(|| {
+                ExecutionError::ValidationError(format!(
+                    "Output '{}' of node {}: path '{}' not found in result",
+                    var_name, node.id, path
+                ))
+            })?;
+            bound.push((var_name.clone(), value));
+        }
+
+        self.variables.extend(bound);
+        Ok(())
+    }
+
+    /// Resolves the plan's declared `outputs` against the current variables.
+    /// Variables the plan does not declare are never returned; an output
+    /// whose reference resolves to nothing, e.g. in a skipped branch, is null.
+    pub fn plan_outputs(&self, plan: &Plan) -> BTreeMap<String, Value> {
+        plan.outputs
+            .iter()
+            .flatten()
+            .map(|(name, reference)| {
+                let value = reference
+                    .strip_prefix('$')
+                    .and_then(|reference| self.resolve_reference(reference))
+                    .unwrap_or(Value::Null);
+                (name.clone(), value)
+            })
+            .collect()
+    }
+
+    /// Evaluates a condition expression against the current variables.
+    pub fn evaluate_expression(&self, source: &str) -> Result<Evaluation, ExprError> {
+        expr::evaluate(source, |reference| self.resolve_reference(reference))
     }
 
     pub fn record_tool_usage(
@@ -167,6 +712,7 @@ impl ExecutionContext {
         spec: Option<&ToolSpec>,
         actual_latency_ms: f64,
         tokens_used: Option<u64>,
+        fuel_used: Option<u64>,
     ) -> Result<UsageRecord, ExecutionError> {
         let mut consumed_latency = actual_latency_ms;
         let mut consumed_cost = 0.0;
@@ -191,6 +737,7 @@ impl ExecutionContext {
         self.total_latency_ms += consumed_latency;
         self.total_cost_usd += consumed_cost;
         self.total_tokens = self.total_tokens.saturating_add(consumed_tokens);
+        self.total_fuel = self.total_fuel.saturating_add(fuel_used.unwrap_or(0));
 
         if let Err(e) = self.check_budget_overrun() {
             // Record a summary trace before surfacing the budget error so downstream
@@ -204,6 +751,7 @@ impl ExecutionContext {
             latency_ms: consumed_latency,
             cost_usd: consumed_cost,
             tokens: consumed_tokens,
+            fuel: fuel_used,
         })
     }
 
@@ -212,6 +760,78 @@ impl ExecutionContext {
         self.rebuild_capability_index();
     }
 
+    /// Registers Rust tools at `inproc://{endpoint}` and adds each to the
+    /// registry with its spec, so plans reach them through the same
+    /// resolution, policy and budget checks as remote tools.
+    pub fn register_inproc_tools(&mut self, endpoint: &str, tools: InprocTransport) {
+        let url = format!("inproc://{}", endpoint);
+        for spec in tools.specs() {
+            self.tool_urls.insert(spec.name.clone(), url.clone());
+            self.tool_specs.insert(spec.name.clone(), spec);
+        }
+        self.rebuild_capability_index();
+        self.tool_client.register_inproc(endpoint, Arc::new(tools));
+    }
+
+    /// Sends `auth` with every request to the URL of `tool_name`. Tools at
+    /// the same URL, such as those discovered on an MCP server, share it, so
+    /// the URL must not have other credentials. Fails if `tool_name` has no
+    /// URL yet.
+    pub fn set_tool_auth(&mut self, tool_name: &str, auth: ToolAuth) -> Result<(), ToolError> {
+        let url = self.tool_urls.get(tool_name).ok_or_else(|| {
+            ToolError::Validation(format!(
+                "Cannot set credentials for {}: it has no registered URL",
+                tool_name
+            ))
+        })?;
+        self.tool_client.set_auth(url, auth).map_err(|e| match e {
+            ToolError::Validation(message) => ToolError::Validation(format!(
+                "Cannot set credentials for {}: {}",
+                tool_name, message
+            )),
+            other => other,
+        })
+    }
+
+    /// Replaces registry entries that point at servers listing their own
+    /// tools, such as MCP servers, with one entry per listed tool and
+    /// registers the tools' specs. Tools already in the registry keep their
+    /// entry. Each server is listed once per context, so calling this again
+    /// only contacts servers registered since.
+    pub async fn discover_tools(&mut self) {
+        let urls: HashSet<String> = self
+            .tool_urls
+            .values()
+            .filter(|url| !self.discovered_urls.contains(*url))
+            .cloned()
+            .collect();
+        for url in urls {
+            self.discovered_urls.insert(url.clone());
+            let tools = match self.tool_client.list_tools(&url).await {
+                Ok(tools) if !tools.is_empty() => tools,
+                Ok(_) => continue,
+                Err(e) => {
+                    tracing::warn!(url = %url, error = %e, "Failed to list tools");
+                    continue;
+                }
+            };
+
+            let listed: HashSet<&str> = tools.iter().map(|spec| spec.name.as_str()).collect();
+            self.tool_urls
+                .retain(|name, tool_url| *tool_url != url || listed.contains(name.as_str()));
+            for spec in tools {
+                let tool_url = self
+                    .tool_urls
+                    .entry(spec.name.clone())
+                    .or_insert_with(|| url.clone());
+                if *tool_url == url {
+                    self.tool_specs.insert(spec.name.clone(), spec);
+                }
+            }
+        }
+        self.rebuild_capability_index();
+    }
+
     fn rebuild_capability_index(&mut self) {
         self.capability_index.clear();
         for (tool_name, spec) in &self.tool_specs {
@@ -339,12 +959,16 @@ impl ExecutionContext {
             ))
         })?;
 
-        let tool_url = self.tool_urls.get(&decision.tool_name).ok_or_else(|| {
-            ExecutionError::ValidationError(format!(
-                "Tool {} not found in tool URLs",
-                decision.tool_name
-            ))
-        })?;
+        let tool_url = self
+            .tool_urls
+            .get(&decision.tool_name)
+            .cloned()
+            .ok_or_else(|| {
+                ExecutionError::ValidationError(format!(
+                    "Tool {} not found in tool URLs",
+                    decision.tool_name
+                ))
+            })?;
 
         let spec = self.tool_specs.get(&decision.tool_name).cloned();
 
@@ -357,11 +981,11 @@ impl ExecutionContext {
             ),
         );
         trace.data = Some(decision.rationale.clone());
-        self.trace_events.push(trace);
+        self.push_trace(trace);
 
         Ok(ToolResolution {
             tool_name: decision.tool_name,
-            tool_url: tool_url.clone(),
+            tool_url,
             spec,
             capability: Some(capability.clone()),
         })
@@ -401,7 +1025,7 @@ impl ExecutionContext {
                                         .map(|v| v.clone())
                                         .unwrap_or(Value::Null),
                                 }));
-                                self.trace_events.push(trace);
+                                self.push_trace(trace);
 
                                 return Err(ExecutionError::ToolExecutionError(message));
                             }
@@ -414,6 +1038,39 @@ impl ExecutionContext {
         Ok(())
     }
 
+    /// Finds the first evidence summary recorded at or after `from` whose mean
+    /// confidence is below `threshold`. Summaries without claims are ignored.
+    fn low_confidence_since(&self, from: usize, threshold: f64) -> Option<StopReason> {
+        self.trace_events[from..]
+            .iter()
+            .filter(|trace| trace.event_type == "evidence_summary")
+            .find_map(|trace| {
+                let data = trace.data.as_ref()?;
+                let claims = data.get("total_claims").and_then(Value::as_u64)?;
+                let confidence = data.get("mean_confidence").and_then(Value::as_f64)?;
+                (claims > 0 && confidence < threshold).then(|| StopReason::MinConfidence {
+                    step_id: trace.step_id.clone(),
+                    confidence,
+                    threshold,
+                })
+            })
+    }
+
+    fn push_stop_trace(&mut self, reason: &StopReason) {
+        let mut data = serde_json::to_value(reason).unwrap_or(Value::Null);
+        if let Value::Object(map) = &mut data {
+            map.insert("description".to_string(), Value::String(reason.to_string()));
+        }
+
+        let mut trace = crate::internal::trace::trace::Trace::new(
+            "plan_stopped".to_string(),
+            "plan".to_string(),
+            format!("Plan stopped: {}", reason),
+        );
+        trace.data = Some(data);
+        self.push_trace(trace);
+    }
+
     pub fn push_budget_summary_trace(&mut self) {
         let latency_budget = self
             .signals
@@ -421,6 +1078,7 @@ impl ExecutionContext {
             .and_then(|s| s.latency_budget_ms)
             .map(|v| v as f64);
         let cost_cap = self.signals.as_ref().and_then(|s| s.cost_cap_usd);
+        let fuel_cap = self.signals.as_ref().and_then(|s| s.fuel_cap);
 
         let summary = serde_json::json!({
             "total_latency_ms": self.total_latency_ms,
@@ -428,6 +1086,8 @@ impl ExecutionContext {
             "total_cost_usd": self.total_cost_usd,
             "cost_cap_usd": cost_cap,
             "total_tokens": self.total_tokens,
+            "total_fuel": self.total_fuel,
+            "fuel_cap": fuel_cap,
         });
 
         let mut trace = crate::internal::trace::trace::Trace::new(
@@ -438,7 +1098,49 @@ impl ExecutionContext {
         trace.cost_usd = Some(self.total_cost_usd);
         trace.tokens_out = Some(self.total_tokens);
         trace.data = Some(summary);
-        self.trace_events.push(trace);
+        self.push_trace(trace);
+    }
+
+    /// Records that the execution was cancelled, with the nodes that were
+    /// still running when it was.
+    pub fn push_cancel_trace(&mut self, interrupted_nodes: &[String]) {
+        let mut trace = crate::internal::trace::trace::Trace::new(
+            "plan_cancelled".to_string(),
+            "plan".to_string(),
+            "Plan cancelled".to_string(),
+        );
+        trace.data = Some(serde_json::json!({
+            "description": "Plan cancelled",
+            "interrupted_nodes": interrupted_nodes,
+            "completed_nodes": self.completed_nodes.len(),
+        }));
+        self.push_trace(trace);
+    }
+
+    /// Tells the tracked run, if any, which nodes are done and which are running.
+    fn report_progress(&self, running: &[&Node]) {
+        if let Some(run) = &self.run {
+            run.report_progress(
+                self.completed_nodes.len(),
+                self.skipped_nodes.len(),
+                running.iter().map(|node| node.id.clone()).collect(),
+            );
+        }
+    }
+
+    /// Marks a node as skipped because none of its incoming edges are active.
+    fn skip_node(&mut self, node: &Node, inactive_predecessors: Vec<String>) {
+        let mut trace = crate::internal::trace::trace::Trace::new(
+            "branch_skipped".to_string(),
+            node.id.clone(),
+            format!("Node {} skipped: no active upstream branch", node.id),
+        );
+        trace.data = Some(serde_json::json!({
+            "description": format!("Node {} skipped: no active upstream branch", node.id),
+            "inactive_predecessors": inactive_predecessors,
+        }));
+        self.push_trace(trace);
+        self.skipped_nodes.insert(node.id.clone());
     }
 
     fn check_budget_overrun(&self) -> Result<(), ExecutionError> {
@@ -459,17 +1161,100 @@ impl ExecutionContext {
                     )));
                 }
             }
+            if let Some(fuel_cap) = signals.fuel_cap {
+                if self.total_fuel > fuel_cap {
+                    return Err(ExecutionError::BudgetExceeded(format!(
+                        "Fuel budget exceeded: {} > {}",
+                        self.total_fuel, fuel_cap
+                    )));
+                }
+            }
         }
         Ok(())
     }
 }
 
+impl ExecutionContext {
+    /// Starts folding the forks of one scheduling round back into this
+    /// context; see [`ExecutionContext::merge_node`].
+    fn begin_round(&self) -> RoundBase {
+        RoundBase {
+            variables: self.variables.clone(),
+            latency_ms: self.total_latency_ms,
+            cost_usd: self.total_cost_usd,
+            tokens: self.total_tokens,
+            fuel: self.total_fuel,
+            slowest_ms: 0.0,
+        }
+    }
+
+    /// Folds the fork of one node of the round back into this context.
+    ///
+    /// Nodes are merged in ready order as they finish. Variable writes are
+    /// diffed against the pre-round variables, so one node's write is never
+    /// clobbered by a sibling's stale copy. Cost and tokens are summed across
+    /// forks, while latency is charged as the slowest node of the round
+    /// because the nodes ran concurrently. Returns the node's own result.
+    fn merge_node(
+        &mut self,
+        round: &mut RoundBase,
+        outcome: NodeOutcome,
+    ) -> Result<(), ExecutionError> {
+        let writes: Vec<(String, Value)> = outcome
+            .ctx
+            .variables
+            .into_iter()
+            .filter(|(name, value)| round.variables.get(name) != Some(value))
+            .collect();
+        self.variables.extend(writes);
+        for trace in outcome.ctx.trace_events {
+            self.push_trace(trace);
+        }
+        self.tool_io.extend(outcome.ctx.tool_io);
+        if let Some(not_taken) = outcome.ctx.branch_not_taken.get(&outcome.node_id) {
+            self.branch_not_taken
+                .insert(outcome.node_id.clone(), not_taken.clone());
+        }
+
+        round.slowest_ms = round
+            .slowest_ms
+            .max(outcome.ctx.total_latency_ms - round.latency_ms);
+        self.total_latency_ms = round.latency_ms + round.slowest_ms;
+        self.total_cost_usd += outcome.ctx.total_cost_usd - round.cost_usd;
+        self.total_tokens = self
+            .total_tokens
+            .saturating_add(outcome.ctx.total_tokens.saturating_sub(round.tokens));
+        self.total_fuel = self
+            .total_fuel
+            .saturating_add(outcome.ctx.total_fuel.saturating_sub(round.fuel));
+
+        outcome.result
+    }
+}
+
+/// The context of a round as it was before any of its nodes ran.
+struct RoundBase {
+    variables: HashMap<String, Value>,
+    latency_ms: f64,
+    cost_usd: f64,
+    tokens: u64,
+    fuel: u64,
+    slowest_ms: f64,
+}
+
+struct NodeOutcome {
+    node_id: String,
+    ctx: ExecutionContext,
+    result: Result<(), ExecutionError>,
+}
+
 #[derive(Debug)]
 pub struct UsageRecord {
     pub tool_name: String,
     pub latency_ms: f64,
     pub cost_usd: f64,
     pub tokens: u64,
+    pub fuel: Option<u64>,
 }
 
 #[derive(Debug)]
@@ -486,6 +1271,51 @@ struct ToolResolution {
     capability: Option<String>,
 }
 
+fn tool_error_kind(error: &ToolError) -> ErrorKind {
+    match error {
+        ToolError::Communication(_) => ErrorKind::Communication,
+        ToolError::Invocation(_) => ErrorKind::Invocation,
+        ToolError::Validation(_) => ErrorKind::Validation,
+    }
+}
+
+/// Why a plan stopped before all of its nodes ran. A stopped plan is not a
+/// failure: its variables and traces up to the stop are returned as usual.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum StopReason {
+    /// `stop_conditions.max_nodes` nodes have run and more were pending.
+    MaxNodes { limit: u32, pending: Vec<String> },
+    /// An evidence summary's mean confidence fell below `stop_conditions.min_confidence`.
+    MinConfidence {
+        step_id: String,
+        confidence: f64,
+        threshold: f64,
+    },
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StopReason::MaxNodes { limit, pending } => write!(
+                f,
+                "max_nodes limit of {} reached with {} node(s) pending",
+                limit,
+                pending.len()
+            ),
+            StopReason::MinConfidence {
+                step_id,
+                confidence,
+                threshold,
+            } => write!(
+                f,
+                "evidence confidence {:.3} at {} fell below min_confidence {:.3}",
+                confidence, step_id, threshold
+            ),
+        }
+    }
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum ExecutionError {
     #[error("Plan validation failed: {0}")]
@@ -496,16 +1326,178 @@ pub enum ExecutionError {
     TimeoutError(String),
     #[error("Budget exceeded: {0}")]
     BudgetExceeded(String),
+    #[error("Checkpoint error: {0}")]
+    CheckpointError(String),
+    #[error("Replay diverged: {0}")]
+    ReplayDivergence(String),
+    #[error("Execution cancelled: {0}")]
+    Cancelled(String),
 }
 
+/// A sub-plan run, handing back the child context along with its outcome.
+type SubPlanFuture<'a> =
+    Pin<Box<dyn Future<Output = (ExecutionContext, Result<(), ExecutionError>)> + Send + 'a>>;
+
 pub struct Scheduler;
 
 impl Scheduler {
     pub async fn execute_plan(
+        &self,
+        ctx: ExecutionContext,
+        plan: &Plan,
+    ) -> Result<ExecutionContext, ExecutionError> {
+        let (ctx, result) = self.run(ctx, plan).await;
+        result.map(|_| ctx)
+    }
+
+    /// Executes a plan like [`Scheduler::execute_plan`], but hands the context
+    /// back even when execution fails, so the traces and tool I/O recorded up
+    /// to the failure can still be inspected or bundled.
+    pub async fn run(
         &self,
         mut ctx: ExecutionContext,
         plan: &Plan,
+    ) -> (ExecutionContext, Result<(), ExecutionError>) {
+        let result = self.start(&mut ctx, plan).await;
+        (ctx, result)
+    }
+
+    async fn start(&self, ctx: &mut ExecutionContext, plan: &Plan) -> Result<(), ExecutionError> {
+        // A run cancelled while queued ends without discovering or hydrating tools
+        let cancelled = ctx.cancel.is_cancelled();
+        if !cancelled {
+            self.prepare(ctx, plan).await?;
+        }
+
+        if let Some(checkpoints) = ctx.checkpoints.clone().filter(|c| c.is_root()) {
+            checkpoints
+                .store
+                .reset_journal(&checkpoints.plan_id)
+                .await
+                .map_err(|e| ExecutionError::CheckpointError(e.to_string()))?;
+            Self::save_checkpoint(ctx, plan, CheckpointStatus::Running, 0, None).await?;
+        }
+
+        if cancelled {
+            return Self::cancel_nodes(ctx, plan, 0, vec![]).await;
+        }
+        self.run_nodes(ctx, plan, 0).await
+    }
+
+    /// Continues a checkpointed execution of `plan_id` from its last
+    /// completed node. `ctx` supplies the tool configuration; variables,
+    /// budget totals and traces come from the checkpoint. Calls to tools with
+    /// `side_effects: true` that finished before the interruption are
+    /// replayed from the journal instead of being invoked again.
+    pub async fn resume(
+        &self,
+        mut ctx: ExecutionContext,
+        store: CheckpointStore,
+        plan_id: &str,
     ) -> Result<ExecutionContext, ExecutionError> {
+        let checkpoint = store
+            .load(plan_id)
+            .await
+            .map_err(|e| ExecutionError::CheckpointError(e.to_string()))?
+            .ok_or_else(|| {
+                ExecutionError::CheckpointError(format!("No checkpoint found for plan {}", plan_id))
+            })?;
+
+        ctx.plan_id = plan_id.to_string();
+        ctx.enable_checkpoints(store, plan_id);
+        checkpoint.restore(&mut ctx);
+        if !checkpoint.status.is_resumable() {
+            return Ok(ctx);
+        }
+
+        let plan = checkpoint.plan;
+        if ctx.cancel.is_cancelled() {
+            return Self::cancel_nodes(&mut ctx, &plan, checkpoint.executed_nodes, vec![])
+                .await
+                .map(|()| ctx);
+        }
+        self.prepare(&mut ctx, &plan).await?;
+
+        let mut trace = crate::internal::trace::trace::Trace::new(
+            "plan_resumed".to_string(),
+            "plan".to_string(),
+            format!(
+                "Resuming plan {} after {} completed nodes",
+                plan_id,
+                checkpoint.completed_nodes.len()
+            ),
+        );
+        trace.data = Some(serde_json::json!({
+            "plan_id": plan_id,
+            "completed_nodes": checkpoint.completed_nodes,
+            "skipped_nodes": checkpoint.skipped_nodes,
+            "previous_status": checkpoint.status,
+            "previous_error": checkpoint.error,
+        }));
+        ctx.push_trace(trace);
+
+        self.run_nodes(&mut ctx, &plan, checkpoint.executed_nodes)
+            .await?;
+        Ok(ctx)
+    }
+
+    /// Re-runs the execution captured in `bundle` without contacting any
+    /// tool: every call is served from the recorded exchanges. The report
+    /// points at the first trace where the replay departs from the recording.
+    pub async fn replay(&self, bundle: &ReplayBundle) -> ReplayReport {
+        let mut ctx = ExecutionContext::new();
+        ctx.plan_id = bundle.plan_id.clone();
+        for tool in &bundle.tools {
+            ctx.tool_urls
+                .insert(tool.clone(), format!("replay://{}", tool));
+        }
+        for (name, spec) in &bundle.tool_specs {
+            ctx.register_tool_spec(name.clone(), spec.clone());
+        }
+        ctx.variables = bundle.inputs.clone();
+        ctx.signals = bundle.signals.clone();
+        ctx.sub_plans = Arc::new(bundle.sub_plans.clone());
+        let source = ReplaySource::new(&bundle.tool_io);
+        ctx.replay = Some(source.clone());
+
+        let (ctx, result) = self.run(ctx, &bundle.plan).await;
+        ReplayReport {
+            plan_id: bundle.plan_id.clone(),
+            recorded_error: bundle.error.clone(),
+            error: result.err().map(|e| e.to_string()),
+            divergence: first_divergence(&bundle.traces, &ctx.trace_events),
+            unused_exchanges: source.remaining(),
+            traces: ctx.trace_events,
+        }
+    }
+
+    async fn save_checkpoint(
+        ctx: &ExecutionContext,
+        plan: &Plan,
+        status: CheckpointStatus,
+        executed_nodes: usize,
+        error: Option<&ExecutionError>,
+    ) -> Result<(), ExecutionError> {
+        let Some(checkpoints) = ctx.checkpoints.as_ref().filter(|c| c.is_root()) else {
+            return Ok(());
+        };
+
+        let mut checkpoint =
+            Checkpoint::capture(&checkpoints.plan_id, plan, ctx, status, executed_nodes);
+        checkpoint.error = error.map(|e| e.to_string());
+        checkpoints
+            .store
+            .save(&checkpoint, &ctx.trace_events)
+            .await
+            .map_err(|e| ExecutionError::CheckpointError(e.to_string()))
+    }
+
+    /// Validates the plan against the context's tools and hydrates their specs.
+    async fn prepare(&self, ctx: &mut ExecutionContext, plan: &Plan) -> Result<(), ExecutionError> {
+        // A replay brings its recorded specs and must not reach the tools.
+        if ctx.replay.is_none() {
+            ctx.discover_tools().await;
+        }
         if ctx.tool_urls.is_empty() {
             plan.validate()
                 .map_err(|e| ExecutionError::ValidationError(e.to_string()))?;
@@ -524,7 +1516,8 @@ impl Scheduler {
             .iter()
             .map(|(name, url)| (name.clone(), url.clone()))
             .collect();
-        if !tool_entries.is_empty() {
+        // A replay brings its recorded specs and must not reach the tools.
+        if !tool_entries.is_empty() && ctx.replay.is_none() {
             let client = ctx.tool_client.clone();
             for (tool_name, url) in tool_entries {
                 if ctx.tool_specs.contains_key(&tool_name) {
@@ -548,21 +1541,46 @@ impl Scheduler {
             }
         }
 
-        // Process nodes in order respecting dependencies
-        let mut remaining_nodes: Vec<&Node> = Self::optimized_node_order(&mut ctx, plan);
-        let mut processed_count = 0;
+        // With specs hydrated, literal arguments can be checked against input schemas
+        if !ctx.tool_urls.is_empty() {
+            plan.validate_with_tool_specs(
+                ctx.tool_urls.keys().map(|k| k.as_str()),
+                &ctx.tool_specs,
+            )
+            .map_err(|e| ExecutionError::ValidationError(e.to_string()))?;
+        }
 
-        while !remaining_nodes.is_empty() && processed_count < 100 {
-            // Prevent infinite loops
-            let mut executed_this_round = false;
+        Ok(())
+    }
+
+    /// Runs every node that has not completed or been skipped yet.
+    /// `executed_count` is the number of nodes already run towards `max_nodes`.
+    async fn run_nodes(
+        &self,
+        ctx: &mut ExecutionContext,
+        plan: &Plan,
+        mut executed_count: usize,
+    ) -> Result<(), ExecutionError> {
+        // Process nodes in order respecting dependencies
+        let mut remaining_nodes: Vec<&Node> = Self::optimized_node_order(ctx, plan);
+        let stop_conditions = plan.stop_conditions.as_ref();
+        let max_nodes = stop_conditions.and_then(|conditions| conditions.max_nodes);
+        let min_confidence = stop_conditions.and_then(|conditions| conditions.min_confidence);
+
+        while !remaining_nodes.is_empty() {
+            if ctx.cancel.is_cancelled() {
+                return Self::cancel_nodes(ctx, plan, executed_count, vec![]).await;
+            }
 
             // Find nodes that can be executed (dependencies satisfied)
             let mut executable_nodes = Vec::new();
             let mut remaining_next = Vec::new();
 
+            let mut skipped_this_round = Vec::new();
+
             for node in remaining_nodes {
-                if ctx.completed_nodes.contains(&node.id) {
-                    continue; // Already completed
+                if ctx.completed_nodes.contains(&node.id) || ctx.skipped_nodes.contains(&node.id) {
+                    continue; // Already resolved
                 }
 
                 if ctx.running_nodes.contains(&node.id) {
@@ -570,49 +1588,116 @@ impl Scheduler {
                     continue;
                 }
 
-                // Check if all dependencies are met
-                let can_execute = if let Some(edges) = &plan.edges {
-                    edges
-                        .iter()
-                        .filter(|edge| edge.to == node.id)
-                        .all(|edge| ctx.completed_nodes.contains(&edge.from))
-                } else {
-                    // No edges, all nodes are independent
-                    true
-                };
-
-                if can_execute {
-                    executable_nodes.push(node);
-                } else {
-                    remaining_next.push(node);
+                match Self::node_readiness(ctx, plan, node) {
+                    NodeReadiness::Ready => executable_nodes.push(node),
+                    NodeReadiness::Waiting => remaining_next.push(node),
+                    NodeReadiness::Skipped(inactive) => skipped_this_round.push((node, inactive)),
                 }
             }
 
+            for (node, inactive) in &skipped_this_round {
+                ctx.skip_node(node, inactive.clone());
+            }
+
             remaining_nodes = remaining_next;
 
-            // Execute all executable nodes
-            for node in executable_nodes {
+            // Hold back ready nodes beyond the max_nodes cap
+            let mut capped = false;
+            if let Some(limit) = max_nodes {
+                let allowance = (limit as usize).saturating_sub(executed_count);
+                if executable_nodes.len() > allowance {
+                    executable_nodes.truncate(allowance);
+                    capped = true;
+                }
+            }
+
+            // Execute all executable nodes concurrently, each against its own fork
+            // of the context, then fold the forks back in ready order so variables
+            // and traces stay deterministic.
+            for node in &executable_nodes {
                 ctx.running_nodes.insert(node.id.clone());
+            }
+            ctx.report_progress(&executable_nodes);
+
+            // Each node is checkpointed as soon as it and the nodes ready
+            // before it have finished. The round keeps running meanwhile, so
+            // saving never waits on a sibling that holds the database.
+            let round_traces_start = ctx.trace_events.len();
+            let mut round = ctx.begin_round();
+            let (finished, mut outcomes) = tokio::sync::mpsc::unbounded_channel();
+            let running =
+                self.execute_ready_nodes(ctx, &executable_nodes)
+                    .for_each(move |outcome| {
+                        let _ = finished.send(outcome);
+                        future::ready(())
+                    });
+            let merging = async {
+                let mut errors = Vec::new();
+                while let Some(outcome) = outcomes.recv().await {
+                    let node_id = outcome.node_id.clone();
+                    ctx.running_nodes.remove(&node_id);
+                    match ctx.merge_node(&mut round, outcome) {
+                        Ok(()) => {
+                            ctx.completed_nodes.insert(node_id);
+                            executed_count += 1;
+                            Self::save_checkpoint(
+                                ctx,
+                                plan,
+                                CheckpointStatus::Running,
+                                executed_count,
+                                None,
+                            )
+                            .await?;
+                        }
+                        Err(e) => errors.push((node_id, e)),
+                    }
+                }
+                Ok::<_, ExecutionError>(errors)
+            };
+            let ((), merged) = tokio::join!(running, merging);
+            let mut errors = merged?;
 
-                let result = self.execute_node(&mut ctx, node).await;
+            if !errors.is_empty() && ctx.cancel.is_cancelled() {
+                let interrupted = errors.into_iter().map(|(node_id, _)| node_id).collect();
+                return Self::cancel_nodes(ctx, plan, executed_count, interrupted).await;
+            }
 
-                // Remove from running set and add to completed
-                ctx.running_nodes.remove(&node.id);
-                ctx.completed_nodes.insert(node.id.clone());
+            if !errors.is_empty() {
+                let (node_id, e) = errors.remove(0);
+                tracing::error!("Node {} execution failed: {}", node_id, e);
+                Self::save_checkpoint(
+                    ctx,
+                    plan,
+                    CheckpointStatus::Failed,
+                    executed_count,
+                    Some(&e),
+                )
+                .await?;
+                return Err(e);
+            }
 
-                match result {
-                    Ok(_) => {
-                        executed_this_round = true;
-                        processed_count += 1;
-                    }
-                    Err(e) => {
-                        tracing::error!("Node {} execution failed: {}", node.id, e);
-                        return Err(e);
-                    }
+            if let Some(limit) = max_nodes.filter(|_| capped) {
+                let pending = plan
+                    .nodes
+                    .iter()
+                    .filter(|node| {
+                        !ctx.completed_nodes.contains(&node.id)
+                            && !ctx.skipped_nodes.contains(&node.id)
+                    })
+                    .map(|node| node.id.clone())
+                    .collect();
+                ctx.stop_reason = Some(StopReason::MaxNodes { limit, pending });
+                break;
+            }
+
+            if let Some(threshold) = min_confidence {
+                if let Some(reason) = ctx.low_confidence_since(round_traces_start, threshold) {
+                    ctx.stop_reason = Some(reason);
+                    break;
                 }
             }
 
-            if !executed_this_round {
+            if executable_nodes.is_empty() && skipped_this_round.is_empty() {
                 // No progress made, probably a circular dependency or missing dependencies
                 return Err(ExecutionError::ValidationError(
                     "No executable nodes found - possible circular dependency".to_string(),
@@ -621,13 +1706,142 @@ impl Scheduler {
 
             // Check if we still have budget
             if let Err(e) = ctx.check_budget_overrun() {
+                Self::save_checkpoint(
+                    ctx,
+                    plan,
+                    CheckpointStatus::Failed,
+                    executed_count,
+                    Some(&e),
+                )
+                .await?;
                 return Err(e);
             }
+
+            Self::save_checkpoint(ctx, plan, CheckpointStatus::Running, executed_count, None)
+                .await?;
         }
 
+        ctx.report_progress(&[]);
         ctx.push_budget_summary_trace();
+        let status = match ctx.stop_reason.clone() {
+            Some(reason) => {
+                ctx.push_stop_trace(&reason);
+                CheckpointStatus::Stopped
+            }
+            None => CheckpointStatus::Completed,
+        };
+        Self::save_checkpoint(ctx, plan, status, executed_count, None).await?;
 
-        Ok(ctx)
+        Ok(())
+    }
+
+    /// Ends a cancelled execution. The checkpoint is saved as failed, so the
+    /// plan can still be resumed.
+    async fn cancel_nodes(
+        ctx: &mut ExecutionContext,
+        plan: &Plan,
+        executed_count: usize,
+        interrupted: Vec<String>,
+    ) -> Result<(), ExecutionError> {
+        let error = ExecutionError::Cancelled(match interrupted.as_slice() {
+            [] => "plan cancelled between rounds".to_string(),
+            nodes => format!("plan cancelled while running {}", nodes.join(", ")),
+        });
+        // Sub-plans are cancelled along with their parent, which records it once
+        if ctx.spawn_depth == 0 {
+            ctx.report_progress(&[]);
+            ctx.push_cancel_trace(&interrupted);
+        }
+        Self::save_checkpoint(
+            ctx,
+            plan,
+            CheckpointStatus::Failed,
+            executed_count,
+            Some(&error),
+        )
+        .await?;
+        Err(error)
+    }
+
+    /// Decides whether a node can run given the state of its predecessors.
+    ///
+    /// A node waits until every predecessor is either completed or skipped. It
+    /// then runs if at least one incoming edge is active; an edge is inactive
+    /// when its source was skipped or is a branch that did not select this node.
+    fn node_readiness(ctx: &ExecutionContext, plan: &Plan, node: &Node) -> NodeReadiness {
+        let mut has_incoming = false;
+        let mut has_active_edge = false;
+        let mut inactive = Vec::new();
+
+        for edge in plan
+            .edges
+            .iter()
+            .flatten()
+            .filter(|edge| edge.to == node.id)
+        {
+            has_incoming = true;
+
+            if ctx.skipped_nodes.contains(&edge.from) {
+                inactive.push(edge.from.clone());
+                continue;
+            }
+
+            if !ctx.completed_nodes.contains(&edge.from) {
+                return NodeReadiness::Waiting;
+            }
+
+            let branch_declined = ctx
+                .branch_not_taken
+                .get(&edge.from)
+                .map(|targets| targets.contains(&node.id))
+                .unwrap_or(false);
+            if branch_declined {
+                inactive.push(edge.from.clone());
+            } else {
+                has_active_edge = true;
+            }
+        }
+
+        if !has_incoming || has_active_edge {
+            NodeReadiness::Ready
+        } else {
+            NodeReadiness::Skipped(inactive)
+        }
+    }
+
+    /// Runs a ready set with at most `ctx.max_parallelism` nodes in flight.
+    /// Outcomes are yielded in the same order as `nodes`, each once it and the
+    /// nodes before it have finished.
+    fn execute_ready_nodes<'a>(
+        &'a self,
+        ctx: &ExecutionContext,
+        nodes: &[&'a Node],
+    ) -> impl Stream<Item = NodeOutcome> + 'a {
+        let max_parallelism = ctx.max_parallelism.max(1);
+        let mut pending = Vec::with_capacity(nodes.len());
+        for &node in nodes {
+            let mut fork = ctx.fork();
+            fork.parent_span_id = Some(fork.span_id.clone());
+            fork.span_id = new_span_id();
+            pending.push(async move {
+                // Cancelling drops the node mid-flight, aborting its tool calls
+                let cancel = fork.cancel.clone();
+                let result = tokio::select! {
+                    result = self.execute_node(&mut fork, node) => result,
+                    _ = cancel.cancelled() => Err(ExecutionError::Cancelled(format!(
+                        "node {} was interrupted",
+                        node.id
+                    ))),
+                };
+                NodeOutcome {
+                    node_id: node.id.clone(),
+                    ctx: fork,
+                    result,
+                }
+            });
+        }
+
+        stream::iter(pending).buffered(max_parallelism)
     }
 
     async fn execute_node(
@@ -660,7 +1874,6 @@ impl Scheduler {
 
         let args = ctx.resolve_args(node.args.as_ref());
         ctx.enforce_tool_policy(&resolution.tool_name, args.as_ref())?;
-        let spec = resolution.spec.clone();
 
         // Add trace event
         let trace_event = crate::internal::trace::trace::Trace::new(
@@ -668,32 +1881,13 @@ impl Scheduler {
             node.id.clone(),
             format!("Calling tool: {}", resolution.tool_name),
         );
-        ctx.trace_events.push(trace_event);
+        ctx.push_trace(trace_event);
 
         // Invoke the tool
-        let start = std::time::Instant::now();
-        let result = timeout(
-            Duration::from_secs(30), // 30 second timeout
-            ctx.tool_client
-                .invoke_tool(&resolution.tool_url, &resolution.tool_name, args),
-        )
-        .await
-        .map_err(|_| {
-            ExecutionError::TimeoutError(format!("Tool call {} timed out", resolution.tool_name))
-        })?
-        .map_err(|e| ExecutionError::ToolExecutionError(e.to_string()))?;
-        let elapsed_ms = start.elapsed().as_secs_f64() * 1000.0;
-        let usage =
-            ctx.record_tool_usage(&resolution.tool_name, spec.as_ref(), elapsed_ms, None)?;
+        let (result, usage) = ctx.invoke_with_policy(node, &resolution, args).await?;
 
         // Store the result in variables as specified by 'out' mapping
-        if let Some(out_map) = &node.out {
-            for (var_name, _result_path) in out_map {
-                // For now, store the full result
-                // In a full implementation, we would extract specific fields based on result_path
-                ctx.variables.insert(var_name.clone(), result.clone());
-            }
-        }
+        ctx.bind_outputs(node, &result)?;
 
         // Add trace event
         let mut trace_event = crate::internal::trace::trace::Trace::new(
@@ -711,7 +1905,7 @@ impl Scheduler {
             "total_cost_usd": ctx.total_cost_usd,
             "total_tokens": ctx.total_tokens,
         }));
-        ctx.trace_events.push(trace_event);
+        ctx.push_trace(trace_event);
 
         Ok(())
     }
@@ -722,7 +1916,6 @@ impl Scheduler {
         node: &Node,
     ) -> Result<(), ExecutionError> {
         let resolution = ctx.resolve_tool(node)?;
-        let spec = resolution.spec.clone();
 
         let collection_value = node
             .args
@@ -754,32 +1947,14 @@ impl Scheduler {
             let resolved_args = ctx.resolve_args(Some(&iteration_args));
             ctx.enforce_tool_policy(&resolution.tool_name, resolved_args.as_ref())?;
 
-            let start = std::time::Instant::now();
-            let result = timeout(
-                Duration::from_secs(30),
-                ctx.tool_client.invoke_tool(
-                    &resolution.tool_url,
-                    &resolution.tool_name,
-                    resolved_args,
-                ),
-            )
-            .await
-            .map_err(|_| {
-                ExecutionError::TimeoutError(format!("Map operation item {} timed out", index))
-            })?
-            .map_err(|e| ExecutionError::ToolExecutionError(e.to_string()))?;
-            let elapsed_ms = start.elapsed().as_secs_f64() * 1000.0;
-            ctx.record_tool_usage(&resolution.tool_name, spec.as_ref(), elapsed_ms, None)?;
+            let (result, _) = ctx
+                .invoke_with_policy(node, &resolution, resolved_args)
+                .await?;
 
             results.push(result);
         }
 
-        if let Some(out_map) = &node.out {
-            for (var_name, _) in out_map {
-                ctx.variables
-                    .insert(var_name.clone(), Value::Array(results.clone()));
-            }
-        }
+        ctx.bind_outputs(node, &Value::Array(results))?;
 
         Ok(())
     }
@@ -815,22 +1990,68 @@ impl Scheduler {
             result.push('\n');
         }
 
-        if let Some(out_map) = &node.out {
-            for (var_name, _) in out_map {
-                ctx.variables
-                    .insert(var_name.clone(), Value::String(result.clone()));
-            }
-        }
+        ctx.bind_outputs(node, &Value::String(result))?;
 
         Ok(())
     }
 
     async fn execute_branch(
         &self,
-        _ctx: &mut ExecutionContext,
-        _node: &Node,
+        ctx: &mut ExecutionContext,
+        node: &Node,
     ) -> Result<(), ExecutionError> {
-        // For now, just execute the branch operation without actual branching logic
+        let condition = node
+            .args
+            .as_ref()
+            .and_then(|args| args.get("condition"))
+            .ok_or_else(|| {
+                ExecutionError::ValidationError(
+                    "Branch operation requires a 'condition' argument".to_string(),
+                )
+            })?;
+
+        let value = match condition {
+            Value::String(source) => {
+                ctx.evaluate_expression(source)
+                    .map_err(|e| {
+                        ExecutionError::ValidationError(format!(
+                            "Branch condition '{}' could not be evaluated: {}",
+                            source, e
+                        ))
+                    })?
+                    .value
+            }
+            other => ctx.resolve_value(other),
+        };
+        let taken = is_truthy(&value);
+
+        let (taken_arm, declined_arm) = if taken {
+            ("then", "else")
+        } else {
+            ("else", "then")
+        };
+        let taken_targets = node.branch_targets(taken_arm);
+        let not_taken: HashSet<String> = node
+            .branch_targets(declined_arm)
+            .into_iter()
+            .filter(|target| !taken_targets.contains(target))
+            .collect();
+
+        let mut trace = crate::internal::trace::trace::Trace::new(
+            "branch_decision".to_string(),
+            node.id.clone(),
+            format!("Branch {} took '{}' arm", node.id, taken_arm),
+        );
+        trace.data = Some(serde_json::json!({
+            "condition": condition,
+            "value": value,
+            "arm": taken_arm,
+            "targets": taken_targets,
+            "not_taken": not_taken,
+        }));
+        ctx.push_trace(trace);
+
+        ctx.branch_not_taken.insert(node.id.clone(), not_taken);
         Ok(())
     }
 
@@ -871,34 +2092,194 @@ impl Scheduler {
                         format!("Assertion evidence summary for {}", node.id),
                     );
                     trace.data = Some(json);
-                    ctx.trace_events.push(trace);
+                    ctx.push_trace(trace);
                 }
             }
         }
 
-        // For now, just log the assertion
-        tracing::info!("Assertion: {}", condition);
+        let evaluation = ctx.evaluate_expression(condition).map_err(|e| {
+            ExecutionError::ValidationError(format!(
+                "Assertion '{}' could not be evaluated: {}",
+                condition, e
+            ))
+        })?;
+        let passed = evaluation.is_truthy();
+
+        let mut trace = crate::internal::trace::trace::Trace::new(
+            "assertion".to_string(),
+            node.id.clone(),
+            format!(
+                "Assertion {} {}",
+                node.id,
+                if passed { "passed" } else { "failed" }
+            ),
+        );
+        trace.data = Some(serde_json::json!({
+            "condition": condition,
+            "value": evaluation.value,
+            "passed": passed,
+            "steps": evaluation.steps_json(),
+        }));
+        ctx.push_trace(trace);
 
-        // Check if the assertion passes
-        // In a real implementation, we would evaluate the condition against the current context
-        if condition == "true" {
+        if passed {
+            tracing::info!("Assertion passed: {}", condition);
             Ok(())
         } else {
             Err(ExecutionError::ValidationError(format!(
-                "Assertion failed: {}",
-                condition
+                "Assertion failed: {} ({})",
+                condition,
+                evaluation.explain()
             )))
         }
     }
 
     async fn execute_spawn(
         &self,
-        _ctx: &mut ExecutionContext,
-        _node: &Node,
+        ctx: &mut ExecutionContext,
+        node: &Node,
     ) -> Result<(), ExecutionError> {
-        // For now, just log the spawn operation
-        tracing::info!("Spawn operation executed");
-        Ok(())
+        if ctx.spawn_depth >= MAX_SPAWN_DEPTH {
+            return Err(ExecutionError::ValidationError(format!(
+                "Spawn {} exceeds the maximum sub-plan depth of {}",
+                node.id, MAX_SPAWN_DEPTH
+            )));
+        }
+
+        let args = node.args.as_ref();
+        let (plan_name, child_plan) = match args.and_then(|args| args.get("plan")) {
+            Some(Value::String(name)) => {
+                let plan = ctx.sub_plans.get(name).cloned().ok_or_else(|| {
+                    ExecutionError::ValidationError(format!(
+                        "Spawn {} references unknown sub-plan: {}",
+                        node.id, name
+                    ))
+                })?;
+                (name.clone(), plan)
+            }
+            Some(inline @ Value::Object(_)) => {
+                let plan = serde_json::from_value::<Plan>(inline.clone()).map_err(|e| {
+                    ExecutionError::ValidationError(format!(
+                        "Spawn {} has an invalid inline plan: {}",
+                        node.id, e
+                    ))
+                })?;
+                ("inline".to_string(), plan)
+            }
+            _ => {
+                return Err(ExecutionError::ValidationError(
+                    "Spawn operation requires a 'plan' argument (inline plan or sub-plan name)"
+                        .to_string(),
+                ))
+            }
+        };
+
+        let mut child = ctx.child_context(node)?;
+        let budget = ctx.budget_slice(
+            args.and_then(|args| args.get("budget")),
+            child_plan.signals.as_ref(),
+        );
+        child.signals = Some(budget.clone());
+        let inputs: Vec<String> = child.variables.keys().cloned().collect();
+
+        let mut start_trace = crate::internal::trace::trace::Trace::new(
+            "spawn_start".to_string(),
+            node.id.clone(),
+            format!("Spawning sub-plan {} from {}", plan_name, node.id),
+        );
+        start_trace.data = Some(serde_json::json!({
+            "description": format!("Spawning sub-plan {} from {}", plan_name, node.id),
+            "plan": plan_name,
+            "inputs": inputs,
+            "budget": budget,
+        }));
+        ctx.push_trace(start_trace);
+
+        let (mut child, result) = self.execute_sub_plan(child, &child_plan).await;
+        // Nest the child's traces and tool I/O under the spawning node and
+        // charge its usage to the parent, even when it failed, like the calls
+        // it made
+        for mut exchange in std::mem::take(&mut child.tool_io) {
+            exchange.step_id = format!("{}/{}", node.id, exchange.step_id);
+            ctx.tool_io.push(exchange);
+        }
+        for mut trace in std::mem::take(&mut child.trace_events) {
+            trace.step_id = format!("{}/{}", node.id, trace.step_id);
+            ctx.push_trace(trace);
+        }
+        ctx.total_latency_ms += child.total_latency_ms;
+        ctx.total_cost_usd += child.total_cost_usd;
+        ctx.total_tokens = ctx.total_tokens.saturating_add(child.total_tokens);
+        ctx.total_fuel = ctx.total_fuel.saturating_add(child.total_fuel);
+        if let Err(e) = result {
+            let e = sub_plan_error(&node.id, e);
+            let mut end_trace = crate::internal::trace::trace::Trace::new(
+                "spawn_end".to_string(),
+                node.id.clone(),
+                format!("Sub-plan {} spawned by {} failed", plan_name, node.id),
+            );
+            end_trace.data = Some(serde_json::json!({
+                "description": format!("Sub-plan {} spawned by {} failed", plan_name, node.id),
+                "plan": plan_name,
+                "error": e.to_string(),
+                "completed_nodes": child.completed_nodes.len(),
+                "latency_ms": child.total_latency_ms,
+                "cost_usd": child.total_cost_usd,
+                "tokens": child.total_tokens,
+                "fuel": child.total_fuel,
+            }));
+            ctx.push_trace(end_trace);
+            return Err(e);
+        }
+
+        let mut outputs = serde_json::Map::new();
+        match args.and_then(|args| args.get("outputs")) {
+            Some(Value::Array(names)) => {
+                for name in names.iter().filter_map(Value::as_str) {
+                    let value = child.variables.get(name).cloned().ok_or_else(|| {
+                        ExecutionError::ValidationError(format!(
+                            "Sub-plan {} spawned by {} did not produce output '{}'",
+                            plan_name, node.id, name
+                        ))
+                    })?;
+                    outputs.insert(name.to_string(), value);
+                }
+            }
+            _ => {
+                // Without declared outputs, return everything the child produced
+                for (name, value) in child.variables {
+                    if !inputs.contains(&name) {
+                        outputs.insert(name, value);
+                    }
+                }
+            }
+        }
+
+        let mut end_trace = crate::internal::trace::trace::Trace::new(
+            "spawn_end".to_string(),
+            node.id.clone(),
+            format!("Sub-plan {} spawned by {} completed", plan_name, node.id),
+        );
+        end_trace.data = Some(serde_json::json!({
+            "description": format!("Sub-plan {} spawned by {} completed", plan_name, node.id),
+            "plan": plan_name,
+            "outputs": outputs.keys().collect::<Vec<_>>(),
+            "completed_nodes": child.completed_nodes.len(),
+            "latency_ms": child.total_latency_ms,
+            "cost_usd": child.total_cost_usd,
+            "tokens": child.total_tokens,
+            "fuel": child.total_fuel,
+            "stop_reason": child.stop_reason,
+        }));
+        ctx.push_trace(end_trace);
+
+        ctx.bind_outputs(node, &Value::Object(outputs))
+    }
+
+    /// Runs a sub-plan behind a boxed future, which breaks the async recursion
+    /// between `run` and `execute_spawn`.
+    fn execute_sub_plan<'a>(&'a self, ctx: ExecutionContext, plan: &'a Plan) -> SubPlanFuture<'a> {
+        Box::pin(self.run(ctx, plan))
     }
 
     async fn execute_mem_read(
@@ -924,27 +2305,27 @@ impl Scheduler {
         let resolution = ctx.resolve_tool(node)?;
 
         let mem_store = crate::internal::mem::store::MemoryStore::new();
-        let start = std::time::Instant::now();
-        let result = mem_store
-            .read(&resolution.tool_url, key)
-            .await
-            .map_err(|e| {
+        let request = serde_json::json!({ "operation": "read", "key": key });
+        let (entry, elapsed_ms) = ctx
+            .memory_exchange(node, &resolution.tool_name, "Memory read", request, async {
+                let entry = mem_store.read(&resolution.tool_url, key).await?;
+                Ok(serde_json::to_value(entry).unwrap_or(Value::Null))
+            })
+            .await?;
+        let result: Option<crate::internal::mem::store::MemoryEntry> =
+            serde_json::from_value(entry).map_err(|e| {
                 ExecutionError::ToolExecutionError(format!("Memory read failed: {}", e))
             })?;
-        let elapsed_ms = start.elapsed().as_secs_f64() * 1000.0;
         ctx.record_tool_usage(
             &resolution.tool_name,
             resolution.spec.as_ref(),
             elapsed_ms,
             None,
+            None,
         )?;
 
         if let Some(entry) = result {
-            if let Some(out_map) = &node.out {
-                for (var_name, _) in out_map {
-                    ctx.variables.insert(var_name.clone(), entry.value.clone());
-                }
-            }
+            ctx.bind_outputs(node, &entry.value)?;
         } else {
             // If key not found, we can either error or set to null/undefined
             if let Some(out_map) = &node.out {
@@ -1079,27 +2460,43 @@ impl Scheduler {
         }
 
         let mem_store = crate::internal::mem::store::MemoryStore::new();
-        let start = std::time::Instant::now();
-        mem_store
-            .write(
-                &resolution.tool_url,
-                key,
-                &value,
-                Some(&provenance),
-                Some(confidence),
-                ttl.as_deref(),
-                evidence_summary_json.as_ref(),
+        let request = serde_json::json!({
+            "operation": "write",
+            "key": key,
+            "value": value,
+            "provenance": provenance,
+            "confidence": confidence,
+            "ttl": ttl,
+            "evidence_summary": evidence_summary_json,
+        });
+        let (_, elapsed_ms) = ctx
+            .memory_exchange(
+                node,
+                &resolution.tool_name,
+                "Memory write",
+                request,
+                async {
+                    mem_store
+                        .write(
+                            &resolution.tool_url,
+                            key,
+                            &value,
+                            Some(&provenance),
+                            Some(confidence),
+                            ttl.as_deref(),
+                            evidence_summary_json.as_ref(),
+                        )
+                        .await?;
+                    Ok(Value::Null)
+                },
             )
-            .await
-            .map_err(|e| {
-                ExecutionError::ToolExecutionError(format!("Memory write failed: {}", e))
-            })?;
-        let elapsed_ms = start.elapsed().as_secs_f64() * 1000.0;
+            .await?;
         ctx.record_tool_usage(
             &resolution.tool_name,
             resolution.spec.as_ref(),
             elapsed_ms,
             None,
+            None,
         )?;
 
         if let Some(json) = evidence_summary_json {
@@ -1109,7 +2506,7 @@ impl Scheduler {
                 format!("Memory write evidence summary for {}", key),
             );
             trace.data = Some(json);
-            ctx.trace_events.push(trace);
+            ctx.push_trace(trace);
         }
         Ok(())
     }
@@ -1172,28 +2569,18 @@ impl Scheduler {
             node.id.clone(),
             "Verification step start".to_string(),
         );
-        ctx.trace_events.push(start_trace);
+        ctx.push_trace(start_trace);
 
         // Invoke the verification tool
-        let start = std::time::Instant::now();
-        let result = timeout(
-            Duration::from_secs(30),
-            ctx.tool_client.invoke_tool(
-                &resolution.tool_url,
-                &resolution.tool_name,
-                Some(verify_args),
-            ),
-        )
-        .await
-        .map_err(|_| ExecutionError::TimeoutError("Verification tool call timed out".to_string()))?
-        .map_err(|e| ExecutionError::ToolExecutionError(format!("Verification failed: {}", e)))?;
-        let elapsed_ms = start.elapsed().as_secs_f64() * 1000.0;
-        let usage = ctx.record_tool_usage(
-            &resolution.tool_name,
-            resolution.spec.as_ref(),
-            elapsed_ms,
-            None,
-        )?;
+        let (result, usage) = ctx
+            .invoke_with_policy(node, &resolution, Some(verify_args))
+            .await
+            .map_err(|e| match e {
+                ExecutionError::ToolExecutionError(msg) => {
+                    ExecutionError::ToolExecutionError(format!("Verification failed: {}", msg))
+                }
+                other => other,
+            })?;
 
         if let Ok(parsed_evidence) =
             serde_json::from_value::<crate::internal::evidence::verify::Evidence>(result.clone())
@@ -1215,16 +2602,12 @@ impl Scheduler {
                     format!("Verification summary for {}", node.id),
                 );
                 trace.data = summary_json;
-                ctx.trace_events.push(trace);
+                ctx.push_trace(trace);
             }
         }
 
         // Store the verification result in output variables
-        if let Some(out_map) = &node.out {
-            for (var_name, _) in out_map {
-                ctx.variables.insert(var_name.clone(), result.clone());
-            }
-        }
+        ctx.bind_outputs(node, &result)?;
 
         let mut end_trace = crate::internal::trace::trace::Trace::new(
             "step_end".to_string(),
@@ -1241,7 +2624,7 @@ impl Scheduler {
             "total_cost_usd": ctx.total_cost_usd,
             "total_tokens": ctx.total_tokens,
         }));
-        ctx.trace_events.push(end_trace);
+        ctx.push_trace(end_trace);
 
         Ok(())
     }
@@ -1251,72 +2634,18 @@ impl Scheduler {
         ctx: &mut ExecutionContext,
         node: &Node,
     ) -> Result<(), ExecutionError> {
-        // Retry operation - execute the tool with retries
+        // Retry operation - execute the tool under the node's retry policy,
+        // which defaults to RetryPolicy::default() for retry nodes
         let resolution = ctx.resolve_tool(node)?;
-        let tool_name = resolution.tool_name.clone();
-        let tool_url = resolution.tool_url.clone();
-        let spec = resolution.spec.clone();
 
         let args = ctx.resolve_args(node.args.as_ref());
-        ctx.enforce_tool_policy(&tool_name, args.as_ref())?;
+        ctx.enforce_tool_policy(&resolution.tool_name, args.as_ref())?;
 
-        // Try up to 3 times
-        let mut attempts = 0;
-        let max_attempts = 3;
+        let (result, _) = ctx.invoke_with_policy(node, &resolution, args).await?;
+        // Store the result in variables as specified by 'out' mapping
+        ctx.bind_outputs(node, &result)?;
 
-        loop {
-            let start = std::time::Instant::now();
-            let invocation = timeout(
-                Duration::from_secs(30),
-                ctx.tool_client
-                    .invoke_tool(&tool_url, &tool_name, args.clone()),
-            )
-            .await;
-            let elapsed_ms = start.elapsed().as_secs_f64() * 1000.0;
-
-            match invocation {
-                Ok(Ok(result)) => {
-                    ctx.record_tool_usage(&tool_name, spec.as_ref(), elapsed_ms, None)?;
-                    // Store the result in variables as specified by 'out' mapping
-                    if let Some(out_map) = &node.out {
-                        for (var_name, _result_path) in out_map {
-                            ctx.variables.insert(var_name.clone(), result.clone());
-                        }
-                    }
-                    return Ok(());
-                }
-                Ok(Err(e)) => {
-                    ctx.record_tool_usage(&tool_name, spec.as_ref(), elapsed_ms, None)?;
-                    attempts += 1;
-                    if attempts >= max_attempts {
-                        return Err(ExecutionError::ToolExecutionError(e.to_string()));
-                    }
-                    tracing::warn!(
-                        "Attempt {} failed for tool {}, retrying: {}",
-                        attempts,
-                        tool_name,
-                        e
-                    );
-                    tokio::time::sleep(Duration::from_millis(500)).await; // Wait before retry
-                }
-                Err(_) => {
-                    ctx.record_tool_usage(&tool_name, spec.as_ref(), elapsed_ms, None)?;
-                    attempts += 1;
-                    if attempts >= max_attempts {
-                        return Err(ExecutionError::TimeoutError(format!(
-                            "Tool call {} timed out after {} attempts",
-                            tool_name, max_attempts
-                        )));
-                    }
-                    tracing::warn!(
-                        "Attempt {} timed out for tool {}, retrying",
-                        attempts,
-                        tool_name
-                    );
-                    tokio::time::sleep(Duration::from_millis(500)).await; // Wait before retry
-                }
-            }
-        }
+        Ok(())
     }
 
     fn optimized_node_order<'a>(ctx: &mut ExecutionContext, plan: &'a Plan) -> Vec<&'a Node> {
@@ -1367,7 +2696,7 @@ impl Scheduler {
             "Plan optimizer determined execution order".to_string(),
         );
         trace.data = Some(optimisation_data);
-        ctx.trace_events.push(trace);
+        ctx.push_trace(trace);
 
         ordered_nodes
     }
@@ -1428,6 +2757,77 @@ impl Scheduler {
     }
 }
 
+/// Attributes an error raised inside a sub-plan to the spawn node that ran it.
+fn sub_plan_error(node_id: &str, error: ExecutionError) -> ExecutionError {
+    let context = |message: String| format!("sub-plan spawned by {}: {}", node_id, message);
+    match error {
+        ExecutionError::ValidationError(m) => ExecutionError::ValidationError(context(m)),
+        ExecutionError::ToolExecutionError(m) => ExecutionError::ToolExecutionError(context(m)),
+        ExecutionError::TimeoutError(m) => ExecutionError::TimeoutError(context(m)),
+        ExecutionError::BudgetExceeded(m) => ExecutionError::BudgetExceeded(context(m)),
+        ExecutionError::CheckpointError(m) => ExecutionError::CheckpointError(context(m)),
+        ExecutionError::ReplayDivergence(m) => ExecutionError::ReplayDivergence(context(m)),
+        ExecutionError::Cancelled(m) => ExecutionError::Cancelled(context(m)),
+    }
+}
+
+enum NodeReadiness {
+    Ready,
+    Waiting,
+    Skipped(Vec<String>),
+}
+
+/// Resolves an `out` path such as `result.items[0].id` against a node result.
+pub fn extract_result_path(result: &Value, path: &str) -> Option<Value> {
+    let rest = path.trim().strip_prefix("result")?;
+    if !(rest.is_empty() || rest.starts_with('.') || rest.starts_with('[')) {
+        return None;
+    }
+    lookup_path(result, rest)
+}
+
+/// Walks `.key`, `[index]` and `[*]` segments starting at `value`. A `[*]`
+/// segment applies the rest of the path to every element of an array.
+pub fn lookup_path(value: &Value, path: &str) -> Option<Value> {
+    let mut current = value;
+    let mut rest = path;
+
+    while let Some(c) = rest.chars().next() {
+        match c {
+            '.' => {
+                rest = &rest[1..];
+                let end = rest.find(['.', '[']).unwrap_or(rest.len());
+                let key = &rest[..end];
+                if key.is_empty() {
+                    return None;
+                }
+                current = current.as_object()?.get(key)?;
+                rest = &rest[end..];
+            }
+            '[' => {
+                let close = rest.find(']')?;
+                let index = &rest[1..close];
+                rest = &rest[close + 1..];
+
+                if index == "*" {
+                    return current
+                        .as_array()?
+                        .iter()
+                        .map(|item| lookup_path(item, rest))
+                        .collect::<Option<Vec<_>>>()
+                        .map(Value::Array);
+                }
+
+                let index: usize = index.parse().ok()?;
+                current = current.as_array()?.get(index)?;
+            }
+            _ => return None,
+        }
+    }
+
+    Some(current.clone())
+}
+
 fn cmp_f64(a: f64, b: f64) -> Ordering {
     a.partial_cmp(&b).unwrap_or(Ordering::Equal)
 }
@@ -1,5 +1,7 @@
+use crate::internal::tools::spec::{pointer_join, SchemaViolation, ToolSpec};
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
+use std::fmt;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Plan {
@@ -7,12 +9,19 @@ pub struct Plan {
     pub nodes: Vec<Node>,
     pub edges: Option<Vec<Edge>>,
     pub stop_conditions: Option<StopConditions>,
+    /// Named results of the plan, each a `$variable.path` reference into the
+    /// final variables. API callers receive these instead of the variables.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub outputs: Option<HashMap<String, String>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Signals {
     pub latency_budget_ms: Option<u64>,
     pub cost_cap_usd: Option<f64>,
+    /// WebAssembly fuel the plan's tools may burn in total.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fuel_cap: Option<u64>,
     pub risk: Option<f64>,
 }
 
@@ -25,6 +34,14 @@ pub struct Node {
     pub args: Option<HashMap<String, serde_json::Value>>,
     pub bind: Option<HashMap<String, String>>,
     pub out: Option<HashMap<String, String>>,
+    /// Per-attempt timeout for tool-backed operations. Defaults to
+    /// [`DEFAULT_TIMEOUT_MS`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_ms: Option<u64>,
+    /// How failed tool calls are retried. Nodes without a policy make a
+    /// single attempt, except `retry` nodes which use [`RetryPolicy::default`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry: Option<RetryPolicy>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -51,6 +68,117 @@ pub enum Operation {
     Retry,
 }
 
+impl Node {
+    /// Downstream node ids listed under a `branch` arm (`"then"` or `"else"`).
+    /// An arm may be written as a single id or an array of ids.
+    pub fn branch_targets(&self, arm: &str) -> Vec<String> {
+        match self.args.as_ref().and_then(|args| args.get(arm)) {
+            Some(serde_json::Value::String(target)) => vec![target.clone()],
+            Some(serde_json::Value::Array(targets)) => targets
+                .iter()
+                .filter_map(|t| t.as_str().map(|s| s.to_string()))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+pub const DEFAULT_TIMEOUT_MS: u64 = 30_000;
+
+/// Kinds of tool failure a [`RetryPolicy`] can retry.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    /// The attempt exceeded the node's `timeout_ms`
+    Timeout,
+    /// The tool could not be reached or returned a malformed response
+    Communication,
+    /// The tool reported an error
+    Invocation,
+    /// Arguments or result did not match the tool's schema
+    Validation,
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ErrorKind::Timeout => "timeout",
+            ErrorKind::Communication => "communication",
+            ErrorKind::Invocation => "invocation",
+            ErrorKind::Validation => "validation",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Retry policy for tool-backed nodes. The delay before attempt `n + 1` is
+/// `initial_backoff_ms * multiplier^(n - 1)`, capped at `max_backoff_ms` and
+/// then spread by up to `jitter` (a fraction of the delay) in either direction.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub initial_backoff_ms: u64,
+    pub multiplier: f64,
+    pub max_backoff_ms: u64,
+    pub jitter: f64,
+    pub retry_on: Vec<ErrorKind>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff_ms: 500,
+            multiplier: 2.0,
+            max_backoff_ms: 10_000,
+            jitter: 0.2,
+            retry_on: vec![
+                ErrorKind::Timeout,
+                ErrorKind::Communication,
+                ErrorKind::Invocation,
+            ],
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy that makes exactly one attempt.
+    pub fn single_attempt() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Self::default()
+        }
+    }
+
+    pub fn is_retryable(&self, kind: ErrorKind) -> bool {
+        self.retry_on.contains(&kind)
+    }
+
+    /// Delay in milliseconds after the given (1-based) failed attempt.
+    /// `sample` is a uniform random number in `[0, 1)` used for jitter.
+    pub fn backoff_ms(&self, attempt: u32, sample: f64) -> u64 {
+        let exponent = attempt.saturating_sub(1).min(63) as i32;
+        let base = (self.initial_backoff_ms as f64 * self.multiplier.powi(exponent))
+            .min(self.max_backoff_ms as f64);
+        let spread = base * self.jitter * (2.0 * sample - 1.0);
+        (base + spread).max(0.0).round() as u64
+    }
+
+    fn check(&self) -> Result<(), String> {
+        if self.max_attempts == 0 {
+            return Err("max_attempts must be at least 1".to_string());
+        }
+        if !self.multiplier.is_finite() || self.multiplier < 1.0 {
+            return Err("multiplier must be at least 1.0".to_string());
+        }
+        if !(0.0..=1.0).contains(&self.jitter) {
+            return Err("jitter must be between 0.0 and 1.0".to_string());
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Edge {
     pub from: String,
@@ -97,10 +225,129 @@ impl Plan {
             }
         }
 
+        if let Some(edge) = self
+            .edges
+            .iter()
+            .flatten()
+            .find(|edge| edge.from == edge.to)
+        {
+            return Err(PlanValidationError::SelfEdge(edge.from.clone()));
+        }
+
+        self.check_acyclic()?;
+
+        for node in &self.nodes {
+            if node.timeout_ms == Some(0) {
+                return Err(PlanValidationError::InvalidRetryPolicy(format!(
+                    "node {}: timeout_ms must be greater than 0",
+                    node.id
+                )));
+            }
+            if let Some(policy) = &node.retry {
+                policy.check().map_err(|message| {
+                    PlanValidationError::InvalidRetryPolicy(format!(
+                        "node {}: {}",
+                        node.id, message
+                    ))
+                })?;
+            }
+        }
+
+        // Branch arms must point at direct successors of the branch node
+        for node in self.nodes.iter().filter(|n| n.op == Operation::Branch) {
+            let successors: HashSet<&str> = self
+                .edges
+                .iter()
+                .flatten()
+                .filter(|edge| edge.from == node.id)
+                .map(|edge| edge.to.as_str())
+                .collect();
+
+            for arm in ["then", "else"] {
+                for target in node.branch_targets(arm) {
+                    if !successors.contains(target.as_str()) {
+                        return Err(PlanValidationError::InvalidBranch(format!(
+                            "Branch {} lists '{}' under '{}' but has no edge to it",
+                            node.id, target, arm
+                        )));
+                    }
+                }
+            }
+        }
+
+        for (name, reference) in self.outputs.iter().flatten() {
+            let root = reference
+                .strip_prefix('$')
+                .map(|path| &path[..path.find(['.', '[']).unwrap_or(path.len())]);
+            if root.is_none_or(str::is_empty) {
+                return Err(PlanValidationError::InvalidOutput(format!(
+                    "{}: '{}' is not a $variable reference",
+                    name, reference
+                )));
+            }
+        }
+
+        // Inline sub-plans must themselves be valid
+        for node in self.nodes.iter().filter(|n| n.op == Operation::Spawn) {
+            match node.args.as_ref().and_then(|args| args.get("plan")) {
+                Some(inline @ serde_json::Value::Object(_)) => {
+                    let sub_plan: Plan = serde_json::from_value(inline.clone()).map_err(|e| {
+                        PlanValidationError::InvalidSubPlan(format!("{}: {}", node.id, e))
+                    })?;
+                    sub_plan.validate().map_err(|e| {
+                        PlanValidationError::InvalidSubPlan(format!("{}: {}", node.id, e))
+                    })?;
+                }
+                Some(serde_json::Value::String(_)) => {}
+                _ => {
+                    return Err(PlanValidationError::InvalidSubPlan(format!(
+                        "{}: spawn requires a 'plan' argument",
+                        node.id
+                    )))
+                }
+            }
+        }
+
         Ok(())
     }
 
+    /// Names of the registered sub-plans that spawn nodes reference, including
+    /// those referenced from inline sub-plans.
+    pub fn sub_plan_names(&self) -> HashSet<String> {
+        let mut names = HashSet::new();
+        for node in self.nodes.iter().filter(|n| n.op == Operation::Spawn) {
+            match node.args.as_ref().and_then(|args| args.get("plan")) {
+                Some(serde_json::Value::String(name)) => {
+                    names.insert(name.clone());
+                }
+                Some(inline @ serde_json::Value::Object(_)) => {
+                    if let Ok(sub_plan) = serde_json::from_value::<Plan>(inline.clone()) {
+                        names.extend(sub_plan.sub_plan_names());
+                    }
+                }
+                _ => {}
+            }
+        }
+        names
+    }
+
     pub fn validate_with_tools<I, T>(&self, tools: I) -> Result<(), PlanValidationError>
+    where
+        I: IntoIterator<Item = T>,
+        T: AsRef<str>,
+    {
+        self.validate_with_tool_specs(tools, &HashMap::new())
+    }
+
+    /// Like [`Plan::validate_with_tools`], and additionally checks literal
+    /// arguments of `call` and `retry` nodes against the `io.input` schema of
+    /// their tool when a spec is available. Arguments holding `$references`
+    /// are only checked for presence, since their values are known at runtime.
+    pub fn validate_with_tool_specs<I, T>(
+        &self,
+        tools: I,
+        specs: &HashMap<String, ToolSpec>,
+    ) -> Result<(), PlanValidationError>
     where
         I: IntoIterator<Item = T>,
         T: AsRef<str>,
@@ -133,6 +380,27 @@ impl Plan {
                 }
             }
 
+            if let Some(spec) = node
+                .tool
+                .as_ref()
+                .filter(|_| matches!(node.op, Operation::Call | Operation::Retry))
+                .and_then(|tool_name| specs.get(tool_name))
+            {
+                let violations = literal_arg_violations(spec, node.args.as_ref());
+                if !violations.is_empty() {
+                    return Err(PlanValidationError::InvalidArgs(format!(
+                        "node {} (tool {}): {}",
+                        node.id,
+                        spec.name,
+                        violations
+                            .iter()
+                            .map(|v| v.to_string())
+                            .collect::<Vec<_>>()
+                            .join("; ")
+                    )));
+                }
+            }
+
             if Self::operation_requires_output(&node.op) {
                 match &node.out {
                     Some(out_map) if !out_map.is_empty() => {
@@ -150,6 +418,108 @@ impl Plan {
         Ok(())
     }
 
+    /// Places the nodes in dependency order with Kahn's algorithm. Returns
+    /// [`PlanValidationError::Cycle`] with one cycle and the nodes that
+    /// depend on it when some nodes cannot be placed.
+    fn check_acyclic(&self) -> Result<(), PlanValidationError> {
+        let mut in_degree: HashMap<&str, usize> = self
+            .nodes
+            .iter()
+            .map(|node| (node.id.as_str(), 0))
+            .collect();
+        let mut successors: HashMap<&str, Vec<&str>> = HashMap::new();
+        for edge in self.edges.iter().flatten() {
+            if let Some(degree) = in_degree.get_mut(edge.to.as_str()) {
+                *degree += 1;
+            }
+            successors
+                .entry(edge.from.as_str())
+                .or_default()
+                .push(edge.to.as_str());
+        }
+
+        let mut ready: Vec<&str> = self
+            .nodes
+            .iter()
+            .map(|node| node.id.as_str())
+            .filter(|id| in_degree[id] == 0)
+            .collect();
+        let mut placed = 0;
+
+        while let Some(id) = ready.pop() {
+            placed += 1;
+            for successor in successors.get(id).into_iter().flatten() {
+                if let Some(degree) = in_degree.get_mut(successor) {
+                    *degree -= 1;
+                    if *degree == 0 {
+                        ready.push(successor);
+                    }
+                }
+            }
+        }
+
+        if placed < self.nodes.len() {
+            let blocked: Vec<&str> = self
+                .nodes
+                .iter()
+                .map(|node| node.id.as_str())
+                .filter(|id| in_degree[id] > 0)
+                .collect();
+            let cycle = Self::find_cycle(&blocked, &successors);
+            let unreachable = blocked
+                .iter()
+                .filter(|id| !cycle.iter().any(|member| member == *id))
+                .map(|id| id.to_string())
+                .collect();
+            return Err(PlanValidationError::Cycle { cycle, unreachable });
+        }
+
+        Ok(())
+    }
+
+    /// Finds one cycle among `blocked`, the nodes Kahn's algorithm could not
+    /// place. The returned path starts and ends with the same node id.
+    fn find_cycle(blocked: &[&str], successors: &HashMap<&str, Vec<&str>>) -> Vec<String> {
+        let blocked_set: HashSet<&str> = blocked.iter().copied().collect();
+        let mut finished: HashSet<&str> = HashSet::new();
+
+        for &start in blocked {
+            if finished.contains(start) {
+                continue;
+            }
+
+            // Iterative DFS keeping the current path so a back edge yields the cycle
+            let mut path: Vec<(&str, usize)> = vec![(start, 0)];
+            while let Some((id, next_index)) = path.last().copied() {
+                let next = successors
+                    .get(id)
+                    .and_then(|targets| targets.get(next_index))
+                    .copied();
+                match next {
+                    Some(target) => {
+                        path.last_mut().unwrap().1 += 1;
+                        if !blocked_set.contains(target) || finished.contains(target) {
+                            continue;
+                        }
+                        if let Some(pos) = path.iter().position(|(on_path, _)| *on_path == target) {
+                            let mut cycle: Vec<String> =
+                                path[pos..].iter().map(|(id, _)| id.to_string()).collect();
+                            cycle.push(target.to_string());
+                            return cycle;
+                        }
+                        path.push((target, 0));
+                    }
+                    None => {
+                        finished.insert(id);
+                        path.pop();
+                    }
+                }
+            }
+        }
+
+        Vec::new()
+    }
+
     fn operation_requires_tool(op: &Operation) -> bool {
         matches!(
             op,
@@ -190,4 +560,73 @@ pub enum PlanValidationError {
     MissingOutputBinding(String),
     #[error("Node {0} requires either a tool or capability")]
     MissingToolOrCapability(String),
+    #[error("Invalid arguments: {0}")]
+    InvalidArgs(String),
+    #[error("Node {0} has an edge to itself")]
+    SelfEdge(String),
+    #[error("{}", format_cycle(.cycle, .unreachable))]
+    Cycle {
+        cycle: Vec<String>,
+        unreachable: Vec<String>,
+    },
+    #[error("Invalid branch: {0}")]
+    InvalidBranch(String),
+    #[error("Invalid sub-plan: {0}")]
+    InvalidSubPlan(String),
+    #[error("Invalid retry policy: {0}")]
+    InvalidRetryPolicy(String),
+    #[error("Invalid output: {0}")]
+    InvalidOutput(String),
+}
+
+/// Checks the statically known part of a node's arguments against a tool's
+/// input schema.
+fn literal_arg_violations(
+    spec: &ToolSpec,
+    args: Option<&HashMap<String, serde_json::Value>>,
+) -> Vec<SchemaViolation> {
+    let schema = &spec.io.input;
+    if !schema.schema_type.is_empty() && schema.schema_type != "object" {
+        return Vec::new();
+    }
+
+    let empty = HashMap::new();
+    let args = args.unwrap_or(&empty);
+    let mut violations = Vec::new();
+
+    for name in schema.required.iter().flatten() {
+        if !args.contains_key(name) {
+            violations.push(SchemaViolation {
+                pointer: pointer_join("", name),
+                message: "required property is missing".to_string(),
+            });
+        }
+    }
+
+    let mut properties: Vec<_> = schema.properties.iter().flatten().collect();
+    properties.sort_by(|a, b| a.0.cmp(b.0));
+    for (name, property) in properties {
+        if let Some(value) = args.get(name).filter(|value| !contains_reference(value)) {
+            property.collect_violations(value, &pointer_join("", name), &mut violations);
+        }
+    }
+
+    violations
+}
+
+fn contains_reference(value: &serde_json::Value) -> bool {
+    match value {
+        serde_json::Value::String(s) => s.starts_with('$'),
+        serde_json::Value::Array(items) => items.iter().any(contains_reference),
+        serde_json::Value::Object(map) => map.values().any(contains_reference),
+        _ => false,
+    }
+}
+
+fn format_cycle(cycle: &[String], unreachable: &[String]) -> String {
+    let mut message = format!("Cycle detected: {}", cycle.join(" -> "));
+    if !unreachable.is_empty() {
+        message.push_str(&format!("; unreachable nodes: {}", unreachable.join(", ")));
+    }
+    message
 }
@@ -1,3 +1,4 @@
+use crate::internal::tools::auth::ToolAuth;
 use serde::{Deserialize, Serialize};
 use std::{collections::HashMap, env, fs, path::Path, sync::Arc};
 use tokio::sync::RwLock;
@@ -13,6 +14,8 @@ const DEFAULT_ENTRIES: &[(&str, &str)] = &[
 struct ToolEntry {
     name: String,
     url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    auth: Option<ToolAuth>,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -23,6 +26,10 @@ pub enum RegistryError {
     Json(#[from] serde_json::Error),
     #[error("HTTP error accessing tool registry: {0}")]
     Http(String),
+    #[error("Invalid tool credentials: {0}")]
+    Auth(String),
+    #[error("Untrusted tool URL: {0}")]
+    Untrusted(String),
 }
 
 pub fn load_tool_registry() -> HashMap<String, String> {
@@ -33,10 +40,46 @@ pub fn load_tool_registry() -> HashMap<String, String> {
     }
 }
 
-fn read_registry(path: &Path) -> Result<HashMap<String, String>, RegistryError> {
-    let contents = fs::read_to_string(path)?;
-    let entries: Vec<ToolEntry> = serde_json::from_str(&contents)?;
+/// The credentials of the entries in the tool registry file that have
+/// `auth`, by tool name. Remote registries carry no credentials.
+///
+/// Credentials are sent to a URL rather than a tool, so entries sharing a
+/// URL must all carry the same `auth`; otherwise one tool would send
+/// another's secrets.
+pub fn load_tool_auth() -> Result<HashMap<String, ToolAuth>, RegistryError> {
+    let path = env::var("AMP_TOOL_CONFIG").unwrap_or_else(|_| DEFAULT_CONFIG_PATH.to_string());
+    // Like the registry itself, an unreadable file means the defaults
+    let entries = read_entries(Path::new(&path)).unwrap_or_default();
+
+    let mut by_url: HashMap<&str, &ToolEntry> = HashMap::new();
+    for entry in &entries {
+        match by_url.get(entry.url.as_str()) {
+            Some(other) if other.auth != entry.auth => {
+                return Err(RegistryError::Auth(format!(
+                    "{} and {} share {} but not their auth",
+                    other.name, entry.name, entry.url
+                )))
+            }
+            Some(_) => {}
+            None => {
+                by_url.insert(&entry.url, entry);
+            }
+        }
+    }
+
     Ok(entries
+        .into_iter()
+        .filter_map(|entry| Some((entry.name, entry.auth?)))
+        .collect())
+}
+
+fn read_entries(path: &Path) -> Result<Vec<ToolEntry>, RegistryError> {
+    let contents = fs::read_to_string(path)?;
+    Ok(serde_json::from_str(&contents)?)
+}
+
+fn read_registry(path: &Path) -> Result<HashMap<String, String>, RegistryError> {
+    Ok(read_entries(path)?
         .into_iter()
         .map(|entry| (entry.name, entry.url))
         .collect())
@@ -49,6 +92,20 @@ pub fn default_registry() -> HashMap<String, String> {
         .collect()
 }
 
+/// Refuses a tool URL that does not come from the local tool config file
+/// unless it is `http://` or `https://`. Other transports spawn commands,
+/// open files or reach local sockets on the kernel host, so only the
+/// kernel's own configuration may name them.
+pub fn check_remote_url(name: &str, url: &str) -> Result<(), RegistryError> {
+    if url.starts_with("http://") || url.starts_with("https://") {
+        return Ok(());
+    }
+    Err(RegistryError::Untrusted(format!(
+        "{} at {}: only http:// and https:// tools can be registered remotely",
+        name, url
+    )))
+}
+
 pub async fn fetch_remote_registry(
     base_url: &str,
 ) -> Result<HashMap<String, String>, RegistryError> {
@@ -71,10 +128,13 @@ pub async fn fetch_remote_registry(
         .await
         .map_err(|e| RegistryError::Http(e.to_string()))?;
 
-    Ok(entries
+    entries
         .into_iter()
-        .map(|entry| (entry.name, entry.url))
-        .collect())
+        .map(|entry| {
+            check_remote_url(&entry.name, &entry.url)?;
+            Ok((entry.name, entry.url))
+        })
+        .collect()
 }
 
 #[derive(Clone, Default)]
@@ -93,8 +153,12 @@ impl RegistryState {
         self.inner.read().await.clone()
     }
 
-    pub async fn register(&self, name: String, url: String) {
+    /// Adds or replaces a tool. Only `http://` and `https://` tools can be
+    /// registered, see [`check_remote_url`].
+    pub async fn register(&self, name: String, url: String) -> Result<(), RegistryError> {
+        check_remote_url(&name, &url)?;
         self.inner.write().await.insert(name, url);
+        Ok(())
     }
 
     pub async fn unregister(&self, name: &str) {
@@ -116,6 +180,7 @@ pub struct RegisterResponse {
 pub fn create_registry_router(state: RegistryState) -> axum::Router {
     use axum::{
         extract::{Path, State},
+        http::StatusCode,
         routing::{delete, get, post},
         Json, Router,
     };
@@ -124,7 +189,11 @@ pub fn create_registry_router(state: RegistryState) -> axum::Router {
         let registry = state.list().await;
         let entries = registry
             .into_iter()
-            .map(|(name, url)| ToolEntry { name, url })
+            .map(|(name, url)| ToolEntry {
+                name,
+                url,
+                auth: None,
+            })
             .collect();
         Json(entries)
     }
@@ -132,9 +201,17 @@ pub fn create_registry_router(state: RegistryState) -> axum::Router {
     async fn register(
         State(state): State<RegistryState>,
         Json(payload): Json<RegisterRequest>,
-    ) -> Json<RegisterResponse> {
-        state.register(payload.name, payload.url).await;
-        Json(RegisterResponse { success: true })
+    ) -> Result<Json<RegisterResponse>, (StatusCode, Json<serde_json::Value>)> {
+        state
+            .register(payload.name, payload.url)
+            .await
+            .map_err(|e| {
+                (
+                    StatusCode::BAD_REQUEST,
+                    Json(serde_json::json!({ "error": e.to_string() })),
+                )
+            })?;
+        Ok(Json(RegisterResponse { success: true }))
     }
 
     async fn unregister(
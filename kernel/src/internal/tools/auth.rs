@@ -0,0 +1,171 @@
+use crate::internal::tools::spec::ToolError;
+use base64::Engine as _;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::PathBuf;
+
+/// Replaces credentials in error messages.
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Credentials sent with every request to a tool's URL, set by the `auth`
+/// of its registry entry. Secrets are read from environment variables, so
+/// the registry file itself holds none, except for static header values.
+///
+/// ```json
+/// {
+///   "bearer_env": "SEARCH_TOKEN",
+///   "headers": { "X-Tenant": "acme" },
+///   "basic": { "username": "amp", "password_env": "SEARCH_PASSWORD" },
+///   "tls": { "cert": "certs/amp.pem", "key": "certs/amp.key", "ca": "certs/ca.pem" }
+/// }
+/// ```
+#[derive(Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct ToolAuth {
+    /// Environment variable holding a bearer token.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bearer_env: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub basic: Option<BasicAuth>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls: Option<ClientTls>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BasicAuth {
+    pub username: String,
+    /// Environment variable holding the password.
+    pub password_env: String,
+}
+
+/// A client certificate for mutual TLS, as PEM files. `key` is a PKCS#8
+/// private key; `ca` adds a root certificate for the tool's server.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ClientTls {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ca: Option<PathBuf>,
+}
+
+// Header values are shown by name only
+impl fmt::Debug for ToolAuth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let headers: BTreeMap<&str, &str> = self
+            .headers
+            .keys()
+            .map(|name| (name.as_str(), REDACTED))
+            .collect();
+        f.debug_struct("ToolAuth")
+            .field("bearer_env", &self.bearer_env)
+            .field("headers", &headers)
+            .field("basic", &self.basic)
+            .field("tls", &self.tls)
+            .finish()
+    }
+}
+
+fn env_secret(name: &str) -> Result<String, ToolError> {
+    std::env::var(name).map_err(|_| {
+        ToolError::Communication(format!(
+            "Tool credentials: environment variable {} is not set",
+            name
+        ))
+    })
+}
+
+fn read_pem(path: &PathBuf) -> Result<Vec<u8>, ToolError> {
+    std::fs::read(path).map_err(|e| {
+        ToolError::Communication(format!(
+            "Tool credentials: failed to read {}: {}",
+            path.display(),
+            e
+        ))
+    })
+}
+
+impl ToolAuth {
+    /// An HTTP client sending these credentials with every request.
+    pub fn client(&self) -> Result<reqwest::Client, ToolError> {
+        let invalid =
+            |what: &str| ToolError::Communication(format!("Tool credentials: invalid {}", what));
+        let mut headers = HeaderMap::new();
+        for (name, value) in &self.headers {
+            let name = HeaderName::from_bytes(name.as_bytes())
+                .map_err(|_| invalid(&format!("header name {}", name)))?;
+            let mut value = HeaderValue::from_str(value)
+                .map_err(|_| invalid(&format!("value for {}", name)))?;
+            value.set_sensitive(true);
+            headers.insert(name, value);
+        }
+        let authorization = match (&self.bearer_env, &self.basic) {
+            (Some(_), Some(_)) => return Err(invalid("auth: both bearer_env and basic are set")),
+            (Some(token_env), None) => Some(format!("Bearer {}", env_secret(token_env)?)),
+            (None, Some(basic)) => Some(format!(
+                "Basic {}",
+                base64::engine::general_purpose::STANDARD.encode(format!(
+                    "{}:{}",
+                    basic.username,
+                    env_secret(&basic.password_env)?
+                ))
+            )),
+            (None, None) => None,
+        };
+        if let Some(authorization) = authorization {
+            let mut value = HeaderValue::from_str(&authorization).map_err(|_| invalid("token"))?;
+            value.set_sensitive(true);
+            headers.insert(AUTHORIZATION, value);
+        }
+
+        let mut builder = reqwest::Client::builder().default_headers(headers);
+        if let Some(tls) = &self.tls {
+            let identity =
+                reqwest::Identity::from_pkcs8_pem(&read_pem(&tls.cert)?, &read_pem(&tls.key)?)
+                    .map_err(|e| invalid(&format!("client certificate: {}", e)))?;
+            builder = builder.identity(identity);
+            if let Some(ca) = &tls.ca {
+                let ca = reqwest::Certificate::from_pem(&read_pem(ca)?)
+                    .map_err(|e| invalid(&format!("CA certificate: {}", e)))?;
+                builder = builder.add_root_certificate(ca);
+            }
+        }
+        builder
+            .build()
+            .map_err(|e| ToolError::Communication(e.to_string()))
+    }
+
+    /// The secret values these credentials send, longest first, for
+    /// redaction. Variables that are not set contribute nothing.
+    pub fn secrets(&self) -> Vec<String> {
+        let mut secrets: Vec<String> = self.headers.values().cloned().collect();
+        if let Some(token) = self
+            .bearer_env
+            .as_ref()
+            .and_then(|name| std::env::var(name).ok())
+        {
+            secrets.push(token);
+        }
+        if let Some(basic) = &self.basic {
+            if let Ok(password) = std::env::var(&basic.password_env) {
+                secrets.push(
+                    base64::engine::general_purpose::STANDARD
+                        .encode(format!("{}:{}", basic.username, password)),
+                );
+                secrets.push(password);
+            }
+        }
+        secrets.retain(|secret| !secret.is_empty());
+        secrets.sort_by_key(|secret| std::cmp::Reverse(secret.len()));
+        secrets
+    }
+}
+
+/// `message` with every one of `secrets` replaced by [`REDACTED`].
+pub fn redact(message: &str, secrets: &[String]) -> String {
+    secrets.iter().fold(message.to_string(), |message, secret| {
+        message.replace(secret.as_str(), REDACTED)
+    })
+}
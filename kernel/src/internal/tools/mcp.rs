@@ -0,0 +1,523 @@
+use crate::internal::tools::spec::{Constraints, IoSpec, Schema, ToolError, ToolSpec};
+use crate::internal::tools::transport::{split_command, LineProcess, ToolTransport};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// The MCP revision the client asks for when it initializes a session.
+pub const MCP_PROTOCOL_VERSION: &str = "2025-03-26";
+
+#[derive(Debug, Serialize)]
+struct RpcRequest<'a> {
+    jsonrpc: &'static str,
+    id: u64,
+    method: &'a str,
+    params: Value,
+}
+
+#[derive(Debug, Serialize)]
+struct RpcNotification<'a> {
+    jsonrpc: &'static str,
+    method: &'a str,
+}
+
+#[derive(Debug, Deserialize)]
+struct RpcResponse {
+    result: Option<Value>,
+    error: Option<RpcError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcResponse {
+    fn into_result(self, method: &str) -> Result<Value, ToolError> {
+        match (self.result, self.error) {
+            (_, Some(error)) => Err(ToolError::Invocation(format!(
+                "{} failed: {} ({})",
+                method, error.message, error.code
+            ))),
+            (Some(result), None) => Ok(result),
+            (None, None) => Err(ToolError::Communication(format!(
+                "{} returned neither a result nor an error",
+                method
+            ))),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ListToolsResult {
+    tools: Vec<McpTool>,
+    next_cursor: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct McpTool {
+    name: String,
+    description: Option<String>,
+    #[serde(default)]
+    input_schema: Value,
+    output_schema: Option<Value>,
+    annotations: Option<McpAnnotations>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct McpAnnotations {
+    read_only_hint: Option<bool>,
+    destructive_hint: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CallToolResult {
+    #[serde(default)]
+    content: Vec<Value>,
+    structured_content: Option<Value>,
+    #[serde(default)]
+    is_error: bool,
+}
+
+impl McpTool {
+    fn to_spec(&self) -> ToolSpec {
+        // Read-only tools are safe to repeat; destructive ones are journaled
+        let side_effects =
+            self.annotations
+                .as_ref()
+                .and_then(|a| match (a.read_only_hint, a.destructive_hint) {
+                    (Some(true), _) => Some(false),
+                    (_, Some(true)) => Some(true),
+                    _ => None,
+                });
+        ToolSpec {
+            name: self.name.clone(),
+            description: self.description.clone(),
+            io: IoSpec {
+                input: schema_from_json(&self.input_schema),
+                output: self
+                    .output_schema
+                    .as_ref()
+                    .map(schema_from_json)
+                    .unwrap_or_else(|| schema_from_json(&Value::Null)),
+            },
+            capabilities: None,
+            constraints: side_effects.map(|side_effects| Constraints {
+                input_tokens_max: None,
+                latency_p50_ms: None,
+                cost_per_call_usd: None,
+                rate_limit_qps: None,
+                side_effects: Some(side_effects),
+            }),
+            provenance: None,
+            quality: None,
+            policy: None,
+        }
+    }
+}
+
+/// Maps a JSON Schema onto the subset a [`Schema`] checks. A `type` given as
+/// a list of types, or missing, accepts any value.
+pub fn schema_from_json(schema: &Value) -> Schema {
+    let properties = schema["properties"].as_object().map(|properties| {
+        properties
+            .iter()
+            .map(|(name, property)| (name.clone(), Box::new(schema_from_json(property))))
+            .collect()
+    });
+    let required = schema["required"].as_array().map(|required| {
+        required
+            .iter()
+            .filter_map(|name| name.as_str().map(str::to_string))
+            .collect()
+    });
+    Schema {
+        schema_type: schema["type"].as_str().unwrap_or_default().to_string(),
+        properties,
+        required,
+        items: schema
+            .get("items")
+            .filter(|items| items.is_object())
+            .map(|items| Box::new(schema_from_json(items))),
+    }
+}
+
+/// The value a plan node binds from a tool call: the structured content when
+/// the tool returns some, otherwise its content blocks with their text joined.
+fn call_result_value(result: CallToolResult) -> Result<Value, ToolError> {
+    let text = result
+        .content
+        .iter()
+        .filter(|block| block["type"] == "text")
+        .filter_map(|block| block["text"].as_str())
+        .collect::<Vec<_>>()
+        .join("\n");
+    if result.is_error {
+        return Err(ToolError::Invocation(if text.is_empty() {
+            "MCP tool reported an error".to_string()
+        } else {
+            text
+        }));
+    }
+    Ok(match result.structured_content {
+        Some(structured) => structured,
+        None => json!({ "content": result.content, "text": text }),
+    })
+}
+
+/// An initialized session with an MCP server.
+enum McpSession {
+    Stdio(Arc<LineProcess>),
+    Http {
+        client: reqwest::Client,
+        url: String,
+        session_id: Option<String>,
+        protocol_version: String,
+    },
+}
+
+enum McpEndpoint {
+    Stdio {
+        program: String,
+        args: Vec<String>,
+    },
+    Http {
+        client: reqwest::Client,
+        url: String,
+    },
+}
+
+/// A Model Context Protocol server reached over stdio (`mcp+stdio://command
+/// args`) or streamable HTTP (`mcp+http://host/mcp`, `mcp+https://...`).
+/// The session is initialized on first use; the server's tools are listed
+/// once and their input schemas become the specs' `io.input`.
+pub struct McpClient {
+    endpoint: McpEndpoint,
+    session: tokio::sync::Mutex<Option<Arc<McpSession>>>,
+    tools: tokio::sync::Mutex<Option<HashMap<String, ToolSpec>>>,
+    next_id: AtomicU64,
+}
+
+impl std::fmt::Debug for McpClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let endpoint = match &self.endpoint {
+            McpEndpoint::Stdio { program, .. } => program,
+            McpEndpoint::Http { url, .. } => url,
+        };
+        f.debug_struct("McpClient")
+            .field("endpoint", endpoint)
+            .finish()
+    }
+}
+
+impl McpClient {
+    /// A client for `url` without its `mcp+` prefix: `stdio://command args`,
+    /// `http://...` or `https://...`.
+    pub fn from_url(url: &str, client: reqwest::Client) -> Result<Self, ToolError> {
+        let endpoint = if let Some(command) = url.strip_prefix("stdio://") {
+            let (program, args) = split_command(command)?;
+            McpEndpoint::Stdio { program, args }
+        } else if url.starts_with("http://") || url.starts_with("https://") {
+            McpEndpoint::Http {
+                client,
+                url: url.to_string(),
+            }
+        } else {
+            return Err(ToolError::Communication(format!(
+                "Unsupported MCP URL: mcp+{}",
+                url
+            )));
+        };
+        Ok(Self {
+            endpoint,
+            session: tokio::sync::Mutex::new(None),
+            tools: tokio::sync::Mutex::new(None),
+            next_id: AtomicU64::new(1),
+        })
+    }
+
+    /// The open session, initializing one first when there is none or the
+    /// server process has exited.
+    async fn session(&self) -> Result<Arc<McpSession>, ToolError> {
+        let mut session = self.session.lock().await;
+        if let Some(open) = session.as_ref() {
+            match open.as_ref() {
+                McpSession::Stdio(process) if !process.is_alive() => {}
+                _ => return Ok(open.clone()),
+            }
+        }
+
+        let params = json!({
+            "protocolVersion": MCP_PROTOCOL_VERSION,
+            "capabilities": {},
+            "clientInfo": { "name": "amp-kernel", "version": env!("CARGO_PKG_VERSION") }
+        });
+        let opened = match &self.endpoint {
+            McpEndpoint::Stdio { program, args } => {
+                let process = LineProcess::spawn(program, args)?;
+                let initializing = McpSession::Stdio(process.clone());
+                self.request_in(&initializing, "initialize", params).await?;
+                process
+                    .send(&encode(&RpcNotification {
+                        jsonrpc: "2.0",
+                        method: "notifications/initialized",
+                    })?)
+                    .await?;
+                initializing
+            }
+            McpEndpoint::Http { client, url } => {
+                let (response, session_id) = self
+                    .post(client, url, None, None, "initialize", params)
+                    .await?;
+                let protocol_version = response
+                    .into_result("initialize")?
+                    .get("protocolVersion")
+                    .and_then(Value::as_str)
+                    .unwrap_or(MCP_PROTOCOL_VERSION)
+                    .to_string();
+                let opened = McpSession::Http {
+                    client: client.clone(),
+                    url: url.clone(),
+                    session_id,
+                    protocol_version,
+                };
+                self.notify_http(&opened, "notifications/initialized")
+                    .await?;
+                opened
+            }
+        };
+        Ok(session.insert(Arc::new(opened)).clone())
+    }
+
+    async fn request(&self, method: &str, params: Value) -> Result<Value, ToolError> {
+        let session = self.session().await?;
+        self.request_in(&session, method, params).await
+    }
+
+    async fn request_in(
+        &self,
+        session: &McpSession,
+        method: &str,
+        params: Value,
+    ) -> Result<Value, ToolError> {
+        let response = match session {
+            McpSession::Stdio(process) => {
+                let message = process
+                    .request(|id| {
+                        encode(&RpcRequest {
+                            jsonrpc: "2.0",
+                            id,
+                            method,
+                            params,
+                        })
+                    })
+                    .await?;
+                serde_json::from_value::<RpcResponse>(message)
+                    .map_err(|e| ToolError::Communication(e.to_string()))?
+            }
+            McpSession::Http {
+                client,
+                url,
+                session_id,
+                protocol_version,
+            } => {
+                self.post(
+                    client,
+                    url,
+                    session_id.as_deref(),
+                    Some(protocol_version),
+                    method,
+                    params,
+                )
+                .await?
+                .0
+            }
+        };
+        response.into_result(method)
+    }
+
+    /// Posts one JSON-RPC request. The server answers with JSON or with an
+    /// event stream that carries the response; `Mcp-Session-Id` is returned
+    /// alongside when the server assigns one.
+    async fn post(
+        &self,
+        client: &reqwest::Client,
+        url: &str,
+        session_id: Option<&str>,
+        protocol_version: Option<&str>,
+        method: &str,
+        params: Value,
+    ) -> Result<(RpcResponse, Option<String>), ToolError> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let mut request = client
+            .post(url)
+            .header("Accept", "application/json, text/event-stream")
+            .json(&RpcRequest {
+                jsonrpc: "2.0",
+                id,
+                method,
+                params,
+            });
+        if let Some(session_id) = session_id {
+            request = request.header("Mcp-Session-Id", session_id);
+        }
+        if let Some(version) = protocol_version {
+            request = request.header("MCP-Protocol-Version", version);
+        }
+
+        let response = request
+            .send()
+            .await
+            .map_err(|e| ToolError::Communication(e.to_string()))?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(ToolError::Communication(format!(
+                "MCP server answered {} with status {}",
+                method, status
+            )));
+        }
+        let assigned_session = response
+            .headers()
+            .get("Mcp-Session-Id")
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+        let is_stream = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.starts_with("text/event-stream"));
+        let body = response
+            .text()
+            .await
+            .map_err(|e| ToolError::Communication(e.to_string()))?;
+
+        let message = if is_stream {
+            body.split("\n\n")
+                .filter_map(|event| {
+                    let data: Vec<&str> = event
+                        .lines()
+                        .filter_map(|line| line.strip_prefix("data:"))
+                        .map(str::trim_start)
+                        .collect();
+                    serde_json::from_str::<Value>(&data.join("\n")).ok()
+                })
+                .find(|message| message["id"] == json!(id) && message.get("method").is_none())
+                .ok_or_else(|| {
+                    ToolError::Communication(format!(
+                        "MCP event stream for {} ended without a response",
+                        method
+                    ))
+                })?
+        } else {
+            serde_json::from_str(&body).map_err(|e| ToolError::Communication(e.to_string()))?
+        };
+        let response =
+            serde_json::from_value(message).map_err(|e| ToolError::Communication(e.to_string()))?;
+        Ok((response, assigned_session))
+    }
+
+    async fn notify_http(&self, session: &McpSession, method: &str) -> Result<(), ToolError> {
+        let McpSession::Http {
+            client,
+            url,
+            session_id,
+            protocol_version,
+        } = session
+        else {
+            return Ok(());
+        };
+        let mut request = client
+            .post(url)
+            .header("Accept", "application/json, text/event-stream")
+            .header("MCP-Protocol-Version", protocol_version.as_str())
+            .json(&RpcNotification {
+                jsonrpc: "2.0",
+                method,
+            });
+        if let Some(session_id) = session_id {
+            request = request.header("Mcp-Session-Id", session_id.as_str());
+        }
+        let response = request
+            .send()
+            .await
+            .map_err(|e| ToolError::Communication(e.to_string()))?;
+        if !response.status().is_success() {
+            return Err(ToolError::Communication(format!(
+                "MCP server answered {} with status {}",
+                method,
+                response.status()
+            )));
+        }
+        Ok(())
+    }
+
+    /// The server's tools by name, listed through every page on first use.
+    async fn tools(&self) -> Result<HashMap<String, ToolSpec>, ToolError> {
+        let mut tools = self.tools.lock().await;
+        if let Some(listed) = tools.as_ref() {
+            return Ok(listed.clone());
+        }
+
+        let mut listed = HashMap::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let params = match &cursor {
+                Some(cursor) => json!({ "cursor": cursor }),
+                None => json!({}),
+            };
+            let page: ListToolsResult =
+                serde_json::from_value(self.request("tools/list", params).await?)
+                    .map_err(|e| ToolError::Communication(format!("Invalid tool list: {}", e)))?;
+            for tool in page.tools {
+                listed.insert(tool.name.clone(), tool.to_spec());
+            }
+            match page.next_cursor {
+                Some(next) if cursor.as_ref() != Some(&next) => cursor = Some(next),
+                _ => break,
+            }
+        }
+        Ok(tools.insert(listed).clone())
+    }
+}
+
+fn encode(message: &impl Serialize) -> Result<Vec<u8>, ToolError> {
+    let mut line =
+        serde_json::to_vec(message).map_err(|e| ToolError::Communication(e.to_string()))?;
+    line.push(b'\n');
+    Ok(line)
+}
+
+#[async_trait]
+impl ToolTransport for McpClient {
+    async fn invoke(&self, tool_name: &str, args: Option<Value>) -> Result<Value, ToolError> {
+        let result = self
+            .request(
+                "tools/call",
+                json!({ "name": tool_name, "arguments": args.unwrap_or_else(|| json!({})) }),
+            )
+            .await?;
+        let result: CallToolResult = serde_json::from_value(result)
+            .map_err(|e| ToolError::Communication(format!("Invalid tool result: {}", e)))?;
+        call_result_value(result)
+    }
+
+    async fn spec(&self, tool_name: &str) -> Result<ToolSpec, ToolError> {
+        self.tools().await?.remove(tool_name).ok_or_else(|| {
+            ToolError::Communication(format!("MCP server has no tool named {}", tool_name))
+        })
+    }
+
+    async fn list_tools(&self) -> Result<Vec<ToolSpec>, ToolError> {
+        let mut tools: Vec<ToolSpec> = self.tools().await?.into_values().collect();
+        tools.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(tools)
+    }
+}
@@ -0,0 +1,59 @@
+use crate::internal::tools::spec::{ToolError, ToolSpec};
+use async_trait::async_trait;
+use futures::future::BoxFuture;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::future::Future;
+
+/// A tool written in Rust and run in the kernel's own process. Gather tools
+/// in an [`InprocTransport`](crate::internal::tools::transport::InprocTransport)
+/// and hand it to
+/// [`ExecutionContext::register_inproc_tools`](crate::internal::exec::scheduler::ExecutionContext::register_inproc_tools),
+/// after which plans call them like any other tool: arguments are checked
+/// against the spec's `io.input`, policies apply and constraints are charged
+/// to the budget.
+#[async_trait]
+pub trait Tool: Send + Sync {
+    fn spec(&self) -> ToolSpec;
+
+    async fn call(&self, args: Option<Value>) -> Result<Value, ToolError>;
+}
+
+type Handler =
+    Box<dyn Fn(Option<Value>) -> BoxFuture<'static, Result<Value, ToolError>> + Send + Sync>;
+
+/// A [`Tool`] answered by a closure.
+pub struct FnTool {
+    spec: ToolSpec,
+    handler: Handler,
+}
+
+impl FnTool {
+    pub fn new<F, Fut>(spec: ToolSpec, handler: F) -> Self
+    where
+        F: Fn(Option<Value>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Value, ToolError>> + Send + 'static,
+    {
+        Self {
+            spec,
+            handler: Box::new(move |args| Box::pin(handler(args))),
+        }
+    }
+}
+
+#[async_trait]
+impl Tool for FnTool {
+    fn spec(&self) -> ToolSpec {
+        self.spec.clone()
+    }
+
+    async fn call(&self, args: Option<Value>) -> Result<Value, ToolError> {
+        (self.handler)(args).await
+    }
+}
+
+/// Reads a tool's arguments into `T`, treating missing arguments as `null`.
+pub fn parse_args<T: DeserializeOwned>(args: Option<Value>) -> Result<T, ToolError> {
+    serde_json::from_value(args.unwrap_or(Value::Null))
+        .map_err(|e| ToolError::Invocation(format!("Invalid arguments: {}", e)))
+}
@@ -1,5 +1,16 @@
+#[cfg(unix)]
+use crate::internal::tools::transport::UnixTransport;
+use crate::internal::tools::{
+    auth::{redact, ToolAuth},
+    mcp::McpClient,
+    transport::{HttpTransport, StdioTransport, ToolTransport},
+    wasm::WasmTransport,
+};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ToolSpec {
@@ -21,13 +32,158 @@ pub struct IoSpec {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Schema {
-    #[serde(rename = "type")]
+    // An empty type (e.g. `{}` in a spec) accepts any value
+    #[serde(rename = "type", default, skip_serializing_if = "String::is_empty")]
     pub schema_type: String,
     pub properties: Option<HashMap<String, Box<Schema>>>,
     pub required: Option<Vec<String>>,
     pub items: Option<Box<Schema>>,
 }
 
+/// A value that does not match a [`Schema`], located by a JSON pointer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SchemaViolation {
+    pub pointer: String,
+    pub message: String,
+}
+
+impl fmt::Display for SchemaViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.pointer.is_empty() {
+            write!(f, "(root): {}", self.message)
+        } else {
+            write!(f, "{}: {}", self.pointer, self.message)
+        }
+    }
+}
+
+impl Schema {
+    /// Checks `value` against this schema and returns every mismatch found.
+    /// Properties not listed in the schema are allowed, as are unknown types.
+    pub fn validate(&self, value: &Value) -> Vec<SchemaViolation> {
+        let mut violations = Vec::new();
+        self.collect_violations(value, "", &mut violations);
+        violations
+    }
+
+    pub fn collect_violations(
+        &self,
+        value: &Value,
+        pointer: &str,
+        violations: &mut Vec<SchemaViolation>,
+    ) {
+        let type_matches = match self.schema_type.as_str() {
+            "object" => value.is_object(),
+            "array" => value.is_array(),
+            "string" => value.is_string(),
+            "number" => value.is_number(),
+            // JSON Schema counts 3.0 as an integer, though serde_json parses it as a float
+            "integer" => {
+                value.is_i64() || value.is_u64() || value.as_f64().is_some_and(|n| n.fract() == 0.0)
+            }
+            "boolean" => value.is_boolean(),
+            "null" => value.is_null(),
+            _ => true,
+        };
+        if !type_matches {
+            violations.push(SchemaViolation {
+                pointer: pointer.to_string(),
+                message: format!(
+                    "expected {}, found {}",
+                    self.schema_type,
+                    json_type_name(value)
+                ),
+            });
+            return;
+        }
+
+        if let Value::Object(map) = value {
+            for name in self.required.iter().flatten() {
+                if !map.contains_key(name) {
+                    violations.push(SchemaViolation {
+                        pointer: pointer_join(pointer, name),
+                        message: "required property is missing".to_string(),
+                    });
+                }
+            }
+            let mut properties: Vec<_> = self.properties.iter().flatten().collect();
+            properties.sort_by(|a, b| a.0.cmp(b.0));
+            for (name, schema) in properties {
+                if let Some(property) = map.get(name) {
+                    schema.collect_violations(property, &pointer_join(pointer, name), violations);
+                }
+            }
+        }
+
+        if let (Value::Array(items), Some(schema)) = (value, &self.items) {
+            for (index, item) in items.iter().enumerate() {
+                schema.collect_violations(
+                    item,
+                    &pointer_join(pointer, &index.to_string()),
+                    violations,
+                );
+            }
+        }
+    }
+}
+
+/// Appends a reference token to a JSON pointer, escaping `~` and `/`.
+pub fn pointer_join(pointer: &str, token: &str) -> String {
+    format!(
+        "{}/{}",
+        pointer,
+        token.replace('~', "~0").replace('/', "~1")
+    )
+}
+
+fn json_type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(n) if n.is_f64() => "number",
+        Value::Number(_) => "integer",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+impl ToolSpec {
+    /// Validates resolved call arguments against `io.input`. Missing arguments
+    /// are checked as an empty object.
+    pub fn validate_input(&self, args: Option<&Value>) -> Result<(), ToolError> {
+        let empty = Value::Object(serde_json::Map::new());
+        let violations = self.io.input.validate(args.unwrap_or(&empty));
+        schema_result(&self.name, "arguments", violations)
+    }
+
+    /// Validates a tool result against `io.output`.
+    pub fn validate_output(&self, result: &Value) -> Result<(), ToolError> {
+        schema_result(&self.name, "result", self.io.output.validate(result))
+    }
+}
+
+fn schema_result(
+    tool_name: &str,
+    what: &str,
+    violations: Vec<SchemaViolation>,
+) -> Result<(), ToolError> {
+    if violations.is_empty() {
+        return Ok(());
+    }
+
+    Err(ToolError::Validation(format!(
+        "Invalid {} for {}: {}",
+        what,
+        tool_name,
+        violations
+            .iter()
+            .map(|v| v.to_string())
+            .collect::<Vec<_>>()
+            .join("; ")
+    )))
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Constraints {
     pub input_tokens_max: Option<u32>,
@@ -53,28 +209,152 @@ pub struct Policy {
     pub deny_if: Option<Vec<String>>,
 }
 
-// Tool client for invoking tools over HTTP
-#[derive(Debug, Clone)]
+// Tool client for invoking tools; the scheme of a tool's URL picks the transport
+#[derive(Clone)]
 pub struct ToolClient {
     client: reqwest::Client,
+    // Subprocesses, MCP sessions and compiled modules by URL, kept between calls
+    sessions: Arc<Mutex<HashMap<String, Arc<dyn ToolTransport>>>>,
+    // Credentials by URL, from the tools' registry entries
+    auth: Arc<Mutex<HashMap<String, ToolAuth>>>,
+    // In-process endpoints by the name in their `inproc://` URL
+    inproc: Arc<Mutex<HashMap<String, Arc<dyn ToolTransport>>>>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct InvokeRequest {
-    args: Option<serde_json::Value>,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-struct InvokeResponse {
-    result: serde_json::Value,
-    error: Option<String>,
+impl fmt::Debug for ToolClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sessions: Vec<String> = self.sessions.lock().unwrap().keys().cloned().collect();
+        f.debug_struct("ToolClient")
+            .field("sessions", &sessions)
+            .finish()
+    }
 }
 
 impl ToolClient {
     pub fn new() -> Self {
         Self {
             client: reqwest::Client::new(),
+            sessions: Arc::new(Mutex::new(HashMap::new())),
+            auth: Arc::new(Mutex::new(HashMap::new())),
+            inproc: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Makes `endpoint` reachable at `inproc://{name}` through this client
+    /// and its clones, replacing any endpoint registered under that name
+    /// before. Other clients do not see it.
+    pub fn register_inproc(&self, name: impl Into<String>, endpoint: Arc<dyn ToolTransport>) {
+        self.inproc.lock().unwrap().insert(name.into(), endpoint);
+    }
+
+    fn inproc_endpoint(&self, name: &str) -> Result<Arc<dyn ToolTransport>, ToolError> {
+        self.inproc
+            .lock()
+            .unwrap()
+            .get(name)
+            .cloned()
+            .ok_or_else(|| {
+                ToolError::Communication(format!("No in-process endpoint named {}", name))
+            })
+    }
+
+    /// Sends `auth` with every HTTP request to `tool_url`, including MCP
+    /// servers over HTTP, and removes its secrets from the errors of calls
+    /// to it. Fails if `tool_url` already has other credentials, since
+    /// every tool at the URL sends them.
+    pub fn set_auth(&self, tool_url: &str, auth: ToolAuth) -> Result<(), ToolError> {
+        let mut credentials = self.auth.lock().unwrap();
+        match credentials.get(tool_url) {
+            Some(existing) if *existing == auth => return Ok(()),
+            Some(_) => {
+                return Err(ToolError::Validation(format!(
+                    "{} already has other credentials",
+                    tool_url
+                )))
+            }
+            None => {}
+        }
+        credentials.insert(tool_url.to_string(), auth);
+        // A session opened before sends no credentials
+        self.sessions.lock().unwrap().remove(tool_url);
+        Ok(())
+    }
+
+    fn auth_for(&self, tool_url: &str) -> Option<ToolAuth> {
+        self.auth.lock().unwrap().get(tool_url).cloned()
+    }
+
+    /// The HTTP client for `tool_url`, sending its credentials if it has any.
+    fn http_client(&self, tool_url: &str) -> Result<reqwest::Client, ToolError> {
+        match self.auth_for(tool_url) {
+            Some(auth) => auth.client(),
+            None => Ok(self.client.clone()),
+        }
+    }
+
+    fn redacted(&self, tool_url: &str, error: ToolError) -> ToolError {
+        match self.auth_for(tool_url) {
+            Some(auth) => error.redacted(&auth.secrets()),
+            None => error,
+        }
+    }
+
+    /// The transport for `tool_url`: `http://` and `https://`, `unix://`,
+    /// `stdio://`, `inproc://`, a WebAssembly module at `wasm://`, or an MCP
+    /// server at `mcp+stdio://`, `mcp+http://` or `mcp+https://`.
+    pub fn transport(&self, tool_url: &str) -> Result<Arc<dyn ToolTransport>, ToolError> {
+        if let Some(command) = tool_url.strip_prefix("stdio://") {
+            return self.session(tool_url, || Ok(Arc::new(StdioTransport::new(command)?)));
+        }
+        if let Some(mcp_url) = tool_url.strip_prefix("mcp+") {
+            return self.session(tool_url, || {
+                Ok(Arc::new(McpClient::from_url(
+                    mcp_url,
+                    self.http_client(tool_url)?,
+                )?))
+            });
+        }
+        // Reads a local file, so only the local tool config may name a module
+        if let Some(location) = tool_url.strip_prefix("wasm://") {
+            return self.session(tool_url, || Ok(Arc::new(WasmTransport::open(location)?)));
+        }
+        if let Some(name) = tool_url.strip_prefix("inproc://") {
+            return self.inproc_endpoint(name);
+        }
+        #[cfg(unix)]
+        if let Some(path) = tool_url.strip_prefix("unix://") {
+            return Ok(Arc::new(UnixTransport::new(path)));
+        }
+        if tool_url.starts_with("http://") || tool_url.starts_with("https://") {
+            if self.auth_for(tool_url).is_some() {
+                return self.session(tool_url, || {
+                    Ok(Arc::new(HttpTransport::new(
+                        self.http_client(tool_url)?,
+                        tool_url,
+                    )))
+                });
+            }
+            return Ok(Arc::new(HttpTransport::new(self.client.clone(), tool_url)));
         }
+        Err(ToolError::Communication(format!(
+            "Unsupported tool URL: {}",
+            tool_url
+        )))
+    }
+
+    /// The transport kept open for `tool_url`, opened by `open` on first use.
+    fn session(
+        &self,
+        tool_url: &str,
+        open: impl FnOnce() -> Result<Arc<dyn ToolTransport>, ToolError>,
+    ) -> Result<Arc<dyn ToolTransport>, ToolError> {
+        let mut sessions = self.sessions.lock().unwrap();
+        if let Some(transport) = sessions.get(tool_url) {
+            return Ok(transport.clone());
+        }
+        let transport = open()?;
+        sessions.insert(tool_url.to_string(), transport.clone());
+        Ok(transport)
     }
 
     pub async fn invoke_tool(
@@ -83,28 +363,27 @@ impl ToolClient {
         tool_name: &str,
         args: Option<serde_json::Value>,
     ) -> Result<serde_json::Value, ToolError> {
-        let request = InvokeRequest { args };
-        let base_url = tool_url.trim_end_matches('/');
-        let invoke_url = format!("{}/invoke/{}", base_url, tool_name);
-
-        let response = self
-            .client
-            .post(invoke_url)
-            .json(&request)
-            .send()
-            .await
-            .map_err(|e| ToolError::Communication(e.to_string()))?;
-
-        let invoke_response: InvokeResponse = response
-            .json()
-            .await
-            .map_err(|e| ToolError::Communication(e.to_string()))?;
-
-        if let Some(error) = invoke_response.error {
-            return Err(ToolError::Invocation(error));
-        }
+        let result = match self.transport(tool_url) {
+            Ok(transport) => transport.invoke(tool_name, args).await,
+            Err(e) => Err(e),
+        };
+        result.map_err(|e| self.redacted(tool_url, e))
+    }
 
-        Ok(invoke_response.result)
+    /// Invokes a tool with at most `fuel_limit` fuel, also returning the
+    /// fuel it burned, even when it failed, if its transport meters compute.
+    pub async fn invoke_tool_metered(
+        &self,
+        tool_url: &str,
+        tool_name: &str,
+        args: Option<Value>,
+        fuel_limit: Option<u64>,
+    ) -> (Result<Value, ToolError>, Option<u64>) {
+        let (result, fuel) = match self.transport(tool_url) {
+            Ok(transport) => transport.invoke_metered(tool_name, args, fuel_limit).await,
+            Err(e) => (Err(e), None),
+        };
+        (result.map_err(|e| self.redacted(tool_url, e)), fuel)
     }
 
     pub async fn get_tool_spec(
@@ -112,21 +391,21 @@ impl ToolClient {
         tool_url: &str,
         tool_name: &str,
     ) -> Result<ToolSpec, ToolError> {
-        let base_url = tool_url.trim_end_matches('/');
-        let spec_url = format!("{}/spec/{}", base_url, tool_name);
-        let response = self
-            .client
-            .get(spec_url)
-            .send()
-            .await
-            .map_err(|e| ToolError::Communication(e.to_string()))?;
-
-        let spec: ToolSpec = response
-            .json()
-            .await
-            .map_err(|e| ToolError::Communication(e.to_string()))?;
+        let result = match self.transport(tool_url) {
+            Ok(transport) => transport.spec(tool_name).await,
+            Err(e) => Err(e),
+        };
+        result.map_err(|e| self.redacted(tool_url, e))
+    }
 
-        Ok(spec)
+    /// The specs of every tool the server at `tool_url` lists; empty for
+    /// transports that cannot list their tools.
+    pub async fn list_tools(&self, tool_url: &str) -> Result<Vec<ToolSpec>, ToolError> {
+        let result = match self.transport(tool_url) {
+            Ok(transport) => transport.list_tools().await,
+            Err(e) => Err(e),
+        };
+        result.map_err(|e| self.redacted(tool_url, e))
     }
 }
 
@@ -139,3 +418,16 @@ pub enum ToolError {
     #[error("Validation error: {0}")]
     Validation(String),
 }
+
+impl ToolError {
+    /// The same error with each of `secrets` replaced in its message.
+    pub fn redacted(self, secrets: &[String]) -> Self {
+        match self {
+            ToolError::Communication(message) => {
+                ToolError::Communication(redact(&message, secrets))
+            }
+            ToolError::Invocation(message) => ToolError::Invocation(redact(&message, secrets)),
+            ToolError::Validation(message) => ToolError::Validation(redact(&message, secrets)),
+        }
+    }
+}
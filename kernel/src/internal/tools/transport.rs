@@ -0,0 +1,535 @@
+use crate::internal::tools::sdk::{FnTool, Tool};
+use crate::internal::tools::spec::{ToolError, ToolSpec};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::future::Future;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStdin, Command};
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+
+/// How the kernel reaches the tools behind one registry URL. The URL scheme
+/// picks the transport: `http://` and `https://`, `unix://`, `stdio://`,
+/// `inproc://` or `wasm://`.
+#[async_trait]
+pub trait ToolTransport: Send + Sync {
+    async fn invoke(&self, tool_name: &str, args: Option<Value>) -> Result<Value, ToolError>;
+
+    async fn spec(&self, tool_name: &str) -> Result<ToolSpec, ToolError>;
+
+    /// Invokes the tool with at most `fuel_limit` fuel and reports the fuel
+    /// it burned, whether or not the call succeeded, for transports that
+    /// meter compute such as WebAssembly. Others ignore the limit and report
+    /// none.
+    async fn invoke_metered(
+        &self,
+        tool_name: &str,
+        args: Option<Value>,
+        _fuel_limit: Option<u64>,
+    ) -> (Result<Value, ToolError>, Option<u64>) {
+        (self.invoke(tool_name, args).await, None)
+    }
+
+    /// The specs of every tool behind the URL, for transports whose servers
+    /// list their tools, such as MCP servers. Others list none.
+    async fn list_tools(&self) -> Result<Vec<ToolSpec>, ToolError> {
+        Ok(Vec::new())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct InvokeRequest {
+    args: Option<Value>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct InvokeResponse {
+    result: Value,
+    error: Option<String>,
+}
+
+/// Tools served over HTTP: `POST {url}/invoke/{name}` and `GET {url}/spec/{name}`.
+#[derive(Debug, Clone)]
+pub struct HttpTransport {
+    client: reqwest::Client,
+    base_url: String,
+}
+
+impl HttpTransport {
+    pub fn new(client: reqwest::Client, base_url: &str) -> Self {
+        Self {
+            client,
+            base_url: base_url.trim_end_matches('/').to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl ToolTransport for HttpTransport {
+    async fn invoke(&self, tool_name: &str, args: Option<Value>) -> Result<Value, ToolError> {
+        let invoke_url = format!("{}/invoke/{}", self.base_url, tool_name);
+        let response = self
+            .client
+            .post(invoke_url)
+            .json(&InvokeRequest { args })
+            .send()
+            .await
+            .map_err(|e| ToolError::Communication(e.to_string()))?;
+        let response = check_status(response, tool_name).await?;
+
+        let invoke_response: InvokeResponse = response
+            .json()
+            .await
+            .map_err(|e| ToolError::Communication(e.to_string()))?;
+
+        if let Some(error) = invoke_response.error {
+            return Err(ToolError::Invocation(error));
+        }
+
+        Ok(invoke_response.result)
+    }
+
+    async fn spec(&self, tool_name: &str) -> Result<ToolSpec, ToolError> {
+        let spec_url = format!("{}/spec/{}", self.base_url, tool_name);
+        let response = self
+            .client
+            .get(spec_url)
+            .send()
+            .await
+            .map_err(|e| ToolError::Communication(e.to_string()))?;
+        let response = check_status(response, tool_name).await?;
+
+        response
+            .json()
+            .await
+            .map_err(|e| ToolError::Communication(e.to_string()))
+    }
+}
+
+/// Passes successful responses through. Others fail with the tool's own
+/// `error` when the body carries one, or else with the status and body, so
+/// that a refused credential reads as such.
+async fn check_status(
+    response: reqwest::Response,
+    tool_name: &str,
+) -> Result<reqwest::Response, ToolError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let body = response.text().await.unwrap_or_default();
+    match serde_json::from_str::<InvokeResponse>(&body) {
+        Ok(InvokeResponse {
+            error: Some(error), ..
+        }) => Err(ToolError::Invocation(error)),
+        _ => Err(ToolError::Communication(format!(
+            "Tool {} responded {}: {}",
+            tool_name,
+            status,
+            body.trim()
+        ))),
+    }
+}
+
+/// A request of the JSON-lines protocol spoken over Unix sockets and stdio.
+/// `method` is `invoke` or `spec`.
+#[derive(Debug, Serialize)]
+struct LineRequest<'a> {
+    id: u64,
+    method: &'a str,
+    tool: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    args: Option<Value>,
+}
+
+/// The answer to the request with the same `id`. For `spec` requests
+/// `result` is the tool's ToolSpec.
+#[derive(Debug, Deserialize)]
+pub(crate) struct LineResponse {
+    #[serde(default)]
+    result: Value,
+    error: Option<String>,
+}
+
+impl LineResponse {
+    pub(crate) fn into_result(self) -> Result<Value, ToolError> {
+        match self.error {
+            Some(error) => Err(ToolError::Invocation(error)),
+            None => Ok(self.result),
+        }
+    }
+}
+
+fn encode_line(request: &LineRequest) -> Result<Vec<u8>, ToolError> {
+    let mut line =
+        serde_json::to_vec(request).map_err(|e| ToolError::Communication(e.to_string()))?;
+    line.push(b'\n');
+    Ok(line)
+}
+
+fn parse_spec(result: Value) -> Result<ToolSpec, ToolError> {
+    serde_json::from_value(result)
+        .map_err(|e| ToolError::Communication(format!("Invalid ToolSpec: {}", e)))
+}
+
+/// Tools listening on a Unix-domain socket, e.g. `unix:///run/amp/search.sock`.
+/// Each call opens a connection, writes one request line and reads one
+/// response line.
+#[cfg(unix)]
+#[derive(Debug, Clone)]
+pub struct UnixTransport {
+    path: std::path::PathBuf,
+}
+
+#[cfg(unix)]
+impl UnixTransport {
+    pub fn new(path: impl Into<std::path::PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    async fn call(
+        &self,
+        method: &str,
+        tool_name: &str,
+        args: Option<Value>,
+    ) -> Result<Value, ToolError> {
+        let communication =
+            |e: std::io::Error| ToolError::Communication(format!("{}: {}", self.path.display(), e));
+        let stream = tokio::net::UnixStream::connect(&self.path)
+            .await
+            .map_err(communication)?;
+        let (reader, mut writer) = stream.into_split();
+
+        let line = encode_line(&LineRequest {
+            id: 0,
+            method,
+            tool: tool_name,
+            args,
+        })?;
+        writer.write_all(&line).await.map_err(communication)?;
+
+        let mut response = String::new();
+        BufReader::new(reader)
+            .read_line(&mut response)
+            .await
+            .map_err(communication)?;
+        if response.is_empty() {
+            return Err(ToolError::Communication(format!(
+                "{}: connection closed without a response",
+                self.path.display()
+            )));
+        }
+        serde_json::from_str::<LineResponse>(&response)
+            .map_err(|e| ToolError::Communication(e.to_string()))?
+            .into_result()
+    }
+}
+
+#[cfg(unix)]
+#[async_trait]
+impl ToolTransport for UnixTransport {
+    async fn invoke(&self, tool_name: &str, args: Option<Value>) -> Result<Value, ToolError> {
+        self.call("invoke", tool_name, args).await
+    }
+
+    async fn spec(&self, tool_name: &str) -> Result<ToolSpec, ToolError> {
+        parse_spec(self.call("spec", tool_name, None).await?)
+    }
+}
+
+type Pending = Arc<Mutex<Option<HashMap<u64, oneshot::Sender<Value>>>>>;
+
+/// A subprocess exchanging JSON messages line by line on its stdin and
+/// stdout. Answers are matched to requests by their numeric `id`; lines with
+/// a `method` are the process's own requests and are ignored. `pending` is
+/// `None` once the process has closed its stdout.
+pub(crate) struct LineProcess {
+    program: String,
+    _child: Child,
+    stdin: tokio::sync::Mutex<ChildStdin>,
+    pending: Pending,
+    next_id: AtomicU64,
+    reader: JoinHandle<()>,
+}
+
+impl Drop for LineProcess {
+    fn drop(&mut self) {
+        self.reader.abort();
+    }
+}
+
+/// Removes a request from `pending` once its caller stops waiting, be it
+/// answered, failed, timed out or cancelled.
+struct ForgetOnDrop<'a> {
+    pending: &'a Pending,
+    id: u64,
+}
+
+impl Drop for ForgetOnDrop<'_> {
+    fn drop(&mut self) {
+        if let Some(pending) = self.pending.lock().unwrap().as_mut() {
+            pending.remove(&self.id);
+        }
+    }
+}
+
+/// Splits the command of a `stdio://` URL into the program and its
+/// whitespace-separated arguments.
+pub(crate) fn split_command(command: &str) -> Result<(String, Vec<String>), ToolError> {
+    let mut parts = command.split_whitespace().map(str::to_string);
+    let program = parts
+        .next()
+        .ok_or_else(|| ToolError::Communication("stdio:// URL names no command".to_string()))?;
+    Ok((program, parts.collect()))
+}
+
+impl LineProcess {
+    pub(crate) fn spawn(program: &str, args: &[String]) -> Result<Arc<Self>, ToolError> {
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| ToolError::Communication(format!("Failed to start {}: {}", program, e)))?;
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = child.stdout.take().expect("stdout is piped");
+
+        let pending: Pending = Arc::new(Mutex::new(Some(HashMap::new())));
+        let reader = tokio::spawn({
+            let pending = pending.clone();
+            let program = program.to_string();
+            async move {
+                let mut lines = BufReader::new(stdout).lines();
+                while let Ok(Some(line)) = lines.next_line().await {
+                    let message = match serde_json::from_str::<Value>(&line) {
+                        Ok(message) if message.get("method").is_none() => message,
+                        Ok(_) => continue,
+                        Err(e) => {
+                            tracing::warn!(program = %program, error = %e, "Ignoring invalid tool output line");
+                            continue;
+                        }
+                    };
+                    let waiting = message["id"].as_u64().and_then(|id| {
+                        pending
+                            .lock()
+                            .unwrap()
+                            .as_mut()
+                            .and_then(|pending| pending.remove(&id))
+                    });
+                    if let Some(waiting) = waiting {
+                        let _ = waiting.send(message);
+                    }
+                }
+                // Callers still waiting see the process as gone
+                pending.lock().unwrap().take();
+            }
+        });
+
+        Ok(Arc::new(Self {
+            program: program.to_string(),
+            _child: child,
+            stdin: tokio::sync::Mutex::new(stdin),
+            pending,
+            next_id: AtomicU64::new(1),
+            reader,
+        }))
+    }
+
+    pub(crate) fn is_alive(&self) -> bool {
+        self.pending.lock().unwrap().is_some()
+    }
+
+    fn exited(&self) -> ToolError {
+        ToolError::Communication(format!("Tool process {} exited", self.program))
+    }
+
+    /// Writes the line `encode` builds for a fresh request id and waits for
+    /// the message answering it.
+    pub(crate) async fn request(
+        &self,
+        encode: impl FnOnce(u64) -> Result<Vec<u8>, ToolError>,
+    ) -> Result<Value, ToolError> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (tx, rx) = oneshot::channel();
+        self.pending
+            .lock()
+            .unwrap()
+            .as_mut()
+            .ok_or_else(|| self.exited())?
+            .insert(id, tx);
+        let _forget = ForgetOnDrop {
+            pending: &self.pending,
+            id,
+        };
+
+        self.send(&encode(id)?).await?;
+        rx.await.map_err(|_| self.exited())
+    }
+
+    /// Writes a line that gets no answer.
+    pub(crate) async fn send(&self, line: &[u8]) -> Result<(), ToolError> {
+        let mut stdin = self.stdin.lock().await;
+        stdin.write_all(line).await.map_err(|_| self.exited())?;
+        stdin.flush().await.map_err(|_| self.exited())
+    }
+}
+
+/// A tool subprocess speaking JSON lines on stdin and stdout, e.g.
+/// `stdio:///opt/tools/extract --strict`. The process is started on first
+/// use and kept running; requests may overlap and are matched to their
+/// responses by id. A process that exits is started again on the next call.
+pub struct StdioTransport {
+    program: String,
+    args: Vec<String>,
+    process: tokio::sync::Mutex<Option<Arc<LineProcess>>>,
+}
+
+impl std::fmt::Debug for StdioTransport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StdioTransport")
+            .field("program", &self.program)
+            .field("args", &self.args)
+            .finish()
+    }
+}
+
+impl StdioTransport {
+    /// `command` is the program followed by its arguments, separated by
+    /// whitespace.
+    pub fn new(command: &str) -> Result<Self, ToolError> {
+        let (program, args) = split_command(command)?;
+        Ok(Self {
+            program,
+            args,
+            process: tokio::sync::Mutex::new(None),
+        })
+    }
+
+    async fn call(
+        &self,
+        method: &str,
+        tool_name: &str,
+        args: Option<Value>,
+    ) -> Result<Value, ToolError> {
+        let process = {
+            let mut process = self.process.lock().await;
+            match process.as_ref().filter(|p| p.is_alive()) {
+                Some(running) => running.clone(),
+                None => process
+                    .insert(LineProcess::spawn(&self.program, &self.args)?)
+                    .clone(),
+            }
+        };
+
+        let response = process
+            .request(|id| {
+                encode_line(&LineRequest {
+                    id,
+                    method,
+                    tool: tool_name,
+                    args,
+                })
+            })
+            .await?;
+        serde_json::from_value::<LineResponse>(response)
+            .map_err(|e| ToolError::Communication(e.to_string()))?
+            .into_result()
+    }
+}
+
+#[async_trait]
+impl ToolTransport for StdioTransport {
+    async fn invoke(&self, tool_name: &str, args: Option<Value>) -> Result<Value, ToolError> {
+        self.call("invoke", tool_name, args).await
+    }
+
+    async fn spec(&self, tool_name: &str) -> Result<ToolSpec, ToolError> {
+        parse_spec(self.call("spec", tool_name, None).await?)
+    }
+}
+
+/// Tools implemented in Rust in the kernel's own process. Add them with
+/// [`tool`](Self::tool) or [`with`](Self::with), then either hand the set to
+/// `ExecutionContext::register_inproc_tools` or register it under a name with
+/// `ToolClient::register_inproc` and point the registry at `inproc://{name}`.
+#[derive(Default)]
+pub struct InprocTransport {
+    tools: HashMap<String, Arc<dyn Tool>>,
+}
+
+impl InprocTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the tool described by `spec`, answered by `handler`.
+    pub fn tool<F, Fut>(self, spec: ToolSpec, handler: F) -> Self
+    where
+        F: Fn(Option<Value>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Value, ToolError>> + Send + 'static,
+    {
+        self.with(FnTool::new(spec, handler))
+    }
+
+    /// Adds `tool` under the name in its spec, replacing any tool of that
+    /// name.
+    pub fn with(mut self, tool: impl Tool + 'static) -> Self {
+        self.tools.insert(tool.spec().name, Arc::new(tool));
+        self
+    }
+
+    /// The specs of the tools, sorted by name.
+    pub fn specs(&self) -> Vec<ToolSpec> {
+        let mut specs: Vec<ToolSpec> = self.tools.values().map(|tool| tool.spec()).collect();
+        specs.sort_by(|a, b| a.name.cmp(&b.name));
+        specs
+    }
+
+    fn get(&self, tool_name: &str) -> Result<&Arc<dyn Tool>, ToolError> {
+        self.tools.get(tool_name).ok_or_else(|| {
+            ToolError::Communication(format!("No in-process tool named {}", tool_name))
+        })
+    }
+}
+
+#[async_trait]
+impl ToolTransport for InprocTransport {
+    async fn invoke(&self, tool_name: &str, args: Option<Value>) -> Result<Value, ToolError> {
+        self.get(tool_name)?.call(args).await
+    }
+
+    async fn spec(&self, tool_name: &str) -> Result<ToolSpec, ToolError> {
+        Ok(self.get(tool_name)?.spec())
+    }
+
+    async fn list_tools(&self) -> Result<Vec<ToolSpec>, ToolError> {
+        Ok(self.specs())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_abandoned_requests_are_forgotten() {
+        // A process that reads every request and never answers
+        let args = ["-c".to_string(), "cat > /dev/null".to_string()];
+        let process = LineProcess::spawn("/bin/sh", &args).unwrap();
+
+        for _ in 0..3 {
+            let request = process.request(|id| Ok(format!("{{\"id\":{}}}\n", id).into_bytes()));
+            let waited = tokio::time::timeout(std::time::Duration::from_millis(50), request).await;
+            assert!(waited.is_err());
+        }
+        assert!(process.is_alive());
+        assert!(process.pending.lock().unwrap().as_ref().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,254 @@
+use crate::internal::tools::spec::{ToolError, ToolSpec};
+use crate::internal::tools::transport::{LineResponse, ToolTransport};
+use async_trait::async_trait;
+use serde_json::Value;
+use wasmtime::{Config, Engine, Linker, Module, Store, StoreLimits, StoreLimitsBuilder, Trap};
+
+/// Custom section holding the module's ToolSpec as JSON.
+pub const SPEC_SECTION: &str = "amp.toolspec";
+
+/// Fuel a call may burn unless the URL sets `fuel`.
+pub const DEFAULT_FUEL: u64 = 100_000_000;
+
+/// Linear memory a call may use unless the URL sets `memory_mb`.
+pub const DEFAULT_MEMORY_MB: usize = 64;
+
+/// Limits applied to every call into a module.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WasmLimits {
+    pub fuel: u64,
+    pub memory_bytes: usize,
+}
+
+impl Default for WasmLimits {
+    fn default() -> Self {
+        Self {
+            fuel: DEFAULT_FUEL,
+            memory_bytes: DEFAULT_MEMORY_MB << 20,
+        }
+    }
+}
+
+/// A tool compiled to WebAssembly and run in a sandbox, e.g.
+/// `wasm:///opt/tools/slugify.wasm?fuel=5000000&memory_mb=16`.
+///
+/// The module imports nothing, so it has no filesystem, network or clock,
+/// and describes itself in an `amp.toolspec` custom section. It exports its
+/// `memory`, `amp_alloc(len: i32) -> i32` and
+/// `amp_invoke(ptr: i32, len: i32) -> i64`. The kernel writes the call's
+/// arguments as JSON into memory from `amp_alloc` and calls `amp_invoke`,
+/// which answers `{"result": ...}` or `{"error": "..."}` as JSON at the
+/// pointer in the high 32 bits of its return value, with the length in the
+/// low 32 bits. Every call runs in a fresh instance under the fuel and
+/// memory limits.
+#[derive(Clone)]
+pub struct WasmTransport {
+    path: String,
+    engine: Engine,
+    module: Module,
+    spec: ToolSpec,
+    limits: WasmLimits,
+}
+
+impl std::fmt::Debug for WasmTransport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WasmTransport")
+            .field("path", &self.path)
+            .field("tool", &self.spec.name)
+            .field("limits", &self.limits)
+            .finish()
+    }
+}
+
+struct CallState {
+    limits: StoreLimits,
+}
+
+impl WasmTransport {
+    /// Compiles the module named by the part of a `wasm://` URL after the
+    /// scheme, with limits from its query string.
+    pub fn open(location: &str) -> Result<Self, ToolError> {
+        let (path, query) = location.split_once('?').unwrap_or((location, ""));
+        let mut limits = WasmLimits::default();
+        for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            let invalid =
+                || ToolError::Communication(format!("Invalid wasm:// parameter: {}", pair));
+            match key {
+                "fuel" => limits.fuel = value.parse().map_err(|_| invalid())?,
+                "memory_mb" => {
+                    limits.memory_bytes = value.parse::<usize>().map_err(|_| invalid())? << 20
+                }
+                _ => return Err(invalid()),
+            }
+        }
+        let bytes = std::fs::read(path)
+            .map_err(|e| ToolError::Communication(format!("Failed to read {}: {}", path, e)))?;
+        Self::from_bytes(path, &bytes, limits)
+    }
+
+    pub fn from_bytes(path: &str, bytes: &[u8], limits: WasmLimits) -> Result<Self, ToolError> {
+        let spec = embedded_spec(bytes)?.ok_or_else(|| {
+            ToolError::Communication(format!("{} has no {} section", path, SPEC_SECTION))
+        })?;
+
+        let mut config = Config::new();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config).map_err(|e| ToolError::Communication(e.to_string()))?;
+        let module = Module::new(&engine, bytes)
+            .map_err(|e| ToolError::Communication(format!("Failed to compile {}: {}", path, e)))?;
+        Ok(Self {
+            path: path.to_string(),
+            engine,
+            module,
+            spec,
+            limits,
+        })
+    }
+
+    /// Runs one call in a fresh instance with `fuel` fuel, returning its
+    /// answer and the fuel it burned, which is charged even when it fails.
+    fn call(&self, args: &Value, fuel: u64) -> (Result<Value, ToolError>, u64) {
+        let mut store = Store::new(
+            &self.engine,
+            CallState {
+                limits: StoreLimitsBuilder::new()
+                    .memory_size(self.limits.memory_bytes)
+                    .instances(1)
+                    .build(),
+            },
+        );
+        store.limiter(|state| &mut state.limits);
+        if let Err(e) = store.set_fuel(fuel) {
+            return (Err(self.communication(e)), 0);
+        }
+        let result = self.run(&mut store, args, fuel);
+        // A store that cannot report its fuel is charged all of it
+        let burned = fuel.saturating_sub(store.get_fuel().unwrap_or(0));
+        (result, burned)
+    }
+
+    fn run(
+        &self,
+        store: &mut Store<CallState>,
+        args: &Value,
+        fuel: u64,
+    ) -> Result<Value, ToolError> {
+        // No host functions are linked, so any import fails instantiation
+        let instance = Linker::new(&self.engine)
+            .instantiate(&mut *store, &self.module)
+            .map_err(|e| self.communication(e))?;
+        let memory = instance
+            .get_memory(&mut *store, "memory")
+            .ok_or_else(|| ToolError::Communication(format!("{} exports no memory", self.path)))?;
+        let alloc = instance
+            .get_typed_func::<i32, i32>(&mut *store, "amp_alloc")
+            .map_err(|e| self.communication(e))?;
+        let invoke = instance
+            .get_typed_func::<(i32, i32), i64>(&mut *store, "amp_invoke")
+            .map_err(|e| self.communication(e))?;
+
+        let input =
+            serde_json::to_vec(args).map_err(|e| ToolError::Communication(e.to_string()))?;
+        let trapped = |e: wasmtime::Error| match e.downcast_ref::<Trap>() {
+            Some(Trap::OutOfFuel) => {
+                ToolError::Invocation(format!("{} ran out of fuel after {}", self.spec.name, fuel))
+            }
+            _ => ToolError::Invocation(format!("{} trapped: {}", self.spec.name, e)),
+        };
+        let len = i32::try_from(input.len())
+            .map_err(|_| ToolError::Invocation("Arguments too large".to_string()))?;
+        let ptr = alloc.call(&mut *store, len).map_err(trapped)?;
+        memory
+            .write(&mut *store, ptr as u32 as usize, &input)
+            .map_err(|e| ToolError::Communication(format!("{}: {}", self.path, e)))?;
+        let packed = invoke.call(&mut *store, (ptr, len)).map_err(trapped)? as u64;
+
+        // The module picks the answer's bounds, so read it in place rather
+        // than allocating whatever length it claims
+        let (start, len) = ((packed >> 32) as usize, (packed & 0xffff_ffff) as usize);
+        let output = start
+            .checked_add(len)
+            .and_then(|end| memory.data(&*store).get(start..end))
+            .ok_or_else(|| {
+                ToolError::Communication(format!(
+                    "{} answered {} bytes at {}, outside its memory",
+                    self.path, len, start
+                ))
+            })?;
+        let response: LineResponse = serde_json::from_slice(output).map_err(|e| {
+            ToolError::Communication(format!("{} answered invalid JSON: {}", self.path, e))
+        })?;
+        response.into_result()
+    }
+
+    fn communication(&self, e: wasmtime::Error) -> ToolError {
+        ToolError::Communication(format!("{}: {}", self.path, e.root_cause()))
+    }
+
+    fn check_name(&self, tool_name: &str) -> Result<(), ToolError> {
+        if tool_name != self.spec.name {
+            return Err(ToolError::Communication(format!(
+                "{} provides {}, not {}",
+                self.path, self.spec.name, tool_name
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ToolTransport for WasmTransport {
+    async fn invoke(&self, tool_name: &str, args: Option<Value>) -> Result<Value, ToolError> {
+        self.invoke_metered(tool_name, args, None).await.0
+    }
+
+    /// Runs the call with the module's fuel limit, or `fuel_limit` when
+    /// that is lower.
+    async fn invoke_metered(
+        &self,
+        tool_name: &str,
+        args: Option<Value>,
+        fuel_limit: Option<u64>,
+    ) -> (Result<Value, ToolError>, Option<u64>) {
+        if let Err(e) = self.check_name(tool_name) {
+            return (Err(e), None);
+        }
+        let fuel = fuel_limit.map_or(self.limits.fuel, |limit| limit.min(self.limits.fuel));
+        let transport = self.clone();
+        let args = args.unwrap_or(Value::Null);
+        // Calls are bounded by fuel rather than awaited, so run them off
+        // the async workers
+        match tokio::task::spawn_blocking(move || transport.call(&args, fuel)).await {
+            Ok((result, burned)) => (result, Some(burned)),
+            Err(e) => (Err(ToolError::Invocation(e.to_string())), Some(fuel)),
+        }
+    }
+
+    async fn spec(&self, tool_name: &str) -> Result<ToolSpec, ToolError> {
+        self.check_name(tool_name)?;
+        Ok(self.spec.clone())
+    }
+
+    async fn list_tools(&self) -> Result<Vec<ToolSpec>, ToolError> {
+        Ok(vec![self.spec.clone()])
+    }
+}
+
+/// Reads the ToolSpec from the module's `amp.toolspec` custom section.
+pub fn embedded_spec(bytes: &[u8]) -> Result<Option<ToolSpec>, ToolError> {
+    for payload in wasmparser::Parser::new(0).parse_all(bytes) {
+        let payload =
+            payload.map_err(|e| ToolError::Communication(format!("Invalid module: {}", e)))?;
+        if let wasmparser::Payload::CustomSection(section) = payload {
+            if section.name() == SPEC_SECTION {
+                return serde_json::from_slice(section.data())
+                    .map(Some)
+                    .map_err(|e| {
+                        ToolError::Communication(format!("Invalid {} section: {}", SPEC_SECTION, e))
+                    });
+            }
+        }
+    }
+    Ok(None)
+}
@@ -0,0 +1,116 @@
+use crate::internal::{exec::run::RunOutcome, trace::trace::Trace};
+use futures::stream::{self, Stream};
+use std::sync::Arc;
+use tokio::sync::watch;
+
+/// An event of a live trace stream. Events are numbered by their position
+/// in the plan's trace stream; the terminal event takes the next number.
+#[derive(Debug, Clone)]
+pub enum LiveEvent {
+    Trace { id: usize, trace: Box<Trace> },
+    End { id: usize, outcome: Box<RunOutcome> },
+}
+
+#[derive(Debug, Default)]
+struct LiveState {
+    traces: Vec<Trace>,
+    outcome: Option<RunOutcome>,
+}
+
+/// The traces of an execution as they are recorded, for subscribers that
+/// follow the execution while it runs. Clones share the same stream.
+#[derive(Debug, Clone)]
+pub struct LiveTrace {
+    state: Arc<watch::Sender<LiveState>>,
+}
+
+impl Default for LiveTrace {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LiveTrace {
+    pub fn new() -> Self {
+        Self::with_history(vec![])
+    }
+
+    /// Starts a stream that already holds `traces`, e.g. those a resumed
+    /// plan recorded before its checkpoint, so event ids stay stable.
+    pub fn with_history(traces: Vec<Trace>) -> Self {
+        let (state, _) = watch::channel(LiveState {
+            traces,
+            outcome: None,
+        });
+        Self {
+            state: Arc::new(state),
+        }
+    }
+
+    pub fn publish(&self, trace: &Trace) {
+        self.state
+            .send_modify(|state| state.traces.push(trace.clone()));
+    }
+
+    /// Number of traces published so far, history included.
+    pub fn published(&self) -> usize {
+        self.state.borrow().traces.len()
+    }
+
+    /// Whether the stream has been ended with [`LiveTrace::finish`].
+    pub fn is_finished(&self) -> bool {
+        self.state.borrow().outcome.is_some()
+    }
+
+    /// Ends the stream. Subscribers receive `outcome` after the last trace.
+    pub fn finish(&self, outcome: RunOutcome) {
+        self.state
+            .send_modify(|state| state.outcome = Some(outcome));
+    }
+
+    /// Streams the events after `last_event_id`, or from the start when it
+    /// is `None`, waiting for new traces until the execution finishes. The
+    /// terminal event is always sent last, even to subscribers that already
+    /// saw it.
+    pub fn subscribe(
+        &self,
+        last_event_id: Option<usize>,
+    ) -> impl Stream<Item = LiveEvent> + Send + 'static {
+        let next = last_event_id.map_or(0, |id| id + 1);
+        stream::unfold(
+            (self.state.subscribe(), next, false),
+            |(mut rx, next, done)| async move {
+                if done {
+                    return None;
+                }
+                loop {
+                    let event = {
+                        let state = rx.borrow_and_update();
+                        if let Some(trace) = state.traces.get(next) {
+                            Some(LiveEvent::Trace {
+                                id: next,
+                                trace: Box::new(trace.clone()),
+                            })
+                        } else {
+                            state.outcome.clone().map(|outcome| LiveEvent::End {
+                                id: state.traces.len(),
+                                outcome: Box::new(outcome),
+                            })
+                        }
+                    };
+                    match event {
+                        Some(event @ LiveEvent::Trace { .. }) => {
+                            return Some((event, (rx, next + 1, false)))
+                        }
+                        Some(event) => return Some((event, (rx, next, true))),
+                        None => {
+                            if rx.changed().await.is_err() {
+                                return None;
+                            }
+                        }
+                    }
+                }
+            },
+        )
+    }
+}
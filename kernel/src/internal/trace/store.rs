@@ -0,0 +1,481 @@
+use crate::internal::{exec::run::RunStatus, trace::trace::Trace};
+use async_trait::async_trait;
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions};
+use sqlx::{Executor, QueryBuilder, Row, Sqlite};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::sync::{OnceCell, RwLock};
+
+/// Page size used when a query does not set `limit`.
+pub const DEFAULT_PAGE_SIZE: usize = 100;
+
+/// Largest page a single query may return.
+pub const MAX_PAGE_SIZE: usize = 1000;
+
+const SCHEMA: &str = r#"
+CREATE TABLE IF NOT EXISTS traces (
+    seq INTEGER PRIMARY KEY AUTOINCREMENT,
+    plan_id TEXT NOT NULL,
+    run_id TEXT,
+    step_id TEXT NOT NULL,
+    event_type TEXT NOT NULL,
+    tool TEXT,
+    cost_usd REAL,
+    ts TEXT NOT NULL,
+    trace TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS traces_plan_id ON traces (plan_id, seq);
+CREATE INDEX IF NOT EXISTS traces_ts ON traces (ts);
+CREATE INDEX IF NOT EXISTS traces_event_type ON traces (event_type);
+CREATE TABLE IF NOT EXISTS runs (
+    seq INTEGER PRIMARY KEY AUTOINCREMENT,
+    run_id TEXT NOT NULL UNIQUE,
+    plan_id TEXT NOT NULL,
+    status TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS runs_plan_id ON runs (plan_id, seq);
+"#;
+
+/// Filters for [`TraceStore::query`]. Every filter that is set must match;
+/// results come back in the order the traces were stored.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TraceQuery {
+    pub plan_id: Option<String>,
+    pub event_type: Option<String>,
+    pub step_id: Option<String>,
+    /// Tool named in the trace's `data.tool`.
+    pub tool: Option<String>,
+    /// Only traces at or after this time.
+    pub since: Option<DateTime<Utc>>,
+    /// Only traces before this time.
+    pub until: Option<DateTime<Utc>>,
+    /// Only traces whose `cost_usd` is at least this much.
+    pub min_cost_usd: Option<f64>,
+    /// Page size, [`DEFAULT_PAGE_SIZE`] by default and at most
+    /// [`MAX_PAGE_SIZE`].
+    pub limit: Option<usize>,
+    /// `next_cursor` of the previous page.
+    pub cursor: Option<String>,
+}
+
+impl TraceQuery {
+    fn page_size(&self) -> usize {
+        self.limit
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE)
+    }
+
+    fn after_seq(&self) -> Result<i64, TraceStoreError> {
+        self.cursor
+            .as_deref()
+            .map(|cursor| {
+                cursor
+                    .parse::<i64>()
+                    .map_err(|_| TraceStoreError::InvalidCursor(cursor.to_string()))
+            })
+            .transpose()
+            .map(|seq| seq.unwrap_or(0))
+    }
+
+    fn matches(&self, plan_id: &str, trace: &Trace) -> bool {
+        self.plan_id.as_deref().is_none_or(|id| id == plan_id)
+            && self
+                .event_type
+                .as_deref()
+                .is_none_or(|event_type| event_type == trace.event_type)
+            && self
+                .step_id
+                .as_deref()
+                .is_none_or(|step_id| step_id == trace.step_id)
+            && self
+                .tool
+                .as_deref()
+                .is_none_or(|tool| tool_of(trace) == Some(tool))
+            && self.since.is_none_or(|since| trace.ts >= since)
+            && self.until.is_none_or(|until| trace.ts < until)
+            && self
+                .min_cost_usd
+                .is_none_or(|min| trace.cost_usd.is_some_and(|cost| cost >= min))
+    }
+}
+
+/// One page of query results. Pass `next_cursor` back as the query's
+/// `cursor` to fetch the next page; it is `None` on the last page.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TracePage {
+    pub traces: Vec<Trace>,
+    pub next_cursor: Option<String>,
+}
+
+/// Where the kernel keeps the traces of finished executions, and how each
+/// run started through the API ended.
+#[async_trait]
+pub trait TraceStore: Send + Sync {
+    /// Stores the complete trace stream of `plan_id`, replacing any traces
+    /// stored for it before (a resumed plan records its whole stream again).
+    async fn save(&self, plan_id: &str, traces: &[Trace]) -> Result<(), TraceStoreError>;
+
+    /// Adds `traces` to the end of the stored stream of `plan_id`, e.g. as
+    /// a running execution records them.
+    async fn append(&self, plan_id: &str, traces: &[Trace]) -> Result<(), TraceStoreError>;
+
+    /// All traces of `plan_id` in order, or `None` if none were stored.
+    async fn load(&self, plan_id: &str) -> Result<Option<Vec<Trace>>, TraceStoreError>;
+
+    async fn query(&self, query: &TraceQuery) -> Result<TracePage, TraceStoreError>;
+
+    /// Stores the final status of a run, replacing any stored for it before.
+    async fn save_run(&self, run: &RunStatus) -> Result<(), TraceStoreError>;
+
+    /// The status of the run of `plan_id` stored last, if any.
+    async fn last_run(&self, plan_id: &str) -> Result<Option<RunStatus>, TraceStoreError>;
+
+    /// The stored status of the run `run_id`, if any.
+    async fn load_run(&self, run_id: &str) -> Result<Option<RunStatus>, TraceStoreError>;
+}
+
+/// Keeps traces in memory until the process exits.
+#[derive(Debug, Default)]
+pub struct MemoryTraceStore {
+    state: RwLock<MemoryState>,
+}
+
+#[derive(Debug, Default)]
+struct MemoryState {
+    next_seq: i64,
+    traces: BTreeMap<i64, (String, Trace)>,
+    plans: HashMap<String, Vec<i64>>,
+    runs: Vec<RunStatus>, // in the order they were stored
+}
+
+impl MemoryTraceStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl MemoryState {
+    fn append(&mut self, plan_id: &str, traces: &[Trace]) {
+        let mut seqs = Vec::with_capacity(traces.len());
+        for trace in traces {
+            self.next_seq += 1;
+            self.traces
+                .insert(self.next_seq, (plan_id.to_string(), trace.clone()));
+            seqs.push(self.next_seq);
+        }
+        self.plans
+            .entry(plan_id.to_string())
+            .or_default()
+            .extend(seqs);
+    }
+}
+
+#[async_trait]
+impl TraceStore for MemoryTraceStore {
+    async fn save(&self, plan_id: &str, traces: &[Trace]) -> Result<(), TraceStoreError> {
+        let mut state = self.state.write().await;
+        for seq in state.plans.remove(plan_id).unwrap_or_default() {
+            state.traces.remove(&seq);
+        }
+        state.append(plan_id, traces);
+        Ok(())
+    }
+
+    async fn append(&self, plan_id: &str, traces: &[Trace]) -> Result<(), TraceStoreError> {
+        self.state.write().await.append(plan_id, traces);
+        Ok(())
+    }
+
+    async fn load(&self, plan_id: &str) -> Result<Option<Vec<Trace>>, TraceStoreError> {
+        let state = self.state.read().await;
+        Ok(state.plans.get(plan_id).map(|seqs| {
+            seqs.iter()
+                .filter_map(|seq| state.traces.get(seq))
+                .map(|(_, trace)| trace.clone())
+                .collect()
+        }))
+    }
+
+    async fn query(&self, query: &TraceQuery) -> Result<TracePage, TraceStoreError> {
+        let page_size = query.page_size();
+        let state = self.state.read().await;
+        let mut matches = state
+            .traces
+            .range(query.after_seq()? + 1..)
+            .filter(|(_, (plan_id, trace))| query.matches(plan_id, trace));
+
+        let page: Vec<(i64, Trace)> = matches
+            .by_ref()
+            .take(page_size)
+            .map(|(seq, (_, trace))| (*seq, trace.clone()))
+            .collect();
+        let next_cursor = match (page.last(), matches.next()) {
+            (Some((seq, _)), Some(_)) => Some(seq.to_string()),
+            _ => None,
+        };
+        Ok(TracePage {
+            traces: page.into_iter().map(|(_, trace)| trace).collect(),
+            next_cursor,
+        })
+    }
+
+    async fn save_run(&self, run: &RunStatus) -> Result<(), TraceStoreError> {
+        let mut state = self.state.write().await;
+        state.runs.retain(|stored| stored.run_id != run.run_id);
+        state.runs.push(run.clone());
+        Ok(())
+    }
+
+    async fn last_run(&self, plan_id: &str) -> Result<Option<RunStatus>, TraceStoreError> {
+        let state = self.state.read().await;
+        Ok(state
+            .runs
+            .iter()
+            .rev()
+            .find(|run| run.plan_id == plan_id)
+            .cloned())
+    }
+
+    async fn load_run(&self, run_id: &str) -> Result<Option<RunStatus>, TraceStoreError> {
+        let state = self.state.read().await;
+        Ok(state.runs.iter().find(|run| run.run_id == run_id).cloned())
+    }
+}
+
+/// SQLite-backed trace storage that survives restarts.
+#[derive(Debug, Clone)]
+pub struct SqliteTraceStore {
+    pool: SqlitePool,
+    schema: Arc<OnceCell<()>>,
+}
+
+impl SqliteTraceStore {
+    /// Opens (creating if needed) a trace database at `path`. The connection
+    /// is established on first use.
+    pub fn open(path: impl AsRef<Path>) -> Self {
+        let options = SqliteConnectOptions::new()
+            .filename(path)
+            .create_if_missing(true);
+        Self::with_options(options)
+    }
+
+    /// A private in-memory database, mainly useful for tests.
+    pub fn in_memory() -> Self {
+        let options = SqliteConnectOptions::from_str("sqlite::memory:")
+            .expect("valid in-memory database url");
+        Self::with_options(options)
+    }
+
+    fn with_options(options: SqliteConnectOptions) -> Self {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .connect_lazy_with(options);
+        Self {
+            pool,
+            schema: Arc::new(OnceCell::new()),
+        }
+    }
+
+    async fn pool(&self) -> Result<&SqlitePool, TraceStoreError> {
+        self.schema
+            .get_or_try_init(|| async { (&self.pool).execute(SCHEMA).await.map(|_| ()) })
+            .await
+            .map_err(|e| TraceStoreError::Database(e.to_string()))?;
+        Ok(&self.pool)
+    }
+
+    /// Stores `traces` after those already stored for `plan_id`.
+    async fn insert(
+        tx: &mut sqlx::Transaction<'_, Sqlite>,
+        plan_id: &str,
+        traces: &[Trace],
+    ) -> Result<(), TraceStoreError> {
+        for trace in traces {
+            let encoded = serde_json::to_string(trace)
+                .map_err(|e| TraceStoreError::Serialization(e.to_string()))?;
+            sqlx::query(
+                "INSERT INTO traces (plan_id, run_id, step_id, event_type, tool, cost_usd, ts, trace)
+                 VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+            )
+            .bind(plan_id)
+            .bind(&trace.run_id)
+            .bind(&trace.step_id)
+            .bind(&trace.event_type)
+            .bind(tool_of(trace))
+            .bind(trace.cost_usd)
+            .bind(timestamp(&trace.ts))
+            .bind(encoded)
+            .execute(&mut **tx)
+            .await
+            .map_err(|e| TraceStoreError::Database(e.to_string()))?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl TraceStore for SqliteTraceStore {
+    async fn save(&self, plan_id: &str, traces: &[Trace]) -> Result<(), TraceStoreError> {
+        let mut tx = self
+            .pool()
+            .await?
+            .begin()
+            .await
+            .map_err(|e| TraceStoreError::Database(e.to_string()))?;
+
+        sqlx::query("DELETE FROM traces WHERE plan_id = ?")
+            .bind(plan_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| TraceStoreError::Database(e.to_string()))?;
+        Self::insert(&mut tx, plan_id, traces).await?;
+
+        tx.commit()
+            .await
+            .map_err(|e| TraceStoreError::Database(e.to_string()))
+    }
+
+    async fn append(&self, plan_id: &str, traces: &[Trace]) -> Result<(), TraceStoreError> {
+        let mut tx = self
+            .pool()
+            .await?
+            .begin()
+            .await
+            .map_err(|e| TraceStoreError::Database(e.to_string()))?;
+        Self::insert(&mut tx, plan_id, traces).await?;
+        tx.commit()
+            .await
+            .map_err(|e| TraceStoreError::Database(e.to_string()))
+    }
+
+    async fn load(&self, plan_id: &str) -> Result<Option<Vec<Trace>>, TraceStoreError> {
+        let rows = sqlx::query("SELECT trace FROM traces WHERE plan_id = ? ORDER BY seq")
+            .bind(plan_id)
+            .fetch_all(self.pool().await?)
+            .await
+            .map_err(|e| TraceStoreError::Database(e.to_string()))?;
+        if rows.is_empty() {
+            return Ok(None);
+        }
+
+        rows.iter()
+            .map(|row| decode(row.get("trace")))
+            .collect::<Result<Vec<_>, _>>()
+            .map(Some)
+    }
+
+    async fn query(&self, query: &TraceQuery) -> Result<TracePage, TraceStoreError> {
+        let page_size = query.page_size();
+        let mut sql: QueryBuilder<Sqlite> =
+            QueryBuilder::new("SELECT seq, trace FROM traces WHERE seq > ");
+        sql.push_bind(query.after_seq()?);
+        if let Some(plan_id) = &query.plan_id {
+            sql.push(" AND plan_id = ").push_bind(plan_id);
+        }
+        if let Some(event_type) = &query.event_type {
+            sql.push(" AND event_type = ").push_bind(event_type);
+        }
+        if let Some(step_id) = &query.step_id {
+            sql.push(" AND step_id = ").push_bind(step_id);
+        }
+        if let Some(tool) = &query.tool {
+            sql.push(" AND tool = ").push_bind(tool);
+        }
+        if let Some(since) = &query.since {
+            sql.push(" AND ts >= ").push_bind(timestamp(since));
+        }
+        if let Some(until) = &query.until {
+            sql.push(" AND ts < ").push_bind(timestamp(until));
+        }
+        if let Some(min_cost_usd) = query.min_cost_usd {
+            sql.push(" AND cost_usd >= ").push_bind(min_cost_usd);
+        }
+        // One extra row tells whether another page follows
+        sql.push(" ORDER BY seq LIMIT ")
+            .push_bind(page_size as i64 + 1);
+
+        let rows = sql
+            .build()
+            .fetch_all(self.pool().await?)
+            .await
+            .map_err(|e| TraceStoreError::Database(e.to_string()))?;
+
+        let next_cursor =
+            (rows.len() > page_size).then(|| rows[page_size - 1].get::<i64, _>("seq").to_string());
+        let traces = rows
+            .iter()
+            .take(page_size)
+            .map(|row| decode(row.get("trace")))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(TracePage {
+            traces,
+            next_cursor,
+        })
+    }
+
+    async fn save_run(&self, run: &RunStatus) -> Result<(), TraceStoreError> {
+        let encoded = serde_json::to_string(run)
+            .map_err(|e| TraceStoreError::Serialization(e.to_string()))?;
+        sqlx::query("INSERT OR REPLACE INTO runs (run_id, plan_id, status) VALUES (?, ?, ?)")
+            .bind(&run.run_id)
+            .bind(&run.plan_id)
+            .bind(encoded)
+            .execute(self.pool().await?)
+            .await
+            .map_err(|e| TraceStoreError::Database(e.to_string()))?;
+        Ok(())
+    }
+
+    async fn last_run(&self, plan_id: &str) -> Result<Option<RunStatus>, TraceStoreError> {
+        let row =
+            sqlx::query("SELECT status FROM runs WHERE plan_id = ? ORDER BY seq DESC LIMIT 1")
+                .bind(plan_id)
+                .fetch_optional(self.pool().await?)
+                .await
+                .map_err(|e| TraceStoreError::Database(e.to_string()))?;
+        row.map(|row| decode_run(row.get("status"))).transpose()
+    }
+
+    async fn load_run(&self, run_id: &str) -> Result<Option<RunStatus>, TraceStoreError> {
+        let row = sqlx::query("SELECT status FROM runs WHERE run_id = ?")
+            .bind(run_id)
+            .fetch_optional(self.pool().await?)
+            .await
+            .map_err(|e| TraceStoreError::Database(e.to_string()))?;
+        row.map(|row| decode_run(row.get("status"))).transpose()
+    }
+}
+
+/// The tool a trace is about, from its `data.tool` field.
+fn tool_of(trace: &Trace) -> Option<&str> {
+    trace.data.as_ref()?.get("tool")?.as_str()
+}
+
+/// Fixed-width UTC timestamps, so they compare correctly as text.
+fn timestamp(ts: &DateTime<Utc>) -> String {
+    ts.to_rfc3339_opts(SecondsFormat::Nanos, true)
+}
+
+fn decode(encoded: String) -> Result<Trace, TraceStoreError> {
+    serde_json::from_str(&encoded).map_err(|e| TraceStoreError::Serialization(e.to_string()))
+}
+
+fn decode_run(encoded: String) -> Result<RunStatus, TraceStoreError> {
+    serde_json::from_str(&encoded).map_err(|e| TraceStoreError::Serialization(e.to_string()))
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum TraceStoreError {
+    #[error("Trace database error: {0}")]
+    Database(String),
+    #[error("Trace serialization error: {0}")]
+    Serialization(String),
+    #[error("Invalid trace cursor: {0}")]
+    InvalidCursor(String),
+}
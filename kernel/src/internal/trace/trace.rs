@@ -4,8 +4,18 @@ use chrono::{DateTime, Utc};
 use ed25519_dalek::{Keypair, Signature, Signer, Verifier};
 use rand::rngs::OsRng;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::env;
+use std::fs;
+use std::path::Path;
 use uuid::Uuid;
 
+/// DER prefix of a PKCS#8 document holding an Ed25519 private key; the
+/// 32-byte secret follows it (RFC 8410).
+const PKCS8_ED25519_PREFIX: [u8; 16] = [
+    0x30, 0x2e, 0x02, 0x01, 0x00, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x04, 0x22, 0x04, 0x20,
+];
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Trace {
     pub plan_id: String,
@@ -18,9 +28,32 @@ pub struct Trace {
     pub citations: Option<Vec<String>>,
     pub signature: Option<String>,
     pub data: Option<serde_json::Value>,
+    /// Hash of the previous trace of the same stream, `None` for the first.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prev_hash: Option<String>,
+    /// Id of the key that signed the trace, see [`key_id`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_id: Option<String>,
+    /// The execution attempt that emitted the trace; a resumed plan keeps
+    /// its `plan_id` but gets a new run.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run_id: Option<String>,
+    /// Span the trace belongs to: the plan itself or one node execution.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub span_id: Option<String>,
+    /// Enclosing span, `None` for the plan's root span.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_span_id: Option<String>,
+}
+
+/// Creates a short random id for a span.
+pub fn new_span_id() -> String {
+    Uuid::new_v4().simple().to_string()[..16].to_string()
 }
 
 impl Trace {
+    /// Creates a trace with a placeholder `plan_id`. Traces pushed to an
+    /// `ExecutionContext` are stamped with the execution's ids.
     pub fn new(event_type: String, step_id: String, description: String) -> Self {
         Self {
             plan_id: Uuid::new_v4().to_string(),
@@ -33,6 +66,11 @@ impl Trace {
             citations: None,
             signature: None,
             data: Some(serde_json::json!({ "description": description })),
+            prev_hash: None,
+            key_id: None,
+            run_id: None,
+            span_id: None,
+            parent_span_id: None,
         }
     }
 
@@ -53,15 +91,32 @@ impl Trace {
             citations: None,
             signature: None,
             data: Some(serde_json::json!({ "description": description })),
+            prev_hash: None,
+            key_id: None,
+            run_id: None,
+            span_id: None,
+            parent_span_id: None,
         }
     }
 
+    /// Canonical serialization of every field except `signature`: compact
+    /// JSON with object keys sorted at every level. This is what gets signed
+    /// and hashed, so changing any other field invalidates both.
+    pub fn canonical_payload(&self) -> Vec<u8> {
+        let mut value = serde_json::to_value(self).expect("trace serializes to JSON");
+        if let serde_json::Value::Object(fields) = &mut value {
+            fields.remove("signature");
+        }
+        serde_json::to_vec(&canonicalize(value)).expect("trace serializes to JSON")
+    }
+
+    /// Hex SHA-256 of the canonical payload, as linked by the next trace.
+    pub fn hash(&self) -> String {
+        hex::encode(Sha256::digest(self.canonical_payload()))
+    }
+
     pub fn sign(&mut self, keypair: &Keypair) -> Result<(), TraceError> {
-        let message = format!(
-            "{}:{}:{}:{}",
-            self.plan_id, self.step_id, self.ts, self.event_type
-        );
-        let signature: Signature = keypair.sign(message.as_bytes());
+        let signature: Signature = keypair.sign(&self.canonical_payload());
         self.signature = Some(Base64Engine.encode(signature.to_bytes()));
         Ok(())
     }
@@ -86,13 +141,8 @@ impl Trace {
             let signature = ed25519_dalek::Signature::from_bytes(&sig_bytes)
                 .map_err(|e| TraceError::SignatureError(e.to_string()))?;
 
-            let message = format!(
-                "{}:{}:{}:{}",
-                self.plan_id, self.step_id, self.ts, self.event_type
-            );
-
             public_key
-                .verify(message.as_bytes(), &signature)
+                .verify(&self.canonical_payload(), &signature)
                 .map_err(|e| TraceError::SignatureError(e.to_string()))
                 .map(|_| true)
         } else {
@@ -103,11 +153,92 @@ impl Trace {
     }
 }
 
+/// Rebuilds `value` with object keys in sorted order, independently of how
+/// `serde_json` maps are ordered.
+fn canonicalize(value: serde_json::Value) -> serde_json::Value {
+    match value {
+        serde_json::Value::Object(fields) => {
+            let mut fields: Vec<_> = fields.into_iter().collect();
+            fields.sort_by(|(a, _), (b, _)| a.cmp(b));
+            serde_json::Value::Object(
+                fields
+                    .into_iter()
+                    .map(|(key, value)| (key, canonicalize(value)))
+                    .collect(),
+            )
+        }
+        serde_json::Value::Array(items) => {
+            serde_json::Value::Array(items.into_iter().map(canonicalize).collect())
+        }
+        other => other,
+    }
+}
+
+/// Links traces into a hash chain: each trace records the hash of the one
+/// appended before it.
+#[derive(Debug, Clone, Default)]
+pub struct TraceChain {
+    last_hash: Option<String>,
+}
+
+impl TraceChain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A chain that continues after `last`, e.g. traces restored from a
+    /// checkpoint.
+    pub fn following(last: Option<&Trace>) -> Self {
+        Self {
+            last_hash: last.map(Trace::hash),
+        }
+    }
+
+    /// Sets `trace.prev_hash` to the previous link and makes `trace` the new
+    /// head. Sign the trace afterwards so the signature covers the link.
+    pub fn link(&mut self, trace: &mut Trace) {
+        trace.prev_hash = self.last_hash.take();
+        self.last_hash = Some(trace.hash());
+    }
+}
+
+/// Checks that `traces` form an unbroken chain in which every trace is
+/// signed by `public_key`, and reports the first event where that fails.
+/// A chain cut short after its last event cannot be told apart from a
+/// complete one. Use [`Keyring::verify_chain`] for chains that span key
+/// rotations.
+pub fn verify_chain(
+    traces: &[Trace],
+    public_key: &ed25519_dalek::PublicKey,
+) -> Result<(), ChainBreak> {
+    let mut keyring = Keyring::new();
+    keyring.add(*public_key, false);
+    keyring.verify_chain(traces)
+}
+
+/// The first event at which a trace chain fails verification.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, thiserror::Error)]
+#[error("Trace chain broken at event {index} ({event_type} in step {step_id}): {reason}")]
+pub struct ChainBreak {
+    pub index: usize,
+    pub step_id: String,
+    pub event_type: String,
+    pub reason: String,
+}
+
 pub struct TraceSigner {
     keypair: Keypair,
     public_key: ed25519_dalek::PublicKey,
 }
 
+impl std::fmt::Debug for TraceSigner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TraceSigner")
+            .field("public_key", &self.public_key_base64())
+            .finish_non_exhaustive()
+    }
+}
+
 impl TraceSigner {
     pub fn new() -> Result<Self, TraceError> {
         let mut rng = OsRng::default();
@@ -120,19 +251,264 @@ impl TraceSigner {
         })
     }
 
+    /// Restores a signer from its 32-byte Ed25519 secret key.
+    pub fn from_secret_bytes(secret: &[u8]) -> Result<Self, TraceError> {
+        let secret = ed25519_dalek::SecretKey::from_bytes(secret)
+            .map_err(|e| TraceError::KeyError(e.to_string()))?;
+        let public_key = ed25519_dalek::PublicKey::from(&secret);
+
+        Ok(Self {
+            keypair: Keypair {
+                secret,
+                public: public_key,
+            },
+            public_key,
+        })
+    }
+
+    /// Parses a secret key given as a PKCS#8 PEM document (`BEGIN PRIVATE
+    /// KEY`, as written by `ampctl keygen` or `openssl genpkey -algorithm
+    /// ed25519`), as base64 or hex text, or as 32 raw bytes.
+    pub fn from_key_material(material: &[u8]) -> Result<Self, TraceError> {
+        if material.starts_with(b"-----BEGIN") {
+            let document = pem::parse(material).map_err(|e| TraceError::KeyError(e.to_string()))?;
+            let der = document.contents();
+            if document.tag() != "PRIVATE KEY" || !der.starts_with(&PKCS8_ED25519_PREFIX) {
+                return Err(TraceError::KeyError(format!(
+                    "Expected an Ed25519 PKCS#8 private key, got {}",
+                    document.tag()
+                )));
+            }
+            return Self::from_secret_bytes(&der[PKCS8_ED25519_PREFIX.len()..]);
+        }
+        if material.len() == ed25519_dalek::SECRET_KEY_LENGTH {
+            return Self::from_secret_bytes(material);
+        }
+
+        let text = std::str::from_utf8(material)
+            .map_err(|_| TraceError::KeyError("Unrecognized key format".to_string()))?
+            .trim();
+        let secret = hex::decode(text)
+            .ok()
+            .or_else(|| Base64Engine.decode(text).ok())
+            .ok_or_else(|| TraceError::KeyError("Unrecognized key format".to_string()))?;
+        Self::from_secret_bytes(&secret)
+    }
+
+    /// Loads a secret key file in any format [`Self::from_key_material`]
+    /// accepts.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, TraceError> {
+        let path = path.as_ref();
+        let material = fs::read(path)
+            .map_err(|e| TraceError::KeyError(format!("{}: {}", path.display(), e)))?;
+        Self::from_key_material(&material)
+    }
+
+    /// Loads the signing key named by `AMP_SIGNING_KEY_FILE`, or given inline
+    /// by `AMP_SIGNING_KEY`. Returns `None` when neither is set.
+    pub fn from_env() -> Result<Option<Self>, TraceError> {
+        if let Some(path) = env::var("AMP_SIGNING_KEY_FILE")
+            .ok()
+            .filter(|path| !path.is_empty())
+        {
+            return Self::from_file(path).map(Some);
+        }
+        match env::var("AMP_SIGNING_KEY") {
+            Ok(key) if !key.is_empty() => Self::from_key_material(key.as_bytes()).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    /// The secret key as a PKCS#8 PEM document.
+    pub fn to_pem(&self) -> String {
+        let mut der = PKCS8_ED25519_PREFIX.to_vec();
+        der.extend_from_slice(self.keypair.secret.as_bytes());
+        pem::encode(&pem::Pem::new("PRIVATE KEY", der))
+    }
+
+    /// The raw 32-byte secret key.
+    pub fn secret_bytes(&self) -> [u8; ed25519_dalek::SECRET_KEY_LENGTH] {
+        self.keypair.secret.to_bytes()
+    }
+
+    /// Id of this signer's key, stamped on every trace it signs.
+    pub fn key_id(&self) -> String {
+        key_id(&self.public_key)
+    }
+
     pub fn sign_trace(&self, trace: &mut Trace) -> Result<(), TraceError> {
+        trace.key_id = Some(self.key_id());
         trace.sign(&self.keypair)
     }
 
+    /// Appends `trace` to `chain` and signs it. The key id is stamped before
+    /// linking, so the next trace's `prev_hash` covers it.
+    pub fn sign_linked(&self, chain: &mut TraceChain, trace: &mut Trace) -> Result<(), TraceError> {
+        trace.key_id = Some(self.key_id());
+        chain.link(trace);
+        self.sign_trace(trace)
+    }
+
+    /// Links `traces` into a fresh hash chain and signs each of them.
+    pub fn sign_chain(&self, traces: &mut [Trace]) -> Result<(), TraceError> {
+        let mut chain = TraceChain::new();
+        for trace in traces {
+            self.sign_linked(&mut chain, trace)?;
+        }
+        Ok(())
+    }
+
     pub fn get_public_key(&self) -> &ed25519_dalek::PublicKey {
         &self.public_key
     }
+
+    /// Base64 encoding of the public key, as embedded in signed artifacts.
+    pub fn public_key_base64(&self) -> String {
+        encode_public_key(&self.public_key)
+    }
+
+    /// Signs an arbitrary message, returning the base64 signature.
+    pub fn sign_bytes(&self, message: &[u8]) -> String {
+        Base64Engine.encode(self.keypair.sign(message).to_bytes())
+    }
+}
+
+/// Encodes an Ed25519 public key as base64.
+pub fn encode_public_key(public_key: &ed25519_dalek::PublicKey) -> String {
+    Base64Engine.encode(public_key.as_bytes())
+}
+
+/// Decodes a base64 Ed25519 public key.
+pub fn decode_public_key(encoded: &str) -> Result<ed25519_dalek::PublicKey, TraceError> {
+    let bytes = Base64Engine
+        .decode(encoded)
+        .map_err(|e| TraceError::SignatureError(e.to_string()))?;
+    ed25519_dalek::PublicKey::from_bytes(&bytes)
+        .map_err(|e| TraceError::SignatureError(e.to_string()))
+}
+
+/// Checks a base64 signature produced by [`TraceSigner::sign_bytes`].
+pub fn verify_bytes(
+    public_key: &ed25519_dalek::PublicKey,
+    message: &[u8],
+    signature: &str,
+) -> Result<(), TraceError> {
+    let bytes = Base64Engine
+        .decode(signature)
+        .map_err(|e| TraceError::SignatureError(e.to_string()))?;
+    let signature =
+        Signature::from_bytes(&bytes).map_err(|e| TraceError::SignatureError(e.to_string()))?;
+    public_key
+        .verify(message, &signature)
+        .map_err(|e| TraceError::SignatureError(e.to_string()))
+}
+
+/// Short identifier of a public key: the first 16 hex digits of its SHA-256.
+pub fn key_id(public_key: &ed25519_dalek::PublicKey) -> String {
+    hex::encode(&Sha256::digest(public_key.as_bytes())[..8])
+}
+
+/// A public key known to a [`Keyring`].
+#[derive(Debug, Clone)]
+pub struct KeyringEntry {
+    pub key_id: String,
+    pub public_key: ed25519_dalek::PublicKey,
+    /// Retired keys no longer sign new traces but still verify old ones.
+    pub retired: bool,
+}
+
+/// The current and retired public keys that traces may be signed with,
+/// looked up by the `key_id` each signed trace carries.
+#[derive(Debug, Clone, Default)]
+pub struct Keyring {
+    entries: Vec<KeyringEntry>,
+}
+
+impl Keyring {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `public_key`, replacing any entry with the same id.
+    pub fn add(&mut self, public_key: ed25519_dalek::PublicKey, retired: bool) {
+        let key_id = key_id(&public_key);
+        self.entries.retain(|entry| entry.key_id != key_id);
+        self.entries.push(KeyringEntry {
+            key_id,
+            public_key,
+            retired,
+        });
+    }
+
+    pub fn entries(&self) -> &[KeyringEntry] {
+        &self.entries
+    }
+
+    pub fn get(&self, key_id: &str) -> Option<&ed25519_dalek::PublicKey> {
+        self.entries
+            .iter()
+            .find(|entry| entry.key_id == key_id)
+            .map(|entry| &entry.public_key)
+    }
+
+    /// Checks the signature of `trace` against the key named by its
+    /// `key_id`. Traces signed before key ids existed are accepted if any
+    /// key verifies them.
+    pub fn verify_trace(&self, trace: &Trace) -> Result<(), TraceError> {
+        match &trace.key_id {
+            Some(key_id) => {
+                let public_key = self.get(key_id).ok_or_else(|| {
+                    TraceError::SignatureError(format!("Unknown signing key {}", key_id))
+                })?;
+                trace.verify_signature(public_key).map(|_| ())
+            }
+            None => {
+                let mut last_error = TraceError::SignatureError("Keyring is empty".to_string());
+                for entry in &self.entries {
+                    match trace.verify_signature(&entry.public_key) {
+                        Ok(_) => return Ok(()),
+                        Err(e) => last_error = e,
+                    }
+                }
+                Err(last_error)
+            }
+        }
+    }
+
+    /// Like [`verify_chain`], but each trace may be signed by any key of
+    /// this keyring, so a chain may span key rotations.
+    pub fn verify_chain(&self, traces: &[Trace]) -> Result<(), ChainBreak> {
+        let mut expected_prev: Option<String> = None;
+        for (index, trace) in traces.iter().enumerate() {
+            let broken = |reason: String| ChainBreak {
+                index,
+                step_id: trace.step_id.clone(),
+                event_type: trace.event_type.clone(),
+                reason,
+            };
+
+            if let Err(e) = self.verify_trace(trace) {
+                return Err(broken(e.to_string()));
+            }
+            if trace.prev_hash != expected_prev {
+                return Err(broken(format!(
+                    "previous hash is {}, expected {}",
+                    trace.prev_hash.as_deref().unwrap_or("missing"),
+                    expected_prev.as_deref().unwrap_or("none (start of chain)")
+                )));
+            }
+            expected_prev = Some(trace.hash());
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
 pub enum TraceError {
     #[error("Signature error: {0}")]
     SignatureError(String),
+    #[error("Key error: {0}")]
+    KeyError(String),
 }
 
 #[cfg(test)]
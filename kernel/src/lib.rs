@@ -5,10 +5,20 @@ pub mod internal {
         pub mod ir;
     }
     pub mod tools {
+        pub mod auth;
+        pub mod mcp;
+        pub mod sdk;
         pub mod spec;
+        pub mod transport;
+        pub mod wasm;
     }
     pub mod exec {
+        pub mod bundle;
+        pub mod checkpoint;
         pub mod constraints;
+        pub mod expr;
+        pub mod replay;
+        pub mod run;
         pub mod scheduler;
     }
     pub mod evidence {
@@ -18,6 +28,8 @@ pub mod internal {
         pub mod store;
     }
     pub mod trace {
+        pub mod live;
+        pub mod store;
         pub mod trace;
     }
     pub mod policy {
@@ -35,4 +47,6 @@ pub use internal::mem::store::{MemoryEntry, MemoryError, MemoryStore};
 pub use internal::plan::ir::{Node, Operation, Plan, PlanValidationError, Signals};
 pub use internal::policy::policy::{PolicyContext, PolicyEngine, PolicyError, PolicyResult};
 pub use internal::tools::spec::{ToolClient, ToolError, ToolSpec};
-pub use internal::trace::trace::{Trace, TraceError, TraceSigner};
+pub use internal::trace::trace::{
+    verify_chain, ChainBreak, Keyring, Trace, TraceChain, TraceError, TraceSigner,
+};
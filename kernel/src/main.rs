@@ -13,7 +13,13 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .init();
 
     // Create the API router
-    let app = amp::internal::api::create_router();
+    let app = match amp::internal::api::create_router() {
+        Ok(app) => app,
+        Err(e) => {
+            tracing::error!("{}", e);
+            std::process::exit(1);
+        }
+    };
 
     let addr = SocketAddr::from(([0, 0, 0, 0], 7777));
     tracing::info!("AMP kernel API server starting on {}", addr);
@@ -0,0 +1,217 @@
+//! Tests for conditional `branch` execution
+
+use amp::internal::{
+    exec::scheduler::{ExecutionContext, ExecutionError, Scheduler},
+    plan::ir::{Edge, Node, Operation, Plan, PlanValidationError},
+};
+use axum::{
+    routing::{get, post},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use tokio::task::JoinHandle;
+
+#[derive(Deserialize)]
+struct ToolInvokeRequest {
+    args: Option<serde_json::Value>,
+}
+
+#[derive(Serialize)]
+struct ToolInvokeResponse {
+    result: serde_json::Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+async fn spawn_echo_server() -> (String, JoinHandle<()>) {
+    async fn handler(Json(payload): Json<ToolInvokeRequest>) -> Json<ToolInvokeResponse> {
+        Json(ToolInvokeResponse {
+            result: payload.args.unwrap_or(serde_json::Value::Null),
+            error: None,
+        })
+    }
+
+    async fn spec_handler() -> Json<serde_json::Value> {
+        Json(json!({
+            "name": "util.echo",
+            "description": "Echoes its arguments",
+            "io": {
+                "input": { "type": "object", "properties": null, "required": null, "items": null },
+                "output": { "type": "object", "properties": null, "required": null, "items": null }
+            },
+            "capabilities": ["util.echo"],
+            "constraints": null
+        }))
+    }
+
+    let app = Router::new()
+        .route("/invoke/util.echo", post(handler))
+        .route("/spec/util.echo", get(spec_handler));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let handle = tokio::spawn(async move {
+        axum::serve(listener, app.into_make_service())
+            .await
+            .expect("util.echo server error");
+    });
+    (format!("http://{}", addr), handle)
+}
+
+fn echo_node(id: &str) -> Node {
+    Node {
+        id: id.to_string(),
+        op: Operation::Call,
+        tool: Some("util.echo".to_string()),
+        capability: None,
+        args: Some(HashMap::from([("from".to_string(), json!(id))])),
+        bind: None,
+        timeout_ms: None,
+        retry: None,
+        out: Some(HashMap::from([(
+            format!("{}_out", id),
+            "result".to_string(),
+        )])),
+    }
+}
+
+fn branch_node(condition: serde_json::Value) -> Node {
+    Node {
+        id: "route".to_string(),
+        op: Operation::Branch,
+        tool: None,
+        capability: None,
+        args: Some(HashMap::from([
+            ("condition".to_string(), condition),
+            ("then".to_string(), json!(["then_a"])),
+            ("else".to_string(), json!("else_a")),
+        ])),
+        bind: None,
+        timeout_ms: None,
+        retry: None,
+        out: None,
+    }
+}
+
+fn edge(from: &str, to: &str) -> Edge {
+    Edge {
+        from: from.to_string(),
+        to: to.to_string(),
+    }
+}
+
+/// route -> then_a -> then_b -> join
+/// route -> else_a -> else_b -> join
+fn branching_plan(condition: serde_json::Value) -> Plan {
+    Plan {
+        signals: None,
+        nodes: vec![
+            branch_node(condition),
+            echo_node("then_a"),
+            echo_node("then_b"),
+            echo_node("else_a"),
+            echo_node("else_b"),
+            echo_node("join"),
+        ],
+        edges: Some(vec![
+            edge("route", "then_a"),
+            edge("then_a", "then_b"),
+            edge("then_b", "join"),
+            edge("route", "else_a"),
+            edge("else_a", "else_b"),
+            edge("else_b", "join"),
+        ]),
+        stop_conditions: None,
+        outputs: None,
+    }
+}
+
+async fn run(
+    plan: &Plan,
+    variables: serde_json::Value,
+) -> Result<ExecutionContext, ExecutionError> {
+    let (url, handle) = spawn_echo_server().await;
+    let mut ctx = ExecutionContext::new();
+    ctx.tool_urls.insert("util.echo".to_string(), url);
+    if let serde_json::Value::Object(map) = variables {
+        ctx.variables = map.into_iter().collect();
+    }
+    let result = Scheduler.execute_plan(ctx, plan).await;
+    handle.abort();
+    result
+}
+
+#[tokio::test]
+async fn test_branch_takes_then_arm_and_skips_else_arm() {
+    let plan = branching_plan(json!("$review.required"));
+    let ctx = run(&plan, json!({ "review": { "required": true } }))
+        .await
+        .expect("branching plan should succeed");
+
+    for id in ["route", "then_a", "then_b", "join"] {
+        assert!(ctx.completed_nodes.contains(id), "{} should run", id);
+    }
+    for id in ["else_a", "else_b"] {
+        assert!(ctx.skipped_nodes.contains(id), "{} should be skipped", id);
+        assert!(!ctx.variables.contains_key(&format!("{}_out", id)));
+    }
+
+    let skipped_traces: Vec<&str> = ctx
+        .trace_events
+        .iter()
+        .filter(|trace| trace.event_type == "branch_skipped")
+        .map(|trace| trace.step_id.as_str())
+        .collect();
+    assert_eq!(skipped_traces, vec!["else_a", "else_b"]);
+
+    let decision = ctx
+        .trace_events
+        .iter()
+        .find(|trace| trace.event_type == "branch_decision")
+        .expect("branch decision trace");
+    assert_eq!(decision.data.as_ref().unwrap()["arm"], json!("then"));
+}
+
+#[tokio::test]
+async fn test_branch_takes_else_arm_on_falsy_condition() {
+    let plan = branching_plan(json!("$review.required"));
+    let ctx = run(&plan, json!({ "review": { "required": false } }))
+        .await
+        .expect("branching plan should succeed");
+
+    for id in ["else_a", "else_b", "join"] {
+        assert!(ctx.completed_nodes.contains(id), "{} should run", id);
+    }
+    for id in ["then_a", "then_b"] {
+        assert!(ctx.skipped_nodes.contains(id), "{} should be skipped", id);
+    }
+}
+
+#[tokio::test]
+async fn test_branch_condition_with_unknown_variable_fails() {
+    let plan = branching_plan(json!("$missing.flag"));
+    let result = run(&plan, json!({})).await;
+
+    match result {
+        Err(ExecutionError::ValidationError(msg)) => {
+            assert!(
+                msg.contains("unknown variable"),
+                "unexpected message: {}",
+                msg
+            )
+        }
+        other => panic!("Expected validation error, got {:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn test_branch_targets_must_be_successors() {
+    let mut plan = branching_plan(json!(true));
+    plan.edges = Some(vec![edge("route", "then_a")]);
+
+    assert!(matches!(
+        plan.validate(),
+        Err(PlanValidationError::InvalidBranch(_))
+    ));
+}
@@ -0,0 +1,324 @@
+//! Tests for signed tar.gz replay bundle archives
+
+use amp::internal::{
+    exec::bundle::{self, BundleError, BundleManifest},
+    exec::replay::ReplayBundle,
+    exec::scheduler::{ExecutionContext, Scheduler},
+    plan::ir::{Node, Operation, Plan},
+    trace::trace::{Keyring, TraceSigner},
+};
+use axum::{
+    routing::{get, post},
+    Json, Router,
+};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use std::io::Read;
+
+#[derive(Deserialize)]
+struct ToolInvokeRequest {
+    args: Option<serde_json::Value>,
+}
+
+#[derive(Serialize)]
+struct ToolInvokeResponse {
+    result: serde_json::Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Runs a one-node `util.echo` plan against a stub server and records it.
+async fn recorded_bundle() -> ReplayBundle {
+    async fn echo(Json(payload): Json<ToolInvokeRequest>) -> Json<ToolInvokeResponse> {
+        Json(ToolInvokeResponse {
+            result: payload.args.unwrap_or(serde_json::Value::Null),
+            error: None,
+        })
+    }
+
+    async fn spec() -> Json<serde_json::Value> {
+        Json(json!({
+            "name": "util.echo",
+            "description": "Echoes its arguments",
+            "io": {
+                "input": { "type": "object" },
+                "output": { "type": "object" }
+            },
+            "capabilities": [],
+            "constraints": null
+        }))
+    }
+
+    let app = Router::new()
+        .route("/invoke/util.echo", post(echo))
+        .route("/spec/util.echo", get(spec));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let handle = tokio::spawn(async move {
+        axum::serve(listener, app.into_make_service())
+            .await
+            .expect("util.echo server error");
+    });
+
+    let plan = Plan {
+        signals: None,
+        nodes: vec![Node {
+            id: "echo".to_string(),
+            op: Operation::Call,
+            tool: Some("util.echo".to_string()),
+            capability: None,
+            args: Some(HashMap::from([("q".to_string(), json!("$question"))])),
+            bind: None,
+            timeout_ms: None,
+            retry: None,
+            out: Some(HashMap::from([(
+                "answer".to_string(),
+                "result.q".to_string(),
+            )])),
+        }],
+        edges: None,
+        stop_conditions: None,
+        outputs: None,
+    };
+    let mut ctx = ExecutionContext::new();
+    ctx.tool_urls
+        .insert("util.echo".to_string(), format!("http://{}", addr));
+    ctx.variables
+        .insert("question".to_string(), json!("refund window?"));
+    let inputs = ctx.variables.clone();
+
+    let (ctx, result) = Scheduler.run(ctx, &plan).await;
+    handle.abort();
+    result.expect("plan should succeed");
+    ReplayBundle::record("run-1", &plan, inputs, &ctx, None)
+}
+
+fn entries(archive: &[u8]) -> Vec<(String, Vec<u8>)> {
+    let mut archive = tar::Archive::new(GzDecoder::new(archive));
+    archive
+        .entries()
+        .unwrap()
+        .map(|entry| {
+            let mut entry = entry.unwrap();
+            let path = entry.path().unwrap().to_string_lossy().into_owned();
+            let mut content = Vec::new();
+            entry.read_to_end(&mut content).unwrap();
+            (path, content)
+        })
+        .collect()
+}
+
+fn build(entries: Vec<(String, Vec<u8>)>) -> Vec<u8> {
+    let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+    for (path, content) in entries {
+        let mut header = tar::Header::new_ustar();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        builder
+            .append_data(&mut header, path, content.as_slice())
+            .unwrap();
+    }
+    builder.into_inner().unwrap().finish().unwrap()
+}
+
+/// Rebuilds `archive` with the content of `path` replaced.
+fn replace(archive: &[u8], path: &str, content: Vec<u8>) -> Vec<u8> {
+    let mut content = Some(content);
+    build(
+        entries(archive)
+            .into_iter()
+            .map(|(name, old)| {
+                let new = if name == path { content.take() } else { None };
+                (name, new.unwrap_or(old))
+            })
+            .collect(),
+    )
+}
+
+/// A keyring trusting only `signer`.
+fn trusting(signer: &TraceSigner) -> Keyring {
+    let mut keyring = Keyring::new();
+    keyring.add(*signer.get_public_key(), false);
+    keyring
+}
+
+fn manifest_of(archive: &[u8]) -> BundleManifest {
+    let (_, content) = entries(archive)
+        .into_iter()
+        .find(|(path, _)| path == "manifest.json")
+        .unwrap();
+    serde_json::from_slice(&content).unwrap()
+}
+
+#[tokio::test]
+async fn test_bundle_archive_round_trips_and_replays() {
+    let recorded = recorded_bundle().await;
+    let signer = TraceSigner::new().unwrap();
+    let archive = bundle::pack(&recorded, &signer).unwrap();
+
+    let paths: Vec<String> = entries(&archive)
+        .into_iter()
+        .map(|(path, _)| path)
+        .collect();
+    assert_eq!(paths[0], "manifest.json");
+    for expected in [
+        "run.json",
+        "plan.json",
+        "inputs.json",
+        "toolspecs/util.echo.json",
+        "traces.jsonl",
+        "tool_io.jsonl",
+    ] {
+        assert!(paths.contains(&expected.to_string()), "{:?}", paths);
+    }
+
+    let (bundle, manifest) = bundle::unpack(&archive, &trusting(&signer)).unwrap();
+    assert_eq!(manifest.format, "amp-replay-bundle");
+    assert_eq!(manifest.plan_id, "run-1");
+    assert_eq!(manifest.public_key, signer.public_key_base64());
+    assert_eq!(manifest.files.len(), paths.len() - 1);
+    assert_eq!(bundle.inputs, recorded.inputs);
+    assert_eq!(bundle.tool_io, recorded.tool_io);
+    assert_eq!(bundle.traces.len(), recorded.traces.len());
+    assert_eq!(bundle.tools, vec!["util.echo"]);
+    assert!(bundle.tool_specs.contains_key("util.echo"));
+
+    let report = Scheduler.replay(&bundle).await;
+    assert!(report.is_faithful(), "{:?}", report.divergence);
+}
+
+#[tokio::test]
+async fn test_tampered_files_are_rejected() {
+    let recorded = recorded_bundle().await;
+    let signer = TraceSigner::new().unwrap();
+    let archive = bundle::pack(&recorded, &signer).unwrap();
+
+    let (_, tool_io) = entries(&archive)
+        .into_iter()
+        .find(|(path, _)| path == "tool_io.jsonl")
+        .unwrap();
+    let forged = String::from_utf8(tool_io)
+        .unwrap()
+        .replace("refund window?", "no refunds");
+    let tampered = replace(&archive, "tool_io.jsonl", forged.clone().into_bytes());
+
+    match bundle::unpack(&tampered, &trusting(&signer)) {
+        Err(BundleError::Integrity(msg)) => assert!(msg.starts_with("tool_io.jsonl"), "{}", msg),
+        other => panic!("Expected integrity error, got {:?}", other.map(|_| ())),
+    }
+
+    // Updating the manifest to match breaks its signature instead
+    let mut manifest = manifest_of(&archive);
+    manifest.files.insert(
+        "tool_io.jsonl".to_string(),
+        hex::encode(<sha2::Sha256 as sha2::Digest>::digest(forged.as_bytes())),
+    );
+    let tampered = replace(
+        &tampered,
+        "manifest.json",
+        serde_json::to_vec(&manifest).unwrap(),
+    );
+    assert!(matches!(
+        bundle::unpack(&tampered, &trusting(&signer)),
+        Err(BundleError::Signature(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_unlisted_and_missing_files_are_rejected() {
+    let recorded = recorded_bundle().await;
+    let signer = TraceSigner::new().unwrap();
+    let archive = bundle::pack(&recorded, &signer).unwrap();
+
+    let mut extra = entries(&archive);
+    extra.push(("toolspecs/evil.json".to_string(), b"{}".to_vec()));
+    match bundle::unpack(&build(extra), &trusting(&signer)) {
+        Err(BundleError::Integrity(msg)) => {
+            assert_eq!(msg, "toolspecs/evil.json is not listed in the manifest")
+        }
+        other => panic!("Expected integrity error, got {:?}", other.map(|_| ())),
+    }
+
+    let missing: Vec<_> = entries(&archive)
+        .into_iter()
+        .filter(|(path, _)| path != "traces.jsonl")
+        .collect();
+    match bundle::unpack(&build(missing), &trusting(&signer)) {
+        Err(BundleError::Integrity(msg)) => assert_eq!(msg, "traces.jsonl is missing"),
+        other => panic!("Expected integrity error, got {:?}", other.map(|_| ())),
+    }
+}
+
+#[tokio::test]
+async fn test_bundles_from_untrusted_keys_are_rejected() {
+    let recorded = recorded_bundle().await;
+    let kernel = TraceSigner::new().unwrap();
+    let impostor = TraceSigner::new().unwrap();
+
+    // A bundle re-signed by another key is intact but not from the kernel
+    let archive = bundle::pack(&recorded, &impostor).unwrap();
+    assert!(bundle::unpack(&archive, &trusting(&impostor)).is_ok());
+    match bundle::unpack(&archive, &trusting(&kernel)) {
+        Err(BundleError::Signature(msg)) => assert!(msg.contains("not a trusted key"), "{}", msg),
+        other => panic!("Expected signature error, got {:?}", other.map(|_| ())),
+    }
+    assert!(matches!(
+        bundle::unpack(&archive, &Keyring::new()),
+        Err(BundleError::Signature(_))
+    ));
+
+    // Keys retired by rotation still vouch for the bundles they signed
+    let mut keyring = trusting(&kernel);
+    keyring.add(*impostor.get_public_key(), true);
+    assert!(bundle::unpack(&archive, &keyring).is_ok());
+
+    // A manifest claiming the kernel's key must carry the kernel's signature
+    let mut manifest = manifest_of(&archive);
+    manifest.public_key = kernel.public_key_base64();
+    let forged = replace(
+        &archive,
+        "manifest.json",
+        serde_json::to_vec(&manifest).unwrap(),
+    );
+    assert!(matches!(
+        bundle::unpack(&forged, &trusting(&kernel)),
+        Err(BundleError::Signature(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_kernel_only_replays_bundles_from_its_keyring() {
+    let recorded = recorded_bundle().await;
+    let impostor = TraceSigner::new().unwrap();
+    let archive = bundle::pack(&recorded, &impostor).unwrap();
+
+    let app = amp::internal::api::create_router().unwrap();
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let kernel = format!("http://{}", listener.local_addr().unwrap());
+    let handle = tokio::spawn(async move {
+        axum::serve(listener, app.into_make_service())
+            .await
+            .expect("kernel api server error");
+    });
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/v1/replay", kernel))
+        .body(archive)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::UNPROCESSABLE_ENTITY);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert!(
+        body["error"]
+            .as_str()
+            .unwrap()
+            .contains("not a trusted key"),
+        "{}",
+        body
+    );
+
+    handle.abort();
+}
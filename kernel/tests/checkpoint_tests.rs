@@ -0,0 +1,386 @@
+//! Tests for checkpointing plan executions to SQLite and resuming them
+
+use amp::internal::{
+    exec::checkpoint::{CheckpointStatus, CheckpointStore},
+    exec::scheduler::{ExecutionContext, ExecutionError, Scheduler},
+    plan::ir::{Edge, Node, Operation, Plan},
+};
+use axum::{
+    extract::{Path, State},
+    routing::{get, post},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+#[derive(Deserialize)]
+struct ToolInvokeRequest {
+    args: Option<serde_json::Value>,
+}
+
+#[derive(Serialize)]
+struct ToolInvokeResponse {
+    result: serde_json::Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+type CallCounts = Arc<Mutex<HashMap<String, usize>>>;
+
+/// Serves `util.echo` and `notify.send`, a tool with side effects. Calls are
+/// counted by `"{tool}:{item or msg}"`. `notify.send` fails the first time it
+/// sees the item "boom" and takes two seconds to answer the message "slow".
+async fn spawn_tool_server() -> (String, CallCounts, JoinHandle<()>) {
+    fn count(calls: &CallCounts, key: String) -> usize {
+        let mut calls = calls.lock().unwrap();
+        let count = calls.entry(key).or_insert(0);
+        *count += 1;
+        *count
+    }
+
+    fn label(args: &serde_json::Value) -> String {
+        args.get("item")
+            .or_else(|| args.get("msg"))
+            .and_then(|v| v.as_str())
+            .unwrap_or_default()
+            .to_string()
+    }
+
+    async fn echo(
+        State(calls): State<CallCounts>,
+        Json(payload): Json<ToolInvokeRequest>,
+    ) -> Json<ToolInvokeResponse> {
+        let args = payload.args.unwrap_or(serde_json::Value::Null);
+        count(&calls, format!("util.echo:{}", label(&args)));
+        Json(ToolInvokeResponse {
+            result: args,
+            error: None,
+        })
+    }
+
+    async fn notify(
+        State(calls): State<CallCounts>,
+        Json(payload): Json<ToolInvokeRequest>,
+    ) -> Json<ToolInvokeResponse> {
+        let args = payload.args.unwrap_or(serde_json::Value::Null);
+        let label = label(&args);
+        let seen = count(&calls, format!("notify.send:{}", label));
+        if label == "slow" {
+            tokio::time::sleep(Duration::from_secs(2)).await;
+        }
+        if label == "boom" && seen == 1 {
+            return Json(ToolInvokeResponse {
+                result: serde_json::Value::Null,
+                error: Some("notification service unavailable".to_string()),
+            });
+        }
+        Json(ToolInvokeResponse {
+            result: json!({ "sent": label }),
+            error: None,
+        })
+    }
+
+    async fn spec(Path(name): Path<String>) -> Json<serde_json::Value> {
+        Json(json!({
+            "name": name,
+            "description": "Test tool",
+            "io": {
+                "input": { "type": "object" },
+                "output": { "type": "object" }
+            },
+            "capabilities": [],
+            "constraints": {
+                "input_tokens_max": null,
+                "latency_p50_ms": null,
+                "cost_per_call_usd": 0.01,
+                "rate_limit_qps": null,
+                "side_effects": name == "notify.send"
+            }
+        }))
+    }
+
+    let calls: CallCounts = Arc::new(Mutex::new(HashMap::new()));
+    let app = Router::new()
+        .route("/invoke/util.echo", post(echo))
+        .route("/invoke/notify.send", post(notify))
+        .route("/spec/:name", get(spec))
+        .with_state(calls.clone());
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let handle = tokio::spawn(async move {
+        axum::serve(listener, app.into_make_service())
+            .await
+            .expect("tool server error");
+    });
+    (format!("http://{}", addr), calls, handle)
+}
+
+fn node(id: &str, op: Operation, tool: &str, args: serde_json::Value) -> Node {
+    let args = match args {
+        serde_json::Value::Object(map) => map.into_iter().collect(),
+        _ => HashMap::new(),
+    };
+
+    Node {
+        id: id.to_string(),
+        op,
+        tool: Some(tool.to_string()),
+        capability: None,
+        args: Some(args),
+        bind: None,
+        timeout_ms: None,
+        retry: None,
+        out: Some(HashMap::from([(
+            format!("{}_out", id),
+            "result".to_string(),
+        )])),
+    }
+}
+
+/// `greet` echoes a message, then `notify` sends one notification per item.
+fn notify_plan(notify: Node) -> Plan {
+    Plan {
+        signals: None,
+        nodes: vec![
+            node(
+                "greet",
+                Operation::Call,
+                "util.echo",
+                json!({ "msg": "hi" }),
+            ),
+            notify,
+        ],
+        edges: Some(vec![Edge {
+            from: "greet".to_string(),
+            to: "notify".to_string(),
+        }]),
+        stop_conditions: None,
+        outputs: None,
+    }
+}
+
+fn context(url: &str, store: &CheckpointStore, plan_id: &str) -> ExecutionContext {
+    let mut ctx = ExecutionContext::new();
+    ctx.tool_urls
+        .insert("util.echo".to_string(), url.to_string());
+    ctx.tool_urls
+        .insert("notify.send".to_string(), url.to_string());
+    ctx.enable_checkpoints(store.clone(), plan_id);
+    ctx
+}
+
+fn calls_for(calls: &CallCounts, key: &str) -> usize {
+    calls.lock().unwrap().get(key).copied().unwrap_or(0)
+}
+
+#[tokio::test]
+async fn test_resume_continues_after_failed_node_without_repeating_side_effects() {
+    let (url, calls, handle) = spawn_tool_server().await;
+    let store = CheckpointStore::in_memory();
+    let plan = notify_plan(node(
+        "notify",
+        Operation::Map,
+        "notify.send",
+        json!({ "collection": ["one", "boom", "two"] }),
+    ));
+
+    let first = Scheduler
+        .execute_plan(context(&url, &store, "plan-1"), &plan)
+        .await;
+    assert!(matches!(first, Err(ExecutionError::ToolExecutionError(_))));
+
+    let checkpoint = store.load("plan-1").await.unwrap().expect("checkpoint");
+    assert_eq!(checkpoint.status, CheckpointStatus::Failed);
+    assert_eq!(checkpoint.completed_nodes, vec!["greet".to_string()]);
+    assert_eq!(checkpoint.variables["greet_out"], json!({ "msg": "hi" }));
+    assert!(checkpoint.error.unwrap().contains("unavailable"));
+
+    let ctx = Scheduler
+        .resume(context(&url, &store, "plan-1"), store.clone(), "plan-1")
+        .await
+        .expect("resume should succeed");
+    handle.abort();
+
+    assert_eq!(calls_for(&calls, "util.echo:hi"), 1);
+    assert_eq!(calls_for(&calls, "notify.send:one"), 1);
+    assert_eq!(calls_for(&calls, "notify.send:boom"), 2);
+    assert_eq!(calls_for(&calls, "notify.send:two"), 1);
+    assert_eq!(
+        ctx.variables["notify_out"],
+        json!([{ "sent": "one" }, { "sent": "boom" }, { "sent": "two" }])
+    );
+
+    let events: Vec<&str> = ctx
+        .trace_events
+        .iter()
+        .map(|trace| trace.event_type.as_str())
+        .collect();
+    assert!(events.contains(&"plan_resumed"));
+    assert!(events.contains(&"tool_replayed"));
+
+    let checkpoint = store.load("plan-1").await.unwrap().unwrap();
+    assert_eq!(checkpoint.status, CheckpointStatus::Completed);
+}
+
+#[tokio::test]
+async fn test_interrupted_side_effect_is_not_invoked_again() {
+    let (url, calls, handle) = spawn_tool_server().await;
+    let store = CheckpointStore::in_memory();
+    let plan = notify_plan(node(
+        "notify",
+        Operation::Call,
+        "notify.send",
+        json!({ "msg": "slow" }),
+    ));
+
+    // Kill the execution while the side-effecting call is in flight
+    let run = tokio::spawn({
+        let ctx = context(&url, &store, "plan-2");
+        let plan = plan.clone();
+        async move { Scheduler.execute_plan(ctx, &plan).await.map(|_| ()) }
+    });
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    run.abort();
+    let _ = run.await;
+
+    let checkpoint = store.load("plan-2").await.unwrap().expect("checkpoint");
+    assert_eq!(checkpoint.status, CheckpointStatus::Running);
+    assert_eq!(checkpoint.completed_nodes, vec!["greet".to_string()]);
+
+    let resumed = Scheduler
+        .resume(context(&url, &store, "plan-2"), store.clone(), "plan-2")
+        .await;
+    handle.abort();
+
+    match resumed {
+        Err(ExecutionError::CheckpointError(msg)) => {
+            assert!(msg.contains("notify.send"), "{}", msg);
+            assert!(msg.contains("interrupted"), "{}", msg);
+        }
+        other => panic!("Expected checkpoint error, got {:?}", other.map(|_| ())),
+    }
+    assert_eq!(calls_for(&calls, "notify.send:slow"), 1);
+    assert_eq!(calls_for(&calls, "util.echo:hi"), 1);
+}
+
+#[tokio::test]
+async fn test_resuming_a_completed_plan_runs_nothing() {
+    let (url, calls, handle) = spawn_tool_server().await;
+    let store = CheckpointStore::in_memory();
+    let plan = notify_plan(node(
+        "notify",
+        Operation::Call,
+        "notify.send",
+        json!({ "msg": "done" }),
+    ));
+
+    Scheduler
+        .execute_plan(context(&url, &store, "plan-3"), &plan)
+        .await
+        .expect("plan should succeed");
+    let ctx = Scheduler
+        .resume(context(&url, &store, "plan-3"), store.clone(), "plan-3")
+        .await
+        .expect("resume should succeed");
+    handle.abort();
+
+    assert_eq!(ctx.completed_nodes.len(), 2);
+    assert_eq!(ctx.variables["notify_out"], json!({ "sent": "done" }));
+    assert_eq!(calls_for(&calls, "notify.send:done"), 1);
+
+    match Scheduler
+        .resume(ExecutionContext::new(), store, "unknown")
+        .await
+    {
+        Err(ExecutionError::CheckpointError(msg)) => {
+            assert!(msg.contains("No checkpoint found"), "{}", msg)
+        }
+        other => panic!("Expected checkpoint error, got {:?}", other.map(|_| ())),
+    }
+}
+
+#[tokio::test]
+async fn test_timed_out_side_effect_is_neither_retried_nor_resumed() {
+    let (url, calls, handle) = spawn_tool_server().await;
+    let store = CheckpointStore::in_memory();
+    let mut notify = node(
+        "notify",
+        Operation::Call,
+        "notify.send",
+        json!({ "msg": "slow" }),
+    );
+    notify.timeout_ms = Some(300);
+    let plan = notify_plan(notify);
+
+    // The call may have gone through after all, so the default policy's
+    // retries on timeout do not apply
+    let first = Scheduler
+        .execute_plan(context(&url, &store, "plan-4"), &plan)
+        .await;
+    assert!(matches!(first, Err(ExecutionError::TimeoutError(_))));
+    assert_eq!(calls_for(&calls, "notify.send:slow"), 1);
+
+    let resumed = Scheduler
+        .resume(context(&url, &store, "plan-4"), store.clone(), "plan-4")
+        .await;
+    handle.abort();
+
+    match resumed {
+        Err(ExecutionError::CheckpointError(msg)) => {
+            assert!(msg.contains("timed out"), "{}", msg)
+        }
+        other => panic!("Expected checkpoint error, got {:?}", other.map(|_| ())),
+    }
+    assert_eq!(calls_for(&calls, "notify.send:slow"), 1);
+}
+
+#[tokio::test]
+async fn test_nodes_are_checkpointed_as_they_finish() {
+    let (url, calls, handle) = spawn_tool_server().await;
+    let store = CheckpointStore::in_memory();
+    let mut plan = notify_plan(node(
+        "notify",
+        Operation::Call,
+        "notify.send",
+        json!({ "msg": "slow" }),
+    ));
+    // Both nodes run in the same round
+    plan.edges = None;
+
+    let run = tokio::spawn({
+        let ctx = context(&url, &store, "plan-5");
+        let plan = plan.clone();
+        async move { Scheduler.execute_plan(ctx, &plan).await.map(|_| ()) }
+    });
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    run.abort();
+    let _ = run.await;
+
+    let checkpoint = store.load("plan-5").await.unwrap().expect("checkpoint");
+    assert_eq!(checkpoint.completed_nodes, vec!["greet".to_string()]);
+    assert!(checkpoint
+        .trace_events
+        .iter()
+        .any(|trace| trace.step_id == "greet"));
+
+    // The stored traces carry on from those of the interrupted run
+    let stored = checkpoint.trace_events.len();
+    let resumed = Scheduler
+        .resume(context(&url, &store, "plan-5"), store.clone(), "plan-5")
+        .await;
+    handle.abort();
+    assert!(matches!(resumed, Err(ExecutionError::CheckpointError(_))));
+    assert_eq!(calls_for(&calls, "util.echo:hi"), 1);
+
+    let checkpoint = store.load("plan-5").await.unwrap().unwrap();
+    assert_eq!(checkpoint.status, CheckpointStatus::Failed);
+    let events: Vec<&str> = checkpoint.trace_events[stored..]
+        .iter()
+        .map(|trace| trace.event_type.as_str())
+        .collect();
+    assert_eq!(events.first(), Some(&"plan_resumed"));
+}
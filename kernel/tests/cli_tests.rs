@@ -14,6 +14,7 @@ async fn test_plan_execution_with_variables() {
         signals: Some(Signals {
             latency_budget_ms: Some(5000),
             cost_cap_usd: Some(1.0),
+            fuel_cap: None,
             risk: Some(0.1),
         }),
         nodes: vec![Node {
@@ -23,6 +24,8 @@ async fn test_plan_execution_with_variables() {
             capability: None,
             args: Some(HashMap::from([("q".to_string(), json!("initial query"))])),
             bind: None,
+            timeout_ms: None,
+            retry: None,
             out: Some(HashMap::from([(
                 "query_result".to_string(),
                 "result".to_string(),
@@ -30,6 +33,7 @@ async fn test_plan_execution_with_variables() {
         }],
         edges: None,
         stop_conditions: None,
+        outputs: None,
     };
 
     // Create execution context with initial variables
@@ -13,6 +13,7 @@ fn test_budget_creation_and_checking() {
     let signals = Some(Signals {
         latency_budget_ms: Some(5000),
         cost_cap_usd: Some(10.0),
+        fuel_cap: None,
         risk: Some(0.1),
     });
 
@@ -37,6 +38,7 @@ fn test_constraint_violations() {
     let signals = Some(Signals {
         latency_budget_ms: Some(100), // Very tight budget
         cost_cap_usd: Some(0.01),     // Very low budget
+        fuel_cap: None,
         risk: Some(0.1),
     });
 
@@ -49,10 +51,13 @@ fn test_constraint_violations() {
             capability: None,
             args: Some(HashMap::new()),
             bind: None,
+            timeout_ms: None,
+            retry: None,
             out: None,
         }],
         edges: None,
         stop_conditions: None,
+        outputs: None,
     };
 
     let tool_spec = ToolSpec {
@@ -1,6 +1,6 @@
 use amp::internal::{
     exec::scheduler::ExecutionContext,
-    plan::ir::{Edge, Node, Operation, Plan, Signals},
+    plan::ir::{Node, Operation, Plan, Signals},
 };
 use serde_json::json;
 use std::collections::HashMap;
@@ -11,6 +11,7 @@ async fn test_e2e_quickstart() {
         signals: Some(Signals {
             latency_budget_ms: Some(5000),
             cost_cap_usd: Some(1.0),
+            fuel_cap: None,
             risk: Some(0.1),
         }),
         nodes: vec![Node {
@@ -20,16 +21,16 @@ async fn test_e2e_quickstart() {
             capability: None,
             args: Some(HashMap::from([("q".to_string(), json!("test query"))])),
             bind: None,
+            timeout_ms: None,
+            retry: None,
             out: Some(HashMap::from([(
                 "result".to_string(),
                 "result".to_string(),
             )])),
         }],
-        edges: Some(vec![Edge {
-            from: "test_node".to_string(),
-            to: "test_node".to_string(),
-        }]),
+        edges: None,
         stop_conditions: None,
+        outputs: None,
     };
 
     assert!(plan.validate().is_ok());
@@ -0,0 +1,183 @@
+//! Tests for the condition expression language used by `assert` and `branch`
+
+use amp::internal::{
+    exec::expr::{evaluate, ExprError},
+    exec::scheduler::{ExecutionContext, ExecutionError, Scheduler},
+    plan::ir::{Node, Operation, Plan},
+};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+fn context(variables: Value) -> ExecutionContext {
+    let mut ctx = ExecutionContext::new();
+    if let Value::Object(map) = variables {
+        ctx.variables = map.into_iter().collect();
+    }
+    ctx
+}
+
+fn evidence_context() -> ExecutionContext {
+    context(json!({
+        "ev1": {
+            "verdicts": [
+                { "claim": "Refund policy is 30 days", "confidence": 0.95 },
+                { "claim": "Refunds require a receipt", "confidence": 0.85 }
+            ],
+            "supports": ["doc-1", "doc-2"]
+        },
+        "hits": [1, 2, null, 4]
+    }))
+}
+
+fn eval(ctx: &ExecutionContext, source: &str) -> Value {
+    ctx.evaluate_expression(source)
+        .unwrap_or_else(|e| panic!("{} failed: {}", source, e))
+        .value
+}
+
+#[test]
+fn test_literals_arithmetic_and_precedence() {
+    let ctx = context(json!({}));
+
+    assert_eq!(eval(&ctx, "1 + 2 * 3"), json!(7));
+    assert_eq!(eval(&ctx, "(1 + 2) * 3"), json!(9));
+    assert_eq!(eval(&ctx, "7 % 4 - -1"), json!(4));
+    assert_eq!(eval(&ctx, "1 / 4"), json!(0.25));
+    assert_eq!(eval(&ctx, "'ab' + \"cd\" == 'abcd'"), json!(true));
+    assert_eq!(eval(&ctx, "!(1 < 2) || null == null"), json!(true));
+    assert_eq!(eval(&ctx, "true and not false"), json!(true));
+}
+
+#[test]
+fn test_references_and_aggregates() {
+    let ctx = evidence_context();
+
+    assert_eq!(eval(&ctx, "$ev1.verdicts[0].confidence"), json!(0.95));
+    assert_eq!(
+        eval(&ctx, "mean($ev1.verdicts[0].confidence) >= 0.9"),
+        json!(true)
+    );
+    assert_eq!(eval(&ctx, "min($ev1.verdicts[*].confidence)"), json!(0.85));
+    assert_eq!(
+        eval(&ctx, "max($ev1.verdicts[*].confidence, 0.99)"),
+        json!(0.99)
+    );
+    assert_eq!(eval(&ctx, "mean([1, 2, 3]) == 2"), json!(true));
+    assert_eq!(eval(&ctx, "sum($hits[0], $hits[1])"), json!(3));
+    assert_eq!(eval(&ctx, "len($ev1.supports) == 2"), json!(true));
+    assert_eq!(eval(&ctx, "count($hits)"), json!(3));
+    assert_eq!(eval(&ctx, "count($ev1.supports, 'doc-2')"), json!(1));
+}
+
+#[test]
+fn test_evaluation_records_sub_expressions() {
+    let ctx = evidence_context();
+    let evaluation = ctx
+        .evaluate_expression("mean($ev1.verdicts[*].confidence) >= 0.95 && len($ev1.supports) > 0")
+        .unwrap();
+
+    assert!(!evaluation.is_truthy());
+    let steps: Vec<&str> = evaluation.steps.iter().map(|(e, _)| e.as_str()).collect();
+    assert_eq!(
+        steps,
+        vec![
+            "$ev1.verdicts[*].confidence",
+            "mean($ev1.verdicts[*].confidence)",
+            "mean($ev1.verdicts[*].confidence) >= 0.95",
+            "mean($ev1.verdicts[*].confidence) >= 0.95 && len($ev1.supports) > 0",
+        ]
+    );
+    assert!(evaluation
+        .explain()
+        .contains("mean($ev1.verdicts[*].confidence) >= 0.95 = false"));
+}
+
+#[test]
+fn test_expression_errors() {
+    let ctx = evidence_context();
+
+    assert!(matches!(
+        ctx.evaluate_expression("$missing.flag == true"),
+        Err(ExprError::UnknownReference(ref r)) if r == "missing.flag"
+    ));
+    assert!(matches!(
+        ctx.evaluate_expression("median($hits)"),
+        Err(ExprError::UnknownFunction(_))
+    ));
+    assert!(matches!(
+        ctx.evaluate_expression("$ev1.supports[0] + 1"),
+        Err(ExprError::Type { ref expr, .. }) if expr == "$ev1.supports[0] + 1"
+    ));
+    assert!(matches!(
+        ctx.evaluate_expression("1 < 2 < 3"),
+        Err(ExprError::Parse { .. })
+    ));
+    assert!(matches!(
+        ctx.evaluate_expression("1 +"),
+        Err(ExprError::Parse { .. })
+    ));
+    assert!(matches!(
+        evaluate(&"(".repeat(100), |_| None),
+        Err(ExprError::Parse { .. })
+    ));
+}
+
+fn assert_plan(condition: &str) -> Plan {
+    Plan {
+        signals: None,
+        nodes: vec![Node {
+            id: "check_confidence".to_string(),
+            op: Operation::Assert,
+            tool: None,
+            capability: None,
+            args: Some(HashMap::from([("condition".to_string(), json!(condition))])),
+            bind: None,
+            timeout_ms: None,
+            retry: None,
+            out: None,
+        }],
+        edges: None,
+        stop_conditions: None,
+        outputs: None,
+    }
+}
+
+#[tokio::test]
+async fn test_assert_passes_and_records_trace() {
+    let plan = assert_plan("mean($ev1.verdicts[0].confidence) >= 0.9");
+    let ctx = Scheduler
+        .execute_plan(evidence_context(), &plan)
+        .await
+        .expect("assertion should pass");
+
+    let trace = ctx
+        .trace_events
+        .iter()
+        .find(|trace| trace.event_type == "assertion")
+        .expect("assertion trace");
+    let data = trace.data.as_ref().unwrap();
+    assert_eq!(data["passed"], json!(true));
+    assert_eq!(
+        data["steps"][0]["expr"],
+        json!("$ev1.verdicts[0].confidence")
+    );
+    assert_eq!(data["steps"][0]["value"], json!(0.95));
+}
+
+#[tokio::test]
+async fn test_assert_failure_reports_sub_expression_values() {
+    let plan = assert_plan("min($ev1.verdicts[*].confidence) >= 0.9");
+    let result = Scheduler.execute_plan(evidence_context(), &plan).await;
+
+    match result {
+        Err(ExecutionError::ValidationError(msg)) => {
+            assert!(msg.starts_with("Assertion failed"), "{}", msg);
+            assert!(
+                msg.contains("min($ev1.verdicts[*].confidence) = 0.85"),
+                "{}",
+                msg
+            );
+        }
+        other => panic!("Expected assertion failure, got {:?}", other.map(|_| ())),
+    }
+}
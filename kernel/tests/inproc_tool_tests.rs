@@ -0,0 +1,207 @@
+//! Tests for Rust tools registered in the kernel process
+
+use amp::internal::{
+    exec::scheduler::{ExecutionContext, ExecutionError, Scheduler},
+    plan::ir::Plan,
+    tools::sdk::{parse_args, Tool},
+    tools::spec::{ToolError, ToolSpec},
+    tools::transport::InprocTransport,
+};
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+/// Extracts every match of a regex, counting its calls.
+#[derive(Default)]
+struct RegexExtract {
+    calls: Arc<AtomicUsize>,
+}
+
+#[derive(Deserialize)]
+struct ExtractArgs {
+    pattern: String,
+    text: String,
+}
+
+#[async_trait]
+impl Tool for RegexExtract {
+    fn spec(&self) -> ToolSpec {
+        serde_json::from_value(json!({
+            "name": "text.extract",
+            "capabilities": ["extract"],
+            "io": {
+                "input": {
+                    "type": "object",
+                    "properties": {
+                        "pattern": { "type": "string" },
+                        "text": { "type": "string" }
+                    },
+                    "required": ["pattern", "text"]
+                },
+                "output": { "type": "object" }
+            },
+            "constraints": { "cost_per_call_usd": 0.01 },
+            "policy": { "deny_if": ["password"] }
+        }))
+        .unwrap()
+    }
+
+    async fn call(&self, args: Option<Value>) -> Result<Value, ToolError> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        let args: ExtractArgs = parse_args(args)?;
+        let pattern = regex::Regex::new(&args.pattern)
+            .map_err(|e| ToolError::Invocation(format!("Invalid pattern: {}", e)))?;
+        let matches: Vec<&str> = pattern.find_iter(&args.text).map(|m| m.as_str()).collect();
+        Ok(json!({ "matches": matches }))
+    }
+}
+
+fn helpers(calls: Arc<AtomicUsize>) -> InprocTransport {
+    let format_spec: ToolSpec = serde_json::from_value(json!({
+        "name": "text.format",
+        "io": {
+            "input": { "type": "object" },
+            "output": { "type": "object" }
+        }
+    }))
+    .unwrap();
+    InprocTransport::new()
+        .with(RegexExtract { calls })
+        .tool(format_spec, |args| async move {
+            let items = args
+                .as_ref()
+                .and_then(|args| args["items"].as_array())
+                .ok_or_else(|| ToolError::Invocation("items must be an array".to_string()))?;
+            let text = items
+                .iter()
+                .map(|item| item.as_str().unwrap_or_default())
+                .collect::<Vec<_>>()
+                .join(", ");
+            Ok(json!({ "text": text }))
+        })
+}
+
+fn extract_plan(text: &str) -> Plan {
+    serde_json::from_value(json!({
+        "nodes": [
+            {
+                "id": "extract",
+                "op": "call",
+                "capability": "extract",
+                "args": { "pattern": "\\d+", "text": text },
+                "out": { "numbers": "result.matches" }
+            },
+            {
+                "id": "format",
+                "op": "call",
+                "tool": "text.format",
+                "args": { "items": "$numbers" },
+                "out": { "summary": "result.text" }
+            }
+        ],
+        "edges": [{ "from": "extract", "to": "format" }]
+    }))
+    .unwrap()
+}
+
+#[tokio::test]
+async fn test_registered_tools_run_with_budget_accounting() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let mut ctx = ExecutionContext::new();
+    ctx.register_inproc_tools("sdk-accounting", helpers(calls.clone()));
+    assert_eq!(ctx.tool_urls["text.extract"], "inproc://sdk-accounting");
+    assert_eq!(ctx.capability_index["extract"], vec!["text.extract"]);
+
+    let (ctx, result) = Scheduler.run(ctx, &extract_plan("a1 b22 c333")).await;
+    result.unwrap();
+    assert_eq!(ctx.variables["numbers"], json!(["1", "22", "333"]));
+    assert_eq!(ctx.variables["summary"], json!("1, 22, 333"));
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+    assert!((ctx.total_cost_usd - 0.01).abs() < 1e-9);
+    assert!(ctx
+        .trace_events
+        .iter()
+        .any(|trace| trace.event_type == "capability_route"));
+}
+
+#[tokio::test]
+async fn test_registered_tools_are_checked_before_they_run() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let mut ctx = ExecutionContext::new();
+    ctx.register_inproc_tools("sdk-checks", helpers(calls.clone()));
+
+    let (ctx, result) = Scheduler.run(ctx, &extract_plan("password 1234")).await;
+    assert!(matches!(result, Err(ExecutionError::ToolExecutionError(_))));
+    assert!(ctx
+        .trace_events
+        .iter()
+        .any(|trace| trace.event_type == "policy_violation"));
+
+    let plan: Plan = serde_json::from_value(json!({
+        "nodes": [{
+            "id": "extract",
+            "op": "call",
+            "tool": "text.extract",
+            "args": { "text": "a1" },
+            "out": { "numbers": "result.matches" }
+        }]
+    }))
+    .unwrap();
+    let mut ctx = ExecutionContext::new();
+    ctx.register_inproc_tools("sdk-checks", helpers(calls.clone()));
+    let (_, result) = Scheduler.run(ctx, &plan).await;
+    let error = result.unwrap_err().to_string();
+    assert!(error.contains("/pattern"), "{}", error);
+    assert_eq!(calls.load(Ordering::SeqCst), 0);
+}
+
+#[tokio::test]
+async fn test_registered_tools_count_against_the_cost_cap() {
+    let mut plan = extract_plan("a1");
+    plan.signals = Some(amp::internal::plan::ir::Signals {
+        latency_budget_ms: None,
+        cost_cap_usd: Some(0.005),
+        fuel_cap: None,
+        risk: None,
+    });
+    let mut ctx = ExecutionContext::new();
+    ctx.register_inproc_tools("sdk-budget", helpers(Arc::default()));
+
+    let (_, result) = Scheduler.run(ctx, &plan).await;
+    assert!(matches!(result, Err(ExecutionError::BudgetExceeded(_))));
+}
+
+#[tokio::test]
+async fn test_registry_entries_for_inproc_endpoints_list_their_tools() {
+    let mut ctx = ExecutionContext::new();
+    ctx.tool_client
+        .register_inproc("sdk-registry", Arc::new(helpers(Arc::default())));
+    ctx.tool_urls
+        .insert("helpers".to_string(), "inproc://sdk-registry".to_string());
+
+    let (ctx, result) = Scheduler.run(ctx, &extract_plan("x9")).await;
+    result.unwrap();
+    assert_eq!(ctx.variables["summary"], json!("9"));
+    let mut tools: Vec<_> = ctx.tool_urls.keys().cloned().collect();
+    tools.sort();
+    assert_eq!(tools, vec!["text.extract", "text.format"]);
+}
+
+#[tokio::test]
+async fn test_parse_args_reports_bad_arguments() {
+    let tool = RegexExtract::default();
+    match tool.call(Some(json!({ "pattern": 1 }))).await {
+        Err(ToolError::Invocation(message)) => {
+            assert!(message.starts_with("Invalid arguments"), "{}", message)
+        }
+        other => panic!("Expected an invocation error, got {:?}", other),
+    }
+    assert!(matches!(
+        tool.call(None).await,
+        Err(ToolError::Invocation(_))
+    ));
+}
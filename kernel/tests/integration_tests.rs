@@ -219,6 +219,7 @@ async fn execute_reference_plan() -> (ExecutionContext, Vec<JoinHandle<()>>) {
         signals: Some(Signals {
             latency_budget_ms: Some(5_000),
             cost_cap_usd: Some(2.0),
+            fuel_cap: None,
             risk: Some(0.2),
         }),
         nodes: vec![
@@ -232,6 +233,8 @@ async fn execute_reference_plan() -> (ExecutionContext, Vec<JoinHandle<()>>) {
                     ("k".to_string(), json!(3)),
                 ])),
                 bind: None,
+                timeout_ms: None,
+                retry: None,
                 out: Some(HashMap::from([(
                     "search_results".to_string(),
                     "result".to_string(),
@@ -250,6 +253,8 @@ async fn execute_reference_plan() -> (ExecutionContext, Vec<JoinHandle<()>>) {
                     ("sources".to_string(), json!("$search_results.hits")),
                 ])),
                 bind: None,
+                timeout_ms: None,
+                retry: None,
                 out: Some(HashMap::from([(
                     "verification".to_string(),
                     "result".to_string(),
@@ -282,6 +287,8 @@ async fn execute_reference_plan() -> (ExecutionContext, Vec<JoinHandle<()>>) {
                     map
                 }),
                 bind: None,
+                timeout_ms: None,
+                retry: None,
                 out: None,
             },
         ],
@@ -299,6 +306,7 @@ async fn execute_reference_plan() -> (ExecutionContext, Vec<JoinHandle<()>>) {
             max_nodes: Some(8),
             min_confidence: Some(0.7),
         }),
+        outputs: None,
     };
 
     let scheduler = Scheduler;
@@ -719,6 +727,7 @@ async fn test_complete_plan_execution_workflow() {
         signals: Some(Signals {
             latency_budget_ms: Some(10000),
             cost_cap_usd: Some(10.0),
+            fuel_cap: None,
             risk: Some(0.1),
         }),
         nodes: vec![
@@ -732,6 +741,8 @@ async fn test_complete_plan_execution_workflow() {
                     ("k".to_string(), json!(5)),
                 ])),
                 bind: None,
+                timeout_ms: None,
+                retry: None,
                 out: Some(HashMap::from([(
                     "search_results".to_string(),
                     "search_results".to_string(),
@@ -747,6 +758,8 @@ async fn test_complete_plan_execution_workflow() {
                     ("sources".to_string(), json!([])), // This would be the search results in a real scenario
                 ])),
                 bind: None,
+                timeout_ms: None,
+                retry: None,
                 out: Some(HashMap::from([(
                     "verification_result".to_string(),
                     "verification_result".to_string(),
@@ -768,6 +781,8 @@ async fn test_complete_plan_execution_workflow() {
                     ("ttl".to_string(), json!("P60D")),
                 ])),
                 bind: None,
+                timeout_ms: None,
+                retry: None,
                 out: None,
             },
         ],
@@ -785,6 +800,7 @@ async fn test_complete_plan_execution_workflow() {
             max_nodes: Some(10),
             min_confidence: Some(0.7),
         }),
+        outputs: None,
     };
 
     // Validate the plan structure
@@ -830,6 +846,7 @@ async fn test_memory_analytics_node_execution() {
         signals: Some(Signals {
             latency_budget_ms: Some(5_000),
             cost_cap_usd: Some(2.0),
+            fuel_cap: None,
             risk: Some(0.2),
         }),
         nodes: vec![
@@ -843,6 +860,8 @@ async fn test_memory_analytics_node_execution() {
                     ("k".to_string(), json!(3)),
                 ])),
                 bind: None,
+                timeout_ms: None,
+                retry: None,
                 out: Some(HashMap::from([(
                     "search_results".to_string(),
                     "result".to_string(),
@@ -861,6 +880,8 @@ async fn test_memory_analytics_node_execution() {
                     ("sources".to_string(), json!("$search_results.hits")),
                 ])),
                 bind: None,
+                timeout_ms: None,
+                retry: None,
                 out: Some(HashMap::from([(
                     "verification".to_string(),
                     "result".to_string(),
@@ -893,6 +914,8 @@ async fn test_memory_analytics_node_execution() {
                     map
                 }),
                 bind: None,
+                timeout_ms: None,
+                retry: None,
                 out: None,
             },
             Node {
@@ -905,6 +928,8 @@ async fn test_memory_analytics_node_execution() {
                     ("key".to_string(), json!("product.todo.brief")),
                 ])),
                 bind: None,
+                timeout_ms: None,
+                retry: None,
                 out: Some(HashMap::from([(
                     "memory_analytics".to_string(),
                     "result".to_string(),
@@ -929,6 +954,7 @@ async fn test_memory_analytics_node_execution() {
             max_nodes: Some(8),
             min_confidence: Some(0.7),
         }),
+        outputs: None,
     };
 
     let scheduler = Scheduler;
@@ -1044,6 +1070,8 @@ async fn test_scheduler_execution_with_dependencies() {
                 capability: None,
                 args: Some(HashMap::from([("q".to_string(), json!("first query"))])),
                 bind: None,
+                timeout_ms: None,
+                retry: None,
                 out: Some(HashMap::from([(
                     "result_a".to_string(),
                     "result".to_string(),
@@ -1056,6 +1084,8 @@ async fn test_scheduler_execution_with_dependencies() {
                 capability: None,
                 args: Some(HashMap::from([("q".to_string(), json!("second query"))])),
                 bind: None,
+                timeout_ms: None,
+                retry: None,
                 out: Some(HashMap::from([(
                     "result_b".to_string(),
                     "result".to_string(),
@@ -1067,6 +1097,7 @@ async fn test_scheduler_execution_with_dependencies() {
             to: "node_b".to_string(), // node_b depends on node_a
         }]),
         stop_conditions: None,
+        outputs: None,
     };
 
     // Validate the plan
@@ -1095,6 +1126,7 @@ async fn test_end_to_end_plan_execution_with_http_tools() {
         signals: Some(Signals {
             latency_budget_ms: Some(5_000),
             cost_cap_usd: Some(2.0),
+            fuel_cap: None,
             risk: Some(0.2),
         }),
         nodes: vec![
@@ -1108,6 +1140,8 @@ async fn test_end_to_end_plan_execution_with_http_tools() {
                     ("k".to_string(), json!(3)),
                 ])),
                 bind: None,
+                timeout_ms: None,
+                retry: None,
                 out: Some(HashMap::from([(
                     "search_results".to_string(),
                     "result".to_string(),
@@ -1126,6 +1160,8 @@ async fn test_end_to_end_plan_execution_with_http_tools() {
                     ("sources".to_string(), json!("$search_results.hits")),
                 ])),
                 bind: None,
+                timeout_ms: None,
+                retry: None,
                 out: Some(HashMap::from([(
                     "verification".to_string(),
                     "result".to_string(),
@@ -1158,6 +1194,8 @@ async fn test_end_to_end_plan_execution_with_http_tools() {
                     map
                 }),
                 bind: None,
+                timeout_ms: None,
+                retry: None,
                 out: None,
             },
             Node {
@@ -1170,6 +1208,8 @@ async fn test_end_to_end_plan_execution_with_http_tools() {
                     ("key".to_string(), json!("product.todo.brief")),
                 ])),
                 bind: None,
+                timeout_ms: None,
+                retry: None,
                 out: Some(HashMap::from([(
                     "memory_analytics".to_string(),
                     "result".to_string(),
@@ -1194,6 +1234,7 @@ async fn test_end_to_end_plan_execution_with_http_tools() {
             max_nodes: Some(8),
             min_confidence: Some(0.7),
         }),
+        outputs: None,
     };
 
     let scheduler = Scheduler;
@@ -1244,6 +1285,7 @@ async fn test_plan_fails_when_cost_budget_exceeded() {
         signals: Some(Signals {
             latency_budget_ms: Some(10_000),
             cost_cap_usd: Some(0.00001),
+            fuel_cap: None,
             risk: Some(0.1),
         }),
         nodes: vec![Node {
@@ -1256,6 +1298,8 @@ async fn test_plan_fails_when_cost_budget_exceeded() {
                 json!("budget guardrails"),
             )])),
             bind: None,
+            timeout_ms: None,
+            retry: None,
             out: Some(HashMap::from([(
                 "search_result".to_string(),
                 "result".to_string(),
@@ -1263,6 +1307,7 @@ async fn test_plan_fails_when_cost_budget_exceeded() {
         }],
         edges: None,
         stop_conditions: None,
+        outputs: None,
     };
 
     let scheduler = Scheduler;
@@ -1292,6 +1337,7 @@ async fn test_tool_policy_blocking_prevents_execution() {
         signals: Some(Signals {
             latency_budget_ms: Some(10_000),
             cost_cap_usd: Some(1.0),
+            fuel_cap: None,
             risk: Some(0.1),
         }),
         nodes: vec![Node {
@@ -1304,6 +1350,8 @@ async fn test_tool_policy_blocking_prevents_execution() {
                 json!("find PII disclosure procedures"),
             )])),
             bind: None,
+            timeout_ms: None,
+            retry: None,
             out: Some(HashMap::from([(
                 "search_result".to_string(),
                 "result".to_string(),
@@ -1311,6 +1359,7 @@ async fn test_tool_policy_blocking_prevents_execution() {
         }],
         edges: None,
         stop_conditions: None,
+        outputs: None,
     };
 
     let scheduler = Scheduler;
@@ -1438,6 +1487,7 @@ async fn test_capability_routing_selects_registered_tool() {
         signals: Some(Signals {
             latency_budget_ms: Some(2_000),
             cost_cap_usd: Some(1.0),
+            fuel_cap: None,
             risk: Some(0.1),
         }),
         nodes: vec![Node {
@@ -1450,6 +1500,8 @@ async fn test_capability_routing_selects_registered_tool() {
                 ("k".to_string(), json!(2)),
             ])),
             bind: None,
+            timeout_ms: None,
+            retry: None,
             out: Some(HashMap::from([(
                 "results".to_string(),
                 "result".to_string(),
@@ -1457,6 +1509,7 @@ async fn test_capability_routing_selects_registered_tool() {
         }],
         edges: None,
         stop_conditions: None,
+        outputs: None,
     };
 
     let scheduler = Scheduler;
@@ -1496,6 +1549,25 @@ async fn test_capability_routing_selects_registered_tool() {
     doc_handle.abort();
 }
 
+/// Splits a Server-Sent Events body into (id, event, JSON data) triples.
+fn parse_sse(body: &str) -> Vec<(usize, String, serde_json::Value)> {
+    body.split("\n\n")
+        .filter_map(|block| {
+            let (mut id, mut event, mut data) = (None, None, None);
+            for line in block.lines() {
+                if let Some(value) = line.strip_prefix("id:") {
+                    id = value.trim().parse().ok();
+                } else if let Some(value) = line.strip_prefix("event:") {
+                    event = Some(value.trim().to_string());
+                } else if let Some(value) = line.strip_prefix("data:") {
+                    data = serde_json::from_str(value.trim()).ok();
+                }
+            }
+            Some((id?, event?, data?))
+        })
+        .collect()
+}
+
 #[tokio::test]
 async fn test_kernel_api_execute_plan_end_to_end() {
     let memory_state: SharedMemoryState = Arc::new(Mutex::new(HashMap::new()));
@@ -1515,7 +1587,7 @@ async fn test_kernel_api_execute_plan_end_to_end() {
     assert!(memory_url.ends_with("7403"));
     assert!(analytics_url.ends_with("7407"));
 
-    let kernel_app = amp::internal::api::create_router();
+    let kernel_app = amp::internal::api::create_router().unwrap();
     let kernel_listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
     let kernel_addr = kernel_listener.local_addr().unwrap();
     let kernel_handle = tokio::spawn(async move {
@@ -1528,6 +1600,7 @@ async fn test_kernel_api_execute_plan_end_to_end() {
         signals: Some(Signals {
             latency_budget_ms: Some(5_000),
             cost_cap_usd: Some(2.0),
+            fuel_cap: None,
             risk: Some(0.2),
         }),
         nodes: vec![
@@ -1541,6 +1614,8 @@ async fn test_kernel_api_execute_plan_end_to_end() {
                     ("k".to_string(), json!(3)),
                 ])),
                 bind: None,
+                timeout_ms: None,
+                retry: None,
                 out: Some(HashMap::from([(
                     "search_results".to_string(),
                     "result".to_string(),
@@ -1559,6 +1634,8 @@ async fn test_kernel_api_execute_plan_end_to_end() {
                     ("sources".to_string(), json!("$search_results.hits")),
                 ])),
                 bind: None,
+                timeout_ms: None,
+                retry: None,
                 out: Some(HashMap::from([(
                     "verification".to_string(),
                     "result".to_string(),
@@ -1591,6 +1668,8 @@ async fn test_kernel_api_execute_plan_end_to_end() {
                     map
                 }),
                 bind: None,
+                timeout_ms: None,
+                retry: None,
                 out: None,
             },
             Node {
@@ -1603,6 +1682,8 @@ async fn test_kernel_api_execute_plan_end_to_end() {
                     ("key".to_string(), json!("product.todo.brief")),
                 ])),
                 bind: None,
+                timeout_ms: None,
+                retry: None,
                 out: Some(HashMap::from([(
                     "memory_analytics".to_string(),
                     "result".to_string(),
@@ -1627,6 +1708,7 @@ async fn test_kernel_api_execute_plan_end_to_end() {
             max_nodes: Some(8),
             min_confidence: Some(0.7),
         }),
+        outputs: None,
     };
 
     let client = Client::new();
@@ -1659,6 +1741,58 @@ async fn test_kernel_api_execute_plan_end_to_end() {
         .and_then(|v| v.as_str())
         .expect("missing plan_id")
         .to_string();
+    assert_eq!(body["status"], "queued");
+
+    // Follow the live stream until the run's terminal event
+    let stream_url = body["stream_url"].as_str().expect("missing stream_url");
+    let stream = client
+        .get(format!("http://{}{}", kernel_addr, stream_url))
+        .send()
+        .await
+        .expect("trace stream request failed");
+    assert_eq!(
+        stream.headers()["content-type"].to_str().unwrap(),
+        "text/event-stream"
+    );
+    let events = parse_sse(&stream.text().await.expect("failed to read trace stream"));
+    let (end_id, end_event, end_data) = events.last().expect("empty trace stream").clone();
+    assert_eq!(end_event, "end");
+    assert_eq!(end_data["status"], "succeeded", "{}", end_data);
+    assert_eq!(end_id, events.len() - 1);
+    assert!(events[..events.len() - 1]
+        .iter()
+        .enumerate()
+        .all(|(index, (id, event, _))| *id == index && event == "trace"));
+
+    // The run reports its final state and progress
+    let run_status: serde_json::Value = client
+        .get(format!(
+            "http://{}{}",
+            kernel_addr,
+            body["status_url"].as_str().expect("missing status_url")
+        ))
+        .send()
+        .await
+        .expect("run status request failed")
+        .json()
+        .await
+        .expect("invalid run status body");
+    assert_eq!(run_status["state"], "succeeded");
+    assert_eq!(run_status["progress"]["completed_nodes"], 4);
+    assert_eq!(run_status["progress"]["total_nodes"], 4);
+    assert_eq!(run_status["current_nodes"], serde_json::json!([]));
+
+    // Reconnecting with Last-Event-ID resumes after that event
+    let resumed = client
+        .get(format!("http://{}{}", kernel_addr, stream_url))
+        .header("Last-Event-ID", (end_id - 2).to_string())
+        .send()
+        .await
+        .expect("trace stream request failed")
+        .text()
+        .await
+        .expect("failed to read trace stream");
+    assert_eq!(parse_sse(&resumed), events[end_id - 1..].to_vec());
 
     // Fetch traces to ensure API recorded execution
     let trace_response = client
@@ -1683,6 +1817,51 @@ async fn test_kernel_api_execute_plan_end_to_end() {
         .unwrap_or(false);
     assert!(analytics_trace_seen, "analytics step trace missing");
 
+    // Every stored trace is signed with the key the kernel publishes
+    let keys: serde_json::Value = client
+        .get(format!("http://{}/v1/keys", kernel_addr))
+        .send()
+        .await
+        .expect("keys request failed")
+        .json()
+        .await
+        .expect("invalid keys body");
+    assert_eq!(keys["keys"][0]["algorithm"], "ed25519");
+    assert_eq!(keys["keys"][0]["status"], "current");
+    let public_key = amp::internal::trace::trace::decode_public_key(
+        keys["keys"][0]["public_key"]
+            .as_str()
+            .expect("missing public key"),
+    )
+    .expect("invalid public key");
+    let traces: Vec<amp::Trace> =
+        serde_json::from_value(trace_body["traces"].clone()).expect("invalid traces");
+    assert!(traces.iter().all(|trace| trace.signature.is_some()));
+
+    // All traces carry the API's plan id and the run id it reported
+    let run_id = body["run_id"].as_str().expect("missing run_id");
+    assert!(traces
+        .iter()
+        .all(|trace| trace.plan_id == plan_id && trace.run_id.as_deref() == Some(run_id)));
+    amp::verify_chain(&traces, &public_key).expect("trace chain should verify");
+
+    // The trace query API finds the analytics step's traces by tool
+    let query: serde_json::Value = client
+        .get(format!(
+            "http://{}/v1/traces?plan_id={}&tool=mesh.mem.analytics&limit=1",
+            kernel_addr, plan_id
+        ))
+        .send()
+        .await
+        .expect("trace query request failed")
+        .json()
+        .await
+        .expect("invalid trace query body");
+    let page = query["traces"].as_array().expect("missing traces");
+    assert_eq!(page.len(), 1);
+    assert_eq!(page[0]["data"]["tool"], "mesh.mem.analytics");
+    assert!(query["next_cursor"].is_string());
+
     // Verify memory write succeeded with expected confidence
     let store = memory_state.lock().await;
     let record = store
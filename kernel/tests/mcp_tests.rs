@@ -0,0 +1,291 @@
+//! Tests for the MCP client transport over stdio and streamable HTTP
+
+use amp::internal::{
+    exec::scheduler::{ExecutionContext, Scheduler},
+    plan::ir::Plan,
+    tools::spec::{ToolClient, ToolError},
+};
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::post,
+    Json, Router,
+};
+use serde_json::{json, Value};
+use std::path::PathBuf;
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+/// A stand-in MCP server in plain sh. It logs a notification before every
+/// answer, lists `echo` and then, on a second page, `fail`. `echo` returns
+/// its arguments as structured content and `fail` reports an error.
+const STAND_IN_SERVER: &str = r#"while IFS= read -r line; do
+  id=$(printf '%s\n' "$line" | sed -n 's/^{"jsonrpc":"2.0","id":\([0-9]*\),.*/\1/p')
+  [ -z "$id" ] && continue
+  echo '{"jsonrpc":"2.0","method":"notifications/message","params":{"level":"info","data":"working"}}'
+  case "$line" in
+    *'"method":"initialize"'*)
+      printf '{"jsonrpc":"2.0","id":%s,"result":{"protocolVersion":"2025-03-26","capabilities":{"tools":{}},"serverInfo":{"name":"stand-in","version":"1.0"}}}\n' "$id" ;;
+    *'"method":"tools/list"'*'"cursor":"page-2"'*)
+      printf '{"jsonrpc":"2.0","id":%s,"result":{"tools":[{"name":"fail","inputSchema":{"type":"object"}}]}}\n' "$id" ;;
+    *'"method":"tools/list"'*)
+      printf '{"jsonrpc":"2.0","id":%s,"result":{"tools":[{"name":"echo","description":"Echoes its arguments","inputSchema":{"type":"object","properties":{"msg":{"type":"string"},"tags":{"type":["array","null"]}},"required":["msg"]},"annotations":{"readOnlyHint":true}}],"nextCursor":"page-2"}}\n' "$id" ;;
+    *'"method":"tools/call"'*'"name":"echo"'*)
+      args=$(printf '%s\n' "$line" | sed 's/.*"arguments":\({[^}]*}\).*/\1/')
+      printf '{"jsonrpc":"2.0","id":%s,"result":{"content":[{"type":"text","text":"echoed"}],"structuredContent":%s}}\n' "$id" "$args" ;;
+    *'"method":"tools/call"'*)
+      printf '{"jsonrpc":"2.0","id":%s,"result":{"content":[{"type":"text","text":"boom"}],"isError":true}}\n' "$id" ;;
+    *)
+      printf '{"jsonrpc":"2.0","id":%s,"error":{"code":-32601,"message":"Method not found"}}\n' "$id" ;;
+  esac
+done
+"#;
+
+/// Writes the stand-in server to a script and returns its `mcp+stdio://` URL.
+fn stand_in_server() -> (String, PathBuf) {
+    let path = std::env::temp_dir().join(format!("amp-mcp-{}.sh", uuid::Uuid::new_v4()));
+    std::fs::write(&path, STAND_IN_SERVER).unwrap();
+    (format!("mcp+stdio:///bin/sh {}", path.display()), path)
+}
+
+fn echo_plan(args: Value) -> Plan {
+    serde_json::from_value(json!({
+        "nodes": [{
+            "id": "say",
+            "op": "call",
+            "tool": "echo",
+            "args": args,
+            "out": { "said": "result.msg" }
+        }]
+    }))
+    .unwrap()
+}
+
+#[tokio::test]
+async fn test_plans_call_tools_discovered_on_an_mcp_server() {
+    let (url, script) = stand_in_server();
+    let mut ctx = ExecutionContext::new();
+    ctx.tool_urls.insert("stand-in".to_string(), url.clone());
+
+    let (ctx, result) = Scheduler
+        .run(ctx, &echo_plan(json!({ "msg": "hello" })))
+        .await;
+    result.unwrap();
+    let _ = std::fs::remove_file(&script);
+    assert_eq!(ctx.variables["said"], json!("hello"));
+
+    // The server's entry became one entry per tool, across both pages
+    let mut tools: Vec<_> = ctx.tool_urls.keys().cloned().collect();
+    tools.sort();
+    assert_eq!(tools, vec!["echo", "fail"]);
+    assert!(ctx.tool_urls.values().all(|tool_url| *tool_url == url));
+
+    let spec = &ctx.tool_specs["echo"];
+    assert_eq!(spec.description.as_deref(), Some("Echoes its arguments"));
+    assert_eq!(spec.io.input.schema_type, "object");
+    assert_eq!(spec.io.input.required, Some(vec!["msg".to_string()]));
+    let properties = spec.io.input.properties.as_ref().unwrap();
+    assert_eq!(properties["msg"].schema_type, "string");
+    assert_eq!(properties["tags"].schema_type, "");
+    assert_eq!(
+        spec.constraints.as_ref().and_then(|c| c.side_effects),
+        Some(false)
+    );
+}
+
+#[tokio::test]
+async fn test_mcp_input_schemas_are_checked_before_calls() {
+    let (url, script) = stand_in_server();
+    let mut ctx = ExecutionContext::new();
+    ctx.tool_urls.insert("stand-in".to_string(), url);
+
+    let (_, result) = Scheduler
+        .run(ctx, &echo_plan(json!({ "text": "hello" })))
+        .await;
+    let _ = std::fs::remove_file(&script);
+    let error = result.unwrap_err().to_string();
+    assert!(error.contains("/msg"), "{}", error);
+}
+
+#[tokio::test]
+async fn test_mcp_tool_errors_are_invocation_errors() {
+    let (url, script) = stand_in_server();
+    let client = ToolClient::new();
+
+    match client.invoke_tool(&url, "fail", None).await {
+        Err(ToolError::Invocation(message)) => assert_eq!(message, "boom"),
+        other => panic!("Expected an invocation error, got {:?}", other),
+    }
+    match client.get_tool_spec(&url, "missing").await {
+        Err(ToolError::Communication(message)) => {
+            assert!(message.contains("no tool named missing"), "{}", message)
+        }
+        other => panic!("Expected a communication error, got {:?}", other),
+    }
+    let _ = std::fs::remove_file(&script);
+}
+
+#[derive(Default)]
+struct HttpServer {
+    initializations: AtomicUsize,
+    listings: AtomicUsize,
+}
+
+/// A streamable HTTP MCP server with one tool, `lookup`. It answers calls
+/// with an event stream and insists on the session it assigned.
+async fn mcp_endpoint(
+    State(server): State<Arc<HttpServer>>,
+    headers: HeaderMap,
+    Json(message): Json<Value>,
+) -> Response {
+    let method = message["method"].as_str().unwrap_or_default();
+    let id = message["id"].clone();
+    if method == "initialize" {
+        server.initializations.fetch_add(1, Ordering::SeqCst);
+        let body = json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "result": {
+                "protocolVersion": "2025-03-26",
+                "capabilities": { "tools": {} },
+                "serverInfo": { "name": "http-stand-in", "version": "1.0" }
+            }
+        });
+        return ([("Mcp-Session-Id", "session-1")], Json(body)).into_response();
+    }
+
+    let in_session = headers
+        .get("Mcp-Session-Id")
+        .is_some_and(|value| value == "session-1")
+        && headers
+            .get("MCP-Protocol-Version")
+            .is_some_and(|value| value == "2025-03-26");
+    if !in_session {
+        return StatusCode::BAD_REQUEST.into_response();
+    }
+    if id.is_null() {
+        return StatusCode::ACCEPTED.into_response();
+    }
+
+    match method {
+        "tools/list" => {
+            server.listings.fetch_add(1, Ordering::SeqCst);
+            Json(json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "result": {
+                    "tools": [{
+                        "name": "lookup",
+                        "inputSchema": {
+                            "type": "object",
+                            "properties": { "q": { "type": "string" } }
+                        }
+                    }]
+                }
+            }))
+            .into_response()
+        }
+        "tools/call" => {
+            let query = message["params"]["arguments"]["q"]
+                .as_str()
+                .unwrap_or_default();
+            let progress = json!({
+                "jsonrpc": "2.0",
+                "method": "notifications/progress",
+                "params": { "progress": 1 }
+            });
+            let response = json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "result": {
+                    "content": [
+                        { "type": "text", "text": format!("found {}", query) },
+                        { "type": "text", "text": "2 results" }
+                    ]
+                }
+            });
+            (
+                [("Content-Type", "text/event-stream")],
+                format!(
+                    "event: message\ndata: {}\n\nevent: message\ndata: {}\n\n",
+                    progress, response
+                ),
+            )
+                .into_response()
+        }
+        _ => Json(json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": { "code": -32601, "message": "Method not found" }
+        }))
+        .into_response(),
+    }
+}
+
+#[tokio::test]
+async fn test_mcp_over_streamable_http() {
+    let server = Arc::new(HttpServer::default());
+    let app = Router::new()
+        .route("/mcp", post(mcp_endpoint))
+        .with_state(server.clone());
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("mcp+http://{}/mcp", listener.local_addr().unwrap());
+    let handle = tokio::spawn(async move {
+        axum::serve(listener, app.into_make_service())
+            .await
+            .expect("mcp server error");
+    });
+
+    let client = ToolClient::new();
+    let tools = client.list_tools(&url).await.unwrap();
+    assert_eq!(tools.len(), 1);
+    assert_eq!(tools[0].name, "lookup");
+
+    let result = client
+        .invoke_tool(&url, "lookup", Some(json!({ "q": "amp" })))
+        .await
+        .unwrap();
+    assert_eq!(result["text"], json!("found amp\n2 results"));
+    assert_eq!(result["content"].as_array().unwrap().len(), 2);
+
+    match client.invoke_tool(&url, "lookup", None).await {
+        Ok(result) => assert_eq!(result["text"], json!("found \n2 results")),
+        Err(e) => panic!("Expected a result, got {}", e),
+    }
+    assert_eq!(server.initializations.load(Ordering::SeqCst), 1);
+
+    handle.abort();
+}
+
+#[tokio::test]
+async fn test_mcp_servers_are_listed_once_per_run() {
+    let server = Arc::new(HttpServer::default());
+    let app = Router::new()
+        .route("/mcp", post(mcp_endpoint))
+        .with_state(server.clone());
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("mcp+http://{}/mcp", listener.local_addr().unwrap());
+    let handle = tokio::spawn(async move {
+        axum::serve(listener, app.into_make_service())
+            .await
+            .expect("mcp server error");
+    });
+
+    // Discovering before the run, as the API does, is not repeated by it
+    let mut ctx = ExecutionContext::new();
+    ctx.tool_urls.insert("http-stand-in".to_string(), url);
+    ctx.discover_tools().await;
+    let plan: Plan = serde_json::from_value(json!({
+        "nodes": [{ "id": "find", "op": "call", "tool": "lookup", "args": { "q": "amp" }, "out": { "found": "result.text" } }]
+    }))
+    .unwrap();
+    let (_, result) = Scheduler.run(ctx, &plan).await;
+    result.unwrap();
+    assert_eq!(server.initializations.load(Ordering::SeqCst), 1);
+    assert_eq!(server.listings.load(Ordering::SeqCst), 1);
+
+    handle.abort();
+}
@@ -0,0 +1,165 @@
+//! Tests for binding node results to variables through `out` paths
+
+use amp::internal::{
+    exec::scheduler::{ExecutionContext, ExecutionError, Scheduler},
+    plan::ir::{Node, Operation, Plan},
+};
+use axum::{
+    routing::{get, post},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use tokio::task::JoinHandle;
+
+#[derive(Deserialize)]
+struct ToolInvokeRequest {
+    args: Option<serde_json::Value>,
+}
+
+#[derive(Serialize)]
+struct ToolInvokeResponse {
+    result: serde_json::Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+async fn spawn_search_server() -> (String, JoinHandle<()>) {
+    async fn handler(Json(payload): Json<ToolInvokeRequest>) -> Json<ToolInvokeResponse> {
+        let query = payload
+            .args
+            .as_ref()
+            .and_then(|args| args.get("q").or_else(|| args.get("item")))
+            .cloned()
+            .unwrap_or(serde_json::Value::Null);
+
+        Json(ToolInvokeResponse {
+            result: json!({
+                "hits": [
+                    { "id": "doc-1", "score": 0.9, "query": query },
+                    { "id": "doc-2", "score": 0.7, "query": query }
+                ],
+                "total": 2
+            }),
+            error: None,
+        })
+    }
+
+    async fn spec_handler() -> Json<serde_json::Value> {
+        Json(json!({
+            "name": "doc.search.local",
+            "description": "Local document search",
+            "io": {
+                "input": { "type": "object", "properties": null, "required": null, "items": null },
+                "output": { "type": "object", "properties": null, "required": null, "items": null }
+            },
+            "capabilities": ["search.documents"],
+            "constraints": null
+        }))
+    }
+
+    let app = Router::new()
+        .route("/invoke/doc.search.local", post(handler))
+        .route("/spec/doc.search.local", get(spec_handler));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let handle = tokio::spawn(async move {
+        axum::serve(listener, app.into_make_service())
+            .await
+            .expect("doc.search.local server error");
+    });
+    (format!("http://{}", addr), handle)
+}
+
+fn single_node_plan(op: Operation, args: serde_json::Value, out: &[(&str, &str)]) -> Plan {
+    let args = match args {
+        serde_json::Value::Object(map) => map.into_iter().collect(),
+        _ => HashMap::new(),
+    };
+
+    Plan {
+        signals: None,
+        nodes: vec![Node {
+            id: "search".to_string(),
+            op,
+            tool: Some("doc.search.local".to_string()),
+            capability: None,
+            args: Some(args),
+            bind: None,
+            timeout_ms: None,
+            retry: None,
+            out: Some(
+                out.iter()
+                    .map(|(var, path)| (var.to_string(), path.to_string()))
+                    .collect(),
+            ),
+        }],
+        edges: None,
+        stop_conditions: None,
+        outputs: None,
+    }
+}
+
+async fn run(plan: &Plan) -> Result<ExecutionContext, ExecutionError> {
+    let (url, handle) = spawn_search_server().await;
+    let mut ctx = ExecutionContext::new();
+    ctx.tool_urls.insert("doc.search.local".to_string(), url);
+    let result = Scheduler.execute_plan(ctx, plan).await;
+    handle.abort();
+    result
+}
+
+#[tokio::test]
+async fn test_call_binds_result_paths() {
+    let plan = single_node_plan(
+        Operation::Call,
+        json!({ "q": "refund policy" }),
+        &[
+            ("everything", "result"),
+            ("hits", "result.hits"),
+            ("first_id", "result.hits[0].id"),
+            ("scores", "result.hits[*].score"),
+        ],
+    );
+
+    let ctx = run(&plan).await.expect("plan should succeed");
+
+    assert_eq!(ctx.variables["everything"]["total"], json!(2));
+    assert_eq!(ctx.variables["hits"].as_array().unwrap().len(), 2);
+    assert_eq!(ctx.variables["first_id"], json!("doc-1"));
+    assert_eq!(ctx.variables["scores"], json!([0.9, 0.7]));
+}
+
+#[tokio::test]
+async fn test_map_binds_paths_against_collected_results() {
+    let plan = single_node_plan(
+        Operation::Map,
+        json!({ "collection": ["a", "b"] }),
+        &[("queries", "result[*].hits[0].query")],
+    );
+
+    let ctx = run(&plan).await.expect("plan should succeed");
+
+    assert_eq!(ctx.variables["queries"], json!(["a", "b"]));
+}
+
+#[tokio::test]
+async fn test_missing_result_path_fails() {
+    let plan = single_node_plan(
+        Operation::Call,
+        json!({ "q": "refund policy" }),
+        &[
+            ("hits", "result.hits"),
+            ("cursor", "result.next_page.cursor"),
+        ],
+    );
+
+    match run(&plan).await {
+        Err(ExecutionError::ValidationError(msg)) => {
+            assert!(msg.contains("result.next_page.cursor"), "{}", msg);
+            assert!(msg.contains("search"), "{}", msg);
+        }
+        other => panic!("Expected validation error, got {:?}", other.map(|_| ())),
+    }
+}
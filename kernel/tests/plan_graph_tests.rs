@@ -0,0 +1,106 @@
+//! Tests for plan graph validation
+
+use amp::internal::{
+    exec::scheduler::{ExecutionContext, ExecutionError, Scheduler},
+    plan::ir::{Edge, Node, Operation, Plan, PlanValidationError},
+};
+use std::collections::HashMap;
+
+fn node(id: &str) -> Node {
+    Node {
+        id: id.to_string(),
+        op: Operation::Assert,
+        tool: None,
+        capability: None,
+        args: Some(HashMap::from([(
+            "condition".to_string(),
+            serde_json::json!("true"),
+        )])),
+        bind: None,
+        timeout_ms: None,
+        retry: None,
+        out: None,
+    }
+}
+
+fn plan(ids: &[&str], edges: &[(&str, &str)]) -> Plan {
+    Plan {
+        signals: None,
+        nodes: ids.iter().map(|id| node(id)).collect(),
+        edges: Some(
+            edges
+                .iter()
+                .map(|(from, to)| Edge {
+                    from: from.to_string(),
+                    to: to.to_string(),
+                })
+                .collect(),
+        ),
+        stop_conditions: None,
+        outputs: None,
+    }
+}
+
+#[test]
+fn test_converging_paths_are_not_cycles() {
+    // a -> b -> d, a -> c -> d, e is independent
+    let diamond = plan(
+        &["d", "c", "b", "a", "e"],
+        &[("a", "b"), ("a", "c"), ("b", "d"), ("c", "d")],
+    );
+    assert!(diamond.validate().is_ok());
+
+    let shortcut = plan(&["a", "b", "c"], &[("a", "b"), ("b", "c"), ("a", "c")]);
+    assert!(shortcut.validate().is_ok());
+}
+
+#[test]
+fn test_cycle_is_reported_with_unreachable_nodes() {
+    // start -> b -> c -> d -> b, d -> after
+    let plan = plan(
+        &["start", "b", "c", "d", "after"],
+        &[
+            ("start", "b"),
+            ("b", "c"),
+            ("c", "d"),
+            ("d", "b"),
+            ("d", "after"),
+        ],
+    );
+
+    match plan.validate() {
+        Err(PlanValidationError::Cycle { cycle, unreachable }) => {
+            assert_eq!(cycle, vec!["b", "c", "d", "b"]);
+            assert_eq!(unreachable, vec!["after"]);
+        }
+        other => panic!("Expected cycle error, got {:?}", other),
+    }
+
+    let message = plan.validate().unwrap_err().to_string();
+    assert_eq!(
+        message,
+        "Cycle detected: b -> c -> d -> b; unreachable nodes: after"
+    );
+}
+
+#[test]
+fn test_self_edge_is_rejected() {
+    let plan = plan(&["a", "b"], &[("a", "b"), ("b", "b")]);
+
+    assert!(matches!(
+        plan.validate(),
+        Err(PlanValidationError::SelfEdge(ref id)) if id == "b"
+    ));
+}
+
+#[tokio::test]
+async fn test_scheduler_rejects_cyclic_plan_before_execution() {
+    let plan = plan(&["a", "b"], &[("a", "b"), ("b", "a")]);
+
+    match Scheduler.execute_plan(ExecutionContext::new(), &plan).await {
+        Err(ExecutionError::ValidationError(msg)) => {
+            assert!(msg.contains("Cycle detected: a -> b -> a"), "{}", msg)
+        }
+        other => panic!("Expected validation error, got {:?}", other.map(|_| ())),
+    }
+}
@@ -0,0 +1,279 @@
+//! Tests for declared plan outputs and the result of a finished run
+
+use amp::internal::{
+    exec::run::RunResult,
+    exec::scheduler::{ExecutionContext, Scheduler},
+    plan::ir::{Plan, PlanValidationError},
+};
+use axum::{
+    extract::Path,
+    routing::{get, post},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::task::JoinHandle;
+
+#[derive(Deserialize)]
+struct ToolInvokeRequest {
+    args: Option<serde_json::Value>,
+}
+
+#[derive(Serialize)]
+struct ToolInvokeResponse {
+    result: serde_json::Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Serves `util.echo` and `ground.verify`, which supports every claim with
+/// 0.9 confidence except those about the moon, which it contradicts at 0.3.
+async fn spawn_tool_server() -> (String, JoinHandle<()>) {
+    async fn echo(Json(payload): Json<ToolInvokeRequest>) -> Json<ToolInvokeResponse> {
+        Json(ToolInvokeResponse {
+            result: payload.args.unwrap_or(serde_json::Value::Null),
+            error: None,
+        })
+    }
+
+    async fn verify(Json(payload): Json<ToolInvokeRequest>) -> Json<ToolInvokeResponse> {
+        let claims = payload.args.unwrap_or_default()["claims"].clone();
+        let verdicts: Vec<serde_json::Value> = claims
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|claim| claim.as_str())
+            .map(|claim| {
+                let (verdict, confidence) = if claim.contains("moon") {
+                    ("contradicted", 0.3)
+                } else {
+                    ("supported", 0.9)
+                };
+                json!({
+                    "claim_id": claim,
+                    "verdict": verdict,
+                    "confidence": confidence,
+                    "needs_citation": false
+                })
+            })
+            .collect();
+        Json(ToolInvokeResponse {
+            result: json!({ "claims": claims, "verdicts": verdicts }),
+            error: None,
+        })
+    }
+
+    async fn spec(Path(name): Path<String>) -> Json<serde_json::Value> {
+        Json(json!({
+            "name": name,
+            "description": "Test tool",
+            "io": {
+                "input": { "type": "object" },
+                "output": { "type": "object" }
+            },
+            "capabilities": [],
+            "constraints": null
+        }))
+    }
+
+    let app = Router::new()
+        .route("/invoke/util.echo", post(echo))
+        .route("/invoke/ground.verify", post(verify))
+        .route("/spec/:name", get(spec));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let handle = tokio::spawn(async move {
+        axum::serve(listener, app.into_make_service())
+            .await
+            .expect("tool server error");
+    });
+    (format!("http://{}", addr), handle)
+}
+
+/// `draft` binds an answer and a scratch note, then two verify steps check
+/// one and three claims. Only `answer`, `verdict` and `missing` are declared.
+fn answer_plan() -> Plan {
+    serde_json::from_value(json!({
+        "signals": { "cost_cap_usd": 1.0 },
+        "nodes": [
+            {
+                "id": "draft",
+                "op": "call",
+                "tool": "util.echo",
+                "args": { "text": "The sky is blue", "note": "scratch" },
+                "out": { "answer": "result.text", "note": "result.note" }
+            },
+            {
+                "id": "check_one",
+                "op": "verify",
+                "tool": "ground.verify",
+                "args": { "claims": ["sky is blue"], "sources": [] },
+                "out": { "first": "result" }
+            },
+            {
+                "id": "check_three",
+                "op": "verify",
+                "tool": "ground.verify",
+                "args": {
+                    "claims": ["sky is blue", "moon is cheese", "grass is green"],
+                    "sources": []
+                },
+                "out": { "second": "result" }
+            }
+        ],
+        "edges": [
+            { "from": "draft", "to": "check_one" },
+            { "from": "draft", "to": "check_three" }
+        ],
+        "outputs": {
+            "answer": "$answer",
+            "verdict": "$first.verdicts[0].verdict",
+            "missing": "$never_bound.value"
+        }
+    }))
+    .unwrap()
+}
+
+#[tokio::test]
+async fn test_run_result_holds_only_declared_outputs() {
+    let (url, server) = spawn_tool_server().await;
+    let mut ctx = ExecutionContext::new();
+    ctx.tool_urls.insert("util.echo".to_string(), url.clone());
+    ctx.tool_urls.insert("ground.verify".to_string(), url);
+
+    let plan = answer_plan();
+    let (ctx, result) = Scheduler.run(ctx, &plan).await;
+    result.unwrap();
+    server.abort();
+    assert!(ctx.variables.contains_key("note"));
+
+    let run_result = RunResult::from_context(&ctx, &plan);
+    assert_eq!(
+        serde_json::to_value(&run_result.outputs).unwrap(),
+        json!({
+            "answer": "The sky is blue",
+            "verdict": "supported",
+            "missing": null
+        })
+    );
+
+    assert_eq!(run_result.budget.cost_cap_usd, Some(1.0));
+    assert_eq!(run_result.budget.latency_budget_ms, None);
+    assert_eq!(run_result.budget.total_cost_usd, ctx.total_cost_usd);
+    assert_eq!(run_result.budget.total_tokens, ctx.total_tokens);
+
+    // The mean is weighted by claims: (0.9 + 0.9 + 0.3 + 0.9) / 4
+    let evidence = run_result.evidence.expect("evidence totals");
+    let mut steps = evidence.steps.clone();
+    steps.sort();
+    assert_eq!(steps, vec!["check_one", "check_three"]);
+    assert_eq!(evidence.total_claims, 4);
+    assert_eq!(evidence.supported_claims, 3);
+    assert_eq!(evidence.contradicted_claims, 1);
+    assert!((evidence.mean_confidence - 0.75).abs() < 1e-9);
+}
+
+#[tokio::test]
+async fn test_plans_without_outputs_return_no_variables() {
+    let plan: Plan = serde_json::from_value(json!({
+        "nodes": [{ "id": "check", "op": "assert", "args": { "condition": "1 < 2" } }]
+    }))
+    .unwrap();
+    let mut ctx = ExecutionContext::new();
+    ctx.variables
+        .insert("secret".to_string(), json!("internal scratch value"));
+
+    let (ctx, result) = Scheduler.run(ctx, &plan).await;
+    result.unwrap();
+
+    let run_result = RunResult::from_context(&ctx, &plan);
+    assert!(run_result.outputs.is_empty());
+    assert!(run_result.evidence.is_none());
+    assert!(!serde_json::to_string(&run_result)
+        .unwrap()
+        .contains("internal scratch value"));
+}
+
+#[test]
+fn test_outputs_must_be_variable_references() {
+    let mut plan = answer_plan();
+    plan.validate().unwrap();
+
+    for reference in ["answer", "$", "$.text", "$[0]"] {
+        plan.outputs = Some([("answer".to_string(), reference.to_string())].into());
+        assert!(
+            matches!(plan.validate(), Err(PlanValidationError::InvalidOutput(_))),
+            "{} should be rejected",
+            reference
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_execute_can_wait_for_the_result() {
+    let (url, server) = spawn_tool_server().await;
+    let config = std::env::temp_dir().join(format!("amp-tools-{}.json", uuid::Uuid::new_v4()));
+    std::fs::write(
+        &config,
+        json!([
+            { "name": "util.echo", "url": url },
+            { "name": "ground.verify", "url": url }
+        ])
+        .to_string(),
+    )
+    .unwrap();
+    // This is the only test in this binary that builds a router
+    std::env::set_var("AMP_TOOL_CONFIG", &config);
+
+    let app = amp::internal::api::create_router().unwrap();
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let kernel = format!("http://{}", listener.local_addr().unwrap());
+    let kernel_handle = tokio::spawn(async move {
+        axum::serve(listener, app.into_make_service())
+            .await
+            .expect("kernel api server error");
+    });
+    let client = reqwest::Client::new();
+
+    let finished: serde_json::Value = client
+        .post(format!("{}/v1/plan/execute", kernel))
+        .json(&json!({ "plan": answer_plan(), "wait": true }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(finished["status"], "succeeded", "{}", finished);
+    let result = &finished["result"];
+    assert_eq!(
+        result["outputs"],
+        json!({
+            "answer": "The sky is blue",
+            "verdict": "supported",
+            "missing": null
+        })
+    );
+    assert_eq!(result["budget"]["cost_cap_usd"], 1.0);
+    assert_eq!(result["evidence"]["total_claims"], 4);
+    assert!(!finished.to_string().contains("scratch"));
+
+    // The run status reports the same result afterwards
+    let status: serde_json::Value = client
+        .get(format!(
+            "{}{}",
+            kernel,
+            finished["status_url"].as_str().unwrap()
+        ))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(&status["result"], result);
+
+    kernel_handle.abort();
+    server.abort();
+    let _ = std::fs::remove_file(&config);
+}
@@ -12,6 +12,7 @@ async fn test_planner_constraints_with_low_budget() {
         signals: Some(Signals {
             latency_budget_ms: Some(10),
             cost_cap_usd: Some(0.001),
+            fuel_cap: None,
             risk: Some(0.1),
         }),
         nodes: vec![Node {
@@ -21,10 +22,13 @@ async fn test_planner_constraints_with_low_budget() {
             capability: None,
             args: Some(HashMap::from([("q".to_string(), json!("refund policy"))])),
             bind: None,
+            timeout_ms: None,
+            retry: None,
             out: None,
         }],
         edges: None,
         stop_conditions: None,
+        outputs: None,
     };
 
     let tool_spec = ToolSpec {
@@ -70,6 +74,7 @@ fn test_plan_validation() {
         nodes: vec![],
         edges: None,
         stop_conditions: None,
+        outputs: None,
     };
     assert!(matches!(
         empty_plan.validate(),
@@ -86,6 +91,8 @@ fn test_plan_validation() {
                 capability: None,
                 args: None,
                 bind: None,
+                timeout_ms: None,
+                retry: None,
                 out: None,
             },
             Node {
@@ -95,11 +102,14 @@ fn test_plan_validation() {
                 capability: None,
                 args: None,
                 bind: None,
+                timeout_ms: None,
+                retry: None,
                 out: None,
             },
         ],
         edges: None,
         stop_conditions: None,
+        outputs: None,
     };
     assert!(matches!(
         plan_with_duplicates.validate(),
@@ -118,6 +128,8 @@ fn test_plan_validate_with_tools_checks() {
             capability: None,
             args: None,
             bind: None,
+            timeout_ms: None,
+            retry: None,
             out: Some(HashMap::from([(
                 "result".to_string(),
                 "result".to_string(),
@@ -125,6 +137,7 @@ fn test_plan_validate_with_tools_checks() {
         }],
         edges: None,
         stop_conditions: None,
+        outputs: None,
     };
 
     let tools = vec!["doc.search.local".to_string()];
@@ -139,6 +152,8 @@ fn test_plan_validate_with_tools_checks() {
             capability: None,
             args: None,
             bind: None,
+            timeout_ms: None,
+            retry: None,
             out: Some(HashMap::from([(
                 "result".to_string(),
                 "result".to_string(),
@@ -146,6 +161,7 @@ fn test_plan_validate_with_tools_checks() {
         }],
         edges: None,
         stop_conditions: None,
+        outputs: None,
     };
     assert!(matches!(
         missing_tool_plan.validate_with_tools(&tools),
@@ -166,10 +182,13 @@ fn test_plan_validate_with_tools_checks() {
             capability: None,
             args: None,
             bind: None,
+            timeout_ms: None,
+            retry: None,
             out: None,
         }],
         edges: None,
         stop_conditions: None,
+        outputs: None,
     };
     assert!(matches!(
         missing_out_plan.validate_with_tools(&tools),
@@ -1,14 +1,11 @@
-use amp::internal::registry::{create_registry_router, RegisterRequest, RegistryState};
+use amp::internal::registry::{
+    create_registry_router, fetch_remote_registry, RegisterRequest, RegistryError, RegistryState,
+};
 use serde_json::json;
 use std::collections::HashMap;
 
-#[tokio::test]
-async fn test_registry_service_registers_and_lists() {
-    let initial = HashMap::new();
-    let state = RegistryState::new(initial);
-    let app = create_registry_router(state.clone());
-
-    let client = reqwest::Client::new();
+async fn serve(state: RegistryState) -> (String, tokio::task::JoinHandle<()>) {
+    let app = create_registry_router(state);
     let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
     let addr = listener.local_addr().unwrap();
     let handle = tokio::spawn(async move {
@@ -16,8 +13,16 @@ async fn test_registry_service_registers_and_lists() {
             .await
             .expect("registry server error");
     });
+    (format!("http://{}", addr), handle)
+}
 
-    let base_url = format!("http://{}", addr);
+#[tokio::test]
+async fn test_registry_service_registers_and_lists() {
+    let initial = HashMap::new();
+    let state = RegistryState::new(initial);
+    let (base_url, handle) = serve(state.clone()).await;
+
+    let client = reqwest::Client::new();
     let register_body = RegisterRequest {
         name: "test.tool".to_string(),
         url: "http://localhost:9999".to_string(),
@@ -45,3 +50,88 @@ async fn test_registry_service_registers_and_lists() {
 
     handle.abort();
 }
+
+#[tokio::test]
+async fn test_remote_registries_cannot_name_local_commands() {
+    let state = RegistryState::new(HashMap::new());
+    let (base_url, handle) = serve(state.clone()).await;
+    let client = reqwest::Client::new();
+
+    // Registering a command is refused and leaves the registry untouched
+    let response = client
+        .post(format!("{}/register", base_url))
+        .json(&RegisterRequest {
+            name: "evil.tool".to_string(),
+            url: "stdio:///bin/sh -c reboot".to_string(),
+        })
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert!(body["error"].as_str().unwrap().contains("evil.tool"));
+    assert!(state.list().await.is_empty());
+
+    // A registry that lists one anyway is not trusted by the kernel
+    handle.abort();
+    let listed = HashMap::from([
+        ("good.tool".to_string(), "https://tools.example".to_string()),
+        (
+            "evil.tool".to_string(),
+            "stdio:///bin/sh -c reboot".to_string(),
+        ),
+    ]);
+    let (base_url, handle) = serve(RegistryState::new(listed)).await;
+    match fetch_remote_registry(&base_url).await {
+        Err(RegistryError::Untrusted(message)) => {
+            assert!(message.contains("stdio:///bin/sh"), "{}", message)
+        }
+        other => panic!("Expected the registry to be refused, got {:?}", other),
+    }
+    handle.abort();
+}
+
+#[tokio::test]
+async fn test_remote_registries_cannot_name_mcp_servers_over_stdio() {
+    let url = "mcp+stdio:///bin/sh -c reboot";
+    let state = RegistryState::new(HashMap::new());
+    assert!(matches!(
+        state
+            .register("evil.server".to_string(), url.to_string())
+            .await,
+        Err(RegistryError::Untrusted(_))
+    ));
+    assert!(state.list().await.is_empty());
+
+    let listed = HashMap::from([("evil.server".to_string(), url.to_string())]);
+    let (base_url, handle) = serve(RegistryState::new(listed)).await;
+    match fetch_remote_registry(&base_url).await {
+        Err(RegistryError::Untrusted(message)) => {
+            assert!(message.contains("mcp+stdio://"), "{}", message)
+        }
+        other => panic!("Expected the registry to be refused, got {:?}", other),
+    }
+    handle.abort();
+}
+
+#[tokio::test]
+async fn test_remote_registries_cannot_name_wasm_modules() {
+    let url = "wasm:///etc/passwd";
+    let state = RegistryState::new(HashMap::new());
+    assert!(matches!(
+        state
+            .register("evil.module".to_string(), url.to_string())
+            .await,
+        Err(RegistryError::Untrusted(_))
+    ));
+
+    let listed = HashMap::from([("evil.module".to_string(), url.to_string())]);
+    let (base_url, handle) = serve(RegistryState::new(listed)).await;
+    match fetch_remote_registry(&base_url).await {
+        Err(RegistryError::Untrusted(message)) => {
+            assert!(message.contains("wasm://"), "{}", message)
+        }
+        other => panic!("Expected the registry to be refused, got {:?}", other),
+    }
+    handle.abort();
+}
@@ -0,0 +1,321 @@
+//! Tests for recording replay bundles and replaying them offline
+
+use amp::internal::{
+    exec::replay::ReplayBundle,
+    exec::scheduler::{ExecutionContext, Scheduler},
+    plan::ir::{Edge, ErrorKind, Node, Operation, Plan, RetryPolicy},
+};
+use axum::{
+    extract::{Path, State},
+    routing::{get, post},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::task::JoinHandle;
+
+#[derive(Deserialize)]
+struct ToolInvokeRequest {
+    args: Option<serde_json::Value>,
+}
+
+#[derive(Serialize)]
+struct ToolInvokeResponse {
+    result: serde_json::Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Serves `util.echo` and `util.flaky`, which echoes too but fails its first
+/// invocation. Both cost $0.01 per call.
+async fn spawn_tool_server() -> (String, JoinHandle<()>) {
+    async fn echo(Json(payload): Json<ToolInvokeRequest>) -> Json<ToolInvokeResponse> {
+        Json(ToolInvokeResponse {
+            result: payload.args.unwrap_or(serde_json::Value::Null),
+            error: None,
+        })
+    }
+
+    async fn flaky(
+        State(calls): State<Arc<AtomicUsize>>,
+        Json(payload): Json<ToolInvokeRequest>,
+    ) -> Json<ToolInvokeResponse> {
+        if calls.fetch_add(1, Ordering::SeqCst) == 0 {
+            return Json(ToolInvokeResponse {
+                result: serde_json::Value::Null,
+                error: Some("transient failure".to_string()),
+            });
+        }
+        Json(ToolInvokeResponse {
+            result: payload.args.unwrap_or(serde_json::Value::Null),
+            error: None,
+        })
+    }
+
+    async fn spec(Path(name): Path<String>) -> Json<serde_json::Value> {
+        Json(json!({
+            "name": name,
+            "description": "Test tool",
+            "io": {
+                "input": { "type": "object" },
+                "output": { "type": "object" }
+            },
+            "capabilities": [],
+            "constraints": {
+                "input_tokens_max": null,
+                "latency_p50_ms": null,
+                "cost_per_call_usd": 0.01,
+                "rate_limit_qps": null,
+                "side_effects": false
+            }
+        }))
+    }
+
+    let flaky_calls = Arc::new(AtomicUsize::new(0));
+    let app = Router::new()
+        .route("/invoke/util.echo", post(echo))
+        .route("/invoke/util.flaky", post(flaky))
+        .route("/spec/:name", get(spec))
+        .with_state(flaky_calls);
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let handle = tokio::spawn(async move {
+        axum::serve(listener, app.into_make_service())
+            .await
+            .expect("tool server error");
+    });
+    (format!("http://{}", addr), handle)
+}
+
+fn node(id: &str, op: Operation, tool: Option<&str>, args: serde_json::Value) -> Node {
+    let args = match args {
+        serde_json::Value::Object(map) => map.into_iter().collect(),
+        _ => HashMap::new(),
+    };
+
+    Node {
+        id: id.to_string(),
+        op,
+        tool: tool.map(|tool| tool.to_string()),
+        capability: None,
+        args: Some(args),
+        bind: None,
+        timeout_ms: None,
+        retry: None,
+        out: Some(HashMap::from([(
+            format!("{}_out", id),
+            "result".to_string(),
+        )])),
+    }
+}
+
+fn plan(nodes: Vec<Node>, edges: Vec<(&str, &str)>) -> Plan {
+    Plan {
+        signals: None,
+        nodes,
+        edges: Some(
+            edges
+                .into_iter()
+                .map(|(from, to)| Edge {
+                    from: from.to_string(),
+                    to: to.to_string(),
+                })
+                .collect(),
+        ),
+        stop_conditions: None,
+        outputs: None,
+    }
+}
+
+/// `greet` echoes `$audience`, then `shout` repeats it through a flaky tool.
+fn chain_plan() -> Plan {
+    plan(
+        vec![
+            node(
+                "greet",
+                Operation::Call,
+                Some("util.echo"),
+                json!({ "msg": "$audience" }),
+            ),
+            node(
+                "shout",
+                Operation::Retry,
+                Some("util.flaky"),
+                json!({ "msg": "$greet_out.msg" }),
+            ),
+        ],
+        vec![("greet", "shout")],
+    )
+}
+
+/// Runs `plan` against the stub tools and records it as a bundle.
+async fn record(plan: &Plan) -> ReplayBundle {
+    let (url, handle) = spawn_tool_server().await;
+    let mut ctx = ExecutionContext::new();
+    ctx.tool_urls.insert("util.echo".to_string(), url.clone());
+    ctx.tool_urls.insert("util.flaky".to_string(), url);
+    ctx.variables
+        .insert("audience".to_string(), json!("operators"));
+    let inputs = ctx.variables.clone();
+
+    let (ctx, result) = Scheduler.run(ctx, plan).await;
+    handle.abort();
+    ReplayBundle::record(
+        "run-1",
+        plan,
+        inputs,
+        &ctx,
+        result.err().map(|e| e.to_string()),
+    )
+}
+
+#[tokio::test]
+async fn test_replay_reproduces_recorded_run_without_tools() {
+    let mut fanout = node(
+        "fanout",
+        Operation::Map,
+        Some("util.echo"),
+        json!({ "collection": ["a", "b"] }),
+    );
+    fanout.retry = Some(RetryPolicy {
+        initial_backoff_ms: 1,
+        retry_on: vec![ErrorKind::Invocation],
+        ..RetryPolicy::default()
+    });
+    let mut nested = node(
+        "nested",
+        Operation::Spawn,
+        None,
+        json!({
+            "plan": {
+                "nodes": [{
+                    "id": "echo",
+                    "op": "call",
+                    "tool": "util.echo",
+                    "args": { "q": "$q" },
+                    "bind": null,
+                    "out": { "echoed": "result.q" }
+                }]
+            },
+            "outputs": ["echoed"]
+        }),
+    );
+    nested.bind = Some(HashMap::from([(
+        "q".to_string(),
+        "$greet_out.msg".to_string(),
+    )]));
+    let mut plan = chain_plan();
+    plan.nodes.extend([fanout, nested]);
+    plan.edges
+        .as_mut()
+        .unwrap()
+        .extend(
+            [("greet", "fanout"), ("greet", "nested")].map(|(from, to)| Edge {
+                from: from.to_string(),
+                to: to.to_string(),
+            }),
+        );
+
+    let bundle = record(&plan).await;
+    assert!(bundle.error.is_none(), "{:?}", bundle.error);
+    assert_eq!(bundle.tools, vec!["util.echo", "util.flaky"]);
+    assert!(bundle.tool_specs.contains_key("util.flaky"));
+    assert_eq!(bundle.inputs["audience"], json!("operators"));
+
+    // greet, two attempts of shout, two fanout items and the nested echo
+    assert_eq!(bundle.tool_io.len(), 6);
+    let shout: Vec<_> = bundle
+        .tool_io
+        .iter()
+        .filter(|exchange| exchange.step_id == "shout")
+        .collect();
+    assert_eq!(shout.len(), 2);
+    assert_eq!(shout[0].error.as_ref().unwrap().kind, ErrorKind::Invocation);
+    assert_eq!(shout[1].response, Some(json!({ "msg": "operators" })));
+    assert!(bundle
+        .tool_io
+        .iter()
+        .any(|exchange| exchange.step_id == "nested/echo"
+            && exchange.request == Some(json!({ "q": "operators" }))));
+
+    // The bundle survives serialization, and replays with every tool offline
+    let bundle: ReplayBundle =
+        serde_json::from_str(&serde_json::to_string(&bundle).unwrap()).unwrap();
+    let report = Scheduler.replay(&bundle).await;
+
+    assert!(report.is_faithful(), "{:?}", report.divergence);
+    assert!(report.error.is_none());
+    assert_eq!(report.unused_exchanges, 0);
+    assert_eq!(report.traces.len(), bundle.traces.len());
+}
+
+#[tokio::test]
+async fn test_replay_reports_first_divergence() {
+    let mut bundle = record(&chain_plan()).await;
+    let recorded_attempt = bundle
+        .traces
+        .iter()
+        .position(|trace| trace.step_id == "shout" && trace.event_type == "tool_attempt")
+        .unwrap();
+
+    // greet now answers differently, so shout sends a request never recorded
+    bundle.tool_io[0].response = Some(json!({ "msg": "auditors" }));
+    let report = Scheduler.replay(&bundle).await;
+
+    assert!(!report.is_faithful());
+    let error = report.error.as_deref().unwrap();
+    assert!(error.starts_with("Replay diverged"), "{}", error);
+    assert!(error.contains("\"auditors\""), "{}", error);
+
+    let divergence = report.divergence.expect("divergence");
+    assert_eq!(divergence.index, recorded_attempt);
+    assert_eq!(
+        divergence.expected.as_ref().unwrap()["step_id"],
+        json!("shout")
+    );
+    assert_eq!(divergence.actual, None);
+    assert!(divergence.to_string().contains("got end of trace"));
+}
+
+#[tokio::test]
+async fn test_replay_compares_trace_contents() {
+    let mut bundle = record(&chain_plan()).await;
+    let step_end = bundle
+        .traces
+        .iter()
+        .position(|trace| trace.event_type == "step_end")
+        .unwrap();
+    bundle.traces[step_end].data.as_mut().unwrap()["tool"] = json!("util.other");
+
+    let report = Scheduler.replay(&bundle).await;
+
+    assert!(report.error.is_none());
+    let divergence = report.divergence.expect("divergence");
+    assert_eq!(divergence.index, step_end);
+    assert_eq!(
+        divergence.actual.unwrap()["data"]["tool"],
+        json!("util.echo")
+    );
+}
+
+#[tokio::test]
+async fn test_failed_runs_replay_to_the_same_error() {
+    let mut chain = chain_plan();
+    chain.nodes[1].op = Operation::Call;
+
+    let bundle = record(&chain).await;
+    let recorded_error = bundle.error.clone().expect("run should fail");
+    assert!(
+        recorded_error.contains("transient failure"),
+        "{}",
+        recorded_error
+    );
+
+    let report = Scheduler.replay(&bundle).await;
+
+    assert!(report.is_faithful(), "{:?}", report.divergence);
+    assert_eq!(report.error, Some(recorded_error));
+}
@@ -0,0 +1,295 @@
+//! Tests for per-node `timeout_ms` and retry policies
+
+use amp::internal::{
+    exec::scheduler::{ExecutionContext, ExecutionError, Scheduler},
+    plan::ir::{ErrorKind, Node, Operation, Plan, PlanValidationError, RetryPolicy},
+};
+use axum::{
+    extract::State,
+    routing::{get, post},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+#[derive(Deserialize)]
+struct ToolInvokeRequest {
+    args: Option<serde_json::Value>,
+}
+
+#[derive(Serialize)]
+struct ToolInvokeResponse {
+    result: serde_json::Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Serves `util.flaky`, which fails its first two invocations, and
+/// `util.slow`, which takes 200 ms to answer. Both cost $0.01 per call.
+async fn spawn_tool_server() -> (String, Arc<AtomicUsize>, JoinHandle<()>) {
+    async fn flaky(
+        State(calls): State<Arc<AtomicUsize>>,
+        Json(payload): Json<ToolInvokeRequest>,
+    ) -> Json<ToolInvokeResponse> {
+        let call = calls.fetch_add(1, Ordering::SeqCst);
+        if call < 2 {
+            return Json(ToolInvokeResponse {
+                result: serde_json::Value::Null,
+                error: Some(format!("transient failure {}", call + 1)),
+            });
+        }
+        Json(ToolInvokeResponse {
+            result: payload.args.unwrap_or(serde_json::Value::Null),
+            error: None,
+        })
+    }
+
+    async fn slow(
+        State(calls): State<Arc<AtomicUsize>>,
+        Json(payload): Json<ToolInvokeRequest>,
+    ) -> Json<ToolInvokeResponse> {
+        calls.fetch_add(1, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        Json(ToolInvokeResponse {
+            result: payload.args.unwrap_or(serde_json::Value::Null),
+            error: None,
+        })
+    }
+
+    async fn spec(
+        axum::extract::Path(name): axum::extract::Path<String>,
+    ) -> Json<serde_json::Value> {
+        Json(json!({
+            "name": name,
+            "description": "Test tool",
+            "io": {
+                "input": { "type": "object" },
+                "output": { "type": "object" }
+            },
+            "capabilities": [],
+            "constraints": {
+                "input_tokens_max": null,
+                "latency_p50_ms": null,
+                "cost_per_call_usd": 0.01,
+                "rate_limit_qps": null,
+                "side_effects": false
+            }
+        }))
+    }
+
+    let calls = Arc::new(AtomicUsize::new(0));
+    let app = Router::new()
+        .route("/invoke/util.flaky", post(flaky))
+        .route("/invoke/util.slow", post(slow))
+        .route("/spec/:name", get(spec))
+        .with_state(calls.clone());
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let handle = tokio::spawn(async move {
+        axum::serve(listener, app.into_make_service())
+            .await
+            .expect("tool server error");
+    });
+    (format!("http://{}", addr), calls, handle)
+}
+
+fn policy(max_attempts: u32, retry_on: Vec<ErrorKind>) -> RetryPolicy {
+    RetryPolicy {
+        max_attempts,
+        initial_backoff_ms: 1,
+        retry_on,
+        ..RetryPolicy::default()
+    }
+}
+
+fn tool_plan(
+    op: Operation,
+    tool: &str,
+    timeout_ms: Option<u64>,
+    retry: Option<RetryPolicy>,
+) -> Plan {
+    Plan {
+        signals: None,
+        nodes: vec![Node {
+            id: "fetch".to_string(),
+            op,
+            tool: Some(tool.to_string()),
+            capability: None,
+            args: Some(HashMap::from([("q".to_string(), json!("refunds"))])),
+            bind: None,
+            timeout_ms,
+            retry,
+            out: Some(HashMap::from([(
+                "fetched".to_string(),
+                "result.q".to_string(),
+            )])),
+        }],
+        edges: None,
+        stop_conditions: None,
+        outputs: None,
+    }
+}
+
+async fn run(plan: &Plan) -> (Result<ExecutionContext, ExecutionError>, usize) {
+    let (url, calls, handle) = spawn_tool_server().await;
+    let mut ctx = ExecutionContext::new();
+    ctx.tool_urls.insert("util.flaky".to_string(), url.clone());
+    ctx.tool_urls.insert("util.slow".to_string(), url);
+    let result = Scheduler.execute_plan(ctx, plan).await;
+    handle.abort();
+    (result, calls.load(Ordering::SeqCst))
+}
+
+fn attempt_traces(ctx: &ExecutionContext) -> Vec<&serde_json::Value> {
+    ctx.trace_events
+        .iter()
+        .filter(|trace| trace.event_type == "tool_attempt")
+        .filter_map(|trace| trace.data.as_ref())
+        .collect()
+}
+
+#[test]
+fn test_backoff_is_exponential_capped_and_jittered() {
+    let policy = RetryPolicy {
+        initial_backoff_ms: 100,
+        multiplier: 2.0,
+        max_backoff_ms: 300,
+        jitter: 0.5,
+        ..RetryPolicy::default()
+    };
+
+    // A sample of 0.5 applies no jitter
+    assert_eq!(policy.backoff_ms(1, 0.5), 100);
+    assert_eq!(policy.backoff_ms(2, 0.5), 200);
+    assert_eq!(policy.backoff_ms(3, 0.5), 300);
+    assert_eq!(policy.backoff_ms(40, 0.5), 300);
+
+    assert_eq!(policy.backoff_ms(2, 0.0), 100);
+    assert_eq!(policy.backoff_ms(2, 1.0), 300);
+}
+
+#[tokio::test]
+async fn test_call_retries_and_charges_every_attempt() {
+    let plan = tool_plan(
+        Operation::Call,
+        "util.flaky",
+        None,
+        Some(policy(3, vec![ErrorKind::Invocation])),
+    );
+
+    let (result, calls) = run(&plan).await;
+    let ctx = result.expect("third attempt should succeed");
+
+    assert_eq!(calls, 3);
+    assert_eq!(ctx.variables["fetched"], json!("refunds"));
+    assert!((ctx.total_cost_usd - 0.03).abs() < 1e-9);
+
+    let attempts = attempt_traces(&ctx);
+    assert_eq!(attempts.len(), 3);
+    assert_eq!(attempts[0]["outcome"], json!("error"));
+    assert_eq!(attempts[0]["error_kind"], json!("invocation"));
+    assert!(attempts[0]["backoff_ms"].is_u64());
+    assert_eq!(attempts[2]["outcome"], json!("ok"));
+    assert_eq!(attempts[2]["attempt"], json!(3));
+}
+
+#[tokio::test]
+async fn test_nodes_without_policy_make_one_attempt() {
+    let plan = tool_plan(Operation::Call, "util.flaky", None, None);
+
+    let (result, calls) = run(&plan).await;
+
+    assert_eq!(calls, 1);
+    match result {
+        Err(ExecutionError::ToolExecutionError(msg)) => {
+            assert!(msg.contains("transient failure 1"), "{}", msg)
+        }
+        other => panic!("Expected tool error, got {:?}", other.map(|_| ())),
+    }
+}
+
+#[tokio::test]
+async fn test_retry_nodes_use_default_policy() {
+    let plan = tool_plan(Operation::Retry, "util.flaky", None, None);
+
+    let (result, calls) = run(&plan).await;
+
+    assert!(result.is_ok());
+    assert_eq!(calls, RetryPolicy::default().max_attempts as usize);
+}
+
+#[tokio::test]
+async fn test_timeout_ms_applies_per_attempt() {
+    let plan = tool_plan(
+        Operation::Call,
+        "util.slow",
+        Some(50),
+        Some(policy(2, vec![ErrorKind::Timeout])),
+    );
+
+    let (result, calls) = run(&plan).await;
+
+    assert_eq!(calls, 2);
+    match result {
+        Err(ExecutionError::TimeoutError(msg)) => {
+            assert!(msg.contains("timed out after 50 ms"), "{}", msg);
+            assert!(msg.contains("after 2 attempts"), "{}", msg);
+        }
+        other => panic!("Expected timeout, got {:?}", other.map(|_| ())),
+    }
+}
+
+#[tokio::test]
+async fn test_non_retryable_errors_fail_immediately() {
+    let plan = tool_plan(
+        Operation::Call,
+        "util.flaky",
+        None,
+        Some(policy(5, vec![ErrorKind::Timeout])),
+    );
+
+    let (result, calls) = run(&plan).await;
+
+    assert_eq!(calls, 1);
+    assert!(matches!(result, Err(ExecutionError::ToolExecutionError(_))));
+}
+
+#[test]
+fn test_policies_are_validated_and_default_from_json() {
+    let node: Node = serde_json::from_value(json!({
+        "id": "fetch",
+        "op": "call",
+        "tool": "util.flaky",
+        "args": {},
+        "bind": null,
+        "out": { "fetched": "result" },
+        "timeout_ms": 250,
+        "retry": { "max_attempts": 4, "retry_on": ["communication"] }
+    }))
+    .unwrap();
+    let retry = node.retry.clone().unwrap();
+    assert_eq!(node.timeout_ms, Some(250));
+    assert_eq!(retry.max_attempts, 4);
+    assert_eq!(retry.retry_on, vec![ErrorKind::Communication]);
+    assert_eq!(
+        retry.initial_backoff_ms,
+        RetryPolicy::default().initial_backoff_ms
+    );
+
+    let invalid = tool_plan(Operation::Call, "util.flaky", None, Some(policy(0, vec![])));
+    assert!(matches!(
+        invalid.validate(),
+        Err(PlanValidationError::InvalidRetryPolicy(ref msg)) if msg.contains("max_attempts")
+    ));
+
+    let zero_timeout = tool_plan(Operation::Call, "util.flaky", Some(0), None);
+    assert!(matches!(
+        zero_timeout.validate(),
+        Err(PlanValidationError::InvalidRetryPolicy(_))
+    ));
+}
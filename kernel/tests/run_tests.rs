@@ -0,0 +1,413 @@
+//! Tests for tracked background runs: status, progress and cancellation
+
+use amp::internal::{
+    api::{router, AppState},
+    exec::run::{RunHandle, RunState},
+    exec::scheduler::{ExecutionContext, ExecutionError, Scheduler},
+    plan::ir::Plan,
+};
+use axum::{
+    extract::Path,
+    routing::{get, post},
+    Json, Router,
+};
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
+
+#[derive(Deserialize)]
+struct ToolInvokeRequest {
+    args: Option<serde_json::Value>,
+}
+
+#[derive(Serialize)]
+struct ToolInvokeResponse {
+    result: serde_json::Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Serves `util.echo` and `util.slow`, which takes a minute to answer.
+async fn spawn_tool_server() -> (String, JoinHandle<()>) {
+    async fn echo(Json(payload): Json<ToolInvokeRequest>) -> Json<ToolInvokeResponse> {
+        Json(ToolInvokeResponse {
+            result: payload.args.unwrap_or(serde_json::Value::Null),
+            error: None,
+        })
+    }
+
+    async fn slow(Json(payload): Json<ToolInvokeRequest>) -> Json<ToolInvokeResponse> {
+        tokio::time::sleep(Duration::from_secs(60)).await;
+        echo(Json(payload)).await
+    }
+
+    async fn spec(Path(name): Path<String>) -> Json<serde_json::Value> {
+        Json(json!({
+            "name": name,
+            "description": "Test tool",
+            "io": {
+                "input": { "type": "object" },
+                "output": { "type": "object" }
+            },
+            "capabilities": [],
+            "constraints": null
+        }))
+    }
+
+    let app = Router::new()
+        .route("/invoke/util.echo", post(echo))
+        .route("/invoke/util.slow", post(slow))
+        .route("/spec/:name", get(spec));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let handle = tokio::spawn(async move {
+        axum::serve(listener, app.into_make_service())
+            .await
+            .expect("tool server error");
+    });
+    (format!("http://{}", addr), handle)
+}
+
+/// `greet` echoes, then `wait` calls the slow tool with a generous timeout.
+fn slow_plan() -> Plan {
+    serde_json::from_value(json!({
+        "nodes": [
+            {
+                "id": "greet",
+                "op": "call",
+                "tool": "util.echo",
+                "args": { "msg": "hello" },
+                "out": { "greeting": "result.msg" }
+            },
+            {
+                "id": "wait",
+                "op": "call",
+                "tool": "util.slow",
+                "args": { "msg": "$greeting" },
+                "timeout_ms": 120000,
+                "out": { "answer": "result.msg" }
+            }
+        ],
+        "edges": [{ "from": "greet", "to": "wait" }]
+    }))
+    .unwrap()
+}
+
+/// Polls `condition` every 10 ms for up to five seconds.
+async fn eventually(mut condition: impl FnMut() -> bool) {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while !condition() {
+        assert!(Instant::now() < deadline, "condition not reached in time");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+#[tokio::test]
+async fn test_cancel_interrupts_in_flight_tool_call() {
+    let (url, server) = spawn_tool_server().await;
+    let mut ctx = ExecutionContext::new();
+    ctx.tool_urls.insert("util.echo".to_string(), url.clone());
+    ctx.tool_urls.insert("util.slow".to_string(), url);
+    let run = RunHandle::new(
+        ctx.plan_id.clone(),
+        ctx.run_id.clone(),
+        2,
+        ctx.cancel.clone(),
+    );
+    ctx.run = Some(run.clone());
+    run.start();
+
+    let plan = slow_plan();
+    let execution = tokio::spawn(async move { Scheduler.run(ctx, &plan).await });
+
+    // Progress shows the finished greeting and the call in flight
+    eventually(|| run.status().current_nodes == vec!["wait".to_string()]).await;
+    let status = run.status();
+    assert_eq!(status.state, RunState::Running);
+    assert_eq!(status.progress.completed_nodes, 1);
+    assert_eq!(status.progress.total_nodes, 2);
+
+    let started = Instant::now();
+    assert!(run.cancel());
+    let (ctx, result) = tokio::time::timeout(Duration::from_secs(2), execution)
+        .await
+        .expect("cancellation should not wait for the tool")
+        .unwrap();
+    assert!(started.elapsed() < Duration::from_secs(2));
+    server.abort();
+
+    match result {
+        Err(ExecutionError::Cancelled(message)) => {
+            assert!(message.contains("wait"), "{}", message)
+        }
+        other => panic!("Expected cancellation, got {:?}", other),
+    }
+    let last = ctx.trace_events.last().unwrap();
+    assert_eq!(last.event_type, "plan_cancelled");
+    assert_eq!(
+        last.data.as_ref().unwrap()["interrupted_nodes"],
+        json!(["wait"])
+    );
+    assert!(ctx.completed_nodes.contains("greet"));
+    assert!(run.status().current_nodes.is_empty());
+}
+
+#[tokio::test]
+async fn test_run_cancelled_before_it_starts_never_contacts_its_tools() {
+    // Counts the connections a discovery or spec fetch would open
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let connections = Arc::new(AtomicUsize::new(0));
+    let server = tokio::spawn({
+        let connections = connections.clone();
+        async move {
+            while listener.accept().await.is_ok() {
+                connections.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+    });
+
+    let mut ctx = ExecutionContext::new();
+    ctx.tool_urls.insert("util.echo".to_string(), url.clone());
+    ctx.tool_urls.insert("util.slow".to_string(), url);
+    ctx.cancel.cancel();
+
+    let (ctx, result) = Scheduler.run(ctx, &slow_plan()).await;
+    server.abort();
+
+    assert!(matches!(result, Err(ExecutionError::Cancelled(_))));
+    assert_eq!(connections.load(Ordering::SeqCst), 0);
+    assert!(ctx.tool_specs.is_empty());
+    assert!(ctx.completed_nodes.is_empty());
+    assert_eq!(
+        ctx.trace_events.last().unwrap().event_type,
+        "plan_cancelled"
+    );
+}
+
+#[tokio::test]
+async fn test_runs_are_reported_and_cancelled_over_the_api() {
+    let (url, server) = spawn_tool_server().await;
+    let config = std::env::temp_dir().join(format!("amp-tools-{}.json", uuid::Uuid::new_v4()));
+    std::fs::write(
+        &config,
+        json!([
+            { "name": "util.echo", "url": url },
+            { "name": "util.slow", "url": url }
+        ])
+        .to_string(),
+    )
+    .unwrap();
+    // This is the only test in this binary that sets the tool config
+    std::env::set_var("AMP_TOOL_CONFIG", &config);
+
+    let app = amp::internal::api::create_router().unwrap();
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let kernel = format!("http://{}", listener.local_addr().unwrap());
+    let kernel_handle = tokio::spawn(async move {
+        axum::serve(listener, app.into_make_service())
+            .await
+            .expect("kernel api server error");
+    });
+    let client = Client::new();
+
+    let started: serde_json::Value = client
+        .post(format!("{}/v1/plan/execute", kernel))
+        .json(&json!({ "plan": slow_plan() }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(started["status"], "queued");
+    let run_id = started["run_id"].as_str().unwrap();
+    let status_url = format!("{}{}", kernel, started["status_url"].as_str().unwrap());
+    assert_eq!(status_url, format!("{}/v1/runs/{}", kernel, run_id));
+
+    // Wait until the slow call is in flight
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        let status: serde_json::Value = client
+            .get(&status_url)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        if status["current_nodes"] == json!(["wait"]) {
+            assert_eq!(status["state"], "running");
+            assert_eq!(
+                status["progress"],
+                json!({ "completed_nodes": 1, "skipped_nodes": 0, "total_nodes": 2 })
+            );
+            break;
+        }
+        assert!(
+            Instant::now() < deadline,
+            "run never reached wait: {}",
+            status
+        );
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    // Traces are stored as they are recorded, not once the run ends
+    let trace_url = format!(
+        "{}/v1/trace/{}",
+        kernel,
+        started["plan_id"].as_str().unwrap()
+    );
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        let response = client.get(&trace_url).send().await.unwrap();
+        if response.status() == StatusCode::OK {
+            let stored: serde_json::Value = response.json().await.unwrap();
+            let steps: Vec<&str> = stored["traces"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .filter_map(|trace| trace["step_id"].as_str())
+                .collect();
+            if steps.contains(&"greet") {
+                assert!(!steps.contains(&"wait"), "{:?}", steps);
+                break;
+            }
+        }
+        assert!(
+            Instant::now() < deadline,
+            "greet's traces were never stored"
+        );
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    let cancel = client
+        .post(format!("{}/cancel", status_url))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(cancel.status(), StatusCode::ACCEPTED);
+
+    // The live stream ends with the cancellation
+    let stream = client
+        .get(format!(
+            "{}{}",
+            kernel,
+            started["stream_url"].as_str().unwrap()
+        ))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(
+        stream.contains("\"event_type\":\"plan_cancelled\""),
+        "{}",
+        stream
+    );
+    let end = stream
+        .split("\n\n")
+        .find(|event| event.contains("event: end"))
+        .expect("missing end event");
+    assert!(end.contains("\"status\":\"cancelled\""), "{}", end);
+
+    let status: serde_json::Value = client
+        .get(&status_url)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(status["state"], "cancelled");
+    assert!(status["finished_at"].is_string());
+
+    // Finished runs cannot be cancelled again, and unknown runs do not exist
+    let again = client
+        .post(format!("{}/cancel", status_url))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(again.status(), StatusCode::CONFLICT);
+    let missing = client
+        .get(format!("{}/v1/runs/no-such-run", kernel))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+
+    kernel_handle.abort();
+    server.abort();
+    let _ = std::fs::remove_file(&config);
+}
+
+#[tokio::test]
+async fn test_finished_runs_are_reported_from_the_store() {
+    let mut state = AppState::new(HashMap::new()).unwrap();
+    state.retention = Duration::ZERO;
+    let app = router(state.clone());
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let kernel = format!("http://{}", listener.local_addr().unwrap());
+    let kernel_handle = tokio::spawn(async move {
+        axum::serve(listener, app.into_make_service())
+            .await
+            .expect("kernel api server error");
+    });
+    let client = Client::new();
+
+    let plan = json!({
+        "nodes": [{ "id": "check", "op": "assert", "args": { "condition": "1 < 2" } }]
+    });
+    let finished: serde_json::Value = client
+        .post(format!("{}/v1/plan/execute", kernel))
+        .json(&json!({ "plan": plan, "wait": true }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(finished["status"], "succeeded");
+    let status_url = format!("{}{}", kernel, finished["status_url"].as_str().unwrap());
+
+    // The handle is let go of once the retention has passed
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while !state.runs.read().await.is_empty() {
+        assert!(Instant::now() < deadline, "run was never forgotten");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    let status: serde_json::Value = client
+        .get(&status_url)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(status["state"], "succeeded");
+    assert_eq!(status["run_id"], finished["run_id"]);
+    assert!(status["finished_at"].is_string());
+
+    let cancelled = client
+        .post(format!("{}/cancel", status_url))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(cancelled.status(), StatusCode::CONFLICT);
+
+    let unknown = client
+        .get(format!("{}/v1/runs/unknown", kernel))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(unknown.status(), StatusCode::NOT_FOUND);
+
+    kernel_handle.abort();
+}
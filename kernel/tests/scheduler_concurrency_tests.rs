@@ -0,0 +1,238 @@
+//! Tests for concurrent execution of independent plan nodes
+
+use amp::internal::{
+    exec::scheduler::{ExecutionContext, Scheduler},
+    plan::ir::{Edge, Node, Operation, Plan, Signals},
+};
+use axum::{
+    extract::State,
+    routing::{get, post},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
+
+const TOOL_DELAY_MS: u64 = 200;
+
+#[derive(Deserialize)]
+struct ToolInvokeRequest {
+    args: Option<serde_json::Value>,
+}
+
+#[derive(Serialize)]
+struct ToolInvokeResponse {
+    result: serde_json::Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Clone, Default)]
+struct InFlight {
+    current: Arc<AtomicUsize>,
+    peak: Arc<AtomicUsize>,
+}
+
+async fn spawn_slow_search_server() -> (String, InFlight, JoinHandle<()>) {
+    async fn handler(
+        State(state): State<InFlight>,
+        Json(payload): Json<ToolInvokeRequest>,
+    ) -> Json<ToolInvokeResponse> {
+        let now = state.current.fetch_add(1, Ordering::SeqCst) + 1;
+        state.peak.fetch_max(now, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(TOOL_DELAY_MS)).await;
+        state.current.fetch_sub(1, Ordering::SeqCst);
+
+        let query = payload
+            .args
+            .as_ref()
+            .and_then(|args| args.get("q"))
+            .cloned()
+            .unwrap_or(serde_json::Value::Null);
+
+        Json(ToolInvokeResponse {
+            result: json!({ "hits": [{ "id": "hit-1", "query": query }] }),
+            error: None,
+        })
+    }
+
+    async fn spec_handler() -> Json<serde_json::Value> {
+        Json(json!({
+            "name": "doc.search.slow",
+            "description": "Search stub that sleeps before answering",
+            "io": {
+                "input": { "type": "object", "properties": null, "required": null, "items": null },
+                "output": { "type": "object", "properties": null, "required": null, "items": null }
+            },
+            "capabilities": ["search.documents"],
+            "constraints": {
+                "input_tokens_max": 100,
+                "latency_p50_ms": 10,
+                "cost_per_call_usd": 0.001,
+                "rate_limit_qps": 50,
+                "side_effects": false
+            }
+        }))
+    }
+
+    let state = InFlight::default();
+    let app = Router::new()
+        .route("/invoke/doc.search.slow", post(handler))
+        .route("/spec/doc.search.slow", get(spec_handler))
+        .with_state(state.clone());
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let handle = tokio::spawn(async move {
+        axum::serve(listener, app.into_make_service())
+            .await
+            .expect("doc.search.slow server error");
+    });
+    (format!("http://{}", addr), state, handle)
+}
+
+fn search_node(id: &str, out_var: &str) -> Node {
+    Node {
+        id: id.to_string(),
+        op: Operation::Call,
+        tool: Some("doc.search.slow".to_string()),
+        capability: None,
+        args: Some(HashMap::from([("q".to_string(), json!(id))])),
+        bind: None,
+        timeout_ms: None,
+        retry: None,
+        out: Some(HashMap::from([(out_var.to_string(), "result".to_string())])),
+    }
+}
+
+fn fan_out_plan(width: usize) -> Plan {
+    Plan {
+        signals: Some(Signals {
+            latency_budget_ms: Some(10_000),
+            cost_cap_usd: Some(1.0),
+            fuel_cap: None,
+            risk: Some(0.1),
+        }),
+        nodes: (0..width)
+            .map(|i| search_node(&format!("search_{}", i), &format!("hits_{}", i)))
+            .collect(),
+        edges: None,
+        stop_conditions: None,
+        outputs: None,
+    }
+}
+
+#[tokio::test]
+async fn test_independent_nodes_run_concurrently() {
+    let (url, in_flight, handle) = spawn_slow_search_server().await;
+
+    let mut ctx = ExecutionContext::new();
+    ctx.tool_urls.insert("doc.search.slow".to_string(), url);
+
+    let started = Instant::now();
+    let result = Scheduler
+        .execute_plan(ctx, &fan_out_plan(5))
+        .await
+        .expect("fan-out plan should succeed");
+    let elapsed = started.elapsed();
+
+    assert!(
+        elapsed < Duration::from_millis(TOOL_DELAY_MS * 3),
+        "fan-out took {:?}, expected concurrent execution",
+        elapsed
+    );
+    assert_eq!(in_flight.peak.load(Ordering::SeqCst), 5);
+
+    for i in 0..5 {
+        let hits = result
+            .variables
+            .get(&format!("hits_{}", i))
+            .expect("each node should bind its output");
+        assert_eq!(hits["hits"][0]["query"], json!(format!("search_{}", i)));
+    }
+    assert_eq!(result.completed_nodes.len(), 5);
+
+    // Cost is summed across the ready set, latency is charged once for the set.
+    assert!((result.total_cost_usd - 0.005).abs() < 1e-9);
+    assert_eq!(result.total_tokens, 500);
+    assert!(result.total_latency_ms < (TOOL_DELAY_MS * 3) as f64);
+
+    let step_ends = result
+        .trace_events
+        .iter()
+        .filter(|trace| trace.event_type == "step_end")
+        .count();
+    assert_eq!(step_ends, 5);
+
+    handle.abort();
+}
+
+#[tokio::test]
+async fn test_max_parallelism_limits_in_flight_nodes() {
+    let (url, in_flight, handle) = spawn_slow_search_server().await;
+
+    let mut ctx = ExecutionContext::new();
+    ctx.tool_urls.insert("doc.search.slow".to_string(), url);
+    ctx.max_parallelism = 2;
+
+    Scheduler
+        .execute_plan(ctx, &fan_out_plan(5))
+        .await
+        .expect("fan-out plan should succeed");
+
+    assert_eq!(in_flight.peak.load(Ordering::SeqCst), 2);
+
+    handle.abort();
+}
+
+#[tokio::test]
+async fn test_dependent_nodes_see_concurrent_writes() {
+    let (url, _in_flight, handle) = spawn_slow_search_server().await;
+
+    let mut plan = fan_out_plan(2);
+    plan.nodes.push(Node {
+        id: "combine".to_string(),
+        op: Operation::Call,
+        tool: Some("doc.search.slow".to_string()),
+        capability: None,
+        args: Some(HashMap::from([(
+            "q".to_string(),
+            json!(["$hits_0.hits[0].query", "$hits_1.hits[0].query"]),
+        )])),
+        bind: None,
+        timeout_ms: None,
+        retry: None,
+        out: Some(HashMap::from([(
+            "combined".to_string(),
+            "result".to_string(),
+        )])),
+    });
+    plan.edges = Some(vec![
+        Edge {
+            from: "search_0".to_string(),
+            to: "combine".to_string(),
+        },
+        Edge {
+            from: "search_1".to_string(),
+            to: "combine".to_string(),
+        },
+    ]);
+
+    let mut ctx = ExecutionContext::new();
+    ctx.tool_urls.insert("doc.search.slow".to_string(), url);
+
+    let result = Scheduler
+        .execute_plan(ctx, &plan)
+        .await
+        .expect("plan should succeed");
+
+    assert_eq!(
+        result.variables["combined"]["hits"][0]["query"],
+        json!(["search_0", "search_1"])
+    );
+
+    handle.abort();
+}
@@ -0,0 +1,277 @@
+//! Tests for validating tool arguments and results against ToolSpec schemas
+
+use amp::internal::{
+    exec::scheduler::{ExecutionContext, ExecutionError, Scheduler},
+    plan::ir::{Node, Operation, Plan, PlanValidationError},
+    tools::spec::{Schema, ToolError, ToolSpec},
+};
+use axum::{
+    extract::State,
+    routing::{get, post},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::task::JoinHandle;
+
+#[derive(Deserialize)]
+struct ToolInvokeRequest {
+    args: Option<serde_json::Value>,
+}
+
+#[derive(Serialize)]
+struct ToolInvokeResponse {
+    result: serde_json::Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+fn strict_spec() -> serde_json::Value {
+    json!({
+        "name": "doc.search.strict",
+        "description": "Search with a strict schema",
+        "io": {
+            "input": {
+                "type": "object",
+                "properties": {
+                    "q": { "type": "string" },
+                    "k": { "type": "integer" },
+                    "filters": { "type": "array", "items": { "type": "string" } }
+                },
+                "required": ["q"]
+            },
+            "output": {
+                "type": "object",
+                "properties": {
+                    "hits": {
+                        "type": "array",
+                        "items": {
+                            "type": "object",
+                            "properties": { "id": { "type": "string" }, "score": {} },
+                            "required": ["id"]
+                        }
+                    }
+                },
+                "required": ["hits"]
+            }
+        },
+        "capabilities": ["search.documents"],
+        "constraints": null
+    })
+}
+
+/// Returns a hit with a numeric id (violating the output schema) when
+/// `q` is "bad-output". Counts invocations.
+async fn spawn_strict_server() -> (String, Arc<AtomicUsize>, JoinHandle<()>) {
+    async fn handler(
+        State(calls): State<Arc<AtomicUsize>>,
+        Json(payload): Json<ToolInvokeRequest>,
+    ) -> Json<ToolInvokeResponse> {
+        calls.fetch_add(1, Ordering::SeqCst);
+        let query = payload
+            .args
+            .as_ref()
+            .and_then(|args| args.get("q"))
+            .and_then(|q| q.as_str())
+            .unwrap_or_default()
+            .to_string();
+        let id = if query == "bad-output" {
+            json!(42)
+        } else {
+            json!("doc-1")
+        };
+
+        Json(ToolInvokeResponse {
+            result: json!({ "hits": [{ "id": "doc-0", "score": 0.5 }, { "id": id }] }),
+            error: None,
+        })
+    }
+
+    async fn spec_handler() -> Json<serde_json::Value> {
+        Json(strict_spec())
+    }
+
+    let calls = Arc::new(AtomicUsize::new(0));
+    let app = Router::new()
+        .route("/invoke/doc.search.strict", post(handler))
+        .route("/spec/doc.search.strict", get(spec_handler))
+        .with_state(calls.clone());
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let handle = tokio::spawn(async move {
+        axum::serve(listener, app.into_make_service())
+            .await
+            .expect("doc.search.strict server error");
+    });
+    (format!("http://{}", addr), calls, handle)
+}
+
+fn search_plan(args: serde_json::Value) -> Plan {
+    let args = match args {
+        serde_json::Value::Object(map) => map.into_iter().collect(),
+        _ => HashMap::new(),
+    };
+
+    Plan {
+        signals: None,
+        nodes: vec![Node {
+            id: "search".to_string(),
+            op: Operation::Call,
+            tool: Some("doc.search.strict".to_string()),
+            capability: None,
+            args: Some(args),
+            bind: None,
+            timeout_ms: None,
+            retry: None,
+            out: Some(HashMap::from([(
+                "hits".to_string(),
+                "result.hits".to_string(),
+            )])),
+        }],
+        edges: None,
+        stop_conditions: None,
+        outputs: None,
+    }
+}
+
+#[test]
+fn test_schema_reports_json_pointer_paths() {
+    let spec: ToolSpec = serde_json::from_value(strict_spec()).unwrap();
+    let violations = spec.io.output.validate(&json!({
+        "hits": [{ "id": "ok" }, { "id": 7 }, { "score": 1 }, "oops"]
+    }));
+
+    let rendered: Vec<String> = violations.iter().map(|v| v.to_string()).collect();
+    assert_eq!(
+        rendered,
+        vec![
+            "/hits/1/id: expected string, found integer",
+            "/hits/2/id: required property is missing",
+            "/hits/3: expected object, found string",
+        ]
+    );
+
+    let schema: Schema = serde_json::from_value(json!({
+        "type": "object",
+        "properties": { "a/b~c": { "type": "boolean" } }
+    }))
+    .unwrap();
+    assert_eq!(
+        schema.validate(&json!({ "a/b~c": "yes" }))[0].pointer,
+        "/a~1b~0c"
+    );
+    assert_eq!(
+        schema.validate(&json!([]))[0].to_string(),
+        "(root): expected object, found array"
+    );
+}
+
+#[test]
+fn test_tool_spec_validation_errors() {
+    let spec: ToolSpec = serde_json::from_value(strict_spec()).unwrap();
+
+    assert!(spec
+        .validate_input(Some(&json!({ "q": "refunds", "k": 3 })))
+        .is_ok());
+    match spec.validate_input(None) {
+        Err(ToolError::Validation(msg)) => {
+            assert_eq!(
+                msg,
+                "Invalid arguments for doc.search.strict: /q: required property is missing"
+            )
+        }
+        other => panic!("Expected validation error, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_invalid_arguments_are_rejected_before_invocation() {
+    let (url, calls, handle) = spawn_strict_server().await;
+    let mut ctx = ExecutionContext::new();
+    ctx.tool_urls.insert("doc.search.strict".to_string(), url);
+    ctx.variables.insert("k".to_string(), json!("five"));
+
+    let plan = search_plan(json!({ "q": "refunds", "k": "$k" }));
+    let result = Scheduler.execute_plan(ctx, &plan).await;
+    handle.abort();
+
+    match result {
+        Err(ExecutionError::ToolExecutionError(msg)) => {
+            assert!(
+                msg.contains("/k: expected integer, found string"),
+                "{}",
+                msg
+            )
+        }
+        other => panic!("Expected tool error, got {:?}", other.map(|_| ())),
+    }
+    assert_eq!(calls.load(Ordering::SeqCst), 0);
+}
+
+#[test]
+fn test_whole_floats_are_integers() {
+    let schema: Schema = serde_json::from_value(json!({ "type": "integer" })).unwrap();
+    assert!(schema.validate(&json!(3)).is_empty());
+    assert!(schema.validate(&json!(3.0)).is_empty());
+    assert!(schema.validate(&json!(-0.0)).is_empty());
+    assert_eq!(
+        schema.validate(&json!(2.5))[0].to_string(),
+        "(root): expected integer, found number"
+    );
+}
+
+#[tokio::test]
+async fn test_invalid_results_are_rejected() {
+    let (url, calls, handle) = spawn_strict_server().await;
+    let mut ctx = ExecutionContext::new();
+    ctx.tool_urls.insert("doc.search.strict".to_string(), url);
+
+    let plan = search_plan(json!({ "q": "bad-output" }));
+    let result = Scheduler.execute_plan(ctx, &plan).await;
+    handle.abort();
+
+    match result {
+        Err(ExecutionError::ToolExecutionError(msg)) => {
+            assert!(
+                msg.contains("Invalid result for doc.search.strict: /hits/1/id"),
+                "{}",
+                msg
+            )
+        }
+        other => panic!("Expected tool error, got {:?}", other.map(|_| ())),
+    }
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn test_static_pass_checks_literal_arguments() {
+    let spec: ToolSpec = serde_json::from_value(strict_spec()).unwrap();
+    let specs = HashMap::from([("doc.search.strict".to_string(), spec)]);
+    let tools = ["doc.search.strict"];
+
+    let bad = search_plan(json!({ "q": "refunds", "k": "five", "filters": ["a", 1] }));
+    match bad.validate_with_tool_specs(tools, &specs) {
+        Err(PlanValidationError::InvalidArgs(msg)) => assert_eq!(
+            msg,
+            "node search (tool doc.search.strict): /filters/1: expected string, found integer; \
+             /k: expected integer, found string"
+        ),
+        other => panic!("Expected invalid args, got {:?}", other),
+    }
+
+    let missing = search_plan(json!({ "k": 5 }));
+    assert!(matches!(
+        missing.validate_with_tool_specs(tools, &specs),
+        Err(PlanValidationError::InvalidArgs(ref msg)) if msg.contains("/q: required property is missing")
+    ));
+
+    // References are resolved at runtime, so only their presence is checked
+    let referenced = search_plan(json!({ "q": "$question", "k": "$k" }));
+    assert!(referenced.validate_with_tool_specs(tools, &specs).is_ok());
+
+    // Without specs the static pass is skipped
+    assert!(bad.validate_with_tools(tools).is_ok());
+}
@@ -0,0 +1,305 @@
+//! Tests for `spawn` sub-plan execution
+
+use amp::internal::{
+    exec::scheduler::{ExecutionContext, ExecutionError, Scheduler},
+    plan::ir::{Node, Operation, Plan, PlanValidationError, Signals},
+};
+use axum::{
+    routing::{get, post},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use tokio::task::JoinHandle;
+
+#[derive(Deserialize)]
+struct ToolInvokeRequest {
+    args: Option<serde_json::Value>,
+}
+
+#[derive(Serialize)]
+struct ToolInvokeResponse {
+    result: serde_json::Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+async fn spawn_echo_server() -> (String, JoinHandle<()>) {
+    async fn handler(Json(payload): Json<ToolInvokeRequest>) -> Json<ToolInvokeResponse> {
+        Json(ToolInvokeResponse {
+            result: payload.args.unwrap_or(serde_json::Value::Null),
+            error: None,
+        })
+    }
+
+    async fn spec_handler() -> Json<serde_json::Value> {
+        Json(json!({
+            "name": "util.echo",
+            "description": "Echoes its arguments",
+            "io": {
+                "input": { "type": "object", "properties": null, "required": null, "items": null },
+                "output": { "type": "object", "properties": null, "required": null, "items": null }
+            },
+            "capabilities": ["util.echo"],
+            "constraints": {
+                "input_tokens_max": 50,
+                "latency_p50_ms": 5,
+                "cost_per_call_usd": 0.001,
+                "rate_limit_qps": 100,
+                "side_effects": false
+            }
+        }))
+    }
+
+    let app = Router::new()
+        .route("/invoke/util.echo", post(handler))
+        .route("/spec/util.echo", get(spec_handler));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let handle = tokio::spawn(async move {
+        axum::serve(listener, app.into_make_service())
+            .await
+            .expect("util.echo server error");
+    });
+    (format!("http://{}", addr), handle)
+}
+
+/// A one-node retrieval plan that echoes `$q` into `echoed`.
+fn retrieval_plan() -> Plan {
+    Plan {
+        signals: None,
+        nodes: vec![Node {
+            id: "echo".to_string(),
+            op: Operation::Call,
+            tool: Some("util.echo".to_string()),
+            capability: None,
+            args: Some(HashMap::from([("q".to_string(), json!("$q"))])),
+            bind: None,
+            timeout_ms: None,
+            retry: None,
+            out: Some(HashMap::from([(
+                "echoed".to_string(),
+                "result.q".to_string(),
+            )])),
+        }],
+        edges: None,
+        stop_conditions: None,
+        outputs: None,
+    }
+}
+
+fn spawn_plan(plan: serde_json::Value, extra_args: serde_json::Value) -> Plan {
+    let mut args = HashMap::from([
+        ("plan".to_string(), plan),
+        ("outputs".to_string(), json!(["echoed"])),
+    ]);
+    if let serde_json::Value::Object(map) = extra_args {
+        args.extend(map);
+    }
+
+    Plan {
+        signals: Some(Signals {
+            latency_budget_ms: Some(10_000),
+            cost_cap_usd: Some(1.0),
+            fuel_cap: None,
+            risk: Some(0.1),
+        }),
+        nodes: vec![Node {
+            id: "retrieve".to_string(),
+            op: Operation::Spawn,
+            tool: None,
+            capability: None,
+            args: Some(args),
+            bind: Some(HashMap::from([("q".to_string(), "$query".to_string())])),
+            timeout_ms: None,
+            retry: None,
+            out: Some(HashMap::from([(
+                "answer".to_string(),
+                "result.echoed".to_string(),
+            )])),
+        }],
+        edges: None,
+        stop_conditions: None,
+        outputs: None,
+    }
+}
+
+async fn run(
+    plan: &Plan,
+    sub_plans: Vec<(&str, Plan)>,
+) -> Result<ExecutionContext, ExecutionError> {
+    let (url, handle) = spawn_echo_server().await;
+    let mut ctx = ExecutionContext::new();
+    ctx.tool_urls.insert("util.echo".to_string(), url);
+    ctx.variables
+        .insert("query".to_string(), json!("refund policy"));
+    ctx.variables.insert("secret".to_string(), json!("hidden"));
+    for (name, sub_plan) in sub_plans {
+        ctx.register_sub_plan(name, sub_plan);
+    }
+    let result = Scheduler.execute_plan(ctx, plan).await;
+    handle.abort();
+    result
+}
+
+#[tokio::test]
+async fn test_spawn_runs_inline_sub_plan_and_returns_outputs() {
+    let plan = spawn_plan(serde_json::to_value(retrieval_plan()).unwrap(), json!({}));
+    let ctx = run(&plan, vec![]).await.expect("spawn should succeed");
+
+    assert_eq!(ctx.variables["answer"], json!("refund policy"));
+    assert!(!ctx.variables.contains_key("echoed"));
+    assert!((ctx.total_cost_usd - 0.001).abs() < 1e-9);
+
+    let steps: Vec<(&str, &str)> = ctx
+        .trace_events
+        .iter()
+        .map(|trace| (trace.event_type.as_str(), trace.step_id.as_str()))
+        .collect();
+    assert!(steps.contains(&("spawn_start", "retrieve")));
+    assert!(steps.contains(&("step_end", "retrieve/echo")));
+    assert!(steps.contains(&("spawn_end", "retrieve")));
+}
+
+#[tokio::test]
+async fn test_spawn_runs_sub_plan_by_reference() {
+    let plan = spawn_plan(json!("retrieval"), json!({}));
+    let ctx = run(&plan, vec![("retrieval", retrieval_plan())])
+        .await
+        .expect("spawn should succeed");
+
+    assert_eq!(ctx.variables["answer"], json!("refund policy"));
+
+    let missing = spawn_plan(json!("unknown"), json!({}));
+    match run(&missing, vec![]).await {
+        Err(ExecutionError::ValidationError(msg)) => {
+            assert!(msg.contains("unknown sub-plan"), "{}", msg)
+        }
+        other => panic!("Expected validation error, got {:?}", other.map(|_| ())),
+    }
+}
+
+#[tokio::test]
+async fn test_sub_plan_only_sees_bound_variables() {
+    let mut child = retrieval_plan();
+    child.nodes.push(Node {
+        id: "check".to_string(),
+        op: Operation::Assert,
+        tool: None,
+        capability: None,
+        args: Some(HashMap::from([(
+            "condition".to_string(),
+            json!("$secret == 'hidden'"),
+        )])),
+        bind: None,
+        timeout_ms: None,
+        retry: None,
+        out: None,
+    });
+
+    let plan = spawn_plan(serde_json::to_value(child).unwrap(), json!({}));
+    match run(&plan, vec![]).await {
+        Err(ExecutionError::ValidationError(msg)) => {
+            assert!(msg.contains("sub-plan spawned by retrieve"), "{}", msg);
+            assert!(msg.contains("unknown variable"), "{}", msg);
+        }
+        other => panic!("Expected validation error, got {:?}", other.map(|_| ())),
+    }
+}
+
+#[tokio::test]
+async fn test_sub_plan_runs_under_its_budget_slice() {
+    let plan = spawn_plan(
+        serde_json::to_value(retrieval_plan()).unwrap(),
+        json!({ "budget": { "cost_usd": 0.0005 } }),
+    );
+
+    match run(&plan, vec![]).await {
+        Err(ExecutionError::BudgetExceeded(msg)) => {
+            assert!(msg.contains("sub-plan spawned by retrieve"), "{}", msg)
+        }
+        other => panic!("Expected budget error, got {:?}", other.map(|_| ())),
+    }
+}
+
+#[tokio::test]
+async fn test_failed_sub_plans_leave_their_traces_and_usage() {
+    let plan = spawn_plan(
+        serde_json::to_value(retrieval_plan()).unwrap(),
+        json!({ "budget": { "cost_usd": 0.0005 } }),
+    );
+    let (url, handle) = spawn_echo_server().await;
+    let mut ctx = ExecutionContext::new();
+    ctx.tool_urls.insert("util.echo".to_string(), url);
+    ctx.variables
+        .insert("query".to_string(), json!("refund policy"));
+
+    let (ctx, result) = Scheduler.run(ctx, &plan).await;
+    handle.abort();
+    assert!(matches!(result, Err(ExecutionError::BudgetExceeded(_))));
+
+    // The child's call is charged to the parent and traced under the spawn
+    assert!((ctx.total_cost_usd - 0.001).abs() < 1e-9);
+    assert!(ctx
+        .trace_events
+        .iter()
+        .any(|trace| trace.event_type == "tool_attempt" && trace.step_id == "retrieve/echo"));
+    let end = ctx
+        .trace_events
+        .iter()
+        .find(|trace| trace.event_type == "spawn_end")
+        .and_then(|trace| trace.data.clone())
+        .expect("a failed spawn still ends");
+    assert!(end["error"]
+        .as_str()
+        .unwrap()
+        .contains("Cost budget exceeded"));
+    assert_eq!(end["cost_usd"], json!(0.001));
+}
+
+#[tokio::test]
+async fn test_sub_plan_usage_saturates_the_parent_totals() {
+    let plan = spawn_plan(serde_json::to_value(retrieval_plan()).unwrap(), json!({}));
+    let (url, handle) = spawn_echo_server().await;
+    let mut ctx = ExecutionContext::new();
+    ctx.tool_urls.insert("util.echo".to_string(), url);
+    ctx.variables
+        .insert("query".to_string(), json!("refund policy"));
+    ctx.total_tokens = u64::MAX - 1;
+
+    let (ctx, result) = Scheduler.run(ctx, &plan).await;
+    handle.abort();
+    result.unwrap();
+    assert_eq!(ctx.total_tokens, u64::MAX);
+}
+
+#[test]
+fn test_sub_plan_names_include_those_of_inline_sub_plans() {
+    let inline = spawn_plan(json!("nested"), json!({}));
+    let mut plan = spawn_plan(serde_json::to_value(inline).unwrap(), json!({}));
+    let mut by_name = plan.nodes[0].clone();
+    by_name.id = "lookup".to_string();
+    by_name
+        .args
+        .as_mut()
+        .unwrap()
+        .insert("plan".to_string(), json!("retrieval"));
+    plan.nodes.push(by_name);
+
+    let mut names: Vec<String> = plan.sub_plan_names().into_iter().collect();
+    names.sort();
+    assert_eq!(names, vec!["nested", "retrieval"]);
+    assert!(retrieval_plan().sub_plan_names().is_empty());
+}
+
+#[test]
+fn test_inline_sub_plans_are_validated() {
+    let empty = json!({ "nodes": [], "edges": null, "signals": null, "stop_conditions": null });
+    let plan = spawn_plan(empty, json!({}));
+
+    assert!(matches!(
+        plan.validate(),
+        Err(PlanValidationError::InvalidSubPlan(_))
+    ));
+}
@@ -0,0 +1,231 @@
+//! Tests for enforcing plan `stop_conditions`
+
+use amp::internal::{
+    exec::scheduler::{ExecutionContext, ExecutionError, Scheduler, StopReason},
+    plan::ir::{Edge, Node, Operation, Plan, StopConditions},
+};
+use axum::{routing::post, Json, Router};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use tokio::task::JoinHandle;
+
+#[derive(Deserialize)]
+struct ToolInvokeRequest {
+    args: Option<serde_json::Value>,
+}
+
+#[derive(Serialize)]
+struct ToolInvokeResponse {
+    result: serde_json::Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Serves `util.echo` and a `ground.verify` stub whose verdict confidence is
+/// the first claim parsed as a number.
+async fn spawn_tool_server() -> (String, JoinHandle<()>) {
+    async fn echo(Json(payload): Json<ToolInvokeRequest>) -> Json<ToolInvokeResponse> {
+        Json(ToolInvokeResponse {
+            result: payload.args.unwrap_or(serde_json::Value::Null),
+            error: None,
+        })
+    }
+
+    async fn verify(Json(payload): Json<ToolInvokeRequest>) -> Json<ToolInvokeResponse> {
+        let confidence = payload
+            .args
+            .as_ref()
+            .and_then(|args| args["claims"][0].as_str())
+            .and_then(|claim| claim.parse::<f64>().ok())
+            .unwrap_or(1.0);
+
+        Json(ToolInvokeResponse {
+            result: json!({
+                "claims": ["claim"],
+                "supports": [],
+                "contradicts": [],
+                "verdicts": [{
+                    "claim_id": "claim",
+                    "verdict": "supported",
+                    "confidence": confidence,
+                    "needs_citation": false
+                }]
+            }),
+            error: None,
+        })
+    }
+
+    let app = Router::new()
+        .route("/invoke/util.echo", post(echo))
+        .route("/invoke/ground.verify", post(verify));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let handle = tokio::spawn(async move {
+        axum::serve(listener, app.into_make_service())
+            .await
+            .expect("tool server error");
+    });
+    (format!("http://{}", addr), handle)
+}
+
+fn echo_node(id: &str) -> Node {
+    Node {
+        id: id.to_string(),
+        op: Operation::Call,
+        tool: Some("util.echo".to_string()),
+        capability: None,
+        args: Some(HashMap::from([("from".to_string(), json!(id))])),
+        bind: None,
+        timeout_ms: None,
+        retry: None,
+        out: Some(HashMap::from([(
+            format!("{}_out", id),
+            "result".to_string(),
+        )])),
+    }
+}
+
+fn verify_node(id: &str, confidence: &str) -> Node {
+    Node {
+        id: id.to_string(),
+        op: Operation::Verify,
+        tool: Some("ground.verify".to_string()),
+        capability: None,
+        args: Some(HashMap::from([
+            ("claims".to_string(), json!([confidence])),
+            ("sources".to_string(), json!([])),
+        ])),
+        bind: None,
+        timeout_ms: None,
+        retry: None,
+        out: Some(HashMap::from([(
+            format!("{}_out", id),
+            "result".to_string(),
+        )])),
+    }
+}
+
+fn chain(nodes: Vec<Node>, stop_conditions: StopConditions) -> Plan {
+    let edges = nodes
+        .windows(2)
+        .map(|pair| Edge {
+            from: pair[0].id.clone(),
+            to: pair[1].id.clone(),
+        })
+        .collect();
+
+    Plan {
+        signals: None,
+        nodes,
+        edges: Some(edges),
+        stop_conditions: Some(stop_conditions),
+        outputs: None,
+    }
+}
+
+async fn run(plan: &Plan) -> Result<ExecutionContext, ExecutionError> {
+    let (url, handle) = spawn_tool_server().await;
+    let mut ctx = ExecutionContext::new();
+    ctx.tool_urls.insert("util.echo".to_string(), url.clone());
+    ctx.tool_urls.insert("ground.verify".to_string(), url);
+    let result = Scheduler.execute_plan(ctx, plan).await;
+    handle.abort();
+    result
+}
+
+#[tokio::test]
+async fn test_max_nodes_caps_executed_nodes() {
+    let plan = chain(
+        vec![
+            echo_node("a"),
+            echo_node("b"),
+            echo_node("c"),
+            echo_node("d"),
+        ],
+        StopConditions {
+            max_nodes: Some(2),
+            min_confidence: None,
+        },
+    );
+
+    let ctx = run(&plan).await.expect("stopped plans are not errors");
+
+    assert_eq!(ctx.completed_nodes.len(), 2);
+    assert!(!ctx.variables.contains_key("c_out"));
+    assert_eq!(
+        ctx.stop_reason,
+        Some(StopReason::MaxNodes {
+            limit: 2,
+            pending: vec!["c".to_string(), "d".to_string()],
+        })
+    );
+
+    let last = ctx.trace_events.last().expect("final trace");
+    assert_eq!(last.event_type, "plan_stopped");
+    assert_eq!(last.data.as_ref().unwrap()["reason"], json!("max_nodes"));
+}
+
+#[tokio::test]
+async fn test_max_nodes_not_reached_completes_normally() {
+    let plan = chain(
+        vec![echo_node("a"), echo_node("b")],
+        StopConditions {
+            max_nodes: Some(2),
+            min_confidence: None,
+        },
+    );
+
+    let ctx = run(&plan).await.expect("plan should succeed");
+
+    assert_eq!(ctx.completed_nodes.len(), 2);
+    assert!(ctx.stop_reason.is_none());
+    assert!(ctx
+        .trace_events
+        .iter()
+        .all(|trace| trace.event_type != "plan_stopped"));
+}
+
+#[tokio::test]
+async fn test_low_confidence_evidence_stops_plan() {
+    let plan = chain(
+        vec![verify_node("check", "0.4"), echo_node("publish")],
+        StopConditions {
+            max_nodes: None,
+            min_confidence: Some(0.8),
+        },
+    );
+
+    let ctx = run(&plan).await.expect("stopped plans are not errors");
+
+    assert!(ctx.completed_nodes.contains("check"));
+    assert!(!ctx.completed_nodes.contains("publish"));
+    match ctx.stop_reason {
+        Some(StopReason::MinConfidence {
+            ref step_id,
+            confidence,
+            threshold,
+        }) => {
+            assert_eq!(step_id, "check");
+            assert!((confidence - 0.4).abs() < 1e-9);
+            assert!((threshold - 0.8).abs() < 1e-9);
+        }
+        ref other => panic!("Expected min_confidence stop, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_confident_evidence_does_not_stop_plan() {
+    let plan = chain(
+        vec![verify_node("check", "0.95"), echo_node("publish")],
+        StopConditions {
+            max_nodes: Some(10),
+            min_confidence: Some(0.8),
+        },
+    );
+
+    let ctx = run(&plan).await.expect("plan should succeed");
+
+    assert!(ctx.completed_nodes.contains("publish"));
+    assert!(ctx.stop_reason.is_none());
+}
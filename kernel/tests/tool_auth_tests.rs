@@ -0,0 +1,350 @@
+//! Tests for per-tool credentials from registry entries
+
+use amp::internal::{
+    api::{AppState, StartupError},
+    exec::scheduler::{ExecutionContext, Scheduler},
+    plan::ir::Plan,
+    registry::{load_tool_auth, RegistryError},
+    tools::auth::{BasicAuth, ClientTls, ToolAuth},
+    tools::spec::{ToolClient, ToolError},
+};
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use base64::Engine as _;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// What a request must carry to be let in.
+struct Expected {
+    authorization: String,
+    tenant: Option<&'static str>,
+}
+
+/// Refuses requests without the expected credentials, echoing what it was
+/// sent the way careless servers do.
+fn refusal(expected: &Expected, headers: &HeaderMap) -> Option<Response> {
+    let authorization = headers
+        .get("authorization")
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    let tenant = headers
+        .get("x-tenant")
+        .and_then(|value| value.to_str().ok());
+    if authorization == expected.authorization && tenant == expected.tenant {
+        return None;
+    }
+    Some(
+        (
+            StatusCode::UNAUTHORIZED,
+            format!("rejected credentials '{}'", authorization),
+        )
+            .into_response(),
+    )
+}
+
+async fn invoke(
+    State(expected): State<Arc<Expected>>,
+    headers: HeaderMap,
+    Json(body): Json<Value>,
+) -> Response {
+    if let Some(refused) = refusal(&expected, &headers) {
+        return refused;
+    }
+    Json(json!({ "result": body["args"] })).into_response()
+}
+
+async fn spec(
+    State(expected): State<Arc<Expected>>,
+    headers: HeaderMap,
+    Path(name): Path<String>,
+) -> Response {
+    if let Some(refused) = refusal(&expected, &headers) {
+        return refused;
+    }
+    Json(json!({
+        "name": name,
+        "io": {
+            "input": { "type": "object" },
+            "output": { "type": "object" }
+        }
+    }))
+    .into_response()
+}
+
+async fn start_server(expected: Expected) -> (String, tokio::task::JoinHandle<()>) {
+    let app = Router::new()
+        .route("/invoke/:name", post(invoke))
+        .route("/spec/:name", get(spec))
+        .with_state(Arc::new(expected));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let handle = tokio::spawn(async move {
+        axum::serve(listener, app.into_make_service())
+            .await
+            .expect("tool server error");
+    });
+    (url, handle)
+}
+
+/// A fresh variable name holding `value`, so tests never share one.
+fn secret_env(value: &str) -> String {
+    let name = format!("AMP_TEST_SECRET_{}", uuid::Uuid::new_v4().simple());
+    std::env::set_var(&name, value);
+    name
+}
+
+fn bearer(token_env: &str) -> ToolAuth {
+    ToolAuth {
+        bearer_env: Some(token_env.to_string()),
+        headers: [("X-Tenant".to_string(), "acme".to_string())].into(),
+        ..ToolAuth::default()
+    }
+}
+
+fn echo_plan() -> Plan {
+    serde_json::from_value(json!({
+        "nodes": [{
+            "id": "echo",
+            "op": "call",
+            "tool": "secure.echo",
+            "args": { "msg": "hello" },
+            "out": { "said": "result.msg" }
+        }]
+    }))
+    .unwrap()
+}
+
+#[tokio::test]
+async fn test_bearer_tokens_and_headers_reach_the_tool() {
+    let token = format!("tok-{}", uuid::Uuid::new_v4());
+    let (url, handle) = start_server(Expected {
+        authorization: format!("Bearer {}", token),
+        tenant: Some("acme"),
+    })
+    .await;
+
+    let mut ctx = ExecutionContext::new();
+    ctx.tool_urls.insert("secure.echo".to_string(), url);
+    ctx.set_tool_auth("secure.echo", bearer(&secret_env(&token)))
+        .unwrap();
+
+    let (ctx, result) = Scheduler.run(ctx, &echo_plan()).await;
+    result.unwrap();
+    assert_eq!(ctx.variables["said"], json!("hello"));
+    assert_eq!(ctx.tool_specs["secure.echo"].name, "secure.echo");
+
+    handle.abort();
+}
+
+#[tokio::test]
+async fn test_refused_credentials_are_redacted_from_errors_and_traces() {
+    let (url, handle) = start_server(Expected {
+        authorization: "Bearer expected".to_string(),
+        tenant: Some("acme"),
+    })
+    .await;
+    let wrong = format!("wrong-{}", uuid::Uuid::new_v4());
+
+    let client = ToolClient::new();
+    client.set_auth(&url, bearer(&secret_env(&wrong))).unwrap();
+    match client
+        .invoke_tool(&url, "secure.echo", Some(json!({})))
+        .await
+    {
+        Err(ToolError::Communication(message)) => {
+            assert!(message.contains("401"), "{}", message);
+            assert!(message.contains("Bearer [REDACTED]"), "{}", message);
+            assert!(!message.contains(&wrong), "{}", message);
+        }
+        other => panic!("Expected the call to be refused, got {:?}", other),
+    }
+
+    let mut ctx = ExecutionContext::new();
+    ctx.tool_urls.insert("secure.echo".to_string(), url);
+    ctx.set_tool_auth("secure.echo", bearer(&secret_env(&wrong)))
+        .unwrap();
+    let (ctx, result) = Scheduler.run(ctx, &echo_plan()).await;
+    let error = result.unwrap_err().to_string();
+    assert!(!error.contains(&wrong), "{}", error);
+    let recorded = serde_json::to_string(&ctx.trace_events).unwrap()
+        + &serde_json::to_string(&ctx.tool_io).unwrap();
+    assert!(recorded.contains("[REDACTED]"));
+    assert!(!recorded.contains(&wrong));
+
+    handle.abort();
+}
+
+#[tokio::test]
+async fn test_basic_auth_reaches_the_tool() {
+    let password = format!("pw-{}", uuid::Uuid::new_v4());
+    let encoded = base64::engine::general_purpose::STANDARD.encode(format!("amp:{}", password));
+    let (url, handle) = start_server(Expected {
+        authorization: format!("Basic {}", encoded),
+        tenant: None,
+    })
+    .await;
+
+    let client = ToolClient::new();
+    client
+        .set_auth(
+            &url,
+            ToolAuth {
+                basic: Some(BasicAuth {
+                    username: "amp".to_string(),
+                    password_env: secret_env(&password),
+                }),
+                ..ToolAuth::default()
+            },
+        )
+        .unwrap();
+    let result = client
+        .invoke_tool(&url, "secure.echo", Some(json!({ "n": 1 })))
+        .await
+        .unwrap();
+    assert_eq!(result, json!({ "n": 1 }));
+
+    // Without credentials the same tool is refused
+    assert!(matches!(
+        ToolClient::new()
+            .invoke_tool(&url, "secure.echo", Some(json!({})))
+            .await,
+        Err(ToolError::Communication(_))
+    ));
+
+    handle.abort();
+}
+
+#[tokio::test]
+async fn test_unusable_credentials_fail_the_call() {
+    let client = ToolClient::new();
+    let url = "http://127.0.0.1:9";
+
+    client
+        .set_auth(url, bearer("AMP_TEST_SECRET_NEVER_SET"))
+        .unwrap();
+    match client.invoke_tool(url, "secure.echo", None).await {
+        Err(ToolError::Communication(message)) => {
+            assert!(message.contains("AMP_TEST_SECRET_NEVER_SET"), "{}", message)
+        }
+        other => panic!("Expected a communication error, got {:?}", other),
+    }
+
+    let client = ToolClient::new();
+    client
+        .set_auth(
+            url,
+            ToolAuth {
+                tls: Some(ClientTls {
+                    cert: "/no/such/cert.pem".into(),
+                    key: "/no/such/key.pem".into(),
+                    ca: None,
+                }),
+                ..ToolAuth::default()
+            },
+        )
+        .unwrap();
+    match client.invoke_tool(url, "secure.echo", None).await {
+        Err(ToolError::Communication(message)) => {
+            assert!(message.contains("/no/such/cert.pem"), "{}", message)
+        }
+        other => panic!("Expected a communication error, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_credentials_are_never_shared_between_tools_silently() {
+    let mut ctx = ExecutionContext::new();
+    let url = "http://127.0.0.1:9";
+    ctx.tool_urls
+        .insert("secure.read".to_string(), url.to_string());
+    ctx.tool_urls
+        .insert("secure.write".to_string(), url.to_string());
+
+    // Tools at one URL share its credentials, so they must agree on them
+    ctx.set_tool_auth("secure.read", bearer("READ_TOKEN"))
+        .unwrap();
+    ctx.set_tool_auth("secure.write", bearer("READ_TOKEN"))
+        .unwrap();
+    match ctx.set_tool_auth("secure.write", bearer("WRITE_TOKEN")) {
+        Err(ToolError::Validation(message)) => {
+            assert!(message.contains("secure.write"), "{}", message);
+            assert!(message.contains("other credentials"), "{}", message);
+        }
+        other => panic!("Expected conflicting credentials to fail, got {:?}", other),
+    }
+
+    assert!(matches!(
+        ctx.set_tool_auth("secure.unknown", bearer("READ_TOKEN")),
+        Err(ToolError::Validation(_))
+    ));
+}
+
+#[test]
+fn test_registry_entries_carry_credentials() {
+    let path = std::env::temp_dir().join(format!("amp-tools-{}.json", uuid::Uuid::new_v4()));
+    std::fs::write(
+        &path,
+        json!([
+            { "name": "doc.search.local", "url": "http://localhost:7401" },
+            {
+                "name": "secure.echo",
+                "url": "https://tools.example",
+                "auth": {
+                    "bearer_env": "SEARCH_TOKEN",
+                    "headers": { "X-Api-Key": "key-123" },
+                    "tls": { "cert": "certs/amp.pem", "key": "certs/amp.key" }
+                }
+            }
+        ])
+        .to_string(),
+    )
+    .unwrap();
+    std::env::set_var("AMP_TOOL_CONFIG", &path);
+    let auth = load_tool_auth().unwrap();
+
+    // Another entry at the same URL without the same auth is refused
+    std::fs::write(
+        &path,
+        json!([
+            {
+                "name": "secure.echo",
+                "url": "https://tools.example",
+                "auth": { "bearer_env": "SEARCH_TOKEN" }
+            },
+            { "name": "secure.other", "url": "https://tools.example" }
+        ])
+        .to_string(),
+    )
+    .unwrap();
+    let conflict = load_tool_auth();
+    // The kernel reports it instead of starting
+    let startup = AppState::new(HashMap::new()).map(|_| ());
+    std::env::remove_var("AMP_TOOL_CONFIG");
+    let _ = std::fs::remove_file(&path);
+    match conflict {
+        Err(RegistryError::Auth(message)) => {
+            assert!(message.contains("secure.other"), "{}", message)
+        }
+        other => panic!("Expected the entries to conflict, got {:?}", other),
+    }
+    assert!(matches!(
+        startup,
+        Err(StartupError::ToolAuth(RegistryError::Auth(_)))
+    ));
+
+    assert_eq!(auth.len(), 1);
+    let entry = &auth["secure.echo"];
+    assert_eq!(entry.bearer_env.as_deref(), Some("SEARCH_TOKEN"));
+    assert_eq!(entry.tls.as_ref().unwrap().ca, None);
+
+    // Header values never show up in logs
+    let debug = format!("{:?}", entry);
+    assert!(debug.contains("X-Api-Key"));
+    assert!(!debug.contains("key-123"));
+}
@@ -0,0 +1,281 @@
+//! Tests for the tool transports chosen by the registry URL scheme
+
+use amp::internal::{
+    exec::scheduler::{ExecutionContext, Scheduler},
+    plan::ir::Plan,
+    tools::spec::{ToolClient, ToolError, ToolSpec},
+    tools::transport::InprocTransport,
+};
+use serde_json::{json, Value};
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+/// A JSON-lines tool in plain sh: `invoke` echoes its args back, except for
+/// `util.fail`, and `spec` describes any tool as taking and returning objects.
+const STDIO_TOOL: &str = r#"while IFS= read -r line; do
+  case "$line" in
+    *'"method":"spec"'*)
+      printf '%s\n' "$line" | sed 's/^{"id":\([0-9]*\),.*"tool":"\([^"]*\)".*$/{"id":\1,"result":{"name":"\2","io":{"input":{"type":"object"},"output":{"type":"object"}}}}/' ;;
+    *'"tool":"util.fail"'*)
+      printf '%s\n' "$line" | sed 's/^{"id":\([0-9]*\),.*$/{"id":\1,"error":"tool failed"}/' ;;
+    *)
+      printf '%s\n' "$line" | sed 's/^{"id":\([0-9]*\),"method":"invoke","tool":"[^"]*","args":\(.*\)}$/{"id":\1,"result":\2}/' ;;
+  esac
+done
+"#;
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("amp-{}-{}", uuid::Uuid::new_v4(), name))
+}
+
+/// Writes `contents` to a script run by `/bin/sh`, so it needs no exec bit.
+fn write_script(contents: &str) -> PathBuf {
+    let path = temp_path("tool.sh");
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+fn object_spec(name: &str) -> ToolSpec {
+    serde_json::from_value(json!({
+        "name": name,
+        "io": {
+            "input": { "type": "object" },
+            "output": { "type": "object" }
+        }
+    }))
+    .unwrap()
+}
+
+/// `left` and `right` call `tool` side by side, then `join` combines them.
+fn fan_in_plan(tool: &str) -> Plan {
+    serde_json::from_value(json!({
+        "nodes": [
+            {
+                "id": "left",
+                "op": "call",
+                "tool": tool,
+                "args": { "msg": "left" },
+                "out": { "left": "result.msg" }
+            },
+            {
+                "id": "right",
+                "op": "call",
+                "tool": tool,
+                "args": { "msg": "right" },
+                "out": { "right": "result.msg" }
+            },
+            {
+                "id": "join",
+                "op": "call",
+                "tool": tool,
+                "args": { "msg": ["$left", "$right"] },
+                "out": { "joined": "result.msg" }
+            }
+        ],
+        "edges": [
+            { "from": "left", "to": "join" },
+            { "from": "right", "to": "join" }
+        ]
+    }))
+    .unwrap()
+}
+
+#[tokio::test]
+async fn test_unix_socket_tools_speak_json_lines() {
+    let path = temp_path("tool.sock");
+    let listener = tokio::net::UnixListener::bind(&path).unwrap();
+    let server = tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = stream.into_split();
+            let mut line = String::new();
+            BufReader::new(reader).read_line(&mut line).await.unwrap();
+            let request: Value = serde_json::from_str(&line).unwrap();
+            let response = match (request["method"].as_str(), request["tool"].as_str()) {
+                (Some("spec"), Some(tool)) => {
+                    json!({ "id": request["id"], "result": object_spec(tool) })
+                }
+                (Some("invoke"), Some("util.fail")) => {
+                    json!({ "id": request["id"], "error": "tool failed" })
+                }
+                _ => json!({ "id": request["id"], "result": request["args"] }),
+            };
+            writer
+                .write_all(format!("{}\n", response).as_bytes())
+                .await
+                .unwrap();
+        }
+    });
+
+    let client = ToolClient::new();
+    let url = format!("unix://{}", path.display());
+    let spec = client.get_tool_spec(&url, "util.echo").await.unwrap();
+    assert_eq!(spec.name, "util.echo");
+    let result = client
+        .invoke_tool(&url, "util.echo", Some(json!({ "msg": "hi" })))
+        .await
+        .unwrap();
+    assert_eq!(result, json!({ "msg": "hi" }));
+    match client.invoke_tool(&url, "util.fail", None).await {
+        Err(ToolError::Invocation(message)) => assert_eq!(message, "tool failed"),
+        other => panic!("Expected an invocation error, got {:?}", other),
+    }
+
+    server.abort();
+    let _ = std::fs::remove_file(&path);
+    assert!(matches!(
+        client.invoke_tool(&url, "util.echo", None).await,
+        Err(ToolError::Communication(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_stdio_tool_runs_a_plan() {
+    let script = write_script(STDIO_TOOL);
+    let mut ctx = ExecutionContext::new();
+    ctx.tool_urls.insert(
+        "util.echo".to_string(),
+        format!("stdio:///bin/sh {}", script.display()),
+    );
+
+    let (ctx, result) = Scheduler.run(ctx, &fan_in_plan("util.echo")).await;
+    result.unwrap();
+    let _ = std::fs::remove_file(&script);
+
+    // The spec came over the same process as the calls
+    assert_eq!(ctx.tool_specs["util.echo"].name, "util.echo");
+    assert_eq!(ctx.variables["left"], json!("left"));
+    assert_eq!(ctx.variables["right"], json!("right"));
+    assert_eq!(ctx.variables["joined"], json!(["left", "right"]));
+}
+
+#[tokio::test]
+async fn test_stdio_overlapping_calls_get_their_own_answers() {
+    let script = write_script(STDIO_TOOL);
+    let client = ToolClient::new();
+    let url = format!("stdio:///bin/sh {}", script.display());
+
+    let calls = (0..20).map(|i| {
+        let client = client.clone();
+        let url = url.clone();
+        async move {
+            client
+                .invoke_tool(&url, "util.echo", Some(json!({ "n": i })))
+                .await
+        }
+    });
+    let results = futures::future::join_all(calls).await;
+    for (i, result) in results.into_iter().enumerate() {
+        assert_eq!(result.unwrap(), json!({ "n": i }));
+    }
+
+    match client.invoke_tool(&url, "util.fail", None).await {
+        Err(ToolError::Invocation(message)) => assert_eq!(message, "tool failed"),
+        other => panic!("Expected an invocation error, got {:?}", other),
+    }
+    let _ = std::fs::remove_file(&script);
+}
+
+#[tokio::test]
+async fn test_stdio_tool_that_exits_fails_the_call() {
+    let script = write_script("exit 0\n");
+    let client = ToolClient::new();
+    let url = format!("stdio:///bin/sh {}", script.display());
+
+    match client.invoke_tool(&url, "util.echo", None).await {
+        Err(ToolError::Communication(message)) => {
+            assert!(message.contains("exited"), "{}", message)
+        }
+        other => panic!("Expected a communication error, got {:?}", other),
+    }
+    let _ = std::fs::remove_file(&script);
+
+    match client
+        .invoke_tool("stdio:///no/such/tool", "util.echo", None)
+        .await
+    {
+        Err(ToolError::Communication(message)) => {
+            assert!(message.contains("Failed to start"), "{}", message)
+        }
+        other => panic!("Expected a communication error, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_inproc_tools_run_without_a_server() {
+    let helpers = InprocTransport::new().tool(object_spec("text.upper"), |args| async move {
+        let msg = args
+            .as_ref()
+            .and_then(|args| args["msg"].as_str())
+            .ok_or_else(|| ToolError::Invocation("msg must be a string".to_string()))?;
+        Ok(json!({ "msg": msg.to_uppercase() }))
+    });
+    let plan: Plan = serde_json::from_value(json!({
+        "nodes": [{
+            "id": "shout",
+            "op": "call",
+            "tool": "text.upper",
+            "args": { "msg": "quiet" },
+            "out": { "loud": "result.msg" }
+        }]
+    }))
+    .unwrap();
+    let mut ctx = ExecutionContext::new();
+    ctx.tool_client
+        .register_inproc("transport-tests", Arc::new(helpers));
+    ctx.tool_urls.insert(
+        "text.upper".to_string(),
+        "inproc://transport-tests".to_string(),
+    );
+
+    let (ctx, result) = Scheduler.run(ctx, &plan).await;
+    result.unwrap();
+    assert_eq!(ctx.variables["loud"], json!("QUIET"));
+    assert_eq!(ctx.tool_specs["text.upper"].name, "text.upper");
+
+    let client = &ctx.tool_client;
+    assert!(matches!(
+        client
+            .invoke_tool("inproc://transport-tests", "text.upper", Some(json!({})))
+            .await,
+        Err(ToolError::Invocation(_))
+    ));
+    assert!(matches!(
+        client
+            .invoke_tool("inproc://transport-tests", "text.lower", None)
+            .await,
+        Err(ToolError::Communication(_))
+    ));
+
+    // The endpoint belongs to the context that registered it
+    assert!(matches!(
+        ToolClient::new()
+            .invoke_tool(
+                "inproc://transport-tests",
+                "text.upper",
+                Some(json!({ "msg": "x" }))
+            )
+            .await,
+        Err(ToolError::Communication(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_unknown_endpoints_are_communication_errors() {
+    let client = ToolClient::new();
+    for url in [
+        "ftp://tools.example",
+        "inproc://no-such-endpoint",
+        "stdio://",
+    ] {
+        assert!(
+            matches!(
+                client.invoke_tool(url, "util.echo", None).await,
+                Err(ToolError::Communication(_))
+            ),
+            "{} should be rejected",
+            url
+        );
+    }
+}
@@ -0,0 +1,298 @@
+//! Tests for the durable trace store and its query filters
+
+use amp::internal::{
+    exec::run::{RunHandle, RunOutcome, RunState, RunStatus},
+    trace::{
+        store::{MemoryTraceStore, SqliteTraceStore, TraceQuery, TraceStore, TraceStoreError},
+        trace::Trace,
+    },
+};
+use chrono::{Duration, TimeZone, Utc};
+use serde_json::json;
+
+/// A trace at `minute` past midnight, about `tool` if one is given.
+fn trace(step_id: &str, event_type: &str, tool: Option<&str>, cost: f64, minute: i64) -> Trace {
+    let mut trace = Trace::new(
+        event_type.to_string(),
+        step_id.to_string(),
+        format!("{} {}", event_type, step_id),
+    );
+    trace.ts = Utc.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap() + Duration::minutes(minute);
+    trace.cost_usd = Some(cost);
+    if let Some(tool) = tool {
+        trace.data = Some(json!({ "tool": tool }));
+    }
+    trace
+}
+
+/// Two plans: `search` and `verify` calls in plan-a, a `search` call in plan-b.
+async fn seed(store: &dyn TraceStore) {
+    store
+        .save(
+            "plan-a",
+            &[
+                trace("search", "step_start", None, 0.0, 0),
+                trace("search", "step_end", Some("doc.search"), 0.02, 1),
+                trace("verify", "step_start", None, 0.0, 2),
+                trace("verify", "step_end", Some("ground.verify"), 0.5, 3),
+            ],
+        )
+        .await
+        .unwrap();
+    store
+        .save(
+            "plan-b",
+            &[
+                trace("search", "step_start", None, 0.0, 10),
+                trace("search", "step_end", Some("doc.search"), 0.04, 11),
+            ],
+        )
+        .await
+        .unwrap();
+}
+
+fn steps(traces: &[Trace]) -> Vec<String> {
+    traces
+        .iter()
+        .map(|trace| format!("{}:{}", trace.step_id, trace.event_type))
+        .collect()
+}
+
+async fn check_filters(store: &dyn TraceStore) {
+    seed(store).await;
+
+    let by_plan = TraceQuery {
+        plan_id: Some("plan-a".to_string()),
+        ..TraceQuery::default()
+    };
+    let page = store.query(&by_plan).await.unwrap();
+    assert_eq!(page.traces.len(), 4);
+    assert!(page.next_cursor.is_none());
+
+    let by_event = TraceQuery {
+        event_type: Some("step_end".to_string()),
+        step_id: Some("search".to_string()),
+        ..TraceQuery::default()
+    };
+    let page = store.query(&by_event).await.unwrap();
+    assert_eq!(page.traces.len(), 2);
+    assert_eq!(page.traces[0].cost_usd, Some(0.02));
+    assert_eq!(page.traces[1].cost_usd, Some(0.04));
+
+    let by_tool = TraceQuery {
+        tool: Some("ground.verify".to_string()),
+        ..TraceQuery::default()
+    };
+    let page = store.query(&by_tool).await.unwrap();
+    assert_eq!(steps(&page.traces), vec!["verify:step_end"]);
+
+    let by_cost = TraceQuery {
+        min_cost_usd: Some(0.03),
+        ..TraceQuery::default()
+    };
+    let page = store.query(&by_cost).await.unwrap();
+    assert_eq!(page.traces.len(), 2);
+    assert!(page
+        .traces
+        .iter()
+        .all(|trace| trace.cost_usd.unwrap() >= 0.03));
+
+    // `since` is inclusive and `until` exclusive
+    let start = Utc.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap();
+    let by_time = TraceQuery {
+        since: Some(start + Duration::minutes(1)),
+        until: Some(start + Duration::minutes(10)),
+        ..TraceQuery::default()
+    };
+    let page = store.query(&by_time).await.unwrap();
+    assert_eq!(
+        steps(&page.traces),
+        vec!["search:step_end", "verify:step_start", "verify:step_end"]
+    );
+}
+
+async fn check_pagination(store: &dyn TraceStore) {
+    seed(store).await;
+
+    let mut query = TraceQuery {
+        limit: Some(4),
+        ..TraceQuery::default()
+    };
+    let first = store.query(&query).await.unwrap();
+    assert_eq!(first.traces.len(), 4);
+    let cursor = first.next_cursor.expect("a second page");
+
+    query.cursor = Some(cursor);
+    let second = store.query(&query).await.unwrap();
+    assert_eq!(second.traces.len(), 2);
+    assert!(second.next_cursor.is_none());
+    assert!(second
+        .traces
+        .iter()
+        .all(|trace| trace.ts.timestamp() > first.traces[3].ts.timestamp()));
+
+    query.cursor = Some("not-a-cursor".to_string());
+    assert!(matches!(
+        store.query(&query).await,
+        Err(TraceStoreError::InvalidCursor(_))
+    ));
+}
+
+async fn check_resave_replaces(store: &dyn TraceStore) {
+    seed(store).await;
+    assert!(store.load("plan-c").await.unwrap().is_none());
+
+    // A resumed plan stores its whole stream again
+    let resumed = vec![
+        trace("search", "step_start", None, 0.0, 20),
+        trace("search", "step_end", Some("doc.search"), 0.02, 21),
+    ];
+    store.save("plan-a", &resumed).await.unwrap();
+
+    let loaded = store.load("plan-a").await.unwrap().unwrap();
+    assert_eq!(steps(&loaded), steps(&resumed));
+    assert_eq!(loaded[1].data, resumed[1].data);
+
+    let all = store.query(&TraceQuery::default()).await.unwrap();
+    assert_eq!(all.traces.len(), 4);
+}
+
+async fn check_append_extends(store: &dyn TraceStore) {
+    seed(store).await;
+
+    // A running plan stores its traces one by one
+    let later = trace("report", "step_start", None, 0.0, 30);
+    store
+        .append("plan-a", std::slice::from_ref(&later))
+        .await
+        .unwrap();
+    store
+        .append("plan-c", &[trace("search", "step_start", None, 0.0, 31)])
+        .await
+        .unwrap();
+
+    let loaded = store.load("plan-a").await.unwrap().unwrap();
+    assert_eq!(loaded.len(), 5);
+    assert_eq!(steps(&loaded[4..]), steps(&[later]));
+    assert_eq!(store.load("plan-c").await.unwrap().unwrap().len(), 1);
+
+    let all = store.query(&TraceQuery::default()).await.unwrap();
+    assert_eq!(all.traces.last().unwrap().step_id, "search");
+    assert_eq!(all.traces.len(), 8);
+}
+
+/// The status of a run of `plan_id` that ended as `status`.
+fn finished_run(plan_id: &str, run_id: &str, status: RunState) -> RunStatus {
+    let run = RunHandle::new(plan_id, run_id, 1, Default::default());
+    run.finish(&RunOutcome {
+        plan_id: plan_id.to_string(),
+        run_id: run_id.to_string(),
+        status,
+        stop_reason: None,
+        error: None,
+        result: None,
+    });
+    run.status()
+}
+
+async fn check_runs(store: &dyn TraceStore) {
+    assert!(store.last_run("plan-a").await.unwrap().is_none());
+
+    // A resumed plan's later run is the one its stream ends with
+    store
+        .save_run(&finished_run("plan-a", "run-1", RunState::Failed))
+        .await
+        .unwrap();
+    store
+        .save_run(&finished_run("plan-b", "run-2", RunState::Succeeded))
+        .await
+        .unwrap();
+    store
+        .save_run(&finished_run("plan-a", "run-3", RunState::Cancelled))
+        .await
+        .unwrap();
+
+    let last = store.last_run("plan-a").await.unwrap().unwrap();
+    assert_eq!(last.run_id, "run-3");
+    assert_eq!(last.outcome().unwrap().status, RunState::Cancelled);
+    let other = store.last_run("plan-b").await.unwrap().unwrap();
+    assert_eq!(other.state, RunState::Succeeded);
+
+    let first = store.load_run("run-1").await.unwrap().unwrap();
+    assert_eq!(first.plan_id, "plan-a");
+    assert_eq!(first.state, RunState::Failed);
+    assert!(first.finished_at.is_some());
+    assert!(store.load_run("run-4").await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn test_memory_store_filters() {
+    check_filters(&MemoryTraceStore::new()).await;
+}
+
+#[tokio::test]
+async fn test_sqlite_store_filters() {
+    check_filters(&SqliteTraceStore::in_memory()).await;
+}
+
+#[tokio::test]
+async fn test_memory_store_paginates() {
+    check_pagination(&MemoryTraceStore::new()).await;
+}
+
+#[tokio::test]
+async fn test_sqlite_store_paginates() {
+    check_pagination(&SqliteTraceStore::in_memory()).await;
+}
+
+#[tokio::test]
+async fn test_memory_store_resave_replaces_plan() {
+    check_resave_replaces(&MemoryTraceStore::new()).await;
+}
+
+#[tokio::test]
+async fn test_sqlite_store_resave_replaces_plan() {
+    check_resave_replaces(&SqliteTraceStore::in_memory()).await;
+}
+
+#[tokio::test]
+async fn test_memory_store_append_extends_plan() {
+    check_append_extends(&MemoryTraceStore::new()).await;
+}
+
+#[tokio::test]
+async fn test_sqlite_store_append_extends_plan() {
+    check_append_extends(&SqliteTraceStore::in_memory()).await;
+}
+
+#[tokio::test]
+async fn test_memory_store_keeps_runs() {
+    check_runs(&MemoryTraceStore::new()).await;
+}
+
+#[tokio::test]
+async fn test_sqlite_store_keeps_runs() {
+    check_runs(&SqliteTraceStore::in_memory()).await;
+}
+
+#[tokio::test]
+async fn test_sqlite_store_survives_reopening() {
+    let path = std::env::temp_dir().join(format!("amp-traces-{}.db", uuid::Uuid::new_v4()));
+    let signer = amp::TraceSigner::new().unwrap();
+    let mut traces = vec![
+        trace("search", "step_start", None, 0.0, 0),
+        trace("search", "step_end", Some("doc.search"), 0.1, 1),
+    ];
+    signer.sign_chain(&mut traces).unwrap();
+
+    SqliteTraceStore::open(&path)
+        .save("plan-a", &traces)
+        .await
+        .unwrap();
+
+    let reopened = SqliteTraceStore::open(&path);
+    let loaded = reopened.load("plan-a").await.unwrap().unwrap();
+    amp::verify_chain(&loaded, signer.get_public_key()).expect("stored traces still verify");
+
+    let _ = std::fs::remove_file(&path);
+}
@@ -0,0 +1,210 @@
+//! Tests for live trace streams of running executions
+
+use amp::internal::{
+    api::{router, AppState},
+    exec::run::{RunOutcome, RunState},
+    exec::scheduler::{ExecutionContext, Scheduler},
+    plan::ir::Plan,
+    trace::live::{LiveEvent, LiveTrace},
+    trace::trace::Trace,
+};
+use futures::StreamExt;
+use serde_json::json;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+fn trace(step_id: &str) -> Trace {
+    Trace::new(
+        "step_start".to_string(),
+        step_id.to_string(),
+        format!("Starting {}", step_id),
+    )
+}
+
+fn outcome(status: RunState) -> RunOutcome {
+    RunOutcome {
+        plan_id: "plan-1".to_string(),
+        run_id: "run-1".to_string(),
+        status,
+        stop_reason: None,
+        error: None,
+        result: None,
+    }
+}
+
+/// Event ids and the step of each trace, with `end:<state>` for the
+/// terminal event.
+fn describe(events: &[LiveEvent]) -> Vec<(usize, String)> {
+    events
+        .iter()
+        .map(|event| match event {
+            LiveEvent::Trace { id, trace } => (*id, trace.step_id.clone()),
+            LiveEvent::End { id, outcome } => (*id, format!("end:{:?}", outcome.status)),
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn test_subscribers_receive_traces_as_they_are_published() {
+    let live = LiveTrace::new();
+    live.publish(&trace("first"));
+    let mut events = Box::pin(live.subscribe(None));
+
+    let LiveEvent::Trace { id, trace: first } = events.next().await.unwrap() else {
+        panic!("expected a trace event");
+    };
+    assert_eq!((id, first.step_id.as_str()), (0, "first"));
+
+    // The subscriber waits for the execution to record more
+    let waiting = tokio::spawn(async move { events.collect::<Vec<_>>().await });
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    assert!(!waiting.is_finished());
+
+    live.publish(&trace("second"));
+    live.finish(outcome(RunState::Succeeded));
+    let rest = tokio::time::timeout(Duration::from_secs(1), waiting)
+        .await
+        .expect("stream should end after the terminal event")
+        .unwrap();
+    assert_eq!(
+        describe(&rest),
+        vec![(1, "second".to_string()), (2, "end:Succeeded".to_string())]
+    );
+}
+
+#[tokio::test]
+async fn test_reconnecting_resumes_after_last_event_id() {
+    let live = LiveTrace::with_history(vec![trace("restored")]);
+    live.publish(&trace("a"));
+    live.publish(&trace("b"));
+    live.finish(outcome(RunState::Failed));
+
+    let all: Vec<_> = live.subscribe(None).collect().await;
+    assert_eq!(
+        describe(&all),
+        vec![
+            (0, "restored".to_string()),
+            (1, "a".to_string()),
+            (2, "b".to_string()),
+            (3, "end:Failed".to_string()),
+        ]
+    );
+
+    let resumed: Vec<_> = live.subscribe(Some(1)).collect().await;
+    assert_eq!(
+        describe(&resumed),
+        vec![(2, "b".to_string()), (3, "end:Failed".to_string())]
+    );
+
+    // A client that already saw the terminal event is told again, then closed
+    let done: Vec<_> = live.subscribe(Some(3)).collect().await;
+    assert_eq!(describe(&done), vec![(3, "end:Failed".to_string())]);
+}
+
+#[tokio::test]
+async fn test_scheduler_publishes_every_trace_it_records() {
+    let plan: Plan = serde_json::from_value(json!({
+        "nodes": [
+            { "id": "left", "op": "assert", "args": { "condition": "1 < 2" } },
+            { "id": "right", "op": "assert", "args": { "condition": "2 < 3" } },
+            {
+                "id": "nested",
+                "op": "spawn",
+                "args": {
+                    "plan": {
+                        "nodes": [
+                            { "id": "inner", "op": "assert", "args": { "condition": "3 < 4" } }
+                        ]
+                    }
+                }
+            }
+        ]
+    }))
+    .unwrap();
+    let live = LiveTrace::new();
+    let mut ctx = ExecutionContext::new();
+    ctx.live = Some(live.clone());
+
+    let (ctx, result) = Scheduler.run(ctx, &plan).await;
+    result.unwrap();
+    live.finish(outcome(RunState::Succeeded));
+
+    let published: Vec<Trace> = live
+        .subscribe(None)
+        .filter_map(|event| async move {
+            match event {
+                LiveEvent::Trace { trace, .. } => Some(*trace),
+                LiveEvent::End { .. } => None,
+            }
+        })
+        .collect()
+        .await;
+    assert_eq!(
+        serde_json::to_value(&published).unwrap(),
+        serde_json::to_value(&ctx.trace_events).unwrap()
+    );
+    assert!(published
+        .iter()
+        .any(|trace| trace.step_id == "nested/inner"));
+}
+
+#[tokio::test]
+async fn test_finished_runs_are_streamed_from_the_store() {
+    let mut state = AppState::new(HashMap::new()).unwrap();
+    state.retention = Duration::ZERO;
+    let app = router(state.clone());
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let kernel = format!("http://{}", listener.local_addr().unwrap());
+    let handle = tokio::spawn(async move {
+        axum::serve(listener, app.into_make_service())
+            .await
+            .expect("kernel api server error");
+    });
+    let client = reqwest::Client::new();
+
+    let plan = json!({
+        "nodes": [{ "id": "check", "op": "assert", "args": { "condition": "1 < 2" } }]
+    });
+    let finished: serde_json::Value = client
+        .post(format!("{}/v1/plan/execute", kernel))
+        .json(&json!({ "plan": plan, "wait": true }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(finished["status"], "succeeded");
+    let plan_id = finished["plan_id"].as_str().unwrap();
+
+    // The finished run's stream and bundle are let go of after the retention
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while !state.live_traces.read().await.is_empty() || !state.plan_bundles.read().await.is_empty()
+    {
+        assert!(Instant::now() < deadline, "run was never forgotten");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    // A late subscriber still gets every trace and the outcome
+    let stream = client
+        .get(format!("{}/v1/trace/{}/stream", kernel, plan_id))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    let stored = state.traces.load(plan_id).await.unwrap().unwrap();
+    assert_eq!(stream.matches("event: trace").count(), stored.len());
+    assert_eq!(stream.matches("event: end").count(), 1);
+    assert!(stream.contains(r#""status":"succeeded""#), "{}", stream);
+
+    let unknown = client
+        .get(format!("{}/v1/trace/unknown/stream", kernel))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(unknown.status(), reqwest::StatusCode::NOT_FOUND);
+
+    handle.abort();
+}
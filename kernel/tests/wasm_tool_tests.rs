@@ -0,0 +1,293 @@
+//! Tests for WebAssembly tools run in the kernel's sandbox
+
+use amp::internal::{
+    exec::scheduler::{ExecutionContext, ExecutionError, Scheduler},
+    plan::ir::{Plan, Signals},
+    tools::spec::{ToolClient, ToolError},
+    tools::wasm::embedded_spec,
+};
+use serde_json::{json, Value};
+use std::path::PathBuf;
+
+/// `amp_invoke` of a tool answering `{"result": <its arguments>}`.
+const ECHO: &str = r#"
+  (data (i32.const 0) "{\"result\":")
+  (func (export "amp_invoke") (param $ptr i32) (param $len i32) (result i64)
+    (local $out i32)
+    (local.set $out (global.get $next))
+    (memory.copy (local.get $out) (i32.const 0) (i32.const 10))
+    (memory.copy (i32.add (local.get $out) (i32.const 10)) (local.get $ptr) (local.get $len))
+    (i32.store8 (i32.add (local.get $out) (i32.add (local.get $len) (i32.const 10))) (i32.const 125))
+    (i64.or
+      (i64.shl (i64.extend_i32_u (local.get $out)) (i64.const 32))
+      (i64.extend_i32_u (i32.add (local.get $len) (i32.const 11)))))
+"#;
+
+/// `amp_invoke` of a tool that never returns.
+const SPIN: &str = r#"
+  (func (export "amp_invoke") (param i32 i32) (result i64)
+    (loop $forever (br $forever))
+    (i64.const 0))
+"#;
+
+/// `amp_invoke` of a tool asking for 64 MiB more memory, answering an error
+/// when it is refused.
+const GROW: &str = r#"
+  (data (i32.const 0) "{\"error\":\"no memory\"}")
+  (func (export "amp_invoke") (param i32 i32) (result i64)
+    (if (i32.eq (memory.grow (i32.const 1024)) (i32.const -1))
+      (then (return (i64.const 21))))
+    (i64.const 0))
+"#;
+
+/// `amp_invoke` of a tool claiming a 4 GiB answer in a 64 KiB memory.
+const OVERSIZED: &str = r#"
+  (func (export "amp_invoke") (param i32 i32) (result i64)
+    (i64.const 0xffffffff))
+"#;
+
+/// Builds a tool module named `name` around `body`, with a bump allocator
+/// and the spec in its custom section.
+fn tool_module(name: &str, body: &str, imports: &str) -> Vec<u8> {
+    let spec = json!({
+        "name": name,
+        "capabilities": ["sandboxed"],
+        "io": {
+            "input": {
+                "type": "object",
+                "properties": { "msg": { "type": "string" } },
+                "required": ["msg"]
+            },
+            "output": { "type": "object" }
+        }
+    });
+    let spec = spec.to_string().replace('"', "\\\"");
+    wat::parse_str(format!(
+        r#"(module
+  (@custom "amp.toolspec" "{spec}")
+  {imports}
+  (memory (export "memory") 1)
+  (global $next (mut i32) (i32.const 1024))
+  (func (export "amp_alloc") (param $len i32) (result i32)
+    (local $ptr i32)
+    (local.set $ptr (global.get $next))
+    (global.set $next (i32.add (global.get $next) (local.get $len)))
+    (local.get $ptr))
+  {body}
+)"#
+    ))
+    .unwrap()
+}
+
+fn write_module(name: &str, body: &str) -> PathBuf {
+    write_bytes(&tool_module(name, body, ""))
+}
+
+fn write_bytes(bytes: &[u8]) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("amp-{}.wasm", uuid::Uuid::new_v4()));
+    std::fs::write(&path, bytes).unwrap();
+    path
+}
+
+fn echo_plan(signals: Option<Signals>) -> Plan {
+    serde_json::from_value(json!({
+        "signals": signals,
+        "nodes": [{
+            "id": "echo",
+            "op": "call",
+            "capability": "sandboxed",
+            "args": { "msg": "hello" },
+            "out": { "said": "result.msg" }
+        }]
+    }))
+    .unwrap()
+}
+
+#[tokio::test]
+async fn test_wasm_tools_describe_themselves_and_meter_fuel() {
+    let module = write_module("wasm.echo", ECHO);
+    let mut ctx = ExecutionContext::new();
+    ctx.tool_urls.insert(
+        "sandbox".to_string(),
+        format!("wasm://{}", module.display()),
+    );
+
+    let (ctx, result) = Scheduler.run(ctx, &echo_plan(None)).await;
+    result.unwrap();
+    let _ = std::fs::remove_file(&module);
+    assert_eq!(ctx.variables["said"], json!("hello"));
+
+    // The registry label became the tool the module describes
+    assert!(ctx.tool_urls.contains_key("wasm.echo"));
+    assert!(!ctx.tool_urls.contains_key("sandbox"));
+    let spec = &ctx.tool_specs["wasm.echo"];
+    assert_eq!(spec.io.input.required, Some(vec!["msg".to_string()]));
+
+    assert!(ctx.total_fuel > 0);
+    assert_eq!(ctx.tool_io.len(), 1);
+    assert_eq!(ctx.tool_io[0].fuel, Some(ctx.total_fuel));
+    let summary = ctx
+        .trace_events
+        .iter()
+        .find(|trace| trace.event_type == "budget_summary")
+        .and_then(|trace| trace.data.clone())
+        .unwrap();
+    assert_eq!(summary["total_fuel"], json!(ctx.total_fuel));
+}
+
+#[tokio::test]
+async fn test_fuel_counts_against_the_plan_budget() {
+    let module = write_module("wasm.echo", ECHO);
+    let mut ctx = ExecutionContext::new();
+    ctx.tool_urls.insert(
+        "wasm.echo".to_string(),
+        format!("wasm://{}", module.display()),
+    );
+
+    let signals = Signals {
+        latency_budget_ms: None,
+        cost_cap_usd: None,
+        fuel_cap: Some(1),
+        risk: None,
+    };
+    let (_, result) = Scheduler.run(ctx, &echo_plan(Some(signals))).await;
+    let _ = std::fs::remove_file(&module);
+    match result {
+        Err(ExecutionError::BudgetExceeded(message)) => {
+            assert!(message.contains("Fuel budget exceeded"), "{}", message)
+        }
+        other => panic!("Expected the fuel budget to run out, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_failed_calls_are_charged_and_capped_by_the_fuel_budget() {
+    let spin = write_module("wasm.spin", SPIN);
+    let mut ctx = ExecutionContext::new();
+    ctx.tool_urls.insert(
+        "wasm.spin".to_string(),
+        format!("wasm://{}?fuel=20000", spin.display()),
+    );
+    let plan: Plan = serde_json::from_value(json!({
+        "signals": { "fuel_cap": 30000 },
+        "nodes": [{
+            "id": "spin",
+            "op": "call",
+            "tool": "wasm.spin",
+            "args": { "msg": "go" },
+            "retry": {
+                "max_attempts": 3,
+                "initial_backoff_ms": 0,
+                "retry_on": ["invocation"]
+            },
+            "out": { "spun": "result" }
+        }]
+    }))
+    .unwrap();
+
+    let (ctx, result) = Scheduler.run(ctx, &plan).await;
+    let _ = std::fs::remove_file(&spin);
+    match result {
+        Err(ExecutionError::BudgetExceeded(message)) => {
+            assert!(message.contains("Fuel budget exceeded"), "{}", message)
+        }
+        other => panic!("Expected the fuel budget to run out, got {:?}", other),
+    }
+
+    // The retry gets only what is left of the cap, and every attempt is
+    // charged what it burned although it ran out
+    let fuel: Vec<_> = ctx.tool_io.iter().map(|exchange| exchange.fuel).collect();
+    assert_eq!(fuel, vec![Some(20000), Some(10000)]);
+    assert_eq!(ctx.total_fuel, 30000);
+}
+
+#[tokio::test]
+async fn test_wasm_tools_are_stopped_by_their_limits() {
+    let client = ToolClient::new();
+
+    let spin = write_module("wasm.spin", SPIN);
+    let url = format!("wasm://{}?fuel=10000", spin.display());
+    match client.invoke_tool(&url, "wasm.spin", None).await {
+        Err(ToolError::Invocation(message)) => {
+            assert!(message.contains("ran out of fuel"), "{}", message)
+        }
+        other => panic!("Expected the tool to run out of fuel, got {:?}", other),
+    }
+    let _ = std::fs::remove_file(&spin);
+
+    let grow = write_module("wasm.grow", GROW);
+    let url = format!("wasm://{}?memory_mb=16", grow.display());
+    match client.invoke_tool(&url, "wasm.grow", None).await {
+        Err(ToolError::Invocation(message)) => assert_eq!(message, "no memory"),
+        other => panic!("Expected the tool to be refused memory, got {:?}", other),
+    }
+    let _ = std::fs::remove_file(&grow);
+
+    let oversized = write_module("wasm.oversized", OVERSIZED);
+    let url = format!("wasm://{}", oversized.display());
+    match client.invoke_tool(&url, "wasm.oversized", None).await {
+        Err(ToolError::Communication(message)) => {
+            assert!(message.contains("outside its memory"), "{}", message)
+        }
+        other => panic!("Expected the answer to be refused, got {:?}", other),
+    }
+    let _ = std::fs::remove_file(&oversized);
+}
+
+#[tokio::test]
+async fn test_wasm_tools_get_no_host_imports() {
+    let imports = r#"(import "wasi_snapshot_preview1" "fd_write" (func (param i32 i32 i32 i32) (result i32)))"#;
+    let module = write_bytes(&tool_module("wasm.echo", ECHO, imports));
+    let client = ToolClient::new();
+    let url = format!("wasm://{}", module.display());
+
+    // The spec is read without instantiating the module
+    let spec = client.get_tool_spec(&url, "wasm.echo").await.unwrap();
+    assert_eq!(spec.name, "wasm.echo");
+    match client
+        .invoke_tool(&url, "wasm.echo", Some(json!({ "msg": "hi" })))
+        .await
+    {
+        Err(ToolError::Communication(message)) => {
+            assert!(message.contains("fd_write"), "{}", message)
+        }
+        other => panic!("Expected instantiation to fail, got {:?}", other),
+    }
+    let _ = std::fs::remove_file(&module);
+}
+
+#[tokio::test]
+async fn test_modules_without_a_spec_are_rejected() {
+    let bare = wat::parse_str("(module)").unwrap();
+    assert!(embedded_spec(&bare).unwrap().is_none());
+
+    let module = write_bytes(&bare);
+    let client = ToolClient::new();
+    for url in [
+        format!("wasm://{}", module.display()),
+        format!("wasm://{}?fuel=lots", module.display()),
+        "wasm:///no/such/tool.wasm".to_string(),
+    ] {
+        assert!(
+            matches!(
+                client.invoke_tool(&url, "wasm.echo", None).await,
+                Err(ToolError::Communication(_))
+            ),
+            "{} should be rejected",
+            url
+        );
+    }
+    let _ = std::fs::remove_file(&module);
+
+    let echo = write_module("wasm.echo", ECHO);
+    let result: Value = client
+        .invoke_tool(
+            &format!("wasm://{}", echo.display()),
+            "wasm.echo",
+            Some(json!({ "n": 1 })),
+        )
+        .await
+        .unwrap();
+    assert_eq!(result, json!({ "n": 1 }));
+    let _ = std::fs::remove_file(&echo);
+}
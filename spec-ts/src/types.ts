@@ -33,6 +33,7 @@ export const PlanSchema = z.object({
   signals: z.object({
     latency_budget_ms: z.number().int().nonnegative().optional(),
     cost_cap_usd: z.number().nonnegative().optional(),
+    fuel_cap: z.number().int().nonnegative().optional(),
     risk: z.number().min(0).max(1).optional(),
   }).optional(),
 });
@@ -60,6 +61,7 @@ export const PlanSchema = z.object({
   signals: z.object({
     latency_budget_ms: z.number().int().nonnegative().optional(),
     cost_cap_usd: z.number().nonnegative().optional(),
+    fuel_cap: z.number().int().nonnegative().optional(),
     risk: z.number().min(0).max(1).optional(),
   }).optional(),
   nodes: z.array(PlanNodeSchema),
@@ -71,6 +73,7 @@ export const PlanSchema = z.object({
     max_nodes: z.number().int().nonnegative().optional(),
     min_confidence: z.number().min(0).max(1).optional(),
   }).optional(),
+  outputs: z.record(z.string().regex(/^\$[^.[]+/)).optional(),
 });
 
 // Evidence Schema
@@ -118,6 +121,11 @@ export const TraceSchema = z.object({
   citations: z.array(z.string()).optional(),
   signature: z.string().optional(),
   data: z.record(z.any()).optional(),
+  prev_hash: z.string().optional(),
+  key_id: z.string().optional(),
+  run_id: z.string().optional(),
+  span_id: z.string().optional(),
+  parent_span_id: z.string().optional(),
 });
 
 // Type exports